@@ -1,15 +1,33 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Error, Result};
 use hocon::{Hocon, HoconLoader};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
 use serde::Deserialize;
 use serde_json::{Number, Value};
 use std::fmt::Debug;
 
+use crate::template::merge_json;
+
 #[derive(Deserialize, Debug)]
 pub struct KubefiConfig {
     pub crd_schema_path: PathBuf,
+    pub zk_crd_schema_path: PathBuf,
+    pub flow_crd_schema_path: PathBuf,
+    pub parameter_context_crd_schema_path: PathBuf,
+    pub authorization_crd_schema_path: PathBuf,
+    pub stateless_crd_schema_path: PathBuf,
+    pub minifi_crd_schema_path: PathBuf,
     pub replace_existing_crd: bool,
+    /// port of the Prometheus metrics endpoint
+    pub metrics_port: u16,
+    /// ConfigMap with the templates, `./templates` is used when empty
+    #[serde(default)]
+    pub templates_config_map: String,
+    #[serde(default)]
+    pub templates_config_map_namespace: String,
 }
 
 pub fn read_kubefi_config() -> Result<KubefiConfig, Error> {
@@ -20,9 +38,45 @@ pub fn read_kubefi_config() -> Result<KubefiConfig, Error> {
     Ok(cfg)
 }
 
+/// Data of the ConfigMap replacing the `./templates` directory
+pub async fn read_templates_config_map(
+    client: Client,
+    ns: &str,
+    name: &str,
+) -> Result<BTreeMap<String, String>> {
+    debug!("Loading templates from ConfigMap {}/{}...", ns, name);
+    let api: Api<ConfigMap> = Api::namespaced(client, ns);
+    let cm = api.get(name).await?;
+    cm.data
+        .filter(|d| !d.is_empty())
+        .ok_or_else(|| Error::msg(format!("Templates ConfigMap {}/{} has no data", ns, name)))
+}
+
+/// Defaults of the template parameters built into the operator
+const BUILT_IN_NIFI_CONFIG: &str = include_str!("../conf/nifi.conf");
+const NIFI_CONFIG_PATH: &str = "./conf/nifi.conf";
+
+/// Template parameters of the operator: built-in defaults, overridden by the values set in
+/// `./conf/nifi.conf`, so the mounted file may contain only the changed ones. Substitutions are
+/// resolved within each of them
 pub fn read_nifi_config() -> Result<Value> {
+    read_nifi_config_file(Path::new(NIFI_CONFIG_PATH))
+}
+
+/// Template parameters with the values of the operator config at `path`
+pub fn read_nifi_config_file(path: &Path) -> Result<Value> {
     debug!("Loading nifi config...");
-    let hocon = HoconLoader::new().load_file("./conf/nifi.conf")?.hocon()?;
+    let mut config = to_json(HoconLoader::new().load_str(BUILT_IN_NIFI_CONFIG)?.hocon()?)?;
+    if path.exists() {
+        let operator_config = to_json(HoconLoader::new().load_file(path)?.hocon()?)?;
+        merge_json(&mut config, operator_config);
+    } else {
+        warn!("{:?} not found, using built-in defaults", path);
+    }
+    Ok(config)
+}
+
+fn to_json(hocon: Hocon) -> Result<Value> {
     hocon_to_json(hocon).ok_or_else(|| Error::msg("Failed to convert config file to JSON"))
 }
 
@@ -35,10 +89,7 @@ fn hocon_to_json(hocon: Hocon) -> Option<Value> {
         )),
         Hocon::String(s) => Some(Value::String(s)),
         Hocon::Array(vec) => Some(Value::Array(
-            vec.into_iter()
-                .map(hocon_to_json)
-                .filter_map(|i| i)
-                .collect(),
+            vec.into_iter().filter_map(hocon_to_json).collect(),
         )),
         Hocon::Hash(map) => Some(Value::Object(
             map.into_iter()
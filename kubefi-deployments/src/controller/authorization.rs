@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use kube::Client;
+
+use crate::controller::ControllerError::{InvalidSpec, MissingProperty};
+use crate::controller::{
+    get_api, read_name, read_namespace, NiFiController, ReplaceResourceStatus,
+};
+use crate::crd::{
+    NiFiAuthorization, NiFiAuthorizationStatus, NiFiDeployment, NiFiDeploymentSpec, PolicyAction,
+};
+use crate::nifi_api::{AccessPolicyComponent, NiFiApi, TenantComponent, TenantReference};
+use crate::template::Template;
+
+// nodes proxy the requests of users to the other nodes
+const PROXY_RESOURCE: &str = "/proxy";
+
+pub type ReplaceAuthorizationStatus = ReplaceResourceStatus<NiFiAuthorizationStatus>;
+
+pub struct AuthorizationController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiController {
+    /// Creates users and groups and sets members of the declared groups and policies,
+    /// the status is replaced only when it is different
+    pub async fn on_apply_authorization(
+        &self,
+        auth: NiFiAuthorization,
+    ) -> Result<Option<ReplaceAuthorizationStatus>> {
+        let (name, ns) = read_authorization_meta(&auth)?;
+        let status = match self.authorization_controller.apply(&auth, &ns).await {
+            Ok(policies) => NiFiAuthorizationStatus {
+                policies,
+                error_msg: "".to_string(),
+            },
+            Err(e) => NiFiAuthorizationStatus {
+                error_msg: e.to_string(),
+                ..auth.status.clone().unwrap_or_default()
+            },
+        };
+        if auth.status.as_ref() == Some(&status) {
+            return Ok(None);
+        }
+        Ok(Some(ReplaceAuthorizationStatus { name, ns, status }))
+    }
+
+    /// Keeps the node identities of a running secured cluster in the proxy policy, authorizers.xml
+    /// seeds them on a new cluster only, so nodes added by a scale-up are authorized here
+    pub async fn on_node_identities(&self, d: &NiFiDeployment) -> Result<bool> {
+        let name = read_name(d)?;
+        let ns = read_namespace(d)?;
+        if d.spec.hibernated() {
+            return Ok(false);
+        }
+        self.authorization_controller
+            .authorize_nodes(&name, &ns, &d.spec)
+            .await
+    }
+
+    /// Users, groups and policies are left in NiFi, removing them could lock users out
+    pub async fn on_delete_authorization(&self, auth: NiFiAuthorization) -> Result<()> {
+        let (name, _) = read_authorization_meta(&auth)?;
+        info!(
+            "NiFiAuthorization {} is deleted, its users, groups and policies are kept in {}",
+            name, &auth.spec.deployment_ref
+        );
+        Ok(())
+    }
+}
+
+impl AuthorizationController {
+    /// Users of the node identities with the write policy of `/proxy`, identities of removed
+    /// nodes are kept
+    async fn authorize_nodes(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        if !self.template.nifi_secured(name, spec) {
+            return Ok(false);
+        }
+        let identities = (0..spec.nifi_replicas)
+            .map(|i| self.template.node_identity(name, ns, i, spec))
+            .collect::<Result<Vec<_>>>()?;
+        let api = NiFiApi::connect(&self.client, &self.template, name, ns, spec, None).await?;
+        let mut users = api
+            .users()
+            .await?
+            .into_iter()
+            .map(|u| (u.component.identity, u.id))
+            .collect::<BTreeMap<_, _>>();
+        let mut updated = false;
+        for identity in &identities {
+            if !users.contains_key(identity) {
+                info!("Creating NiFi user of node {} in {}", identity, name);
+                let user = api.create_user(identity).await?;
+                users.insert(identity.clone(), user.id);
+                updated = true;
+            }
+        }
+        let nodes = tenant_refs(&identities, &users, "user")?;
+        match api.access_policy("write", PROXY_RESOURCE).await? {
+            Some(current) => {
+                let missing = nodes
+                    .into_iter()
+                    .filter(|n| !current.component.users.contains(n))
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    info!(
+                        "Adding {} NiFi nodes to the proxy policy of {}",
+                        missing.len(),
+                        name
+                    );
+                    let mut component = current.component.clone();
+                    component.users.extend(missing);
+                    api.update_access_policy(&current, &component).await?;
+                    updated = true;
+                }
+            }
+            None => {
+                info!("Creating the proxy policy of {}", name);
+                let component = AccessPolicyComponent {
+                    id: None,
+                    resource: PROXY_RESOURCE.to_string(),
+                    action: "write".to_string(),
+                    users: nodes,
+                    user_groups: vec![],
+                };
+                api.create_access_policy(&component).await?;
+                updated = true;
+            }
+        }
+        Ok(updated)
+    }
+
+    async fn apply(&self, auth: &NiFiAuthorization, ns: &str) -> Result<u32> {
+        let spec = &auth.spec;
+        let d = get_api::<NiFiDeployment>(&self.client, ns)
+            .get(&spec.deployment_ref)
+            .await?;
+        let pod_name = format!("{}-0", &spec.deployment_ref);
+        let api = NiFiApi::connect(
+            &self.client,
+            &self.template,
+            &spec.deployment_ref,
+            ns,
+            &d.spec,
+            Some(&pod_name),
+        )
+        .await?;
+
+        let identities = spec
+            .users
+            .iter()
+            .chain(spec.groups.iter().flat_map(|g| g.members.iter()))
+            .chain(spec.policies.iter().flat_map(|p| p.users.iter()));
+        let mut users = api
+            .users()
+            .await?
+            .into_iter()
+            .map(|u| (u.component.identity, u.id))
+            .collect::<BTreeMap<_, _>>();
+        for identity in identities {
+            if !users.contains_key(identity) {
+                info!(
+                    "Creating NiFi user {} in {}",
+                    identity, &spec.deployment_ref
+                );
+                let user = api.create_user(identity).await?;
+                users.insert(identity.clone(), user.id);
+            }
+        }
+
+        let existing_groups = api.user_groups().await?;
+        let mut groups = existing_groups
+            .iter()
+            .map(|g| (g.component.identity.clone(), g.id.clone()))
+            .collect::<BTreeMap<_, _>>();
+        for group in &spec.groups {
+            let component = TenantComponent {
+                id: None,
+                identity: group.name.clone(),
+                users: tenant_refs(&group.members, &users, "user")?,
+            };
+            match existing_groups
+                .iter()
+                .find(|g| g.component.identity == group.name)
+            {
+                Some(current) if same_tenants(&current.component.users, &component.users) => (),
+                Some(current) => {
+                    info!(
+                        "Updating members of NiFi user group {} in {}",
+                        &group.name, &spec.deployment_ref
+                    );
+                    api.update_user_group(current, &component).await?;
+                }
+                None => {
+                    info!(
+                        "Creating NiFi user group {} in {}",
+                        &group.name, &spec.deployment_ref
+                    );
+                    let created = api.create_user_group(&component).await?;
+                    groups.insert(group.name.clone(), created.id);
+                }
+            }
+        }
+
+        for policy in &spec.policies {
+            let action = match policy.action {
+                PolicyAction::Read => "read",
+                PolicyAction::Write => "write",
+            };
+            let component = AccessPolicyComponent {
+                id: None,
+                resource: policy.resource.clone(),
+                action: action.to_string(),
+                users: tenant_refs(&policy.users, &users, "user")?,
+                user_groups: tenant_refs(&policy.groups, &groups, "user group")?,
+            };
+            match api.access_policy(action, &policy.resource).await? {
+                Some(current)
+                    if same_tenants(&current.component.users, &component.users)
+                        && same_tenants(&current.component.user_groups, &component.user_groups) => {
+                }
+                Some(current) => {
+                    info!(
+                        "Updating NiFi policy {} {} in {}",
+                        &action, &policy.resource, &spec.deployment_ref
+                    );
+                    api.update_access_policy(&current, &component).await?;
+                }
+                None => {
+                    info!(
+                        "Creating NiFi policy {} {} in {}",
+                        &action, &policy.resource, &spec.deployment_ref
+                    );
+                    api.create_access_policy(&component).await?;
+                }
+            }
+        }
+        Ok(spec.policies.len() as u32)
+    }
+}
+
+fn tenant_refs(
+    names: &[String],
+    ids: &BTreeMap<String, String>,
+    kind: &str,
+) -> Result<Vec<TenantReference>> {
+    names
+        .iter()
+        .map(|name| {
+            ids.get(name)
+                .map(|id| TenantReference { id: id.clone() })
+                .ok_or_else(|| {
+                    Error::from(InvalidSpec(format!("NiFi {} {} is not found", kind, name)))
+                })
+        })
+        .collect()
+}
+
+fn same_tenants(current: &[TenantReference], expected: &[TenantReference]) -> bool {
+    let mut current = current.to_vec();
+    let mut expected = expected.to_vec();
+    current.sort();
+    current.dedup();
+    expected.sort();
+    expected.dedup();
+    current == expected
+}
+
+fn read_authorization_meta(auth: &NiFiAuthorization) -> Result<(String, String)> {
+    let missing =
+        |property: &str| Error::from(MissingProperty(property.to_string(), auth.kind.clone()));
+    let name = auth.metadata.name.clone().ok_or_else(|| missing("name"))?;
+    let ns = auth
+        .metadata
+        .namespace
+        .clone()
+        .ok_or_else(|| missing("namespace"))?;
+    Ok((name, ns))
+}
@@ -0,0 +1,76 @@
+use std::rc::Rc;
+
+use anyhow::Result;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::Service;
+use kube::Client;
+
+use crate::controller::service::service_changed;
+use crate::controller::workload::{apply_workload, config_hash, config_hash_of, delete_resource};
+use crate::controller::{get_api, recreate_on_change};
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::Template;
+
+pub struct AuthProxyController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl AuthProxyController {
+    /// oauth2-proxy Deployment and Service of `spec.authProxy`,
+    /// both of them are deleted once the proxy is disabled
+    pub async fn handle_auth_proxy(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let proxy_name = format!("{}-auth-proxy", name);
+        let deployment_updated = self.handle_deployment(&proxy_name, name, ns, spec).await?;
+        let svc_updated = recreate_on_change::<Service, _, _>(
+            &self.client,
+            &proxy_name,
+            name,
+            ns,
+            |name| self.template.auth_proxy_service(name, spec),
+            service_changed,
+        )
+        .await?;
+        Ok(deployment_updated || svc_updated)
+    }
+
+    async fn handle_deployment(
+        &self,
+        proxy_name: &str,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let current = get_api::<Deployment>(&self.client, ns)
+            .get(proxy_name)
+            .await
+            .ok();
+        // the hash covers the rendered Ingress host and NiFi protocol as well
+        let yaml = match self.template.auth_proxy_deployment(name, spec, "")? {
+            Some(yaml) => yaml,
+            None if current.is_some() => {
+                info!("Auth proxy is disabled, deleting {}", proxy_name);
+                delete_resource::<Deployment>(&self.client, proxy_name, ns).await?;
+                return Ok(true);
+            }
+            None => return Ok(false),
+        };
+        let hash = config_hash(&yaml, &spec.auth_proxy)?;
+        if current.as_ref().and_then(|d| config_hash_of(d)) == Some(hash.as_str()) {
+            return Ok(false);
+        }
+        match self.template.auth_proxy_deployment(name, spec, &hash)? {
+            Some(yaml) => {
+                apply_workload::<Deployment>(&self.client, proxy_name, ns, &yaml, &hash, false)
+                    .await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::chrono::{Duration, Utc};
+use kube::api::{ListParams, Meta, PatchParams, PatchStrategy};
+use kube::Client;
+
+use crate::controller::health::elapsed;
+use crate::controller::statefulset::quantity;
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{get_api, KUBEFI_LABELS};
+use crate::crd::{AutoscalingCfg, ClusterHealth, NiFiDeployment};
+use crate::cron::Cron;
+use crate::resources::PodMetricsList;
+
+const DEFAULT_COOLDOWN_SECONDS: u64 = 300;
+// metric within 10% of the target keeps the replicas, as with HorizontalPodAutoscaler
+const TOLERANCE: f64 = 0.1;
+const SCHEDULE_LOOKBACK_DAYS: i64 = 31;
+
+pub struct AutoscalingController {
+    pub client: Rc<Client>,
+}
+
+impl AutoscalingController {
+    /// Patches `nifiReplicas` towards the targets of `spec.autoscaling` and returns the time of the
+    /// change, a scale-down removes one node per step to offload it via the usual scaling path
+    pub async fn autoscale(
+        &self,
+        d: &NiFiDeployment,
+        name: &str,
+        ns: &str,
+        health: Option<&ClusterHealth>,
+    ) -> Result<Option<String>> {
+        let cfg = match d.spec.autoscaling.as_ref() {
+            Some(cfg) if cfg.enabled == Some(true) => cfg,
+            _ => return Ok(None),
+        };
+        let current = d.spec.nifi_replicas;
+        let last_scale_time = d.status.as_ref().and_then(|s| s.last_scale_time.as_ref());
+        let cooldown = cfg.cooldown_seconds.unwrap_or(DEFAULT_COOLDOWN_SECONDS);
+        if current == 0
+            || d.spec.hibernated()
+            || last_scale_time.is_some_and(|t| !elapsed(t, cooldown))
+        {
+            return Ok(None);
+        }
+        let desired = match self
+            .desired_replicas(cfg, current, name, ns, health)
+            .await?
+        {
+            Some(desired) if desired < current => current - 1,
+            Some(desired) => desired,
+            None => return Ok(None),
+        };
+        if desired == current {
+            return Ok(None);
+        }
+        info!(
+            "Autoscaling {} from {} to {} NiFi nodes",
+            name, current, desired
+        );
+        let params = PatchParams {
+            patch_strategy: PatchStrategy::Merge,
+            ..PatchParams::default()
+        };
+        let patch = json!({ "spec": { "nifiReplicas": desired } });
+        get_api::<NiFiDeployment>(&self.client, ns)
+            .patch(name, &params, serde_json::to_vec(&patch)?)
+            .await?;
+        Ok(Some(Utc::now().to_rfc3339()))
+    }
+
+    /// The largest replicas proposed by the metrics, within min and max replicas,
+    /// or at least the replicas of the active schedule when there is one
+    async fn desired_replicas(
+        &self,
+        cfg: &AutoscalingCfg,
+        current: u8,
+        name: &str,
+        ns: &str,
+        health: Option<&ClusterHealth>,
+    ) -> Result<Option<u8>> {
+        let mut proposals = Vec::new();
+        if let (Some(target), Some(health)) = (cfg.target_queued_flow_files_per_node, health) {
+            let per_node = health.queued_flow_files as f64 / current as f64;
+            proposals.push(replicas_for(current, per_node, target as f64));
+        }
+        if let Some(target) = cfg.target_cpu_utilization_percent {
+            let utilization = self.cpu_utilization(name, ns).await?;
+            proposals.push(replicas_for(current, utilization, target as f64));
+        }
+        Ok(bounded_replicas(cfg, proposals, scheduled_replicas(cfg)?))
+    }
+
+    /// CPU usage of NiFi Pods containers in percent of their CPU requests
+    async fn cpu_utilization(&self, name: &str, ns: &str) -> Result<f64> {
+        let is_member = |pod: &str| {
+            pod.strip_prefix(&format!("{}-", name))
+                .is_some_and(|o| o.parse::<u8>().is_ok())
+        };
+        let lp = ListParams::default().labels(KUBEFI_LABELS);
+        let pods = get_api::<Pod>(&self.client, ns).list(&lp).await?;
+        let mut requests = BTreeMap::new();
+        for pod in pods.items.iter().filter(|p| is_member(&Meta::name(*p))) {
+            let containers = pod.spec.iter().flat_map(|s| s.containers.iter());
+            for c in containers {
+                let cpu = c
+                    .resources
+                    .as_ref()
+                    .and_then(|r| r.requests.as_ref())
+                    .and_then(|r| r.get("cpu"))
+                    .and_then(|q| quantity(&q.0));
+                if let Some(cpu) = cpu {
+                    requests.insert((Meta::name(pod), c.name.clone()), cpu);
+                }
+            }
+        }
+        if requests.is_empty() {
+            return Err(Error::from(InvalidSpec(format!(
+                "Pods of {} have no CPU requests to autoscale on",
+                name
+            ))));
+        }
+        let path = format!("/apis/metrics.k8s.io/v1beta1/namespaces/{}/pods", ns);
+        let request = hyper::Request::get(path).body(vec![])?;
+        let metrics = self.client.request::<PodMetricsList>(request).await?;
+        let usage = metrics
+            .items
+            .iter()
+            .flat_map(|p| {
+                let pod = p.metadata.name.clone().unwrap_or_default();
+                p.containers
+                    .iter()
+                    .map(move |c| ((pod.clone(), c.name.clone()), c))
+            })
+            .filter(|(key, _)| requests.contains_key(key))
+            .filter_map(|(_, c)| c.usage.get("cpu").and_then(|q| quantity(q)))
+            .sum::<f64>();
+        Ok(usage / requests.values().sum::<f64>() * 100f64)
+    }
+}
+
+/// Replicas of the schedule which fired last within the lookback period
+fn scheduled_replicas(cfg: &AutoscalingCfg) -> Result<Option<u8>> {
+    let now = Utc::now();
+    let lookback = Duration::days(SCHEDULE_LOOKBACK_DAYS);
+    let mut active = None;
+    for schedule in &cfg.schedules {
+        let cron = Cron::parse(&schedule.cron).map_err(|e| {
+            Error::from(InvalidSpec(format!(
+                "autoscaling schedule is invalid: {}",
+                e
+            )))
+        })?;
+        if let Some(fired) = cron.last_before(now, lookback) {
+            if active.is_none_or(|(last, _)| fired > last) {
+                active = Some((fired, schedule.replicas));
+            }
+        }
+    }
+    Ok(active.map(|(_, replicas)| replicas))
+}
+
+/// The largest of the proposals within min and max replicas, or at least the scheduled replicas
+fn bounded_replicas(cfg: &AutoscalingCfg, proposals: Vec<u8>, scheduled: Option<u8>) -> Option<u8> {
+    let min = cfg.min_replicas.unwrap_or(1).max(1);
+    let max = cfg.max_replicas.max(min);
+    match scheduled {
+        Some(scheduled) => Some(
+            proposals
+                .into_iter()
+                .fold(scheduled.max(1), u8::max)
+                .min(max),
+        ),
+        None => proposals.into_iter().max().map(|r| r.clamp(min, max)),
+    }
+}
+
+/// Replicas bringing the average metric to the target, unchanged within the tolerance
+fn replicas_for(current: u8, value: f64, target: f64) -> u8 {
+    let ratio = value / target;
+    if (ratio - 1f64).abs() <= TOLERANCE {
+        return current;
+    }
+    (current as f64 * ratio).ceil().min(u8::MAX as f64) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crd::ScalingScheduleCfg;
+
+    fn cfg(min_replicas: Option<u8>, max_replicas: u8) -> AutoscalingCfg {
+        AutoscalingCfg {
+            enabled: Some(true),
+            min_replicas,
+            max_replicas,
+            target_queued_flow_files_per_node: None,
+            target_cpu_utilization_percent: None,
+            cooldown_seconds: None,
+            schedules: vec![],
+        }
+    }
+
+    fn schedule(cron: &str, replicas: u8) -> ScalingScheduleCfg {
+        ScalingScheduleCfg {
+            cron: cron.to_string(),
+            replicas,
+        }
+    }
+
+    #[test]
+    fn test_replicas_for() {
+        assert_eq!(replicas_for(3, 1500f64, 1000f64), 5);
+        assert_eq!(replicas_for(4, 500f64, 1000f64), 2);
+        // within the tolerance
+        assert_eq!(replicas_for(3, 1090f64, 1000f64), 3);
+        assert_eq!(replicas_for(3, 910f64, 1000f64), 3);
+        assert_eq!(replicas_for(3, 0f64, 1000f64), 0);
+        assert_eq!(replicas_for(200, 2000f64, 1000f64), u8::MAX);
+    }
+
+    #[test]
+    fn test_bounded_replicas() {
+        let cfg = cfg(Some(2), 5);
+        assert_eq!(bounded_replicas(&cfg, vec![], None), None);
+        assert_eq!(bounded_replicas(&cfg, vec![3, 4], None), Some(4));
+        assert_eq!(bounded_replicas(&cfg, vec![0], None), Some(2));
+        assert_eq!(bounded_replicas(&cfg, vec![9], None), Some(5));
+        // the schedule is not limited by min replicas, metrics only add nodes to it
+        assert_eq!(bounded_replicas(&cfg, vec![], Some(1)), Some(1));
+        assert_eq!(bounded_replicas(&cfg, vec![0], Some(1)), Some(1));
+        assert_eq!(bounded_replicas(&cfg, vec![3], Some(1)), Some(3));
+        assert_eq!(bounded_replicas(&cfg, vec![], Some(0)), Some(1));
+        assert_eq!(bounded_replicas(&cfg, vec![], Some(8)), Some(5));
+    }
+
+    #[test]
+    fn test_bounded_replicas_defaults() {
+        // min replicas is 1 by default and max replicas is at least min replicas
+        assert_eq!(bounded_replicas(&cfg(None, 3), vec![0], None), Some(1));
+        assert_eq!(bounded_replicas(&cfg(Some(0), 3), vec![0], None), Some(1));
+        assert_eq!(bounded_replicas(&cfg(Some(4), 2), vec![1], None), Some(4));
+    }
+
+    #[test]
+    fn test_scheduled_replicas() {
+        let mut cfg = cfg(None, 5);
+        assert_eq!(scheduled_replicas(&cfg).unwrap(), None);
+        // the schedule of every minute fired last
+        cfg.schedules = vec![schedule("0 0 1 1 *", 2), schedule("* * * * *", 4)];
+        assert_eq!(scheduled_replicas(&cfg).unwrap(), Some(4));
+        cfg.schedules = vec![schedule("0 8 * *", 2)];
+        assert!(scheduled_replicas(&cfg).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::batch::v1beta1::CronJob;
+use k8s_openapi::api::core::v1::{
+    PersistentVolumeClaim, Pod, PodTemplateSpec, TypedLocalObjectReference,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use kube::api::PostParams;
+use kube::Client;
+use openssl::sha::Sha256;
+
+use crate::controller::flowbackup::backup_to_s3;
+use crate::controller::statefulset::{set_claims, TEMPLATE_HASH_ANNOTATION};
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{
+    from_yaml, get_api, get_or_create, hex_digest, recreate_on_change, resource_updated,
+};
+use crate::crd::{NiFiDeployment, NiFiDeploymentSpec};
+use crate::discovery::ClusterApis;
+use crate::resources::VolumeSnapshot;
+use crate::template::Template;
+
+/// Backup id, a new value takes VolumeSnapshots of all NiFi and ZooKeeper PVCs
+pub const BACKUP_ANNOTATION: &str = "io.github.novakov-alexey/backup";
+const SNAPSHOT_API_GROUP: &str = "snapshot.storage.k8s.io";
+const DEFAULT_BACKUP_INTERVAL_SECONDS: u64 = 86400;
+
+pub struct BackupController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+    pub apis: ClusterApis,
+}
+
+impl BackupController {
+    pub async fn handle_backup(&self, d: &NiFiDeployment, name: &str, ns: &str) -> Result<bool> {
+        let backup = match d
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(BACKUP_ANNOTATION))
+        {
+            Some(backup) => backup,
+            None => return Ok(false),
+        };
+        if !self.apis.volume_snapshot {
+            return Err(Error::from(InvalidSpec(format!(
+                "backup {} is requested, but {}/v1 API is not available",
+                backup, SNAPSHOT_API_GROUP
+            ))));
+        }
+        let pvcs = set_claims(&self.client, name, ns).await?;
+        let names = pvcs
+            .iter()
+            .map(|pvc| (pvc, snapshot_name(pvc, backup)))
+            .collect::<Vec<_>>();
+        let snapshots = names.iter().map(|(pvc, snapshot)| {
+            get_or_create::<VolumeSnapshot, _>(&self.client, snapshot, name, ns, move |name| {
+                self.template.volume_snapshot(name, pvc, backup, &d.spec)
+            })
+        });
+        let mut updated = false;
+        for result in futures::future::join_all(snapshots).await {
+            updated |= resource_updated(result?);
+        }
+        if updated {
+            info!("Backup {} of {} PVCs is taken: {:?}", backup, name, &pvcs);
+        }
+        Ok(updated)
+    }
+
+    /// Uploads a flow backup to `spec.backup.s3` once `spec.backup.intervalSeconds` passed
+    /// since the last one, returns the RFC 3339 time of the new backup
+    pub async fn handle_s3_backup(
+        &self,
+        d: &NiFiDeployment,
+        name: &str,
+        ns: &str,
+    ) -> Result<Option<String>> {
+        let backup = match &d.spec.backup {
+            Some(backup) if backup.s3.is_some() => backup,
+            _ => return Ok(None),
+        };
+        let last_backup_time = d.status.as_ref().and_then(|s| s.last_backup_time.clone());
+        if backup.schedule.is_some() {
+            return self.scheduled_backup_time(name, ns, last_backup_time).await;
+        }
+        let interval = backup
+            .interval_seconds
+            .unwrap_or(DEFAULT_BACKUP_INTERVAL_SECONDS);
+        let last_backup = last_backup_time
+            .as_ref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+        let now = Utc::now();
+        if let Some(last_backup) = last_backup {
+            if now.signed_duration_since(last_backup) < Duration::seconds(interval as i64) {
+                return Ok(None);
+            }
+        }
+        backup_to_s3(&self.client, &self.template, name, ns, &d.spec).await?;
+        info!("Flow backup of {} is uploaded to S3", name);
+        Ok(Some(now.to_rfc3339()))
+    }
+
+    /// Last run of the backup CronJob when it is newer than `last_backup_time`
+    async fn scheduled_backup_time(
+        &self,
+        name: &str,
+        ns: &str,
+        last_backup_time: Option<String>,
+    ) -> Result<Option<String>> {
+        let cronjob = match get_api::<CronJob>(&self.client, ns)
+            .get(&cronjob_name(name))
+            .await
+        {
+            Ok(cronjob) => cronjob,
+            Err(_) => return Ok(None),
+        };
+        let scheduled = cronjob
+            .status
+            .and_then(|s| s.last_schedule_time)
+            .map(|t| t.0.to_rfc3339());
+        Ok(scheduled.filter(|t| Some(t) != last_backup_time.as_ref()))
+    }
+
+    /// Keeps the CronJob of `spec.backup.schedule` in sync with the spec, so backups
+    /// do not depend on the operator running. It is deleted once the schedule is removed
+    pub async fn handle_backup_schedule(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        recreate_on_change::<CronJob, _, _>(
+            &self.client,
+            &cronjob_name(name),
+            name,
+            ns,
+            |name| self.backup_cronjob(name, spec),
+            |current, expected| spec_hash(current) != spec_hash(expected),
+        )
+        .await
+    }
+
+    /// The CronJob template with the S3 backup Pod of the first NiFi node
+    fn backup_cronjob(&self, name: &str, spec: &NiFiDeploymentSpec) -> Result<Option<String>> {
+        let mut cronjob = match self.template.s3_backup_cronjob(name, spec)? {
+            Some(yaml) => from_yaml::<CronJob>(&yaml)?,
+            None => return Ok(None),
+        };
+        let pod = match self
+            .template
+            .s3_backup_pod(name, &format!("{}-0", name), spec)?
+        {
+            Some(yaml) => from_yaml::<Pod>(&yaml)?,
+            None => return Ok(None),
+        };
+        if let Some(job) = cronjob
+            .spec
+            .as_mut()
+            .and_then(|s| s.job_template.spec.as_mut())
+        {
+            // Job Pods get generated names
+            job.template = PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: pod.metadata.labels,
+                    ..ObjectMeta::default()
+                }),
+                spec: pod.spec,
+            };
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_string(&cronjob.spec)?.as_bytes());
+        let mut annotations = cronjob.metadata.annotations.unwrap_or_default();
+        annotations.insert(TEMPLATE_HASH_ANNOTATION.to_string(), hex_digest(hasher));
+        cronjob.metadata.annotations = Some(annotations);
+        Ok(Some(serde_yaml::to_string(&cronjob)?))
+    }
+}
+
+fn cronjob_name(name: &str) -> String {
+    format!("{}-s3-backup", name)
+}
+
+fn spec_hash(cronjob: &CronJob) -> Option<&String> {
+    cronjob
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|a| a.get(TEMPLATE_HASH_ANNOTATION))
+}
+
+fn snapshot_name(pvc: &str, backup: &str) -> String {
+    format!("{}-{}", pvc, backup)
+}
+
+/// Creates PVCs of a new StatefulSet from VolumeSnapshots of `source_set` PVCs,
+/// the StatefulSet then adopts them by name instead of provisioning empty volumes
+pub async fn restore_claims(
+    client: &Client,
+    ns: &str,
+    set: &StatefulSet,
+    source_set: &str,
+    backup: &str,
+) -> Result<()> {
+    let spec = match &set.spec {
+        Some(spec) => spec,
+        None => return Ok(()),
+    };
+    let set_name = set.metadata.name.clone().unwrap_or_default();
+    let pvc_api = get_api::<PersistentVolumeClaim>(client, ns);
+    let snapshot_api = get_api::<VolumeSnapshot>(client, ns);
+    let labels: BTreeMap<String, String> = spec.selector.match_labels.clone().unwrap_or_default();
+    for ordinal in 0..spec.replicas.unwrap_or(1) {
+        for claim in spec.volume_claim_templates.iter().flatten() {
+            let claim_name = claim.metadata.name.clone().unwrap_or_default();
+            let pvc_name = format!("{}-{}-{}", &claim_name, &set_name, ordinal);
+            if pvc_api.get(&pvc_name).await.is_ok() {
+                continue;
+            }
+            let source = snapshot_name(
+                &format!("{}-{}-{}", &claim_name, source_set, ordinal),
+                backup,
+            );
+            snapshot_api.get(&source).await.map_err(|_| {
+                Error::from(InvalidSpec(format!(
+                    "VolumeSnapshot {} of backup {} is not found",
+                    &source, backup
+                )))
+            })?;
+            let mut pvc_spec = claim.spec.clone().unwrap_or_default();
+            pvc_spec.data_source = Some(TypedLocalObjectReference {
+                api_group: Some(SNAPSHOT_API_GROUP.to_string()),
+                kind: "VolumeSnapshot".to_string(),
+                name: source.clone(),
+            });
+            let pvc = PersistentVolumeClaim {
+                metadata: ObjectMeta {
+                    name: Some(pvc_name.clone()),
+                    labels: Some(labels.clone()),
+                    ..ObjectMeta::default()
+                },
+                spec: Some(pvc_spec),
+                ..PersistentVolumeClaim::default()
+            };
+            debug!(
+                "Restoring PVC {} from VolumeSnapshot {}",
+                &pvc_name, &source
+            );
+            pvc_api.create(&PostParams::default(), &pvc).await?;
+        }
+    }
+    Ok(())
+}
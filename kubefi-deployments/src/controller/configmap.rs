@@ -5,8 +5,8 @@ use k8s_openapi::api::core::v1::ConfigMap;
 use kube::api::DeleteParams;
 use kube::Client;
 
-use crate::controller::{create_from_yaml, from_yaml, get_api, get_or_create};
-use crate::crd::NiFiDeployment;
+use crate::controller::{create_from_yaml, from_yaml, get_api, get_or_create, resource_updated};
+use crate::crd::{NiFiDeployment, NiFiDeploymentSpec};
 use crate::template::Template;
 
 use super::either::Either::{Left, Right};
@@ -23,15 +23,12 @@ impl ConfigMapController {
         name: &str,
         ns: &str,
     ) -> Result<bool> {
-        let zk_cm_name = format!("{}-zookeeper", &name);
-        let zk_cm = get_or_create::<ConfigMap, _>(&self.client, &zk_cm_name, &name, &ns, |name| {
-            self.template.zk_configmap(name)
-        });
+        let zk_cm = self.handle_zk_configmap(name, ns, &d.spec);
 
         let nifi_cm_name = format!("{}-config", &name);
         let nifi_cm =
-            get_or_create::<ConfigMap, _>(&self.client, &nifi_cm_name, &name, &ns, |name| {
-                self.template.nifi_configmap(name, &ns, &d.spec)
+            get_or_create::<ConfigMap, _>(&self.client, &nifi_cm_name, name, ns, |name| {
+                self.template.nifi_configmap(name, ns, &d.spec)
             });
 
         let (r1, r2) = futures::future::join(zk_cm, nifi_cm).await;
@@ -40,7 +37,7 @@ impl ConfigMapController {
         match nifi_cm {
             Left(maybe_cm) => match maybe_cm {
                 Some(existing_cm) => {
-                    self.handle_update(&d, &name, &ns, &nifi_cm_name, existing_cm)
+                    self.handle_update(d, name, ns, &nifi_cm_name, existing_cm)
                         .await
                 }
                 None => Ok(false),
@@ -49,6 +46,20 @@ impl ConfigMapController {
         }
     }
 
+    pub async fn handle_zk_configmap(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let zk_cm_name = format!("{}-zookeeper", &name);
+        get_or_create::<ConfigMap, _>(&self.client, &zk_cm_name, name, ns, |name| {
+            self.template.zk_configmap(name, spec)
+        })
+        .await
+        .map(resource_updated)
+    }
+
     async fn handle_update(
         &self,
         d: &NiFiDeployment,
@@ -57,7 +68,7 @@ impl ConfigMapController {
         cm_name: &str,
         current: ConfigMap,
     ) -> Result<bool> {
-        let maybe_yaml = self.template.nifi_configmap(&cr_name, &ns, &d.spec)?;
+        let maybe_yaml = self.template.nifi_configmap(cr_name, ns, &d.spec)?;
         match maybe_yaml {
             Some(yaml) => {
                 let expected_cm = from_yaml::<ConfigMap>(&yaml)?;
@@ -70,7 +81,7 @@ impl ConfigMapController {
                     }
                 }
                 if current.data != expected_data {
-                    self.recreate_cm(&cr_name, &ns, &cm_name, &d)
+                    self.recreate_cm(cr_name, ns, cm_name, d)
                         .await
                         .map(|_| true)
                 } else {
@@ -89,15 +100,15 @@ impl ConfigMapController {
         d: &NiFiDeployment,
     ) -> Result<()> {
         let params = &DeleteParams::default();
-        let api = get_api::<ConfigMap>(&self.client, &ns);
-        api.delete(&nifi_cm_name, params).await?;
+        let api = get_api::<ConfigMap>(&self.client, ns);
+        api.delete(nifi_cm_name, params).await?;
 
         debug!("Creating new ConfigMap: {}", &nifi_cm_name);
         create_from_yaml::<ConfigMap, _, _>(
-            &name,
-            &ns,
+            name,
+            ns,
             &self.client,
-            |name| self.template.nifi_configmap(name, &ns, &d.spec),
+            |name| self.template.nifi_configmap(name, ns, &d.spec),
             Ok,
         )
         .await
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use k8s_openapi::api::core::v1::{Event, EventSource, ObjectReference};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time};
+use k8s_openapi::chrono::Utc;
+use k8s_openapi::Resource;
+use kube::api::{Meta, PostParams};
+use kube::Client;
+
+use crate::controller::get_api;
+
+const EVENT_COMPONENT: &str = "kubefi";
+
+/// Warning Event of the resource, shown by `kubectl describe` and `kubectl get events`
+pub async fn warning_event<T: Resource + Meta>(
+    client: &Client,
+    resource: &T,
+    reason: &str,
+    message: &str,
+) -> Result<()> {
+    let ns = Meta::namespace(resource).unwrap_or_default();
+    let now = Time(Utc::now());
+    let event = Event {
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}.", Meta::name(resource))),
+            namespace: Some(ns.clone()),
+            ..ObjectMeta::default()
+        },
+        involved_object: ObjectReference {
+            api_version: Some(T::API_VERSION.to_string()),
+            kind: Some(T::KIND.to_string()),
+            name: Some(Meta::name(resource)),
+            namespace: Some(ns.clone()),
+            uid: resource.meta().uid.clone(),
+            ..ObjectReference::default()
+        },
+        type_: Some("Warning".to_string()),
+        reason: Some(reason.to_string()),
+        message: Some(message.to_string()),
+        count: Some(1),
+        first_timestamp: Some(now.clone()),
+        last_timestamp: Some(now),
+        source: Some(EventSource {
+            component: Some(EVENT_COMPONENT.to_string()),
+            host: None,
+        }),
+        reporting_component: Some(EVENT_COMPONENT.to_string()),
+        ..Event::default()
+    };
+    get_api::<Event>(client, &ns)
+        .create(&PostParams::default(), &event)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::{ConfigMap, PersistentVolumeClaim, Pod};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::ByteString;
+use kube::api::{DeleteParams, ListParams, LogParams, PostParams};
+use kube::Client;
+use tokio::time::{delay_for, Duration};
+
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{from_yaml, get_api};
+use crate::crd::NiFiDeploymentSpec;
+use crate::nifi_api::NiFiApi;
+use crate::template::Template;
+
+/// Label of ConfigMaps with flow backups, its value is the deployment name.
+/// Backups are not managed by Kubefi labels, so they are kept when the deployment is deleted
+pub const FLOW_BACKUP_LABEL: &str = "io.github.novakov-alexey/flow-backup";
+const FLOW_BACKUP_IMAGE_ANNOTATION: &str = "io.github.novakov-alexey/flow-backup-image";
+// files of the NiFi data volume, missing ones are skipped
+const FLOW_BACKUP_FILES: [&str; 4] = [
+    "flow.xml.gz",
+    "flow.json.gz",
+    "users.xml",
+    "authorizations.xml",
+];
+const DATA_CLAIM: &str = "data";
+// 2 second polls of backup Pods, which may pull their images first
+const POD_CHECKS: u8 = 150;
+
+/// Copies the flow of the first NiFi node running `image` into a ConfigMap and returns its name.
+/// One backup is taken per image, so a retried rollout keeps the backup of the original version
+pub async fn backup_flow(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+    image: &str,
+) -> Result<String> {
+    let cm_name = backup_name(name, image);
+    let cms = get_api::<ConfigMap>(client, ns);
+    if cms.get(&cm_name).await.is_ok() {
+        debug!("Flow backup {} already exists", &cm_name);
+        return Ok(cm_name);
+    }
+    let pod_name = format!("{}-0", name);
+    let pods = get_api::<Pod>(client, ns);
+    let node_name = pods
+        .get(&pod_name)
+        .await?
+        .spec
+        .and_then(|s| s.node_name)
+        .ok_or_else(|| {
+            Error::msg(format!(
+                "flow backup needs NiFi Pod {} scheduled to a node",
+                &pod_name
+            ))
+        })?;
+    let yaml = template
+        .flow_backup_pod(name, &pod_name, &node_name, image, &FLOW_BACKUP_FILES, spec)?
+        .ok_or_else(|| {
+            Error::from(InvalidSpec(
+                "flow backup needs NiFi PVCs, set spec.upgrade.flowBackup to false".to_string(),
+            ))
+        })?;
+    info!("Taking flow backup {} of {}", &cm_name, &pod_name);
+    let output = run_pod(client, ns, &from_yaml::<Pod>(&yaml)?).await?;
+    let files = parse_files(&output)?;
+    if !files.contains_key(FLOW_BACKUP_FILES[0]) && !files.contains_key(FLOW_BACKUP_FILES[1]) {
+        return Err(Error::msg(format!(
+            "flow backup of {} found no flow on the data volume",
+            &pod_name
+        )));
+    }
+
+    let mut labels = BTreeMap::new();
+    labels.insert(FLOW_BACKUP_LABEL.to_string(), name.to_string());
+    let mut annotations = BTreeMap::new();
+    annotations.insert(FLOW_BACKUP_IMAGE_ANNOTATION.to_string(), image.to_string());
+    let cm = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(cm_name.clone()),
+            namespace: Some(ns.to_string()),
+            labels: Some(labels),
+            annotations: Some(annotations),
+            ..ObjectMeta::default()
+        },
+        binary_data: Some(files),
+        ..ConfigMap::default()
+    };
+    cms.create(&PostParams::default(), &cm).await?;
+    Ok(cm_name)
+}
+
+/// The most recent flow backup of a deployment
+pub async fn latest_flow_backup(client: &Client, name: &str, ns: &str) -> Result<Option<String>> {
+    let lp = ListParams::default().labels(&format!("{}={}", FLOW_BACKUP_LABEL, name));
+    let cms = get_api::<ConfigMap>(client, ns).list(&lp).await?;
+    Ok(cms
+        .into_iter()
+        .max_by_key(|cm| cm.metadata.creation_timestamp.clone().map(|t| t.0))
+        .and_then(|cm| cm.metadata.name))
+}
+
+/// Uploads an archive of the flow, templates and state of the cluster coordinator to `spec.backup.s3`
+pub async fn backup_to_s3(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+) -> Result<()> {
+    let pod_name = coordinator_pod(client, template, name, ns, spec).await;
+    let yaml = template
+        .s3_backup_pod(name, &pod_name, spec)?
+        .ok_or_else(|| {
+            Error::from(InvalidSpec(
+                "S3 backup needs spec.backup.s3 and NiFi PVCs".to_string(),
+            ))
+        })?;
+    info!("Uploading backup of {} to S3", &pod_name);
+    let output = run_pod(client, ns, &from_yaml::<Pod>(&yaml)?).await?;
+    debug!("S3 backup of {}: {}", &pod_name, output.trim());
+    Ok(())
+}
+
+/// Seeds data PVCs of a new NiFi StatefulSet from the `spec.restore.from` archive before the set
+/// is created. Flow, templates, users and authorizations go to every node, the local state of the
+/// backed up coordinator to the first one only
+pub async fn restore_from_s3(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+    set: &StatefulSet,
+) -> Result<()> {
+    let from = match &spec.restore {
+        Some(restore) => &restore.from,
+        None => return Ok(()),
+    };
+    let url = restore_url(spec, from)?;
+    for ordinal in 0..spec.nifi_replicas {
+        let pod_name = format!("{}-{}", name, ordinal);
+        create_data_claim(client, ns, set, &pod_name).await?;
+        let yaml = template
+            .s3_restore_pod(name, &pod_name, &url, ordinal == 0, spec)?
+            .ok_or_else(|| {
+                Error::from(InvalidSpec(
+                    "restore needs spec.backup.s3 and NiFi PVCs".to_string(),
+                ))
+            })?;
+        info!("Restoring {} of {} from {}", &pod_name, name, &url);
+        let output = run_pod(client, ns, &from_yaml::<Pod>(&yaml)?).await?;
+        debug!("Restored files of {}: {}", &pod_name, output.trim());
+    }
+    Ok(())
+}
+
+/// Backup names are resolved against the bucket and prefix of `spec.backup.s3`
+fn restore_url(spec: &NiFiDeploymentSpec, from: &str) -> Result<String> {
+    if from.starts_with("s3://") {
+        return Ok(from.to_string());
+    }
+    let s3 = spec
+        .backup
+        .as_ref()
+        .and_then(|b| b.s3.as_ref())
+        .ok_or_else(|| {
+            Error::from(InvalidSpec(format!(
+                "backup {} cannot be found without spec.backup.s3",
+                from
+            )))
+        })?;
+    let prefix = s3
+        .prefix
+        .as_ref()
+        .map(|p| format!("{}/", p.trim_end_matches('/')))
+        .unwrap_or_default();
+    let extension = if from.ends_with(".tar.gz") {
+        ""
+    } else {
+        ".tar.gz"
+    };
+    Ok(format!(
+        "s3://{}/{}{}{}",
+        s3.bucket, prefix, from, extension
+    ))
+}
+
+/// The data PVC of a Pod as the StatefulSet would create it, so the set adopts it by name
+async fn create_data_claim(
+    client: &Client,
+    ns: &str,
+    set: &StatefulSet,
+    pod_name: &str,
+) -> Result<()> {
+    let pvc_name = format!("{}-{}", DATA_CLAIM, pod_name);
+    let api = get_api::<PersistentVolumeClaim>(client, ns);
+    if api.get(&pvc_name).await.is_ok() {
+        return Ok(());
+    }
+    let spec = set.spec.as_ref();
+    let claim = spec
+        .and_then(|s| s.volume_claim_templates.as_ref())
+        .and_then(|claims| {
+            claims
+                .iter()
+                .find(|c| c.metadata.name.as_deref() == Some(DATA_CLAIM))
+        })
+        .ok_or_else(|| {
+            Error::from(InvalidSpec(
+                "restore needs the data volume claim template of NiFi".to_string(),
+            ))
+        })?;
+    let pvc = PersistentVolumeClaim {
+        metadata: ObjectMeta {
+            name: Some(pvc_name.clone()),
+            labels: spec.and_then(|s| s.selector.match_labels.clone()),
+            ..ObjectMeta::default()
+        },
+        spec: claim.spec.clone(),
+        ..PersistentVolumeClaim::default()
+    };
+    debug!("Creating PVC {} to restore", &pvc_name);
+    api.create(&PostParams::default(), &pvc).await?;
+    Ok(())
+}
+
+/// Pod of the elected cluster coordinator, the first Pod when the REST API is not available
+async fn coordinator_pod(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+) -> String {
+    let first_pod = format!("{}-0", name);
+    let cluster = match NiFiApi::connect(client, template, name, ns, spec, Some(&first_pod)).await {
+        Ok(api) => api.cluster().await,
+        Err(e) => Err(e),
+    };
+    match cluster {
+        Ok(cluster) => cluster
+            .coordinator()
+            .and_then(|n| n.address.split('.').next())
+            .map(|pod| pod.to_string())
+            .unwrap_or(first_pod),
+        Err(e) => {
+            warn!(
+                "NiFi cluster coordinator of {} is unknown, using {}: {}",
+                name, &first_pod, e
+            );
+            first_pod
+        }
+    }
+}
+
+/// Runs a Pod to completion and returns its output, the Pod is deleted afterwards
+async fn run_pod(client: &Client, ns: &str, pod: &Pod) -> Result<String> {
+    let pods = get_api::<Pod>(client, ns);
+    let pod_name = pod.metadata.name.clone().unwrap_or_default();
+    // left by an interrupted run
+    if pods.get(&pod_name).await.is_ok() {
+        pods.delete(&pod_name, &DeleteParams::default()).await?;
+        wait_deleted(client, ns, &pod_name).await;
+    }
+    pods.create(&PostParams::default(), pod).await?;
+    let output = read_output(client, ns, &pod_name).await;
+    pods.delete(&pod_name, &DeleteParams::default()).await?;
+    output
+}
+
+async fn read_output(client: &Client, ns: &str, pod_name: &str) -> Result<String> {
+    let pods = get_api::<Pod>(client, ns);
+    for _ in 0..POD_CHECKS {
+        delay_for(Duration::from_secs(2)).await;
+        let phase = pods.get(pod_name).await?.status.and_then(|s| s.phase);
+        match phase.as_deref() {
+            Some("Succeeded") => return Ok(pods.logs(pod_name, &LogParams::default()).await?),
+            Some("Failed") => {
+                let logs = pods.logs(pod_name, &LogParams::default()).await?;
+                return Err(Error::msg(format!("Pod {} failed: {}", pod_name, logs)));
+            }
+            _ => continue,
+        }
+    }
+    Err(Error::msg(format!(
+        "Pod {} did not complete in time",
+        pod_name
+    )))
+}
+
+async fn wait_deleted(client: &Client, ns: &str, pod_name: &str) {
+    let pods = get_api::<Pod>(client, ns);
+    for _ in 0..POD_CHECKS {
+        if pods.get(pod_name).await.is_err() {
+            return;
+        }
+        delay_for(Duration::from_secs(2)).await;
+    }
+}
+
+/// Lines of `<file name> <base64 content>` printed by the backup Pod
+fn parse_files(output: &str) -> Result<BTreeMap<String, ByteString>> {
+    let mut files = BTreeMap::new();
+    for line in output.lines() {
+        if let Some((file, content)) = line.split_once(' ') {
+            if FLOW_BACKUP_FILES.contains(&file) {
+                files.insert(
+                    file.to_string(),
+                    ByteString(base64::decode(content.trim())?),
+                );
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// e.g. nifi-flow-backup-1.11.4 for apache/nifi:1.11.4
+fn backup_name(name: &str, image: &str) -> String {
+    let tag = image
+        .rsplit([':', '/'])
+        .next()
+        .unwrap_or(image)
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' | '.' => c,
+            _ => '-',
+        })
+        .collect::<String>();
+    format!("{}-flow-backup-{}", name, tag.trim_matches(['-', '.']))
+}
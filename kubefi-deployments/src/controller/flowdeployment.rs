@@ -0,0 +1,192 @@
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use kube::Client;
+
+use crate::controller::ControllerError::{InvalidSpec, MissingProperty};
+use crate::controller::{get_api, NiFiController, ReplaceResourceStatus};
+use crate::crd::{FlowDeployment, FlowDeploymentStatus, NiFiDeployment};
+use crate::nifi_api::{NiFiApi, ProcessGroup, VersionControlInformation};
+use crate::template::Template;
+
+pub type ReplaceFlowStatus = ReplaceResourceStatus<FlowDeploymentStatus>;
+
+pub struct FlowController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiController {
+    /// Imports the flow into its NiFiDeployment or changes the version of the already imported
+    /// process group, the status is replaced only when it is different
+    pub async fn on_apply_flow(&self, flow: FlowDeployment) -> Result<Option<ReplaceFlowStatus>> {
+        let (name, ns) = read_flow_meta(&flow)?;
+        let status = match self.flow_controller.deploy_flow(&flow, &ns).await {
+            Ok((group, version)) => FlowDeploymentStatus {
+                process_group_id: Some(group),
+                version: Some(version),
+                error_msg: "".to_string(),
+            },
+            Err(e) => FlowDeploymentStatus {
+                error_msg: e.to_string(),
+                ..flow.status.clone().unwrap_or_default()
+            },
+        };
+        if flow.status.as_ref() == Some(&status) {
+            return Ok(None);
+        }
+        Ok(Some(ReplaceFlowStatus { name, ns, status }))
+    }
+
+    /// The process group is left in NiFi, it may still hold queued flowfiles
+    pub async fn on_delete_flow(&self, flow: FlowDeployment) -> Result<()> {
+        let (name, _) = read_flow_meta(&flow)?;
+        if let Some(group) = flow.status.and_then(|s| s.process_group_id) {
+            info!(
+                "FlowDeployment {} is deleted, process group {} is kept in {}",
+                name, group, &flow.spec.deployment_ref
+            );
+        }
+        Ok(())
+    }
+}
+
+impl FlowController {
+    async fn deploy_flow(&self, flow: &FlowDeployment, ns: &str) -> Result<(String, i32)> {
+        let spec = &flow.spec;
+        let d = get_api::<NiFiDeployment>(&self.client, ns)
+            .get(&spec.deployment_ref)
+            .await?;
+        let pod_name = format!("{}-0", &spec.deployment_ref);
+        let api = NiFiApi::connect(
+            &self.client,
+            &self.template,
+            &spec.deployment_ref,
+            ns,
+            &d.spec,
+            Some(&pod_name),
+        )
+        .await?;
+        let target = self.target_flow(&api, flow, &d, ns).await?;
+        let parent_id = match &spec.parent_group_id {
+            Some(id) => id.clone(),
+            None => api.root_group_id().await?,
+        };
+        let groups = api.process_groups(&parent_id).await?;
+        let imported = groups.iter().find(|g| {
+            g.component
+                .version_control_information
+                .as_ref()
+                .is_some_and(|v| v.registry_id == target.registry_id && v.flow_id == target.flow_id)
+        });
+        match imported {
+            Some(group) => {
+                let version = group
+                    .component
+                    .version_control_information
+                    .as_ref()
+                    .map(|v| v.version);
+                if version != Some(target.version) {
+                    info!(
+                        "Changing flow {} of process group {} in {} from version {:?} to {}",
+                        &spec.flow, &group.id, &spec.deployment_ref, version, target.version
+                    );
+                    update_version(&api, group, &target).await?;
+                }
+                Ok((group.id.clone(), target.version))
+            }
+            None => {
+                info!(
+                    "Importing flow {} version {} into {}",
+                    &spec.flow, target.version, &spec.deployment_ref
+                );
+                api.import_flow(&parent_id, &target)
+                    .await
+                    .map(|g| (g.id, target.version))
+            }
+        }
+    }
+
+    /// Registry, bucket and flow ids of the flow with the version to deploy
+    async fn target_flow(
+        &self,
+        api: &NiFiApi,
+        flow: &FlowDeployment,
+        d: &NiFiDeployment,
+        ns: &str,
+    ) -> Result<VersionControlInformation> {
+        let spec = &flow.spec;
+        let invalid = |reason: String| Error::from(InvalidSpec(reason));
+        let client_name = spec
+            .registry_client
+            .clone()
+            .or_else(|| {
+                self.template
+                    .registry_client(&spec.deployment_ref, ns, &d.spec)
+                    .map(|(name, _)| name)
+            })
+            .ok_or_else(|| {
+                invalid(format!(
+                    "registryClient is not set and {} has no NiFi Registry",
+                    &spec.deployment_ref
+                ))
+            })?;
+        let registry_id = api
+            .registry_clients()
+            .await?
+            .into_iter()
+            .find(|c| c.component.name == client_name)
+            .map(|c| c.id)
+            .ok_or_else(|| invalid(format!("Registry client {} is not found", &client_name)))?;
+        let bucket_id = api
+            .bucket_id(&registry_id, &spec.bucket)
+            .await?
+            .ok_or_else(|| invalid(format!("bucket {} is not found", &spec.bucket)))?;
+        let flow_id = api
+            .flow_id(&registry_id, &bucket_id, &spec.flow)
+            .await?
+            .ok_or_else(|| {
+                invalid(format!(
+                    "flow {} is not found in bucket {}",
+                    &spec.flow, &spec.bucket
+                ))
+            })?;
+        let version = match spec.version {
+            Some(version) => version,
+            None => api
+                .latest_flow_version(&registry_id, &bucket_id, &flow_id)
+                .await?
+                .ok_or_else(|| invalid(format!("flow {} has no versions", &spec.flow)))?,
+        };
+        Ok(VersionControlInformation {
+            group_id: None,
+            registry_id,
+            bucket_id,
+            flow_id,
+            version,
+        })
+    }
+}
+
+async fn update_version(
+    api: &NiFiApi,
+    group: &ProcessGroup,
+    target: &VersionControlInformation,
+) -> Result<()> {
+    let request = api.update_flow_version(group, target).await?;
+    let path = format!("/versions/update-requests/{}", &request.request_id);
+    let change = format!("flow version change of process group {}", &group.id);
+    api.wait_for_update(&path, request, &change).await
+}
+
+fn read_flow_meta(flow: &FlowDeployment) -> Result<(String, String)> {
+    let missing =
+        |property: &str| Error::from(MissingProperty(property.to_string(), flow.kind.clone()));
+    let name = flow.metadata.name.clone().ok_or_else(|| missing("name"))?;
+    let ns = flow
+        .metadata
+        .namespace
+        .clone()
+        .ok_or_else(|| missing("namespace"))?;
+    Ok((name, ns))
+}
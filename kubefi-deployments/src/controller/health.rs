@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::Result;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use kube::api::DeleteParams;
+use kube::Client;
+
+use crate::controller::events::warning_event;
+use crate::controller::hibernate::{
+    hibernated_condition, start_flow, waking_up, HIBERNATED_CONDITION,
+};
+use crate::controller::{get_api, read_name, read_namespace, NiFiController, ReplaceStatus};
+use crate::crd::{
+    BackPressureAlertsCfg, ClusterHealth, NiFiDeployment, NiFiDeploymentStatus,
+    SaturatedConnection, StatusCondition,
+};
+use crate::nifi_api::{ConnectionStatus, NiFiApi, NodeStatus};
+use crate::template::Template;
+
+const DEFAULT_DISCONNECTED_SECONDS: u64 = 300;
+const DEFAULT_THRESHOLD_PERCENT: u32 = 80;
+const DEFAULT_SUSTAINED_SECONDS: u64 = 300;
+const BACK_PRESSURE_CONDITION: &str = "BackPressure";
+
+pub struct HealthController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiController {
+    /// Records connected nodes and the coordinator of the NiFi cluster and heals disconnected
+    /// nodes when enabled, the status is replaced only when it is different
+    pub async fn on_health_probe(&self, d: NiFiDeployment) -> Result<Option<ReplaceStatus>> {
+        let enabled = d.spec.api.as_ref().and_then(|a| a.health_probe);
+        if enabled == Some(false) {
+            return Ok(None);
+        }
+        let name = read_name(&d)?;
+        let ns = read_namespace(&d)?;
+        let cluster = if d.spec.running_nifi_replicas() == 0 {
+            None
+        } else {
+            match self.health_controller.probe(&d, &name, &ns).await {
+                Ok(health) => Some(health),
+                Err(e) => {
+                    warn!("Health probe of NiFi cluster {} failed: {}", &name, e);
+                    None
+                }
+            }
+        };
+        let mut status = d.status.clone().unwrap_or_else(|| NiFiDeploymentStatus {
+            nifi_replicas: d.spec.nifi_replicas,
+            ..NiFiDeploymentStatus::default()
+        });
+        if let (Some(_), Some(health)) = (alerts_cfg(&d), &cluster) {
+            let i = status
+                .conditions
+                .iter()
+                .position(|c| c.type_ == BACK_PRESSURE_CONDITION);
+            let condition = back_pressure_condition(health, i.map(|i| &status.conditions[i]));
+            match i {
+                Some(i) => status.conditions[i] = condition,
+                None => status.conditions.push(condition),
+            }
+        }
+        let rejoined = cluster.as_ref().is_some_and(|c| {
+            c.connected_nodes == c.total_nodes && c.connected_nodes >= d.spec.nifi_replicas as u32
+        });
+        if waking_up(&d) && rejoined {
+            match self.health_controller.resume(&d, &name, &ns).await {
+                Ok(_) => {
+                    status
+                        .conditions
+                        .retain(|c| c.type_ != HIBERNATED_CONDITION);
+                    status.conditions.push(hibernated_condition(false));
+                }
+                Err(e) => warn!("Failed to start the flow of {}: {}", &name, e),
+            }
+        }
+        let scaled = self
+            .autoscaling_controller
+            .autoscale(&d, &name, &ns, cluster.as_ref())
+            .await;
+        match scaled {
+            Ok(Some(time)) => status.last_scale_time = Some(time),
+            Ok(None) => (),
+            Err(e) => warn!("Autoscaling of {} failed: {}", &name, e),
+        }
+        status.cluster = cluster;
+        if d.status.as_ref() == Some(&status) {
+            return Ok(None);
+        }
+        Ok(Some(ReplaceStatus { name, ns, status }))
+    }
+}
+
+impl HealthController {
+    async fn resume(&self, d: &NiFiDeployment, name: &str, ns: &str) -> Result<()> {
+        let api = NiFiApi::connect(&self.client, &self.template, name, ns, &d.spec, None).await?;
+        start_flow(&api, name).await
+    }
+
+    async fn probe(&self, d: &NiFiDeployment, name: &str, ns: &str) -> Result<ClusterHealth> {
+        let api = NiFiApi::connect(&self.client, &self.template, name, ns, &d.spec, None).await?;
+        let summary = api.cluster_summary().await?;
+        let flow = api.flow_status().await?;
+        let cluster = if summary.clustered {
+            Some(api.cluster().await?)
+        } else {
+            None
+        };
+        let previous = d
+            .status
+            .as_ref()
+            .and_then(|s| s.cluster.as_ref())
+            .map(|c| c.disconnected_nodes.clone())
+            .unwrap_or_default();
+        let saturated_connections = match alerts_cfg(d) {
+            Some(cfg) => {
+                let previous = d
+                    .status
+                    .as_ref()
+                    .and_then(|s| s.cluster.as_ref())
+                    .map(|c| c.saturated_connections.clone())
+                    .unwrap_or_default();
+                match self.saturated_connections(&api, d, cfg, &previous).await {
+                    Ok(saturated) => saturated,
+                    Err(e) => {
+                        warn!("Connection queues of {} are not checked: {}", name, e);
+                        previous
+                    }
+                }
+            }
+            None => BTreeMap::new(),
+        };
+        let mut disconnected_nodes = BTreeMap::new();
+        let disconnected = cluster
+            .iter()
+            .flat_map(|c| c.nodes.iter())
+            .filter(|n| matches!(n.status, NodeStatus::Disconnected | NodeStatus::Offloaded));
+        for node in disconnected {
+            let pod = node.address.split('.').next().unwrap_or(&node.address);
+            let since = previous
+                .get(pod)
+                .cloned()
+                .unwrap_or_else(|| Utc::now().to_rfc3339());
+            match self.heal(d, name, ns, pod, &since).await {
+                Ok(true) => continue,
+                Ok(false) => (),
+                Err(e) => warn!("Failed to restart disconnected NiFi node {}: {}", pod, e),
+            }
+            disconnected_nodes.insert(pod.to_string(), since);
+        }
+        Ok(ClusterHealth {
+            connected_nodes: summary.connected_node_count,
+            total_nodes: summary.total_node_count,
+            queued_flow_files: flow.flow_files_queued,
+            queued_bytes: flow.bytes_queued,
+            active_threads: flow.active_thread_count,
+            coordinator: cluster
+                .as_ref()
+                .and_then(|c| c.coordinator())
+                .map(|n| n.address.clone()),
+            disconnected_nodes,
+            saturated_connections,
+        })
+    }
+
+    /// Connections queued above the alert threshold, a Warning Event is emitted once
+    /// a connection stays so for the sustained period
+    async fn saturated_connections(
+        &self,
+        api: &NiFiApi,
+        d: &NiFiDeployment,
+        cfg: &BackPressureAlertsCfg,
+        previous: &BTreeMap<String, SaturatedConnection>,
+    ) -> Result<BTreeMap<String, SaturatedConnection>> {
+        let threshold = cfg.threshold_percent.unwrap_or(DEFAULT_THRESHOLD_PERCENT);
+        let sustained = cfg.sustained_seconds.unwrap_or(DEFAULT_SUSTAINED_SECONDS);
+        let mut saturated = BTreeMap::new();
+        for c in api.connection_statuses().await? {
+            let percent = c
+                .percent_use_count
+                .unwrap_or(0)
+                .max(c.percent_use_bytes.unwrap_or(0));
+            if percent < threshold {
+                continue;
+            }
+            let mut connection =
+                previous
+                    .get(&c.id)
+                    .cloned()
+                    .unwrap_or_else(|| SaturatedConnection {
+                        name: connection_name(&c),
+                        since: Utc::now().to_rfc3339(),
+                        alerted: false,
+                    });
+            if !connection.alerted && elapsed(&connection.since, sustained) {
+                let message = format!(
+                    "Connection {} queues {} flowfiles of {} bytes at {}% of its back-pressure threshold since {}",
+                    &connection.name, c.flow_files_queued, c.bytes_queued, percent, &connection.since
+                );
+                match warning_event(&self.client, d, "BackPressure", &message).await {
+                    Ok(_) => connection.alerted = true,
+                    Err(e) => warn!("Failed to emit back-pressure Event: {}", e),
+                }
+            }
+            saturated.insert(c.id, connection);
+        }
+        Ok(saturated)
+    }
+
+    /// Deletes the Pod of a node disconnected for longer than allowed, true when it is deleted
+    async fn heal(
+        &self,
+        d: &NiFiDeployment,
+        name: &str,
+        ns: &str,
+        pod: &str,
+        since: &str,
+    ) -> Result<bool> {
+        let cfg = match &d.spec.auto_heal {
+            Some(cfg) if cfg.enabled == Some(true) => cfg,
+            _ => return Ok(false),
+        };
+        // nodes removed by a scale-down are offloaded on purpose
+        let ordinal = pod
+            .strip_prefix(&format!("{}-", name))
+            .and_then(|o| o.parse::<u8>().ok());
+        if ordinal.is_none_or(|o| o >= d.spec.nifi_replicas) {
+            return Ok(false);
+        }
+        let threshold = cfg
+            .disconnected_seconds
+            .unwrap_or(DEFAULT_DISCONNECTED_SECONDS);
+        if !elapsed(since, threshold) {
+            return Ok(false);
+        }
+        info!(
+            "Deleting Pod {} of NiFi node disconnected since {}",
+            pod, since
+        );
+        get_api::<Pod>(&self.client, ns)
+            .delete(pod, &DeleteParams::default())
+            .await?;
+        Ok(true)
+    }
+}
+
+fn alerts_cfg(d: &NiFiDeployment) -> Option<&BackPressureAlertsCfg> {
+    d.spec
+        .back_pressure_alerts
+        .as_ref()
+        .filter(|cfg| cfg.enabled == Some(true))
+}
+
+/// True while any queue is above the threshold for the sustained period,
+/// the transition time is kept until the condition status changes
+fn back_pressure_condition(
+    health: &ClusterHealth,
+    previous: Option<&StatusCondition>,
+) -> StatusCondition {
+    let alerted = health
+        .saturated_connections
+        .values()
+        .filter(|c| c.alerted)
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>();
+    let mut condition = if alerted.is_empty() {
+        StatusCondition::new(BACK_PRESSURE_CONDITION, false, "QueuesBelowThreshold", "")
+    } else {
+        let message = format!("Queues above the threshold: {}", alerted.join(", "));
+        StatusCondition::new(BACK_PRESSURE_CONDITION, true, "QueuesSaturated", &message)
+    };
+    if let Some(previous) = previous.filter(|p| p.status == condition.status) {
+        condition.last_transition_time = previous.last_transition_time.clone();
+    }
+    condition
+}
+
+/// Connection name, its source and destination when it has no name
+fn connection_name(c: &ConnectionStatus) -> String {
+    match (c.name.as_deref(), &c.source_name, &c.destination_name) {
+        (Some(name), _, _) if !name.is_empty() => name.to_string(),
+        (_, Some(source), Some(destination)) => format!("{} -> {}", source, destination),
+        _ => c.id.clone(),
+    }
+}
+
+/// Whether at least `seconds` passed since the RFC 3339 time
+pub fn elapsed(since: &str, seconds: u64) -> bool {
+    DateTime::parse_from_rfc3339(since)
+        .is_ok_and(|t| Utc::now().signed_duration_since(t) >= Duration::seconds(seconds as i64))
+}
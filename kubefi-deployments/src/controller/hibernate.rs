@@ -0,0 +1,59 @@
+use anyhow::{Error, Result};
+use tokio::time::{delay_for, Duration};
+
+use crate::crd::{NiFiDeployment, StatusCondition};
+use crate::nifi_api::NiFiApi;
+
+pub const HIBERNATED_CONDITION: &str = "Hibernated";
+// 5 second polls while running tasks of the stopped flow complete
+const STOP_CHECKS: u16 = 60;
+
+/// Stops all components of the root process group and waits for their running tasks,
+/// so the NiFi StatefulSet can be scaled to zero
+pub async fn stop_flow(api: &NiFiApi, name: &str) -> Result<()> {
+    let root_id = api.root_group_id().await?;
+    info!("Stopping the flow of {} before hibernation", name);
+    api.schedule_process_group(&root_id, "STOPPED").await?;
+    let mut threads = api.flow_status().await?.active_thread_count;
+    for _ in 0..STOP_CHECKS {
+        if threads == 0 {
+            return Ok(());
+        }
+        delay_for(Duration::from_secs(5)).await;
+        threads = api.flow_status().await?.active_thread_count;
+    }
+    Err(Error::msg(format!(
+        "the flow still has {} active threads",
+        threads
+    )))
+}
+
+/// Starts all components of the root process group once NiFi is back from hibernation
+pub async fn start_flow(api: &NiFiApi, name: &str) -> Result<()> {
+    let root_id = api.root_group_id().await?;
+    info!("Starting the flow of {} after hibernation", name);
+    api.schedule_process_group(&root_id, "RUNNING").await
+}
+
+/// True while hibernating, false once the flow is started again
+pub fn hibernated_condition(hibernated: bool) -> StatusCondition {
+    if hibernated {
+        StatusCondition::new(
+            HIBERNATED_CONDITION,
+            true,
+            "ScaledToZero",
+            "the flow is stopped and NiFi is scaled to zero",
+        )
+    } else {
+        StatusCondition::new(HIBERNATED_CONDITION, false, "FlowStarted", "")
+    }
+}
+
+/// The flow of this deployment was stopped by hibernation and is not started yet
+pub fn waking_up(d: &NiFiDeployment) -> bool {
+    !d.spec.hibernated()
+        && d.status
+            .iter()
+            .flat_map(|s| s.conditions.iter())
+            .any(|c| c.type_ == HIBERNATED_CONDITION && c.status == "True")
+}
@@ -0,0 +1,99 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use kube::api::Meta;
+use openssl::pkey::PKey;
+use serde_json::Value;
+
+use crate::controller::ControllerError::ImageNotVerified;
+use crate::crd::{NiFiDeployment, NiFiDeploymentSpec};
+use crate::oci_api::OciApi;
+use crate::template::Template;
+
+/// Verified images of a deployment generation by reference, see `verifiedImages` of the spec
+type VerifiedImages = BTreeMap<String, String>;
+
+pub struct ImageSignatureController {
+    pub template: Rc<Template>,
+    // by deployment uid, images of the same generation are not resolved again. Shared with the
+    // controllers of template overrides, which are built on every event
+    pub verified: Rc<RefCell<HashMap<String, (i64, VerifiedImages)>>>,
+}
+
+impl ImageSignatureController {
+    /// Spec of a deployment with its rendered images pinned by digest, when `imageVerification`
+    /// of the operator config requires digests or cosign signatures. Images failing the
+    /// verification are rejected before anything is rendered with them
+    pub async fn verify_images(&self, d: &NiFiDeployment) -> Result<NiFiDeploymentSpec> {
+        let cfg = self.template.image_verification_config();
+        // values of environment variables are strings
+        let flag = |key: &str| match &cfg[key] {
+            Value::Bool(b) => *b,
+            Value::String(s) => s == "true",
+            _ => false,
+        };
+        let require_digest = flag("requireDigest");
+        let verify_signatures = flag("enabled");
+        if !require_digest && !verify_signatures {
+            return Ok(d.spec.clone());
+        }
+        let name = Meta::name(d);
+        let key = d.metadata.uid.clone().unwrap_or_else(|| {
+            format!(
+                "{}/{}",
+                d.metadata.namespace.clone().unwrap_or_default(),
+                &name
+            )
+        });
+        let generation = d.metadata.generation.unwrap_or_default();
+        let mut verified = match self.verified.borrow().get(&key) {
+            Some((g, images)) if *g == generation => images.clone(),
+            _ => BTreeMap::new(),
+        };
+        // images of reloaded templates are verified within the same generation too
+        for image in self.template.images(&name, &d.spec)? {
+            if verified.contains_key(&image) {
+                continue;
+            }
+            if require_digest && !image.contains("@sha256:") {
+                return Err(Error::from(ImageNotVerified(format!(
+                    "{} is not pinned by digest",
+                    image
+                ))));
+            }
+            let pinned = if verify_signatures {
+                let public_key = cfg["publicKey"].as_str().unwrap_or_default();
+                self.verify_signature(&image, public_key)
+                    .await
+                    .map_err(|e| Error::from(ImageNotVerified(format!("{}: {}", image, e))))?
+            } else {
+                image.clone()
+            };
+            verified.insert(image, pinned);
+        }
+        self.verified
+            .borrow_mut()
+            .insert(key, (generation, verified.clone()));
+        Ok(NiFiDeploymentSpec {
+            verified_images: Some(verified),
+            ..d.spec.clone()
+        })
+    }
+
+    /// Image with the digest of its valid signature, a tag is kept for the upgrade path checks and
+    /// ignored by the container runtime
+    async fn verify_signature(&self, image: &str, public_key: &str) -> Result<String> {
+        if public_key.trim().is_empty() {
+            return Err(Error::msg("imageVerification.publicKey is not configured"));
+        }
+        let mut api = OciApi::new(image);
+        let digest = api.digest().await?;
+        let public_key = PKey::public_key_from_pem(public_key.as_bytes())?;
+        api.verify_cosign_signature(&digest, &public_key).await?;
+        let pinned = format!("{}@{}", image.split('@').next().unwrap_or(image), &digest);
+        debug!("Verified cosign signature of {}", &pinned);
+        Ok(pinned)
+    }
+}
@@ -0,0 +1,55 @@
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use kube::Client;
+
+use crate::controller::{recreate_on_change, ControllerError};
+use crate::crd::NiFiDeploymentSpec;
+use crate::discovery::ClusterApis;
+use crate::resources::{DestinationRule, VirtualService};
+use crate::template::Template;
+
+pub struct MeshController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+    pub apis: ClusterApis,
+}
+
+impl MeshController {
+    pub async fn handle_mesh(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        if !self.apis.istio {
+            let vs = self.template.virtual_service(name, spec)?;
+            let dr = self.template.destination_rule(name, spec)?;
+            return match vs.or(dr) {
+                Some(_) => Err(Error::new(ControllerError::InvalidSpec(
+                    "mesh VirtualService or DestinationRule is enabled, but networking.istio.io API is not available"
+                        .to_string(),
+                ))),
+                None => Ok(false),
+            };
+        }
+        let vs = recreate_on_change::<VirtualService, _, _>(
+            &self.client,
+            name,
+            name,
+            ns,
+            |name| self.template.virtual_service(name, spec),
+            |current, expected| current.spec != expected.spec,
+        );
+        let dr = recreate_on_change::<DestinationRule, _, _>(
+            &self.client,
+            name,
+            name,
+            ns,
+            |name| self.template.destination_rule(name, spec),
+            |current, expected| current.spec != expected.spec,
+        );
+        let (vs, dr) = futures::future::join(vs, dr).await;
+        Ok(vs? || dr?)
+    }
+}
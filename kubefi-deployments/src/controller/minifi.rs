@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment};
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{Meta, PostParams};
+use kube::Client;
+use serde_json::{Map, Value};
+
+use crate::controller::workload::{
+    apply_workload, config_hash, delete_resource, registry_api, resolve_flow, workload_labels,
+};
+use crate::controller::ControllerError::{InvalidSpec, MissingProperty};
+use crate::controller::{create_resource, get_api, NiFiController, ReplaceResourceStatus};
+use crate::crd::{
+    MiNiFiDeployment, MiNiFiDeploymentSpec, MiNiFiDeploymentStatus, MiNiFiFlowCfg, MiNiFiWorkload,
+    NiFiDeployment,
+};
+use crate::template::Template;
+
+const CONFIG_KEY: &str = "config.yml";
+const CONFIG_VERSION: u8 = 3;
+
+pub type ReplaceMiNiFiStatus = ReplaceResourceStatus<MiNiFiDeploymentStatus>;
+
+pub struct MiNiFiController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiController {
+    /// Writes config.yml to the `<name>-minifi` ConfigMap and runs the agents in a Deployment
+    /// or a DaemonSet, the status is replaced only when it is different
+    pub async fn on_apply_minifi(
+        &self,
+        md: MiNiFiDeployment,
+    ) -> Result<Option<ReplaceMiNiFiStatus>> {
+        let (name, ns) = read_minifi_meta(&md)?;
+        let status = match self.minifi_controller.apply(&md.spec, &name, &ns).await {
+            Ok((version, config_hash)) => MiNiFiDeploymentStatus {
+                version,
+                config_hash: Some(config_hash),
+                error_msg: "".to_string(),
+            },
+            Err(e) => MiNiFiDeploymentStatus {
+                error_msg: e.to_string(),
+                ..md.status.clone().unwrap_or_default()
+            },
+        };
+        if md.status.as_ref() == Some(&status) {
+            return Ok(None);
+        }
+        Ok(Some(ReplaceMiNiFiStatus { name, ns, status }))
+    }
+
+    /// Data still queued in the agents is lost, the workload and config.yml are deleted with the resource
+    pub async fn on_delete_minifi(&self, md: MiNiFiDeployment) -> Result<()> {
+        let (name, ns) = read_minifi_meta(&md)?;
+        let client = &self.minifi_controller.client;
+        delete_resource::<Deployment>(client, &name, &ns).await?;
+        delete_resource::<DaemonSet>(client, &name, &ns).await?;
+        delete_resource::<ConfigMap>(client, &configmap_name(&name), &ns).await
+    }
+}
+
+impl MiNiFiController {
+    async fn apply(
+        &self,
+        spec: &MiNiFiDeploymentSpec,
+        name: &str,
+        ns: &str,
+    ) -> Result<(Option<i32>, String)> {
+        let (config, version) = match (&spec.config, &spec.flow) {
+            (Some(config), None) => (config.clone(), None),
+            (None, Some(flow)) => {
+                let (config, version) = self.flow_config(spec, flow, ns).await?;
+                (config, Some(version))
+            }
+            _ => {
+                return Err(Error::from(InvalidSpec(
+                    "either config or flow must be set".to_string(),
+                )))
+            }
+        };
+        let config_hash = config_hash(&config, spec)?;
+        self.apply_configmap(name, ns, &config).await?;
+        let yaml = self
+            .template
+            .minifi(name, spec, &config_hash)?
+            .ok_or_else(|| Error::msg(format!("MiNiFi template of {} is empty", name)))?;
+        let client = &self.client;
+        match spec.workload {
+            Some(MiNiFiWorkload::DaemonSet) => {
+                delete_resource::<Deployment>(client, name, ns).await?;
+                apply_workload::<DaemonSet>(client, name, ns, &yaml, &config_hash, false).await?
+            }
+            _ => {
+                delete_resource::<DaemonSet>(client, name, ns).await?;
+                apply_workload::<Deployment>(client, name, ns, &yaml, &config_hash, false).await?
+            }
+        }
+        Ok((version, config_hash))
+    }
+
+    /// config.yml converted from the Registry flow, Remote Process Groups send to the
+    /// `deploymentRef` cluster when it is set
+    async fn flow_config(
+        &self,
+        spec: &MiNiFiDeploymentSpec,
+        flow: &MiNiFiFlowCfg,
+        ns: &str,
+    ) -> Result<(String, i32)> {
+        let api = registry_api(
+            &self.client,
+            &self.template,
+            &flow.registry_url,
+            &spec.deployment_ref,
+            ns,
+        )
+        .await?;
+        let resolved = resolve_flow(&api, &flow.bucket, &flow.flow, flow.version).await?;
+        let snapshot = api
+            .flow_snapshot(&resolved.bucket_id, &resolved.flow_id, resolved.version)
+            .await?;
+        let site_to_site_url = match &spec.deployment_ref {
+            Some(deployment) => {
+                let d = get_api::<NiFiDeployment>(&self.client, ns)
+                    .get(deployment)
+                    .await?;
+                let url = self.template.nifi_api_url(deployment, ns, &d.spec, None);
+                Some(url.replace("/nifi-api", "/nifi"))
+            }
+            None => None,
+        };
+        let config = minifi_config(&snapshot["flowContents"], site_to_site_url.as_deref());
+        Ok((serde_yaml::to_string(&config)?, resolved.version))
+    }
+
+    async fn apply_configmap(&self, name: &str, ns: &str, config: &str) -> Result<()> {
+        let api = get_api::<ConfigMap>(&self.client, ns);
+        let cm_name = configmap_name(name);
+        let mut data = BTreeMap::new();
+        data.insert(CONFIG_KEY.to_string(), config.to_string());
+        let mut cm = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(cm_name.clone()),
+                namespace: Some(ns.to_string()),
+                labels: Some(workload_labels("minifi", name)),
+                ..ObjectMeta::default()
+            },
+            data: Some(data),
+            ..ConfigMap::default()
+        };
+        match api.get(&cm_name).await {
+            Ok(current) if current.data == cm.data => Ok(()),
+            Ok(current) => {
+                debug!("Updating MiNiFi config ConfigMap {}", &cm_name);
+                cm.metadata.resource_version = Meta::resource_ver(&current);
+                api.replace(&cm_name, &PostParams::default(), &cm)
+                    .await
+                    .map(|_| ())
+                    .map_err(Error::from)
+            }
+            Err(_) => create_resource(&api, cm).await.map(|_| ()),
+        }
+    }
+}
+
+/// MiNiFi config.yml of schema version 3 with the components of a VersionedProcessGroup
+fn minifi_config(flow: &Value, site_to_site_url: Option<&str>) -> Value {
+    let mut config = Map::new();
+    config.insert("MiNiFi Config Version".to_string(), json!(CONFIG_VERSION));
+    config.insert(
+        "Flow Controller".to_string(),
+        json!({ "name": flow["name"], "comment": "" }),
+    );
+    // ports of Remote Process Groups are identified by the ids of the remote NiFi ports
+    let mut remote_ports = HashMap::new();
+    collect_remote_ports(flow, &mut remote_ports);
+    if let Value::Object(group) = process_group(flow, site_to_site_url, &remote_ports) {
+        for (key, value) in group {
+            if key != "id" && key != "name" {
+                config.insert(key, value);
+            }
+        }
+    }
+    Value::Object(config)
+}
+
+fn collect_remote_ports(group: &Value, ports: &mut HashMap<String, String>) {
+    for rpg in items(group, "remoteProcessGroups") {
+        for key in &["inputPorts", "outputPorts"] {
+            for port in items(rpg, key) {
+                if let (Some(id), Some(target)) =
+                    (port["identifier"].as_str(), port["targetId"].as_str())
+                {
+                    ports.insert(id.to_string(), target.to_string());
+                }
+            }
+        }
+    }
+    for child in items(group, "processGroups") {
+        collect_remote_ports(child, ports);
+    }
+}
+
+fn process_group(
+    group: &Value,
+    site_to_site_url: Option<&str>,
+    remote_ports: &HashMap<String, String>,
+) -> Value {
+    let port_id = |component: &Value| {
+        let id = component["id"].as_str().unwrap_or_default();
+        json!(remote_ports.get(id).map(|t| t.as_str()).unwrap_or(id))
+    };
+    let processors = items(group, "processors")
+        .iter()
+        .map(|p| {
+            json!({
+                "id": p["identifier"],
+                "name": p["name"],
+                "class": p["type"],
+                "max concurrent tasks": p["concurrentlySchedulableTaskCount"],
+                "scheduling strategy": p["schedulingStrategy"],
+                "scheduling period": p["schedulingPeriod"],
+                "penalization period": p["penaltyDuration"],
+                "yield period": p["yieldDuration"],
+                "run duration nanos": p["runDurationMillis"].as_u64().unwrap_or(0) * 1_000_000,
+                "auto-terminated relationships list": items(p, "autoTerminatedRelationships"),
+                "Properties": properties(&p["properties"])
+            })
+        })
+        .collect::<Vec<_>>();
+    let services = items(group, "controllerServices")
+        .iter()
+        .map(|s| {
+            json!({
+                "id": s["identifier"],
+                "name": s["name"],
+                "type": s["type"],
+                "Properties": properties(&s["properties"])
+            })
+        })
+        .collect::<Vec<_>>();
+    let ports = |key: &str| {
+        items(group, key)
+            .iter()
+            .map(|p| {
+                json!({
+                    "id": p["identifier"],
+                    "name": p["name"],
+                    "max concurrent tasks": p["concurrentlySchedulableTaskCount"]
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    let funnels = items(group, "funnels")
+        .iter()
+        .map(|f| json!({ "id": f["identifier"] }))
+        .collect::<Vec<_>>();
+    let connections = items(group, "connections")
+        .iter()
+        .map(|c| {
+            json!({
+                "id": c["identifier"],
+                "name": c["name"],
+                "source id": port_id(&c["source"]),
+                "source relationship names": items(c, "selectedRelationships"),
+                "destination id": port_id(&c["destination"]),
+                "max work queue size": c["backPressureObjectThreshold"],
+                "max work queue data size": c["backPressureDataSizeThreshold"],
+                "flowfile expiration": c["flowFileExpiration"],
+                "queue prioritizer class": c["prioritizers"].get(0).unwrap_or(&json!(""))
+            })
+        })
+        .collect::<Vec<_>>();
+    let remote_groups = items(group, "remoteProcessGroups")
+        .iter()
+        .map(|r| {
+            let remote_ports = |key: &str| {
+                items(r, key)
+                    .iter()
+                    .map(|p| {
+                        json!({
+                            "id": p["targetId"],
+                            "name": p["name"],
+                            "max concurrent tasks": p["concurrentlySchedulableTaskCount"],
+                            "use compression": p["useCompression"]
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let url = site_to_site_url
+                .map(|u| json!(u))
+                .unwrap_or_else(|| r["targetUris"].clone());
+            json!({
+                "id": r["identifier"],
+                "name": r["name"],
+                "url": url,
+                "timeout": r["communicationsTimeout"],
+                "yield period": r["yieldDuration"],
+                "transport protocol": r["transportProtocol"],
+                "Input Ports": remote_ports("inputPorts"),
+                "Output Ports": remote_ports("outputPorts")
+            })
+        })
+        .collect::<Vec<_>>();
+    let groups = items(group, "processGroups")
+        .iter()
+        .map(|g| process_group(g, site_to_site_url, remote_ports))
+        .collect::<Vec<_>>();
+    json!({
+        "id": group["identifier"],
+        "name": group["name"],
+        "Processors": processors,
+        "Controller Services": services,
+        "Process Groups": groups,
+        "Input Ports": ports("inputPorts"),
+        "Output Ports": ports("outputPorts"),
+        "Funnels": funnels,
+        "Connections": connections,
+        "Remote Process Groups": remote_groups
+    })
+}
+
+fn items<'a>(value: &'a Value, key: &str) -> Vec<&'a Value> {
+    value[key]
+        .as_array()
+        .map(|a| a.iter().collect())
+        .unwrap_or_default()
+}
+
+/// Properties without a value are left to their defaults
+fn properties(properties: &Value) -> Value {
+    let set = properties
+        .as_object()
+        .map(|p| {
+            p.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<Map<_, _>>()
+        })
+        .unwrap_or_default();
+    Value::Object(set)
+}
+
+fn configmap_name(name: &str) -> String {
+    format!("{}-minifi", name)
+}
+
+fn read_minifi_meta(md: &MiNiFiDeployment) -> Result<(String, String)> {
+    let missing =
+        |property: &str| Error::from(MissingProperty(property.to_string(), md.kind.clone()));
+    let name = md.metadata.name.clone().ok_or_else(|| missing("name"))?;
+    let ns = md
+        .metadata
+        .namespace
+        .clone()
+        .ok_or_else(|| missing("namespace"))?;
+    Ok((name, ns))
+}
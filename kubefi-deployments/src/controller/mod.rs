@@ -4,43 +4,128 @@ extern crate kube;
 extern crate kube_derive;
 extern crate serde;
 
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::rc::Rc;
 use std::{error, fmt};
 
 use anyhow::Error;
-use k8s_openapi::api::apps::v1::StatefulSet;
-use k8s_openapi::api::core::v1::{ConfigMap, Service};
-use k8s_openapi::api::extensions::v1beta1::Ingress;
+use k8s_openapi::api::apps::v1::{Deployment, StatefulSet};
+use k8s_openapi::api::batch::v1beta1::CronJob;
+use k8s_openapi::api::core::v1::{ConfigMap, PersistentVolumeClaim, Service, ServiceAccount};
+use k8s_openapi::api::networking::v1::NetworkPolicy;
+use k8s_openapi::api::networking::v1beta1::Ingress;
+use k8s_openapi::api::policy::v1beta1::PodDisruptionBudget;
+use k8s_openapi::api::rbac::v1::{Role, RoleBinding};
 use k8s_openapi::Resource;
-use kube::api::{DeleteParams, ListParams, Meta, PostParams};
+use kube::api::{DeleteParams, ListParams, Meta, PatchParams, PatchStrategy, PostParams};
 use kube::{Api, Client};
+use openssl::sha::Sha256;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use crate::anyhow::Result;
+use crate::controller::authorization::AuthorizationController;
+use crate::controller::authproxy::AuthProxyController;
+use crate::controller::autoscaling::AutoscalingController;
+use crate::controller::backup::BackupController;
 use crate::controller::configmap::ConfigMapController;
+use crate::controller::flowbackup::latest_flow_backup;
+use crate::controller::flowdeployment::FlowController;
+use crate::controller::health::HealthController;
+use crate::controller::hibernate::{hibernated_condition, HIBERNATED_CONDITION};
+use crate::controller::imagesignature::ImageSignatureController;
+use crate::controller::mesh::MeshController;
+use crate::controller::minifi::MiNiFiController;
+use crate::controller::networkpolicy::NetworkPolicyController;
+use crate::controller::nificomponents::NiFiComponentsController;
+use crate::controller::nifiupgrade::{restarts_pending, rollout_pending};
+use crate::controller::parametercontext::ParameterContextController;
+use crate::controller::pdb::PdbController;
+use crate::controller::pravega::PravegaController;
+use crate::controller::registry::RegistryController;
+use crate::controller::sensitivekey::SensitivePropsKeyController;
 use crate::controller::service::ServiceController;
-use crate::controller::statefulset::StatefulSetController;
+use crate::controller::serviceaccount::ServiceAccountController;
+use crate::controller::singleuser::SingleUserController;
+use crate::controller::statefulset::{set_claims, StatefulSetController};
+use crate::controller::stateless::StatelessController;
+use crate::controller::templates::versioned_controllers;
+use crate::controller::tls::TlsController;
+use crate::controller::vault::VaultController;
+use crate::controller::zkupgrade::zk_roll_pending;
+use crate::controller::zookeeper::ZK_CLUSTER_LABEL;
 use crate::controller::ControllerError::MissingProperty;
-use crate::crd::{NiFiDeployment, NiFiDeploymentStatus};
-use crate::template::Template;
+use crate::crd::{NiFiDeployment, NiFiDeploymentStatus, ReclaimPolicy, StatusCondition};
+use crate::discovery::ClusterApis;
+use crate::resources::{
+    Certificate, DestinationRule, HttpRoute, IngressV1, PravegaZookeeperCluster, Route,
+    VirtualService,
+};
+use crate::template::{Template, TemplateVersion};
 use crate::{read_type, Namespace};
 
+pub use self::authorization::ReplaceAuthorizationStatus;
 use self::either::Either;
 use self::either::Either::{Left, Right};
+pub use self::flowdeployment::ReplaceFlowStatus;
+pub use self::minifi::ReplaceMiNiFiStatus;
+pub use self::parametercontext::ReplaceParameterContextStatus;
+pub use self::render::render_manifests;
+pub use self::stateless::ReplaceStatelessStatus;
+pub use self::zookeeper::ReplaceZkStatus;
 
+mod authorization;
+mod authproxy;
+mod autoscaling;
+mod backup;
 mod configmap;
+mod events;
+mod flowbackup;
+mod flowdeployment;
+mod health;
+mod hibernate;
+mod imagesignature;
+mod mesh;
+mod minifi;
+mod networkpolicy;
+mod nificomponents;
+mod nifiupgrade;
+mod parametercontext;
+mod pdb;
+mod pravega;
+mod registry;
+mod render;
+mod scaledown;
+mod sensitivekey;
 mod service;
+mod serviceaccount;
+mod singleuser;
 mod statefulset;
+mod stateless;
+mod templates;
+mod tls;
+mod vault;
+mod version;
+mod workload;
+mod zkupgrade;
+mod zookeeper;
 
 const KUBEFI_LABELS: &str = "app.kubernetes.io/managed-by=Kubefi,release=nifi";
 const NIFI_APP_LABEL: &str = "nifi";
+// name of the custom resource which a resource belongs to
+const INSTANCE_LABEL: &str = "app.kubernetes.io/instance";
+const IMAGE_VERIFIED_CONDITION: &str = "ImageVerified";
+const UPGRADED_CONDITION: &str = "Upgraded";
 const ZK_APP_LABEL: &str = "zookeeper";
 
 #[derive(Debug)]
 pub enum ControllerError {
     MissingProperty(String, String),
+    InvalidSpec(String),
+    UpgradeFailed(String),
+    ImageNotVerified(String),
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -50,6 +135,14 @@ pub struct ReplaceStatus {
     pub status: NiFiDeploymentStatus,
 }
 
+/// Status of a custom resource other than NiFiDeployment to replace after it is applied
+#[derive(Debug, Clone)]
+pub struct ReplaceResourceStatus<S> {
+    pub name: String,
+    pub ns: String,
+    pub status: S,
+}
+
 impl fmt::Display for ControllerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -58,6 +151,13 @@ impl fmt::Display for ControllerError {
                 "Property {:?} for {} resource is missing",
                 property, kind
             ),
+            ControllerError::InvalidSpec(reason) => write!(f, "Invalid spec: {}", reason),
+            ControllerError::UpgradeFailed(reason) => {
+                write!(f, "Rolling upgrade failed: {}", reason)
+            }
+            ControllerError::ImageNotVerified(reason) => {
+                write!(f, "Image verification failed: {}", reason)
+            }
         }
     }
 }
@@ -66,6 +166,9 @@ impl error::Error for ControllerError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
             ControllerError::MissingProperty(_, _) => None,
+            ControllerError::InvalidSpec(_) => None,
+            ControllerError::UpgradeFailed(_) => None,
+            ControllerError::ImageNotVerified(_) => None,
         }
     }
 }
@@ -73,9 +176,34 @@ impl error::Error for ControllerError {
 pub struct NiFiController {
     pub namespace: Namespace,
     client: Rc<Client>,
+    template: Rc<Template>,
     cm_controller: ConfigMapController,
     svc_controller: ServiceController,
+    sa_controller: ServiceAccountController,
     sets_controller: StatefulSetController,
+    pdb_controller: PdbController,
+    np_controller: NetworkPolicyController,
+    mesh_controller: MeshController,
+    backup_controller: BackupController,
+    pravega_controller: PravegaController,
+    registry_controller: RegistryController,
+    flow_controller: FlowController,
+    parameter_context_controller: ParameterContextController,
+    components_controller: NiFiComponentsController,
+    authorization_controller: AuthorizationController,
+    health_controller: HealthController,
+    autoscaling_controller: AutoscalingController,
+    stateless_controller: StatelessController,
+    minifi_controller: MiNiFiController,
+    tls_controller: TlsController,
+    single_user_controller: SingleUserController,
+    sensitive_key_controller: SensitivePropsKeyController,
+    auth_proxy_controller: AuthProxyController,
+    vault_controller: VaultController,
+    image_signature_controller: ImageSignatureController,
+    apis: ClusterApis,
+    // controllers of the template sets of the `versions` directory, built again on reloads
+    versions: RefCell<BTreeMap<TemplateVersion, Rc<NiFiController>>>,
 }
 
 #[derive(Clone, Debug)]
@@ -89,6 +217,7 @@ impl NiFiController {
         ns: Namespace,
         client: Rc<Client>,
         template: Rc<Template>,
+        apis: ClusterApis,
     ) -> Result<NiFiController> {
         let cm_controller = ConfigMapController {
             client: client.clone(),
@@ -97,54 +226,369 @@ impl NiFiController {
         let svc_controller = ServiceController {
             client: client.clone(),
             template: template.clone(),
+            apis: apis.clone(),
+        };
+        let sa_controller = ServiceAccountController {
+            client: client.clone(),
+            template: template.clone(),
         };
         let sets_controller = StatefulSetController {
             client: client.clone(),
-            template,
+            template: template.clone(),
+        };
+        let pdb_controller = PdbController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let np_controller = NetworkPolicyController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let mesh_controller = MeshController {
+            client: client.clone(),
+            template: template.clone(),
+            apis: apis.clone(),
+        };
+        let backup_controller = BackupController {
+            client: client.clone(),
+            template: template.clone(),
+            apis: apis.clone(),
+        };
+        let pravega_controller = PravegaController {
+            client: client.clone(),
+            template: template.clone(),
+            apis: apis.clone(),
+        };
+        let registry_controller = RegistryController {
+            client: client.clone(),
+            template: template.clone(),
+            apis: apis.clone(),
+        };
+        let flow_controller = FlowController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let parameter_context_controller = ParameterContextController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let components_controller = NiFiComponentsController {
+            client: client.clone(),
+            template: template.clone(),
         };
+        let authorization_controller = AuthorizationController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let health_controller = HealthController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let autoscaling_controller = AutoscalingController {
+            client: client.clone(),
+        };
+        let stateless_controller = StatelessController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let minifi_controller = MiNiFiController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let single_user_controller = SingleUserController {
+            client: client.clone(),
+        };
+        let sensitive_key_controller = SensitivePropsKeyController {
+            client: client.clone(),
+        };
+        let auth_proxy_controller = AuthProxyController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let vault_controller = VaultController {
+            client: client.clone(),
+            template: template.clone(),
+        };
+        let image_signature_controller = ImageSignatureController {
+            template: template.clone(),
+            verified: Rc::new(RefCell::new(HashMap::new())),
+        };
+        let tls_controller = TlsController {
+            client: client.clone(),
+            template: template.clone(),
+            apis: apis.clone(),
+        };
+        let versions = versioned_controllers(&ns, &client, &template, &apis)?;
         Ok(NiFiController {
             namespace: ns,
             client,
+            template,
             cm_controller,
             svc_controller,
+            sa_controller,
             sets_controller,
+            pdb_controller,
+            np_controller,
+            mesh_controller,
+            backup_controller,
+            pravega_controller,
+            registry_controller,
+            flow_controller,
+            parameter_context_controller,
+            components_controller,
+            authorization_controller,
+            health_controller,
+            autoscaling_controller,
+            stateless_controller,
+            minifi_controller,
+            tls_controller,
+            single_user_controller,
+            sensitive_key_controller,
+            auth_proxy_controller,
+            vault_controller,
+            image_signature_controller,
+            apis,
+            versions: RefCell::new(versions),
         })
     }
 
     pub async fn on_apply(&self, d: NiFiDeployment) -> Result<Option<ReplaceStatus>> {
         let name = read_name(&d)?;
         let ns = read_namespace(&d)?;
-        let status = match self.handle_event(d.clone(), &name, &ns).await {
-            Ok(true) => {
-                let status = NiFiDeploymentStatus {
-                    nifi_replicas: d.spec.nifi_replicas,
-                    error_msg: "".to_string(),
-                };
-                Some(ReplaceStatus { name, ns, status })
-            }
-            Ok(_) => None,
-            Err(e) => {
-                let status = NiFiDeploymentStatus {
-                    nifi_replicas: d.spec.nifi_replicas,
-                    error_msg: e.to_string(),
-                };
-                Some(ReplaceStatus { name, ns, status })
+        let result = match self.for_version(&d.spec) {
+            Some(controller) => controller.apply(&d, &name, &ns).await,
+            None => self.apply(&d, &name, &ns).await,
+        };
+        let (error_msg, errors) = match result {
+            Ok(true) => ("".to_string(), vec![]),
+            Ok(_) => return Ok(None),
+            Err(e) => (e.to_string(), error_conditions(&e)),
+        };
+        // conditions set by the health probe are kept
+        let mut conditions = d
+            .status
+            .iter()
+            .flat_map(|s| s.conditions.iter())
+            .filter(|c| c.type_ != UPGRADED_CONDITION && c.type_ != IMAGE_VERIFIED_CONDITION)
+            .cloned()
+            .collect::<Vec<_>>();
+        conditions.extend(errors);
+        // the condition is cleared by the health probe once the flow is started again
+        if d.spec.hibernated()
+            && !conditions
+                .iter()
+                .any(|c| c.type_ == HIBERNATED_CONDITION && c.status == "True")
+        {
+            conditions.retain(|c| c.type_ != HIBERNATED_CONDITION);
+            conditions.push(hibernated_condition(true));
+        }
+        let flow_backup = latest_flow_backup(&self.client, &name, &ns)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to find flow backups of {}: {}", &name, e);
+                None
+            });
+        let status = NiFiDeploymentStatus {
+            nifi_replicas: d.spec.nifi_replicas,
+            error_msg,
+            conditions,
+            flow_backup,
+            last_backup_time: d.status.as_ref().and_then(|s| s.last_backup_time.clone()),
+            last_scale_time: d.status.as_ref().and_then(|s| s.last_scale_time.clone()),
+            cluster: d.status.as_ref().and_then(|s| s.cluster.clone()),
+            certificate_expiry: d.status.as_ref().and_then(|s| s.certificate_expiry.clone()),
+            single_user_secret: Template::single_user_secret_name(&name, &d.spec),
+            sensitive_props_key_secret: Some(Template::sensitive_props_key(&name, &d.spec).name),
+        };
+        Ok(Some(ReplaceStatus { name, ns, status }))
+    }
+
+    /// Keeps the Registry client of a running NiFi cluster, NiFi may still be starting, so errors are
+    /// retried on the next run
+    pub async fn on_registry_client(&self, d: &NiFiDeployment) -> Result<bool> {
+        if d.spec.hibernated() {
+            return Ok(false);
+        }
+        let name = read_name(d)?;
+        let ns = read_namespace(d)?;
+        self.registry_controller
+            .register_client(&name, &ns, &d.spec)
+            .await
+    }
+
+    /// Keeps the declared controller services and reporting tasks of a running NiFi cluster,
+    /// errors are retried on the next run
+    pub async fn on_nifi_components(&self, d: &NiFiDeployment) -> Result<bool> {
+        if d.spec.hibernated() {
+            return Ok(false);
+        }
+        let name = read_name(d)?;
+        let ns = read_namespace(d)?;
+        self.components_controller
+            .handle_components(&name, &ns, &d.spec)
+            .await
+    }
+
+    /// Whether the NiFi StatefulSet of a deployment has a rollout or queued restarts in progress,
+    /// or its ZooKeeper StatefulSet a rolling upgrade, every apply takes their next step
+    pub async fn on_rollout(&self, d: &NiFiDeployment) -> Result<bool> {
+        let name = read_name(d)?;
+        let ns = read_namespace(d)?;
+        let api = get_api::<StatefulSet>(&self.client, &ns);
+        let nifi_pending = match api.get(&name).await {
+            Ok(set) => rollout_pending(&set) || restarts_pending(&set),
+            Err(_) => false,
+        };
+        let zk_pending = match api.get(&format!("{}-zookeeper", &name)).await {
+            Ok(set) => zk_roll_pending(&set),
+            Err(_) => false,
+        };
+        Ok(nifi_pending || zk_pending)
+    }
+
+    /// Takes a scheduled object storage backup when it is due, errors are reported in the status
+    pub async fn on_backup(&self, d: NiFiDeployment) -> Result<Option<ReplaceStatus>> {
+        let name = read_name(&d)?;
+        let ns = read_namespace(&d)?;
+        let mut status = d.status.clone().unwrap_or_else(|| NiFiDeploymentStatus {
+            nifi_replicas: d.spec.nifi_replicas,
+            ..NiFiDeploymentStatus::default()
+        });
+        let backup = match self.image_signature_controller.verify_images(&d).await {
+            Ok(spec) => {
+                let d = NiFiDeployment { spec, ..d };
+                self.backup_controller
+                    .handle_s3_backup(&d, &name, &ns)
+                    .await
             }
+            Err(e) => Err(e),
         };
-        Ok(status)
+        match backup {
+            Ok(Some(time)) => {
+                status.last_backup_time = Some(time);
+                status.error_msg = "".to_string();
+            }
+            Ok(None) => return Ok(None),
+            Err(e) => status.error_msg = format!("S3 backup failed: {}", e),
+        }
+        Ok(Some(ReplaceStatus { name, ns, status }))
     }
 
     pub async fn on_delete(&self, d: NiFiDeployment) -> Result<()> {
+        let name = read_name(&d)?;
         let ns = read_namespace(&d)?;
         let params = &DeleteParams::default();
-        let lp = ListParams::default().labels(KUBEFI_LABELS);
-
-        let sts = self.delete_resources::<StatefulSet>(&ns, &params, &lp);
-        let svc = self.delete_resources::<Service>(&ns, &params, &lp);
-        let cm = self.delete_resources::<ConfigMap>(&ns, &params, &lp);
-        let ing = self.delete_resources::<Ingress>(&ns, &params, &lp);
-        let (r1, r2, r3, r4) = futures::future::join4(sts, svc, cm, ing).await;
-        r1.and(r2).and(r3).and(r4)
+        // resources of other deployments and of ZookeeperClusters are left to their own lifecycle
+        let selector = format!(
+            "{},{}={},!{}",
+            KUBEFI_LABELS, INSTANCE_LABEL, name, ZK_CLUSTER_LABEL
+        );
+        let lp = ListParams::default().labels(&selector);
+        let reclaim_policy = d
+            .spec
+            .persistence
+            .as_ref()
+            .and_then(|p| p.reclaim_policy.clone())
+            .unwrap_or(ReclaimPolicy::Retain);
+        // claims must be found before their StatefulSets are gone
+        let claims = match reclaim_policy {
+            ReclaimPolicy::Delete => set_claims(&self.client, &name, &ns).await?,
+            ReclaimPolicy::Retain => vec![],
+        };
+
+        let sts = self.delete_resources::<StatefulSet>(&ns, params, &lp);
+        let svc = self.delete_resources::<Service>(&ns, params, &lp);
+        let cm = self.delete_resources::<ConfigMap>(&ns, params, &lp);
+        let ing = async {
+            if self.apis.ingress_v1 {
+                self.delete_resources::<IngressV1>(&ns, params, &lp).await
+            } else {
+                self.delete_resources::<Ingress>(&ns, params, &lp).await
+            }
+        };
+        let route = async {
+            if self.apis.route {
+                self.delete_resources::<Route>(&ns, params, &lp).await
+            } else {
+                Ok(())
+            }
+        };
+        let http_route = async {
+            if self.apis.http_route {
+                self.delete_resources::<HttpRoute>(&ns, params, &lp).await
+            } else {
+                Ok(())
+            }
+        };
+        let pravega_zk = async {
+            if self.apis.pravega_zk {
+                self.delete_resources::<PravegaZookeeperCluster>(&ns, params, &lp)
+                    .await
+            } else {
+                Ok(())
+            }
+        };
+        let mesh = async {
+            if self.apis.istio {
+                self.delete_resources::<VirtualService>(&ns, params, &lp)
+                    .await
+                    .and(
+                        self.delete_resources::<DestinationRule>(&ns, params, &lp)
+                            .await,
+                    )
+            } else {
+                Ok(())
+            }
+        };
+        let sa = self.delete_resources::<ServiceAccount>(&ns, params, &lp);
+        let role = self.delete_resources::<Role>(&ns, params, &lp);
+        let binding = self.delete_resources::<RoleBinding>(&ns, params, &lp);
+        let pdb = self.delete_resources::<PodDisruptionBudget>(&ns, params, &lp);
+        let np = self.delete_resources::<NetworkPolicy>(&ns, params, &lp);
+        let cronjob = self.delete_resources::<CronJob>(&ns, params, &lp);
+        let deployments = self.delete_resources::<Deployment>(&ns, params, &lp);
+        // cert-manager keeps the Secrets of deleted Certificates
+        let certificates = async {
+            if self.apis.cert_manager {
+                self.delete_resources::<Certificate>(&ns, params, &lp).await
+            } else {
+                Ok(())
+            }
+        };
+        let ((r1, r2, r3, r4), (r5, r6, r7, r8), (r9, r10, r11, r12), (r13, r14, r15, r16)) =
+            futures::future::join4(
+                futures::future::join4(sts, svc, cm, ing),
+                futures::future::join4(sa, role, binding, pdb),
+                futures::future::join4(np, route, http_route, mesh),
+                futures::future::join4(pravega_zk, cronjob, certificates, deployments),
+            )
+            .await;
+        r1.and(r2)
+            .and(r3)
+            .and(r4)
+            .and(r5)
+            .and(r6)
+            .and(r7)
+            .and(r8)
+            .and(r9)
+            .and(r10)
+            .and(r11)
+            .and(r12)
+            .and(r13)
+            .and(r14)
+            .and(r15)
+            .and(r16)?;
+        self.delete_claims(&ns, params, &claims).await
+    }
+
+    async fn delete_claims(&self, ns: &str, params: &DeleteParams, names: &[String]) -> Result<()> {
+        debug!("PVCs to delete: {:?}", names);
+        let api = get_api::<PersistentVolumeClaim>(&self.client, ns);
+        let deletes = names.iter().map(|name| api.delete(name, params));
+        futures::future::join_all(deletes)
+            .await
+            .into_iter()
+            .try_fold((), |_, r| r.map(|_| ()).map_err(Error::from))
     }
 
     async fn delete_resources<T: Resource + Clone + DeserializeOwned + Meta + Debug>(
@@ -153,10 +597,10 @@ impl NiFiController {
         params: &DeleteParams,
         lp: &ListParams,
     ) -> Result<()> {
-        let names = find_names::<T>(&self.client, &ns, &lp).await?;
+        let names = find_names::<T>(&self.client, ns, lp).await?;
         debug!("{} to delete: {:?}", read_type::<T>("Resources"), &names);
-        let api = get_api::<T>(&self.client, &ns);
-        let deletes = names.iter().map(|name| api.delete(&name, &params));
+        let api = get_api::<T>(&self.client, ns);
+        let deletes = names.iter().map(|name| api.delete(name, params));
         futures::future::join_all(deletes)
             .await
             .into_iter()
@@ -167,28 +611,125 @@ impl NiFiController {
                 })
                 .map(|_| ())
             })
-            .fold(Ok(()), |acc, r| acc.and(r.map_err(Error::from)))
+            .try_fold((), |_, r| r.map_err(Error::from))
     }
 
-    async fn handle_event(&self, d: NiFiDeployment, name: &str, ns: &str) -> Result<bool> {
-        let nifi_cm_updated = self.cm_controller.handle_configmaps(&d, &name, &ns).await?;
+    /// Renders a deployment with the templates of this controller, or with its `templateOverrides`
+    /// merged over them
+    async fn apply(&self, d: &NiFiDeployment, name: &str, ns: &str) -> Result<bool> {
+        match &d.spec.template_overrides {
+            Some(overrides) => {
+                let controller = self.with_template_overrides(ns, overrides).await?;
+                controller.handle_event(d.clone(), name, ns).await
+            }
+            None => self.handle_event(d.clone(), name, ns).await,
+        }
+    }
+
+    async fn handle_event(&self, mut d: NiFiDeployment, name: &str, ns: &str) -> Result<bool> {
+        let vault_updated = self
+            .vault_controller
+            .handle_vault_secrets(name, ns, &d.spec)
+            .await?;
+        // keystores must exist before the Pods mounting them are created
+        let tls_updated = self.tls_controller.handle_tls(name, ns, &d.spec).await?;
+        let single_user_updated = self
+            .single_user_controller
+            .handle_single_user(name, ns, &d.spec)
+            .await?;
+        let sensitive_key_updated = self
+            .sensitive_key_controller
+            .handle_sensitive_props_key(name, ns, &d.spec)
+            .await?;
+        let nifi_cm_updated = self.cm_controller.handle_configmaps(&d, name, ns).await?;
         let cm_state = ConfigMapState {
             updated: nifi_cm_updated,
             logging_cm: d.clone().spec.logging_config_map,
         };
         let service_updated = self
             .svc_controller
-            .handle_services(&name, &ns, &d.spec.ingress)
+            .handle_services(name, ns, &d.spec)
+            .await?;
+        let sa_updated = self
+            .sa_controller
+            .handle_service_account(name, ns, &d.spec)
+            .await?;
+        // nothing is rolled out with images failing the verification, the rest render them pinned
+        d.spec = self.image_signature_controller.verify_images(&d).await?;
+        let pravega_zk_updated = self
+            .pravega_controller
+            .handle_pravega_zk(name, ns, &d.spec)
             .await?;
         let sets_updated = self
             .sets_controller
-            .handle_sets(&d, &name, &ns, cm_state, service_updated)
+            .handle_sets(&d, name, ns, cm_state)
+            .await?;
+        let pdbs_updated = self.pdb_controller.handle_pdbs(name, ns, &d.spec).await?;
+        let policies_updated = self
+            .np_controller
+            .handle_network_policies(name, ns, &d.spec)
+            .await?;
+        let mesh_updated = self.mesh_controller.handle_mesh(name, ns, &d.spec).await?;
+        let backup_taken = self.backup_controller.handle_backup(&d, name, ns).await?;
+        let registry_updated = self
+            .registry_controller
+            .handle_registry(name, ns, &d.spec)
+            .await?;
+        let backup_schedule_updated = self
+            .backup_controller
+            .handle_backup_schedule(name, ns, &d.spec)
+            .await?;
+        let auth_proxy_updated = self
+            .auth_proxy_controller
+            .handle_auth_proxy(name, ns, &d.spec)
             .await?;
         debug!(
-            "Resource updates: configmap = {}, statefulsets = {}, services = {}",
-            nifi_cm_updated, sets_updated, service_updated
+            "Resource updates: configmap = {}, statefulsets = {}, services = {}, service account = {}, pdbs = {}, network policies = {}, mesh = {}, backup = {}, backup schedule = {}, registry = {}, pravega zookeeper = {}, tls = {}, single user = {}, sensitive props key = {}, auth proxy = {}, vault = {}",
+            nifi_cm_updated, sets_updated, service_updated, sa_updated, pdbs_updated, policies_updated, mesh_updated, backup_taken, backup_schedule_updated, registry_updated, pravega_zk_updated, tls_updated, single_user_updated, sensitive_key_updated, auth_proxy_updated, vault_updated
         );
-        Ok(nifi_cm_updated || sets_updated || service_updated)
+        Ok(nifi_cm_updated
+            || sets_updated
+            || service_updated
+            || sa_updated
+            || pdbs_updated
+            || policies_updated
+            || mesh_updated
+            || backup_taken
+            || backup_schedule_updated
+            || registry_updated
+            || pravega_zk_updated
+            || tls_updated
+            || single_user_updated
+            || sensitive_key_updated
+            || auth_proxy_updated
+            || vault_updated)
+    }
+}
+
+fn resource_updated<T>(result: Either<Option<T>, Option<T>>) -> bool {
+    match result {
+        Left(Some(_)) => false,
+        Right(Some(_)) => true,
+        _ => false,
+    }
+}
+
+/// Status conditions of errors which stop a rollout, other errors are reported in `errorMsg` only
+fn error_conditions(e: &Error) -> Vec<StatusCondition> {
+    match e.downcast_ref::<ControllerError>() {
+        Some(ControllerError::UpgradeFailed(reason)) => vec![StatusCondition::new(
+            UPGRADED_CONDITION,
+            false,
+            "NodeNotRejoined",
+            reason,
+        )],
+        Some(ControllerError::ImageNotVerified(reason)) => vec![StatusCondition::new(
+            IMAGE_VERIFIED_CONDITION,
+            false,
+            "SignatureNotVerified",
+            reason,
+        )],
+        _ => vec![],
     }
 }
 
@@ -231,16 +772,47 @@ async fn get_or_create_convert<
     get_yaml: F,
     convert: C,
 ) -> Result<Either<Option<T>, Option<T>>> {
-    let api = get_api::<T>(&client.clone(), &ns);
-    match api.get(&name).await {
-        Err(_) => create_from_yaml(&cr_name, &ns, &client, get_yaml, convert).await,
+    let api = get_api::<T>(&client.clone(), ns);
+    match api.get(name).await {
+        Err(_) => create_from_yaml(cr_name, ns, client, get_yaml, convert).await,
         Ok(res) => {
             debug!("Found existing {}: {}", read_type::<T>("resource"), &name);
-            Ok(Left(Some(res)))
+            with_instance_label(&api, res, cr_name)
+                .await
+                .map(Some)
+                .map(Left)
         }
     }
 }
 
+/// Labels resources created before they carried the instance label, so that they are found when
+/// their deployment is deleted
+async fn with_instance_label<T: Resource + Clone + DeserializeOwned + Meta>(
+    api: &Api<T>,
+    res: T,
+    cr_name: &str,
+) -> Result<T> {
+    let labels = Meta::meta(&res).labels.as_ref();
+    if labels.is_some_and(|l| l.contains_key(INSTANCE_LABEL)) {
+        return Ok(res);
+    }
+    debug!(
+        "Labeling {} with {}={}",
+        Meta::name(&res),
+        INSTANCE_LABEL,
+        cr_name
+    );
+    let params = PatchParams {
+        patch_strategy: PatchStrategy::Merge,
+        ..PatchParams::default()
+    };
+    let patch = json!({ "metadata": { "labels": { INSTANCE_LABEL: cr_name } } });
+    let patched = api
+        .patch(&Meta::name(&res), &params, serde_json::to_vec(&patch)?)
+        .await?;
+    Ok(patched)
+}
+
 async fn create_from_yaml<
     T: Resource + Serialize + Clone + DeserializeOwned + Meta,
     F: FnOnce(&str) -> Result<Option<String>>,
@@ -252,12 +824,12 @@ async fn create_from_yaml<
     get_yaml: F,
     convert: C,
 ) -> Result<Either<Option<T>, Option<T>>, Error> {
-    let yaml = get_yaml(&cr_name)?;
+    let yaml = get_yaml(cr_name)?;
     match yaml {
         Some(y) => {
             let resource = from_yaml(&y)?;
             let converted = convert(resource)?;
-            let api = get_api::<T>(&client.clone(), &ns);
+            let api = get_api::<T>(&client.clone(), ns);
             create_resource(&api, converted).await.map(Some).map(Right)
         }
         None => {
@@ -271,6 +843,46 @@ async fn create_from_yaml<
     }
 }
 
+/// Creates a resource or recreates it when `changed` detects a difference to the rendered template.
+/// Existing resource is deleted if its template renders nothing, i.e. the resource got disabled.
+async fn recreate_on_change<
+    T: Resource + Serialize + Clone + DeserializeOwned + Meta + Debug,
+    F: Fn(&str) -> Result<Option<String>>,
+    C: FnOnce(&T, &T) -> bool,
+>(
+    client: &Client,
+    name: &str,
+    cr_name: &str,
+    ns: &str,
+    get_yaml: F,
+    changed: C,
+) -> Result<bool> {
+    match get_or_create::<T, _>(client, name, cr_name, ns, &get_yaml).await? {
+        Left(Some(current)) => {
+            let expected = get_yaml(cr_name)?.map(|y| from_yaml::<T>(&y)).transpose()?;
+            let api = get_api::<T>(client, ns);
+            match expected {
+                Some(expected) if changed(&current, &expected) => {
+                    api.delete(name, &DeleteParams::default()).await?;
+                    debug!("Creating new {}: {}", read_type::<T>("resource"), &name);
+                    create_resource(&api, expected).await.map(|_| true)
+                }
+                Some(_) => Ok(false),
+                None => {
+                    debug!(
+                        "{} is disabled, deleting: {}",
+                        read_type::<T>("resource"),
+                        &name
+                    );
+                    api.delete(name, &DeleteParams::default()).await?;
+                    Ok(true)
+                }
+            }
+        }
+        r => Ok(resource_updated(r)),
+    }
+}
+
 async fn create_resource<T: Serialize + Clone + DeserializeOwned + Meta>(
     api: &Api<T>,
     resource: T,
@@ -282,7 +894,7 @@ async fn create_resource<T: Serialize + Clone + DeserializeOwned + Meta>(
 fn from_yaml<T: Resource + Serialize + Clone + DeserializeOwned + Meta>(
     y: &str,
 ) -> Result<T, Error> {
-    serde_yaml::from_str(&y).map_err(Error::new)
+    serde_yaml::from_str(y).map_err(Error::new)
 }
 
 async fn delete_resources<T: Resource + Clone + DeserializeOwned + Meta + Debug>(
@@ -291,10 +903,10 @@ async fn delete_resources<T: Resource + Clone + DeserializeOwned + Meta + Debug>
     params: &DeleteParams,
     lp: &ListParams,
 ) -> Result<()> {
-    let names = find_names::<T>(&client, &ns, &lp).await?;
+    let names = find_names::<T>(client, ns, lp).await?;
     debug!("{} to delete: {:?}", read_type::<T>("Resources"), &names);
-    let api = get_api::<T>(&client, &ns);
-    let deletes = names.iter().map(|name| api.delete(&name, &params));
+    let api = get_api::<T>(client, ns);
+    let deletes = names.iter().map(|name| api.delete(name, params));
     futures::future::join_all(deletes)
         .await
         .into_iter()
@@ -305,7 +917,7 @@ async fn delete_resources<T: Resource + Clone + DeserializeOwned + Meta + Debug>
             })
             .map(|_| ())
         })
-        .fold(Ok(()), |acc, r| acc.and(r.map_err(Error::from)))
+        .try_fold((), |_, r| r.map_err(Error::from))
 }
 
 async fn find_names<T: Resource + Clone + DeserializeOwned + Meta>(
@@ -313,12 +925,22 @@ async fn find_names<T: Resource + Clone + DeserializeOwned + Meta>(
     ns: &str,
     lp: &ListParams,
 ) -> Result<Vec<String>> {
-    let api: Api<T> = get_api(&client, &ns);
-    let list = &api.list(&lp).await?;
+    let api: Api<T> = get_api(client, ns);
+    let list = &api.list(lp).await?;
     let names = list.into_iter().map(Meta::name).collect();
     Ok(names)
 }
 
 fn get_api<T: Resource>(client: &Client, ns: &str) -> Api<T> {
-    Api::namespaced(client.clone(), &ns)
+    Api::namespaced(client.clone(), ns)
+}
+
+/// Hex encoded SHA-256 digest, unlike `DefaultHasher` it is stable across Rust releases,
+/// so hashes stored in annotations survive an operator upgrade
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finish()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
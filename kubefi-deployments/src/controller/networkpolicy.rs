@@ -0,0 +1,158 @@
+use std::rc::Rc;
+
+use anyhow::Result;
+use k8s_openapi::api::networking::v1::{
+    NetworkPolicy, NetworkPolicyPeer, NetworkPolicyPort, NetworkPolicySpec,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::Client;
+
+use crate::controller::recreate_on_change;
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::Template;
+
+pub struct NetworkPolicyController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NetworkPolicyController {
+    pub async fn handle_network_policies(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let nifi_policy = recreate_on_change::<NetworkPolicy, _, _>(
+            &self.client,
+            name,
+            name,
+            ns,
+            |name| self.template.nifi_network_policy(name, spec),
+            policy_changed,
+        );
+        let zk_policy_name = format!("{}-zookeeper", &name);
+        let zk_policy = recreate_on_change::<NetworkPolicy, _, _>(
+            &self.client,
+            &zk_policy_name,
+            name,
+            ns,
+            |name| self.template.zk_network_policy(name, spec),
+            policy_changed,
+        );
+        let (nifi_policy, zk_policy) = futures::future::join(nifi_policy, zk_policy).await;
+        Ok(nifi_policy? || zk_policy?)
+    }
+}
+
+fn policy_changed(current: &NetworkPolicy, expected: &NetworkPolicy) -> bool {
+    normalized(current) != normalized(expected)
+}
+
+/// Spec with the defaults set by the API server, e.g. `policyTypes` and the TCP port protocol,
+/// so that templates leaving them out are not recreated on every reconcile
+fn normalized(policy: &NetworkPolicy) -> NetworkPolicySpec {
+    let mut spec = policy.spec.clone().unwrap_or_default();
+    none_if_empty(&mut spec.ingress);
+    none_if_empty(&mut spec.egress);
+    if spec.policy_types.is_none() {
+        let mut types = vec!["Ingress".to_string()];
+        if spec.egress.is_some() {
+            types.push("Egress".to_string());
+        }
+        spec.policy_types = Some(types);
+    }
+    normalize_selector(&mut spec.pod_selector);
+    for rule in spec.ingress.iter_mut().flatten() {
+        normalize_rule(&mut rule.from, &mut rule.ports);
+    }
+    for rule in spec.egress.iter_mut().flatten() {
+        normalize_rule(&mut rule.to, &mut rule.ports);
+    }
+    spec
+}
+
+fn normalize_rule(
+    peers: &mut Option<Vec<NetworkPolicyPeer>>,
+    ports: &mut Option<Vec<NetworkPolicyPort>>,
+) {
+    none_if_empty(peers);
+    none_if_empty(ports);
+    for peer in peers.iter_mut().flatten() {
+        if let Some(selector) = peer.namespace_selector.as_mut() {
+            normalize_selector(selector);
+        }
+        if let Some(selector) = peer.pod_selector.as_mut() {
+            normalize_selector(selector);
+        }
+    }
+    for port in ports.iter_mut().flatten() {
+        port.protocol.get_or_insert_with(|| "TCP".to_string());
+    }
+}
+
+fn normalize_selector(selector: &mut LabelSelector) {
+    none_if_empty(&mut selector.match_expressions);
+    if selector.match_labels.as_ref().is_some_and(|l| l.is_empty()) {
+        selector.match_labels = None;
+    }
+}
+
+fn none_if_empty<T>(values: &mut Option<Vec<T>>) {
+    if values.as_ref().is_some_and(|v| v.is_empty()) {
+        *values = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(spec: serde_json::Value) -> NetworkPolicy {
+        serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "nifi" },
+            "spec": spec
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_server_defaults_are_no_change() {
+        let expected = policy(serde_json::json!({
+            "podSelector": { "matchLabels": { "app": "nifi" } },
+            "ingress": [{
+                "from": [{ "namespaceSelector": {}, "podSelector": { "matchLabels": {} } }],
+                "ports": [{ "port": 8080 }]
+            }]
+        }));
+        let current = policy(serde_json::json!({
+            "podSelector": { "matchLabels": { "app": "nifi" } },
+            "policyTypes": ["Ingress"],
+            "ingress": [{
+                "from": [{ "namespaceSelector": {}, "podSelector": {} }],
+                "ports": [{ "port": 8080, "protocol": "TCP" }]
+            }]
+        }));
+        assert!(!policy_changed(&current, &expected));
+    }
+
+    #[test]
+    fn test_policy_changed() {
+        let current = policy(serde_json::json!({
+            "podSelector": { "matchLabels": { "app": "nifi" } },
+            "policyTypes": ["Ingress"],
+            "ingress": [{ "ports": [{ "port": 8080, "protocol": "TCP" }] }]
+        }));
+        let port = policy(serde_json::json!({
+            "podSelector": { "matchLabels": { "app": "nifi" } },
+            "ingress": [{ "ports": [{ "port": 9443 }] }]
+        }));
+        assert!(policy_changed(&current, &port));
+        let egress = policy(serde_json::json!({
+            "podSelector": { "matchLabels": { "app": "nifi" } },
+            "ingress": [{ "ports": [{ "port": 8080 }] }],
+            "egress": [{ "ports": [{ "port": 53, "protocol": "UDP" }] }]
+        }));
+        assert!(policy_changed(&current, &egress));
+    }
+}
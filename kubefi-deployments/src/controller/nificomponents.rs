@@ -0,0 +1,399 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::Meta;
+use kube::{Api, Client};
+use openssl::sha::Sha256;
+use serde::Serialize;
+use tokio::time::{delay_for, Duration};
+
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{get_api, hex_digest};
+use crate::crd::{
+    ControllerServiceCfg, ControllerServiceScope, NiFiComponentCfg, NiFiDeploymentSpec,
+    ReportingTaskCfg, SecretKeyRef,
+};
+use crate::nifi_api::{Bundle, ComponentConfig, ConfiguredComponent, NiFiApi};
+use crate::template::Template;
+
+/// Comments of components created by Kubefi start with it, followed by the hash of their spec
+const MANAGED_BY: &str = "Managed by Kubefi";
+// 2 second polls while a controller service is disabled
+const DISABLE_CHECKS: u8 = 30;
+
+/// Controller services and reporting tasks of a running NiFi cluster configured via the REST API
+pub struct NiFiComponentsController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiComponentsController {
+    /// Provisions `spec.controllerServices` and then `spec.reportingTasks`, so the tasks can reference
+    /// the services. Returns true when any of them changed
+    pub async fn handle_components(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        if spec.controller_services.is_empty() && spec.reporting_tasks.is_empty() {
+            return Ok(false);
+        }
+        let pod_name = format!("{}-0", name);
+        let api = NiFiApi::connect(
+            &self.client,
+            &self.template,
+            name,
+            ns,
+            spec,
+            Some(&pod_name),
+        )
+        .await?;
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let services_updated = if spec.controller_services.is_empty() {
+            false
+        } else {
+            apply_controller_services(&api, &secrets, name, spec).await?
+        };
+        let tasks_updated = if spec.reporting_tasks.is_empty() {
+            false
+        } else {
+            apply_reporting_tasks(&api, &secrets, name, spec).await?
+        };
+        Ok(services_updated || tasks_updated)
+    }
+}
+
+/// Creates, updates and enables declared services, services created by Kubefi
+/// and removed from the spec are deleted
+async fn apply_controller_services(
+    api: &NiFiApi,
+    secrets: &Api<Secret>,
+    name: &str,
+    spec: &NiFiDeploymentSpec,
+) -> Result<bool> {
+    let root_id = api.root_group_id().await?;
+    let mut updated = false;
+    for scope in &[
+        ControllerServiceScope::ProcessGroup,
+        ControllerServiceScope::Controller,
+    ] {
+        let group_id = match scope {
+            ControllerServiceScope::ProcessGroup => Some(root_id.as_str()),
+            ControllerServiceScope::Controller => None,
+        };
+        let existing = api.controller_services(group_id).await?;
+        let mut ids = component_ids(&existing);
+        let declared = spec
+            .controller_services
+            .iter()
+            .filter(|s| {
+                s.scope
+                    .as_ref()
+                    .unwrap_or(&ControllerServiceScope::ProcessGroup)
+                    == scope
+            })
+            .collect::<Vec<_>>();
+        for cfg in &declared {
+            let config = component_config(&cfg.component, cfg, secrets, &ids).await?;
+            let current = existing
+                .iter()
+                .find(|s| s.component.name == cfg.component.name);
+            let (id, changed) = apply_service(api, group_id, cfg, &config, current).await?;
+            updated |= changed;
+            ids.insert(cfg.component.name.clone(), id);
+        }
+        for service in existing.iter().filter(|s| {
+            is_managed(&s.component)
+                && !declared
+                    .iter()
+                    .any(|d| d.component.name == s.component.name)
+        }) {
+            info!(
+                "Deleting controller service {} of {}",
+                &service.component.name, name
+            );
+            let service = disable_service(api, service).await?;
+            api.delete_controller_service(&service).await?;
+            updated = true;
+        }
+    }
+    Ok(updated)
+}
+
+/// Creates, updates and starts declared reporting tasks, tasks created by Kubefi
+/// and removed from the spec are deleted
+async fn apply_reporting_tasks(
+    api: &NiFiApi,
+    secrets: &Api<Secret>,
+    name: &str,
+    spec: &NiFiDeploymentSpec,
+) -> Result<bool> {
+    let ids = component_ids(&api.controller_services(None).await?);
+    let existing = api.reporting_tasks().await?;
+    let mut updated = false;
+    for cfg in &spec.reporting_tasks {
+        let config = ComponentConfig {
+            scheduling_period: cfg.scheduling_period.clone(),
+            ..component_config(&cfg.component, cfg, secrets, &ids).await?
+        };
+        let current = existing
+            .iter()
+            .find(|t| t.component.name == cfg.component.name);
+        updated |= apply_task(api, cfg, &config, current).await?;
+    }
+    for task in existing.iter().filter(|t| {
+        is_managed(&t.component)
+            && !spec
+                .reporting_tasks
+                .iter()
+                .any(|d| d.component.name == t.component.name)
+    }) {
+        info!(
+            "Deleting reporting task {} of {}",
+            &task.component.name, name
+        );
+        let task = if is_running(&task.component) {
+            api.set_reporting_task_state(task, "STOPPED").await?
+        } else {
+            task.clone()
+        };
+        api.delete_reporting_task(&task).await?;
+        updated = true;
+    }
+    Ok(updated)
+}
+
+/// Creates the task or updates it when its spec or plain properties changed, returns true when it changed
+async fn apply_task(
+    api: &NiFiApi,
+    cfg: &ReportingTaskCfg,
+    config: &ComponentConfig,
+    current: Option<&ConfiguredComponent>,
+) -> Result<bool> {
+    let running = cfg.running.unwrap_or(true);
+    let task = match current {
+        None => {
+            info!("Creating reporting task {}", &cfg.component.name);
+            let task = api.create_reporting_task(config).await?;
+            if running {
+                api.set_reporting_task_state(&task, "RUNNING").await?;
+            }
+            return Ok(true);
+        }
+        Some(task) if task.component.type_ != config.type_ => {
+            return Err(Error::from(InvalidSpec(format!(
+                "reporting task {} already exists with type {}",
+                &cfg.component.name, &task.component.type_
+            ))))
+        }
+        Some(task) => task,
+    };
+    if component_changed(&task.component, config) {
+        info!("Updating reporting task {}", &cfg.component.name);
+        let stopped = if is_running(&task.component) {
+            api.set_reporting_task_state(task, "STOPPED").await?
+        } else {
+            task.clone()
+        };
+        let task = api.update_reporting_task(&stopped, config).await?;
+        if running {
+            api.set_reporting_task_state(&task, "RUNNING").await?;
+        }
+        Ok(true)
+    } else if running != is_running(&task.component) {
+        let state = if running { "RUNNING" } else { "STOPPED" };
+        info!(
+            "Changing reporting task {} to {}",
+            &cfg.component.name, state
+        );
+        api.set_reporting_task_state(task, state)
+            .await
+            .map(|_| true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Creates the service or updates it when its spec or plain properties changed,
+/// returns its id and whether it changed
+async fn apply_service(
+    api: &NiFiApi,
+    group_id: Option<&str>,
+    cfg: &ControllerServiceCfg,
+    config: &ComponentConfig,
+    current: Option<&ConfiguredComponent>,
+) -> Result<(String, bool)> {
+    let enabled = cfg.enabled.unwrap_or(true);
+    let service = match current {
+        None => {
+            info!("Creating controller service {}", &cfg.component.name);
+            let service = api.create_controller_service(group_id, config).await?;
+            if enabled {
+                api.set_controller_service_state(&service, "ENABLED")
+                    .await?;
+            }
+            return Ok((service.id, true));
+        }
+        Some(service) if service.component.type_ != config.type_ => {
+            return Err(Error::from(InvalidSpec(format!(
+                "controller service {} already exists with type {}",
+                &cfg.component.name, &service.component.type_
+            ))))
+        }
+        Some(service) => service,
+    };
+    let running = is_enabled(&service.component);
+    if component_changed(&service.component, config) {
+        info!("Updating controller service {}", &cfg.component.name);
+        let disabled = disable_service(api, service).await?;
+        let service = api.update_controller_service(&disabled, config).await?;
+        if enabled {
+            api.set_controller_service_state(&service, "ENABLED")
+                .await?;
+        }
+        Ok((service.id, true))
+    } else if enabled != running {
+        let state = if enabled { "ENABLED" } else { "DISABLED" };
+        info!(
+            "Changing controller service {} to {}",
+            &cfg.component.name, state
+        );
+        api.set_controller_service_state(service, state)
+            .await
+            .map(|s| (s.id, true))
+    } else {
+        Ok((service.id.clone(), false))
+    }
+}
+
+/// Disables a service and waits until the state changes, so it can be updated or deleted
+async fn disable_service(
+    api: &NiFiApi,
+    service: &ConfiguredComponent,
+) -> Result<ConfiguredComponent> {
+    let mut current = service.clone();
+    if current.component.state.as_deref() == Some("DISABLED") {
+        return Ok(current);
+    }
+    if is_enabled(&current.component) {
+        current = api
+            .set_controller_service_state(&current, "DISABLED")
+            .await?;
+    }
+    for _ in 0..DISABLE_CHECKS {
+        if current.component.state.as_deref() == Some("DISABLED") {
+            return Ok(current);
+        }
+        delay_for(Duration::from_secs(2)).await;
+        current = api.controller_service(&service.id).await?;
+    }
+    Err(Error::msg(format!(
+        "controller service {} is not disabled, its referencing components may be running",
+        &service.component.name
+    )))
+}
+
+/// Configuration of a declared component with Secret values and ids of referenced services,
+/// its comments carry a hash of `spec` and of the resource versions of the Secrets
+async fn component_config<T: Serialize>(
+    cfg: &NiFiComponentCfg,
+    spec: &T,
+    secrets: &Api<Secret>,
+    ids: &BTreeMap<String, String>,
+) -> Result<ComponentConfig> {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_string(spec)?.as_bytes());
+    let mut properties = cfg
+        .properties
+        .iter()
+        .map(|(k, v)| (k.clone(), Some(v.clone())))
+        .collect::<BTreeMap<_, _>>();
+    for (property, secret_ref) in &cfg.secret_properties {
+        let (value, version) = secret_key_value(secrets, secret_ref).await?;
+        hasher.update(version.as_bytes());
+        properties.insert(property.clone(), Some(value));
+    }
+    for (property, service) in &cfg.service_properties {
+        let id = ids.get(service).ok_or_else(|| {
+            Error::from(InvalidSpec(format!(
+                "controller service {} referenced by {} is not found",
+                service, &cfg.name
+            )))
+        })?;
+        properties.insert(property.clone(), Some(id.clone()));
+    }
+    Ok(ComponentConfig {
+        id: None,
+        name: cfg.name.clone(),
+        type_: cfg.type_.clone(),
+        bundle: cfg.bundle.as_ref().map(|b| Bundle {
+            group: b.group.clone(),
+            artifact: b.artifact.clone(),
+            version: b.version.clone(),
+        }),
+        properties,
+        comments: Some(format!("{}, spec hash {}", MANAGED_BY, hex_digest(hasher))),
+        state: None,
+        scheduling_period: None,
+    })
+}
+
+fn component_ids(components: &[ConfiguredComponent]) -> BTreeMap<String, String> {
+    components
+        .iter()
+        .map(|c| (c.component.name.clone(), c.id.clone()))
+        .collect()
+}
+
+/// Value of a Secret key with the resource version of the Secret
+pub async fn secret_key_value(
+    secrets: &Api<Secret>,
+    secret_ref: &SecretKeyRef,
+) -> Result<(String, String)> {
+    let secret = secrets.get(&secret_ref.name).await?;
+    let value = secret
+        .data
+        .as_ref()
+        .and_then(|d| d.get(&secret_ref.key))
+        .ok_or_else(|| {
+            Error::from(InvalidSpec(format!(
+                "key {} is missing in Secret {}",
+                &secret_ref.key, &secret_ref.name
+            )))
+        })?;
+    Ok((
+        String::from_utf8(value.0.clone())?,
+        Meta::resource_ver(&secret).unwrap_or_default(),
+    ))
+}
+
+/// Sensitive values are masked by NiFi, changes of Secrets are detected by the hash in the comments
+fn component_changed(current: &ComponentConfig, expected: &ComponentConfig) -> bool {
+    current.comments != expected.comments
+        || expected
+            .properties
+            .iter()
+            .filter(|(k, _)| current.properties.get(*k) != Some(&Some("********".to_string())))
+            .any(|(k, v)| current.properties.get(k) != Some(v))
+}
+
+fn is_enabled(component: &ComponentConfig) -> bool {
+    matches!(
+        component.state.as_deref(),
+        Some("ENABLED") | Some("ENABLING")
+    )
+}
+
+fn is_running(component: &ComponentConfig) -> bool {
+    component.state.as_deref() == Some("RUNNING")
+}
+
+fn is_managed(component: &ComponentConfig) -> bool {
+    component
+        .comments
+        .as_ref()
+        .is_some_and(|c| c.starts_with(MANAGED_BY))
+}
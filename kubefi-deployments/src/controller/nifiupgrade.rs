@@ -0,0 +1,524 @@
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::{
+    ControllerRevision, RollingUpdateStatefulSetStrategy, StatefulSet,
+};
+use k8s_openapi::api::core::v1::{Pod, PodTemplateSpec};
+use k8s_openapi::chrono::Utc;
+use kube::api::{DeleteParams, PatchParams, PatchStrategy, PostParams};
+use kube::Client;
+use serde_json::Value;
+
+use crate::controller::get_api;
+use crate::controller::health::elapsed;
+use crate::controller::statefulset::{CANARY_FAILED_ANNOTATION, TEMPLATE_HASH_ANNOTATION};
+use crate::controller::zkupgrade::{annotation, is_ready, ordinal};
+use crate::controller::ControllerError::UpgradeFailed;
+use crate::crd::NiFiDeploymentSpec;
+use crate::nifi_api::{NiFiApi, NodeStatus};
+use crate::template::Template;
+
+const REVISION_LABEL: &str = "controller-revision-hash";
+const DEFAULT_CANARY_SOAK_SECONDS: u64 = 300;
+// RFC 3339 time the partition was lowered to the NiFi node which has to reconnect next
+const ROLLOUT_STEP_ANNOTATION: &str = "io.github.novakov-alexey/rollout-step-since";
+// seconds a NiFi node of a rollout step has to start and reconnect to the cluster
+const NODE_RECONNECT_SECONDS: u64 = 900;
+// template hash to roll back to if the canary of the rollout fails
+const CANARY_ROLLBACK_ANNOTATION: &str = "io.github.novakov-alexey/canary-rollback-hash";
+// RFC 3339 time the canary reconnected and its soak started
+const CANARY_SOAK_ANNOTATION: &str = "io.github.novakov-alexey/canary-soak-since";
+const CANARY_ANNOTATIONS: [&str; 2] = [CANARY_ROLLBACK_ANNOTATION, CANARY_SOAK_ANNOTATION];
+// state of a rollout in progress, kept when the set is replaced without starting a new rollout
+const ROLLOUT_ANNOTATIONS: [&str; 3] = [
+    ROLLOUT_STEP_ANNOTATION,
+    CANARY_ROLLBACK_ANNOTATION,
+    CANARY_SOAK_ANNOTATION,
+];
+// NiFi Pods left to restart one at a time, highest ordinal first
+const PENDING_RESTARTS_ANNOTATION: &str = "io.github.novakov-alexey/pending-restarts";
+// `<Pod>/<uid>` of the NiFi Pod deleted by the last restart
+const RESTARTING_ANNOTATION: &str = "io.github.novakov-alexey/restarting";
+// RFC 3339 time the Pod of the last restart was deleted
+const RESTART_SINCE_ANNOTATION: &str = "io.github.novakov-alexey/restart-since";
+const RESTART_ANNOTATIONS: [&str; 3] = [
+    PENDING_RESTARTS_ANNOTATION,
+    RESTARTING_ANNOTATION,
+    RESTART_SINCE_ANNOTATION,
+];
+
+/// Takes the next step of moving NiFi Pods to the current StatefulSet revision one at a time from
+/// the highest ordinal by lowering the RollingUpdate partition. The partition is lowered again only
+/// once the node at it reports CONNECTED via the REST API, Pods below the partition keep the old
+/// revision while it does not. Every apply takes one step, the deployment is applied again by
+/// `run_rollouts` while `rollout_pending` holds for its set.
+/// A rollout started `with_canary` soaks the first updated node before the others, the set is
+/// rolled back to its previous template if the cluster does not stay connected meanwhile
+pub async fn advance_nifi_rollout(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+) -> Result<()> {
+    let set = get_api::<StatefulSet>(client, ns).get(name).await?;
+    let status = set.status.clone().unwrap_or_default();
+    // the update revision is of an older generation until the set is observed
+    if status.observed_generation.is_none() || status.observed_generation < set.metadata.generation
+    {
+        return Ok(());
+    }
+    let replicas = spec.running_nifi_replicas() as i32;
+    let current = partition(&set).min(replicas);
+    let step_since = annotation(&set, ROLLOUT_STEP_ANNOTATION);
+    if replicas == 0 || current == replicas {
+        return match replicas {
+            0 => finish_rollout(client, ns, name, &set).await,
+            _ => step(client, ns, name, replicas - 1, &[]).await,
+        };
+    }
+    let pod_name = format!("{}-{}", name, current);
+    let rollback_hash = match current == replicas - 1 {
+        true => annotation(&set, CANARY_ROLLBACK_ANNOTATION),
+        false => None,
+    };
+    let soak_seconds = spec
+        .upgrade
+        .as_ref()
+        .and_then(|u| u.canary_soak_seconds)
+        .unwrap_or(DEFAULT_CANARY_SOAK_SECONDS);
+    if let (Some(hash), Some(since)) = (&rollback_hash, annotation(&set, CANARY_SOAK_ANNOTATION)) {
+        if !cluster_connected(client, template, name, ns, spec, &pod_name).await {
+            rollback_canary(client, ns, name, set, hash).await?;
+            return Err(Error::from(UpgradeFailed(format!(
+                "canary NiFi node {} did not stay connected, {} is rolled back to the previous template",
+                &pod_name, name
+            ))));
+        }
+        if !elapsed(&since, soak_seconds) {
+            return Ok(());
+        }
+        info!("Canary NiFi node {} stayed connected", &pod_name);
+        return match current {
+            0 => finish_rollout(client, ns, name, &set).await,
+            _ => step(client, ns, name, current - 1, &CANARY_ANNOTATIONS).await,
+        };
+    }
+    if !node_updated(
+        client,
+        template,
+        name,
+        ns,
+        spec,
+        &pod_name,
+        &status.update_revision,
+    )
+    .await
+    {
+        return match step_since {
+            Some(since) if elapsed(&since, NODE_RECONNECT_SECONDS) => {
+                Err(Error::from(UpgradeFailed(format!(
+                    "NiFi node {} did not reconnect to the cluster, rollout of {} is stopped",
+                    pod_name, name
+                ))))
+            }
+            Some(_) => {
+                debug!("Waiting for NiFi node {} to rejoin the cluster", &pod_name);
+                Ok(())
+            }
+            // a partition lowered before the step time was recorded
+            None => step(client, ns, name, current, &[]).await,
+        };
+    }
+    debug!("NiFi node {} is connected", &pod_name);
+    if rollback_hash.is_some() {
+        info!(
+            "Soaking canary NiFi node {} for {} seconds",
+            &pod_name, soak_seconds
+        );
+        let patch = json!({
+            "metadata": { "annotations": { CANARY_SOAK_ANNOTATION: Utc::now().to_rfc3339() } }
+        });
+        return patch_set(client, ns, name, &patch).await;
+    }
+    match current {
+        0 => finish_rollout(client, ns, name, &set).await,
+        _ => step(client, ns, name, current - 1, &[]).await,
+    }
+}
+
+/// Whether a NiFi StatefulSet has Pods left to update or the last updated one to reconnect
+pub fn rollout_pending(set: &StatefulSet) -> bool {
+    partition(set) > 0
+        || ROLLOUT_ANNOTATIONS
+            .iter()
+            .any(|key| annotation(set, key).is_some())
+}
+
+/// Whether a NiFi StatefulSet has queued restarts or a restarted node to reconnect
+pub fn restarts_pending(set: &StatefulSet) -> bool {
+    RESTART_ANNOTATIONS
+        .iter()
+        .any(|key| annotation(set, key).is_some())
+}
+
+/// Keeps the rollout and restart state of the existing set in its replacement which starts no
+/// new rollout, a new rollout restarts every node anyway
+pub fn with_rollout_state(set: &StatefulSet, new_set: &mut StatefulSet) {
+    for key in ROLLOUT_ANNOTATIONS.iter().chain(RESTART_ANNOTATIONS.iter()) {
+        if let Some(value) = annotation(set, key) {
+            new_set
+                .metadata
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Starts the rollout of the replacement with a canary, the existing set is the one to roll back to
+pub fn with_canary(set: &StatefulSet, new_set: &mut StatefulSet) {
+    // a canary replaced by another spec change still rolls back to the template before both
+    let rollback_hash = annotation(set, CANARY_ROLLBACK_ANNOTATION)
+        .or_else(|| annotation(set, TEMPLATE_HASH_ANNOTATION));
+    if let Some(hash) = rollback_hash {
+        new_set
+            .metadata
+            .annotations
+            .get_or_insert_with(Default::default)
+            .insert(CANARY_ROLLBACK_ANNOTATION.to_string(), hash);
+    }
+}
+
+/// Queues restarts of NiFi nodes, e.g. to load renewed keystores, which `advance_nifi_restarts`
+/// takes one at a time. Nodes of a set which does not exist yet start with the current Secrets
+pub async fn queue_nifi_restarts(
+    client: &Client,
+    name: &str,
+    ns: &str,
+    pods: &[String],
+) -> Result<()> {
+    let set = match get_api::<StatefulSet>(client, ns).get(name).await {
+        Ok(set) => set,
+        Err(_) => return Ok(()),
+    };
+    let mut queued = pending_restarts(&set)
+        .into_iter()
+        .chain(pods.iter().cloned())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    queued.sort_by_key(|pod| Reverse(ordinal(pod, name)));
+    debug!("Queued restarts of NiFi nodes: {:?}", &queued);
+    let patch = json!({
+        "metadata": { "annotations": { PENDING_RESTARTS_ANNOTATION: queued.join(",") } }
+    });
+    patch_set(client, ns, name, &patch).await
+}
+
+/// Restarts the next queued NiFi node once the previously restarted one reports CONNECTED again,
+/// one node per apply while no rollout is in progress. Pods which do not exist are skipped, the
+/// restarts stop while a restarted node does not reconnect in time
+pub async fn advance_nifi_restarts(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+) -> Result<()> {
+    let set = get_api::<StatefulSet>(client, ns).get(name).await?;
+    if rollout_pending(&set) || !restarts_pending(&set) {
+        return Ok(());
+    }
+    let api = get_api::<Pod>(client, ns);
+    if let Some(restarting) = annotation(&set, RESTARTING_ANNOTATION) {
+        let (pod_name, uid) = restarting.split_once('/').unwrap_or((&restarting, ""));
+        let current_uid = api.get(pod_name).await.ok().and_then(|p| p.metadata.uid);
+        // the StatefulSet creates the Pod again with the same name
+        if current_uid.as_deref() == Some(uid) {
+            api.delete(pod_name, &DeleteParams::default()).await?;
+            return Ok(());
+        }
+        if !node_updated(client, template, name, ns, spec, pod_name, &None).await {
+            return match annotation(&set, RESTART_SINCE_ANNOTATION) {
+                Some(since) if elapsed(&since, NODE_RECONNECT_SECONDS) => {
+                    Err(Error::from(UpgradeFailed(format!(
+                        "NiFi node {} did not reconnect to the cluster after its restart, restarts of {} are stopped",
+                        pod_name, name
+                    ))))
+                }
+                _ => Ok(()),
+            };
+        }
+        debug!("Restarted NiFi node {} is connected", pod_name);
+    }
+    let mut annotations = RESTART_ANNOTATIONS
+        .iter()
+        .map(|key| (key.to_string(), Value::Null))
+        .collect::<serde_json::Map<_, _>>();
+    let mut pending = pending_restarts(&set).into_iter();
+    while let Some(pod_name) = pending.next() {
+        let uid = match api.get(&pod_name).await {
+            Ok(pod) => pod.metadata.uid.unwrap_or_default(),
+            Err(_) => continue,
+        };
+        let remaining = pending.by_ref().collect::<Vec<_>>();
+        if !remaining.is_empty() {
+            annotations.insert(
+                PENDING_RESTARTS_ANNOTATION.to_string(),
+                Value::String(remaining.join(",")),
+            );
+        }
+        annotations.insert(
+            RESTARTING_ANNOTATION.to_string(),
+            Value::String(format!("{}/{}", &pod_name, uid)),
+        );
+        annotations.insert(
+            RESTART_SINCE_ANNOTATION.to_string(),
+            Value::String(Utc::now().to_rfc3339()),
+        );
+        patch_set(
+            client,
+            ns,
+            name,
+            &json!({ "metadata": { "annotations": annotations } }),
+        )
+        .await?;
+        info!("Restarting NiFi node {}", &pod_name);
+        return api
+            .delete(&pod_name, &DeleteParams::default())
+            .await
+            .map(|_| ())
+            .map_err(Error::from);
+    }
+    info!("Queued restarts of NiFi nodes of {} are done", name);
+    patch_set(
+        client,
+        ns,
+        name,
+        &json!({ "metadata": { "annotations": annotations } }),
+    )
+    .await
+}
+
+fn pending_restarts(set: &StatefulSet) -> Vec<String> {
+    annotation(set, PENDING_RESTARTS_ANNOTATION)
+        .map(|pods| {
+            pods.split(',')
+                .filter(|p| !p.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether every node is connected, asked via the canary
+async fn cluster_connected(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+    pod_name: &str,
+) -> bool {
+    let cluster = match NiFiApi::connect(client, template, name, ns, spec, Some(pod_name)).await {
+        Ok(api) => api.cluster().await,
+        Err(e) => Err(e),
+    };
+    match cluster {
+        Ok(cluster)
+            if cluster
+                .nodes
+                .iter()
+                .all(|n| n.status == NodeStatus::Connected) =>
+        {
+            true
+        }
+        Ok(cluster) => {
+            warn!(
+                "NiFi cluster {} is degraded during canary soak: {:?}",
+                name, cluster.nodes
+            );
+            false
+        }
+        Err(e) => {
+            warn!("Canary NiFi node {} is not available: {}", pod_name, e);
+            false
+        }
+    }
+}
+
+/// Restores the Pod template of the current revision, which Pods below the partition never left,
+/// the template hash of the rolled back spec is recorded as failed
+async fn rollback_canary(
+    client: &Client,
+    ns: &str,
+    name: &str,
+    mut set: StatefulSet,
+    rollback_hash: &str,
+) -> Result<()> {
+    let revision = set
+        .status
+        .as_ref()
+        .and_then(|s| s.current_revision.clone())
+        .ok_or_else(|| {
+            Error::from(UpgradeFailed(format!(
+                "{} has no current revision to roll back to",
+                name
+            )))
+        })?;
+    let data = get_api::<ControllerRevision>(client, ns)
+        .get(&revision)
+        .await?
+        .data
+        .map(|d| d.0)
+        .unwrap_or_default();
+    // a revision keeps the Pod template as a patch of the set spec
+    let template: PodTemplateSpec = serde_json::from_value(data["spec"]["template"].clone())?;
+    let annotations = set
+        .metadata
+        .annotations
+        .get_or_insert_with(Default::default);
+    if let Some(hash) = annotations.insert(
+        TEMPLATE_HASH_ANNOTATION.to_string(),
+        rollback_hash.to_string(),
+    ) {
+        annotations.insert(CANARY_FAILED_ANNOTATION.to_string(), hash);
+    }
+    for key in ROLLOUT_ANNOTATIONS.iter() {
+        annotations.remove(*key);
+    }
+    if let Some(spec) = set.spec.as_mut() {
+        spec.template = template;
+    }
+    // the canary moves back to the current revision as the Pods below it were never updated
+    with_partition(&mut set, 0);
+    warn!("Rolling back NiFi statefulset {}", name);
+    get_api::<StatefulSet>(client, ns)
+        .replace(name, &PostParams::default(), &set)
+        .await
+        .map(|_| ())
+        .map_err(Error::from)
+}
+
+/// RollingUpdate partition of a StatefulSet, 0 when all Pods are updated
+pub fn partition(set: &StatefulSet) -> i32 {
+    set.spec
+        .as_ref()
+        .and_then(|s| s.update_strategy.as_ref())
+        .and_then(|s| s.rolling_update.as_ref())
+        .and_then(|r| r.partition)
+        .unwrap_or(0)
+}
+
+pub fn with_partition(set: &mut StatefulSet, partition: i32) {
+    if let Some(strategy) = set.spec.as_mut().and_then(|s| s.update_strategy.as_mut()) {
+        strategy.rolling_update = Some(RollingUpdateStatefulSetStrategy {
+            partition: Some(partition),
+        });
+    }
+}
+
+/// Lowers the partition to the next node to update, its reconnect deadline starts now. The
+/// `cleared` annotations are removed
+async fn step(
+    client: &Client,
+    ns: &str,
+    name: &str,
+    partition: i32,
+    cleared: &[&str],
+) -> Result<()> {
+    info!(
+        "Rolling out NiFi statefulset {} to ordinal {}",
+        name, partition
+    );
+    let mut annotations = serde_json::Map::new();
+    for key in cleared {
+        annotations.insert(key.to_string(), Value::Null);
+    }
+    annotations.insert(
+        ROLLOUT_STEP_ANNOTATION.to_string(),
+        Value::String(Utc::now().to_rfc3339()),
+    );
+    let patch = json!({
+        "metadata": { "annotations": annotations },
+        "spec": { "updateStrategy": { "rollingUpdate": { "partition": partition } } }
+    });
+    patch_set(client, ns, name, &patch).await
+}
+
+async fn finish_rollout(client: &Client, ns: &str, name: &str, set: &StatefulSet) -> Result<()> {
+    if !rollout_pending(set) {
+        return Ok(());
+    }
+    info!("NiFi statefulset {} is rolled out", name);
+    let annotations = ROLLOUT_ANNOTATIONS
+        .iter()
+        .map(|key| (key.to_string(), Value::Null))
+        .collect::<serde_json::Map<_, _>>();
+    let patch = json!({ "metadata": { "annotations": annotations } });
+    patch_set(client, ns, name, &patch).await
+}
+
+async fn patch_set(client: &Client, ns: &str, name: &str, patch: &Value) -> Result<()> {
+    let params = PatchParams {
+        patch_strategy: PatchStrategy::Merge,
+        ..PatchParams::default()
+    };
+    get_api::<StatefulSet>(client, ns)
+        .patch(name, &params, serde_json::to_vec(patch)?)
+        .await
+        .map(|_| ())
+        .map_err(Error::from)
+}
+
+/// Whether the Pod of a node is Ready at the revision, if any, and the node is connected
+async fn node_updated(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+    pod_name: &str,
+    revision: &Option<String>,
+) -> bool {
+    let updated = match get_api::<Pod>(client, ns).get(pod_name).await {
+        Ok(pod) => {
+            let pod_revision = pod
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|l| l.get(REVISION_LABEL));
+            is_ready(&pod) && (revision.is_none() || pod_revision == revision.as_ref())
+        }
+        Err(_) => false,
+    };
+    updated && node_connected(client, template, name, ns, spec, pod_name).await
+}
+
+/// Asks the node itself, as its own login token is valid there
+async fn node_connected(
+    client: &Client,
+    template: &Template,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+    pod_name: &str,
+) -> bool {
+    let status = match NiFiApi::connect(client, template, name, ns, spec, Some(pod_name)).await {
+        Ok(api) => api
+            .cluster()
+            .await
+            .map(|c| c.node(pod_name).map(|n| n.status.clone())),
+        Err(e) => Err(e),
+    };
+    match status {
+        Ok(status) => status == Some(NodeStatus::Connected),
+        Err(e) => {
+            debug!("NiFi node {} is not available yet: {}", pod_name, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::Secret;
+use kube::Client;
+use openssl::sha::Sha256;
+
+use crate::controller::nificomponents::secret_key_value;
+use crate::controller::ControllerError::{InvalidSpec, MissingProperty};
+use crate::controller::{get_api, hex_digest, NiFiController, ReplaceResourceStatus};
+use crate::crd::{
+    FlowDeployment, NiFiDeployment, NiFiParameterContext, NiFiParameterContextStatus, ParameterCfg,
+};
+use crate::nifi_api::{
+    NiFiApi, Parameter, ParameterContext, ParameterContextComponent, ParameterEntity,
+};
+use crate::template::Template;
+
+pub type ReplaceParameterContextStatus = ReplaceResourceStatus<NiFiParameterContextStatus>;
+
+pub struct ParameterContextController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiController {
+    /// Creates or updates the parameter context in NiFi and binds process groups to it,
+    /// the status is replaced only when it is different
+    pub async fn on_apply_parameter_context(
+        &self,
+        pc: NiFiParameterContext,
+    ) -> Result<Option<ReplaceParameterContextStatus>> {
+        let (name, ns) = read_parameter_context_meta(&pc)?;
+        let status = match self
+            .parameter_context_controller
+            .apply_context(&pc, &name, &ns)
+            .await
+        {
+            Ok((context_id, parameters_hash)) => NiFiParameterContextStatus {
+                context_id: Some(context_id),
+                parameters_hash: Some(parameters_hash),
+                error_msg: "".to_string(),
+            },
+            Err(e) => NiFiParameterContextStatus {
+                error_msg: e.to_string(),
+                ..pc.status.clone().unwrap_or_default()
+            },
+        };
+        if pc.status.as_ref() == Some(&status) {
+            return Ok(None);
+        }
+        Ok(Some(ReplaceParameterContextStatus { name, ns, status }))
+    }
+
+    /// The context is left in NiFi, as bound process groups may still reference its parameters
+    pub async fn on_delete_parameter_context(&self, pc: NiFiParameterContext) -> Result<()> {
+        let (name, _) = read_parameter_context_meta(&pc)?;
+        if let Some(id) = pc.status.and_then(|s| s.context_id) {
+            info!(
+                "NiFiParameterContext {} is deleted, parameter context {} is kept in {}",
+                name, id, &pc.spec.deployment_ref
+            );
+        }
+        Ok(())
+    }
+}
+
+impl ParameterContextController {
+    async fn apply_context(
+        &self,
+        pc: &NiFiParameterContext,
+        name: &str,
+        ns: &str,
+    ) -> Result<(String, String)> {
+        let spec = &pc.spec;
+        let d = get_api::<NiFiDeployment>(&self.client, ns)
+            .get(&spec.deployment_ref)
+            .await?;
+        let pod_name = format!("{}-0", &spec.deployment_ref);
+        let api = NiFiApi::connect(
+            &self.client,
+            &self.template,
+            &spec.deployment_ref,
+            ns,
+            &d.spec,
+            Some(&pod_name),
+        )
+        .await?;
+        let (parameters, hash) = self.parameters(&spec.parameters, ns).await?;
+        let context_name = spec
+            .context_name
+            .clone()
+            .unwrap_or_else(|| name.to_string());
+        let mut component = ParameterContextComponent {
+            id: None,
+            name: context_name.clone(),
+            description: spec.description.clone(),
+            parameters,
+        };
+        let contexts = api.parameter_contexts().await?;
+        let context_id = match contexts.iter().find(|c| c.component.name == context_name) {
+            Some(context) => {
+                let applied_hash = pc.status.as_ref().and_then(|s| s.parameters_hash.as_ref());
+                let removed = removed_parameters(context, &component.parameters);
+                if applied_hash != Some(&hash)
+                    || !removed.is_empty()
+                    || parameters_changed(context, &component)
+                {
+                    info!(
+                        "Updating parameter context {} of {}",
+                        &context_name, &spec.deployment_ref
+                    );
+                    component.parameters.extend(removed);
+                    let request = api.update_parameter_context(context, &component).await?;
+                    let path = format!(
+                        "/parameter-contexts/{}/update-requests/{}",
+                        &context.id, &request.request_id
+                    );
+                    let change = format!("update of parameter context {}", &context_name);
+                    api.wait_for_update(&path, request, &change).await?;
+                }
+                context.id.clone()
+            }
+            None => {
+                info!(
+                    "Creating parameter context {} of {}",
+                    &context_name, &spec.deployment_ref
+                );
+                api.create_parameter_context(&component).await?.id
+            }
+        };
+        self.bind_groups(&api, pc, ns, &context_id).await?;
+        Ok((context_id, hash))
+    }
+
+    /// Parameters with their values read from Secrets and a hash of the spec
+    /// with resource versions of the Secrets instead of the sensitive values
+    async fn parameters(
+        &self,
+        parameters: &[ParameterCfg],
+        ns: &str,
+    ) -> Result<(Vec<ParameterEntity>, String)> {
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let mut hasher = Sha256::new();
+        let mut entities = Vec::new();
+        for p in parameters {
+            hasher.update(serde_json::to_string(p)?.as_bytes());
+            let (value, sensitive) = match (&p.value, &p.value_from) {
+                (Some(value), None) => (value.clone(), p.sensitive.unwrap_or(false)),
+                (None, Some(secret_ref)) => {
+                    let (value, version) = secret_key_value(&secrets, secret_ref).await?;
+                    hasher.update(version.as_bytes());
+                    (value, p.sensitive.unwrap_or(true))
+                }
+                _ => {
+                    return Err(Error::from(InvalidSpec(format!(
+                        "parameter {} must have either value or valueFrom",
+                        &p.name
+                    ))))
+                }
+            };
+            entities.push(ParameterEntity {
+                parameter: Parameter {
+                    name: p.name.clone(),
+                    sensitive,
+                    value: Some(value),
+                    description: p.description.clone(),
+                },
+            });
+        }
+        Ok((entities, hex_digest(hasher)))
+    }
+
+    async fn bind_groups(
+        &self,
+        api: &NiFiApi,
+        pc: &NiFiParameterContext,
+        ns: &str,
+        context_id: &str,
+    ) -> Result<()> {
+        let flows = get_api::<FlowDeployment>(&self.client, ns);
+        let mut group_ids = pc.spec.process_group_ids.clone();
+        for flow_name in &pc.spec.flow_deployments {
+            let flow = flows.get(flow_name).await?;
+            match flow.status.and_then(|s| s.process_group_id) {
+                Some(id) => group_ids.push(id),
+                None => {
+                    return Err(Error::msg(format!(
+                        "FlowDeployment {} has no process group yet",
+                        flow_name
+                    )))
+                }
+            }
+        }
+        for id in group_ids {
+            let group = api.process_group(&id).await?;
+            let bound = group
+                .component
+                .parameter_context
+                .as_ref()
+                .map(|c| c.id.as_str());
+            if bound != Some(context_id) {
+                info!(
+                    "Binding process group {} to parameter context {}",
+                    &id, context_id
+                );
+                api.set_parameter_context(&group, context_id).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of the context which are not in the spec anymore, sent without values to remove them
+fn removed_parameters(
+    context: &ParameterContext,
+    desired: &[ParameterEntity],
+) -> Vec<ParameterEntity> {
+    context
+        .component
+        .parameters
+        .iter()
+        .filter(|p| !desired.iter().any(|d| d.parameter.name == p.parameter.name))
+        .map(|p| ParameterEntity {
+            parameter: Parameter {
+                value: None,
+                description: None,
+                ..p.parameter.clone()
+            },
+        })
+        .collect()
+}
+
+/// Compares all but sensitive values, those are masked by NiFi
+fn parameters_changed(context: &ParameterContext, desired: &ParameterContextComponent) -> bool {
+    context.component.description != desired.description
+        || desired.parameters.iter().any(|d| {
+            let current = context
+                .component
+                .parameters
+                .iter()
+                .find(|p| p.parameter.name == d.parameter.name);
+            match current {
+                Some(p) if d.parameter.sensitive => !p.parameter.sensitive,
+                Some(p) => p.parameter != d.parameter,
+                None => true,
+            }
+        })
+}
+
+fn read_parameter_context_meta(pc: &NiFiParameterContext) -> Result<(String, String)> {
+    let missing =
+        |property: &str| Error::from(MissingProperty(property.to_string(), pc.kind.clone()));
+    let name = pc.metadata.name.clone().ok_or_else(|| missing("name"))?;
+    let ns = pc
+        .metadata
+        .namespace
+        .clone()
+        .ok_or_else(|| missing("namespace"))?;
+    Ok((name, ns))
+}
@@ -0,0 +1,60 @@
+use std::rc::Rc;
+
+use anyhow::Result;
+use k8s_openapi::api::policy::v1beta1::PodDisruptionBudget;
+use kube::Client;
+
+use crate::controller::recreate_on_change;
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::Template;
+
+pub struct PdbController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl PdbController {
+    pub async fn handle_pdbs(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        // PDB spec is immutable before Kubernetes 1.15, so it is recreated on change
+        let nifi_pdb = recreate_on_change::<PodDisruptionBudget, _, _>(
+            &self.client,
+            name,
+            name,
+            ns,
+            |name| self.template.nifi_pdb(name, spec),
+            max_unavailable_changed,
+        );
+        let zk_pdb = self.handle_zk_pdb(name, ns, spec);
+        let (nifi_pdb, zk_pdb) = futures::future::join(nifi_pdb, zk_pdb).await;
+        Ok(nifi_pdb? || zk_pdb?)
+    }
+
+    pub async fn handle_zk_pdb(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let zk_pdb_name = format!("{}-zookeeper", &name);
+        recreate_on_change::<PodDisruptionBudget, _, _>(
+            &self.client,
+            &zk_pdb_name,
+            name,
+            ns,
+            |name| self.template.zk_pdb(name, spec),
+            max_unavailable_changed,
+        )
+        .await
+    }
+}
+
+fn max_unavailable_changed(current: &PodDisruptionBudget, expected: &PodDisruptionBudget) -> bool {
+    let max_unavailable =
+        |pdb: &PodDisruptionBudget| pdb.spec.as_ref().and_then(|s| s.max_unavailable.clone());
+    max_unavailable(current) != max_unavailable(expected)
+}
@@ -0,0 +1,127 @@
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use kube::api::{PatchParams, PatchStrategy};
+use kube::Client;
+use serde_json::Value;
+use tokio::time::{delay_for, Duration};
+
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{from_yaml, get_api, get_or_create};
+use crate::crd::NiFiDeploymentSpec;
+use crate::discovery::ClusterApis;
+use crate::resources::PravegaZookeeperCluster;
+use crate::template::Template;
+
+use super::either::Either::{Left, Right};
+
+const READY_CHECKS: u32 = 60;
+
+pub struct PravegaController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+    pub apis: ClusterApis,
+}
+
+impl PravegaController {
+    /// Creates or patches the ZookeeperCluster of the pravega zookeeper-operator and waits
+    /// until its servers are ready, so NiFi nodes do not start against a missing ensemble
+    pub async fn handle_pravega_zk(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        if !spec.zk.pravega() {
+            return Ok(false);
+        }
+        if !self.apis.pravega_zk {
+            return Err(Error::from(InvalidSpec(
+                "spec.zk.operator is Pravega, but zookeeper.pravega.io/v1beta1 API is not available"
+                    .to_string(),
+            )));
+        }
+        let zk_name = format!("{}-zookeeper", &name);
+        let get_yaml = |name: &str| self.template.pravega_zk_cluster(name, spec);
+        let updated = match get_or_create::<PravegaZookeeperCluster, _>(
+            &self.client,
+            &zk_name,
+            name,
+            ns,
+            get_yaml,
+        )
+        .await?
+        {
+            Left(Some(current)) => match get_yaml(name)? {
+                Some(yaml) => {
+                    let expected = from_yaml::<PravegaZookeeperCluster>(&yaml)?;
+                    if contains(&current.spec, &expected.spec) {
+                        false
+                    } else {
+                        self.patch_spec(&zk_name, ns, &expected.spec).await?;
+                        true
+                    }
+                }
+                None => false,
+            },
+            Right(Some(_)) => true,
+            _ => false,
+        };
+        self.wait_ready(&zk_name, ns, spec.zk.replicas as i32)
+            .await?;
+        Ok(updated)
+    }
+
+    async fn patch_spec(&self, name: &str, ns: &str, spec: &Value) -> Result<()> {
+        let patch = json!({ "spec": spec });
+        debug!(
+            "Patching pravega ZookeeperCluster {} with: {}",
+            name, &patch
+        );
+        let params = PatchParams {
+            patch_strategy: PatchStrategy::Merge,
+            ..PatchParams::default()
+        };
+        let api = get_api::<PravegaZookeeperCluster>(&self.client, ns);
+        api.patch(name, &params, serde_json::to_vec(&patch)?)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn wait_ready(&self, name: &str, ns: &str, replicas: i32) -> Result<()> {
+        let api = get_api::<PravegaZookeeperCluster>(&self.client, ns);
+        for _ in 0..READY_CHECKS {
+            let ready = api
+                .get(name)
+                .await?
+                .status
+                .and_then(|s| s.ready_replicas)
+                .unwrap_or(0);
+            if ready >= replicas {
+                return Ok(());
+            }
+            debug!(
+                "Waiting for pravega ZookeeperCluster {}: {}/{} servers are ready",
+                name, ready, replicas
+            );
+            delay_for(Duration::from_secs(2)).await;
+        }
+        // NiFi init container keeps waiting for ZooKeeper clients port
+        warn!(
+            "pravega ZookeeperCluster {} is not ready after {} checks, continuing",
+            name, READY_CHECKS
+        );
+        Ok(())
+    }
+}
+
+/// Every field rendered by Kubefi has the same value in the current spec, the operator adds its defaults
+fn contains(current: &Value, expected: &Value) -> bool {
+    match (current, expected) {
+        (Value::Object(c), Value::Object(e)) => e
+            .iter()
+            .all(|(k, v)| c.get(k).is_some_and(|cv| contains(cv, v))),
+        (c, e) => c == e,
+    }
+}
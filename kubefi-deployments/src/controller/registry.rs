@@ -0,0 +1,162 @@
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::networking::v1beta1::Ingress;
+use kube::api::{DeleteParams, PostParams};
+use kube::Client;
+
+use crate::controller::service::{ingress_changed, ingress_v1_changed, service_changed};
+use crate::controller::statefulset::{pod_template_changed, with_template_hash};
+use crate::controller::{from_yaml, get_api, get_or_create_convert, recreate_on_change};
+use crate::crd::NiFiDeploymentSpec;
+use crate::discovery::ClusterApis;
+use crate::nifi_api::NiFiApi;
+use crate::resources::IngressV1;
+use crate::template::Template;
+
+use super::either::Either::{Left, Right};
+
+pub struct RegistryController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+    pub apis: ClusterApis,
+}
+
+impl RegistryController {
+    /// NiFi Registry StatefulSet, Service and Ingress of `spec.registry`,
+    /// all of them are deleted once the registry is disabled
+    pub async fn handle_registry(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let set_updated = self.handle_registry_set(name, ns, spec).await?;
+        let registry_name = registry_name(name);
+        let svc_updated = recreate_on_change::<Service, _, _>(
+            &self.client,
+            &registry_name,
+            name,
+            ns,
+            |name| self.template.registry_service(name, spec),
+            service_changed,
+        )
+        .await?;
+        let ingress_name = format!("{}-ingress", &registry_name);
+        let ingress_updated = if self.apis.ingress_v1 {
+            recreate_on_change::<IngressV1, _, _>(
+                &self.client,
+                &ingress_name,
+                name,
+                ns,
+                |name| self.template.registry_ingress_v1(name, spec),
+                ingress_v1_changed,
+            )
+            .await?
+        } else {
+            recreate_on_change::<Ingress, _, _>(
+                &self.client,
+                &ingress_name,
+                name,
+                ns,
+                |name| self.template.registry_ingress(name, spec),
+                ingress_changed,
+            )
+            .await?
+        };
+        Ok(set_updated || svc_updated || ingress_updated)
+    }
+
+    /// The single Registry Pod is restarted by the RollingUpdate strategy on any template change
+    async fn handle_registry_set(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let set_name = registry_name(name);
+        let get_yaml = |name: &str| self.template.registry_statefulset(name, spec);
+        let set = get_or_create_convert::<StatefulSet, _, _>(
+            &self.client,
+            &set_name,
+            name,
+            ns,
+            get_yaml,
+            with_template_hash,
+        )
+        .await?;
+        let api = get_api::<StatefulSet>(&self.client, ns);
+        match (set, get_yaml(name)?) {
+            (Left(Some(current)), Some(yaml)) => {
+                let mut expected = with_template_hash(from_yaml::<StatefulSet>(&yaml)?)?;
+                if !pod_template_changed(&self.client, ns, &current, &expected).await? {
+                    return Ok(false);
+                }
+                info!("Updating NiFi Registry statefulset {}", &set_name);
+                expected.metadata.resource_version = current.metadata.resource_version;
+                api.replace(&set_name, &PostParams::default(), &expected)
+                    .await
+                    .map(|_| true)
+                    .map_err(Error::from)
+            }
+            (Left(Some(_)), None) => {
+                info!("NiFi Registry is disabled, deleting {}", &set_name);
+                api.delete(&set_name, &DeleteParams::default()).await?;
+                Ok(true)
+            }
+            (Right(Some(_)), _) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    /// Creates the Registry client in NiFi or updates its URL, so versioned flows work
+    /// without setting the client up in the UI. Returns true when the client changed
+    pub async fn register_client(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let (client_name, url) = match self.template.registry_client(name, ns, spec) {
+            Some(client) => client,
+            None => return Ok(false),
+        };
+        let pod_name = format!("{}-0", name);
+        let api = NiFiApi::connect(
+            &self.client,
+            &self.template,
+            name,
+            ns,
+            spec,
+            Some(&pod_name),
+        )
+        .await?;
+        let clients = api.registry_clients().await?;
+        match clients.iter().find(|c| c.component.name == client_name) {
+            Some(client) if client.component.uri == url => Ok(false),
+            Some(client) => {
+                info!(
+                    "Updating NiFi Registry client {} of {} to {}",
+                    &client_name, name, &url
+                );
+                api.update_registry_client(client, &url).await.map(|_| true)
+            }
+            None => {
+                info!(
+                    "Creating NiFi Registry client {} of {} for {}",
+                    &client_name, name, &url
+                );
+                api.create_registry_client(&client_name, &url)
+                    .await
+                    .map(|_| true)
+            }
+        }
+    }
+}
+
+/// Name of the Registry StatefulSet and Service of a deployment
+pub fn registry_name(name: &str) -> String {
+    format!("{}-registry", name)
+}
@@ -0,0 +1,156 @@
+use anyhow::{Error, Result};
+
+use crate::controller::version::versioned_template;
+use crate::controller::workload::config_hash;
+use crate::controller::ControllerError::InvalidSpec;
+use crate::crd::NiFiDeploymentSpec;
+use crate::discovery::ClusterApis;
+use crate::template::Template;
+
+/// Manifests rendered from the templates for a NiFiDeployment in the order the controller applies
+/// them, by template name, with the template set of the NiFi version. Secrets, certificates and
+/// backup Pods created from the cluster state are not included
+pub fn render_manifests(
+    template: &Template,
+    apis: &ClusterApis,
+    name: &str,
+    ns: &str,
+    spec: &NiFiDeploymentSpec,
+) -> Result<Vec<(&'static str, String)>> {
+    let versioned = versioned_template(template, spec);
+    let template = versioned.as_deref().unwrap_or(template);
+    let mut manifests = vec![
+        ("nifi-configmap", template.nifi_configmap(name, ns, spec)?),
+        ("zk-configmap", template.zk_configmap(name, spec)?),
+        ("nifi-service", template.nifi_service(name, spec)?),
+        (
+            "nifi-headless-service",
+            template.nifi_headless_service(name, spec)?,
+        ),
+        ("zk-service", template.zk_service(name, spec)?),
+        (
+            "zk-headless-service",
+            template.zk_headless_service(name, spec)?,
+        ),
+        (
+            "zk-metrics-service",
+            template.zk_metrics_service(name, spec)?,
+        ),
+    ];
+    for ordinal in 0..spec.nifi_replicas {
+        manifests.push((
+            "nifi-pod-service",
+            template.nifi_pod_service(name, ordinal, spec)?,
+        ));
+    }
+    if apis.ingress_v1 {
+        manifests.push(("ingress-v1", template.ingress_v1(name, spec)?));
+    } else {
+        manifests.push(("ingress", template.ingress(name, spec)?));
+    }
+    manifests.extend(vec![
+        (
+            "route",
+            available(
+                apis.route,
+                "route.openshift.io/v1",
+                template.route(name, spec)?,
+            )?,
+        ),
+        (
+            "httproute",
+            available(
+                apis.http_route,
+                "gateway.networking.k8s.io/v1",
+                template.http_route(name, spec)?,
+            )?,
+        ),
+        (
+            "nifi-serviceaccount",
+            template.nifi_service_account(name, spec)?,
+        ),
+        ("nifi-role", template.nifi_role(name, spec)?),
+        ("nifi-rolebinding", template.nifi_role_binding(name, spec)?),
+        ("zk-statefulset", template.zk_statefulset(name, spec)?),
+        (
+            "pravega-zookeepercluster",
+            available(
+                apis.pravega_zk,
+                "zookeeper.pravega.io/v1beta1",
+                template.pravega_zk_cluster(name, spec)?,
+            )?,
+        ),
+        ("nifi-statefulset", template.nifi_statefulset(name, spec)?),
+        ("nifi-pdb", template.nifi_pdb(name, spec)?),
+        ("zk-pdb", template.zk_pdb(name, spec)?),
+        (
+            "nifi-networkpolicy",
+            template.nifi_network_policy(name, spec)?,
+        ),
+        ("zk-networkpolicy", template.zk_network_policy(name, spec)?),
+        (
+            "virtualservice",
+            available(
+                apis.istio,
+                "networking.istio.io/v1beta1",
+                template.virtual_service(name, spec)?,
+            )?,
+        ),
+        (
+            "destinationrule",
+            available(
+                apis.istio,
+                "networking.istio.io/v1beta1",
+                template.destination_rule(name, spec)?,
+            )?,
+        ),
+        (
+            "nifi-registry-statefulset",
+            template.registry_statefulset(name, spec)?,
+        ),
+        (
+            "nifi-registry-service",
+            template.registry_service(name, spec)?,
+        ),
+    ]);
+    if apis.ingress_v1 {
+        manifests.push((
+            "nifi-registry-ingress-v1",
+            template.registry_ingress_v1(name, spec)?,
+        ));
+    } else {
+        manifests.push((
+            "nifi-registry-ingress",
+            template.registry_ingress(name, spec)?,
+        ));
+    }
+    manifests.push(("s3-backup-cronjob", template.s3_backup_cronjob(name, spec)?));
+    // the controller stores the hash of the rendered Deployment in its Pod template
+    let auth_proxy = match template.auth_proxy_deployment(name, spec, "")? {
+        Some(yaml) => {
+            let hash = config_hash(&yaml, &spec.auth_proxy)?;
+            template.auth_proxy_deployment(name, spec, &hash)?
+        }
+        None => None,
+    };
+    manifests.push(("auth-proxy", auth_proxy));
+    manifests.push((
+        "auth-proxy-service",
+        template.auth_proxy_service(name, spec)?,
+    ));
+    Ok(manifests
+        .into_iter()
+        .filter_map(|(t, yaml)| yaml.map(|y| (t, y.trim().to_string())))
+        .filter(|(_, yaml)| !yaml.is_empty())
+        .collect())
+}
+
+fn available(served: bool, api: &str, yaml: Option<String>) -> Result<Option<String>> {
+    match yaml {
+        Some(y) if !y.trim().is_empty() && !served => Err(Error::from(InvalidSpec(format!(
+            "the spec requires {} API, which is not available",
+            api
+        )))),
+        yaml => Ok(yaml),
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{Error, Result};
+use tokio::time::{delay_for, Duration};
+
+use crate::nifi_api::{NiFiApi, Node, NodeStatus};
+
+// 5 second polls while a NiFi node disconnects and moves its flowfiles away
+const OFFLOAD_CHECKS: u16 = 120;
+
+/// Disconnects and offloads NiFi nodes of ordinals from `replicas` up to `current`, highest first,
+/// so their flowfiles are moved to the remaining nodes before the StatefulSet is shrunk
+pub async fn offload_nodes(api: &NiFiApi, name: &str, current: i32, replicas: i32) -> Result<()> {
+    let cluster = api.cluster().await?;
+    for ordinal in (replicas..current).rev() {
+        let pod_name = format!("{}-{}", name, ordinal);
+        match cluster.node(&pod_name) {
+            Some(node) => offload_node(api, &pod_name, node).await?,
+            None => debug!(
+                "{} is not a NiFi cluster node, nothing to offload",
+                &pod_name
+            ),
+        }
+    }
+    Ok(())
+}
+
+async fn offload_node(api: &NiFiApi, pod_name: &str, node: &Node) -> Result<()> {
+    let mut status = node.status.clone();
+    for _ in 0..OFFLOAD_CHECKS {
+        match status {
+            NodeStatus::Offloaded | NodeStatus::Removed => {
+                info!("NiFi node {} is offloaded", pod_name);
+                return Ok(());
+            }
+            NodeStatus::Connected | NodeStatus::Connecting => {
+                info!("Disconnecting NiFi node {}", pod_name);
+                api.disconnect_node(&node.node_id).await?;
+            }
+            NodeStatus::Disconnected => {
+                info!("Offloading NiFi node {}", pod_name);
+                api.offload_node(&node.node_id).await?;
+            }
+            NodeStatus::Disconnecting | NodeStatus::Offloading => (),
+        }
+        delay_for(Duration::from_secs(5)).await;
+        status = api.node(&node.node_id).await?.status;
+    }
+    Err(Error::msg(format!(
+        "NiFi node {} is still {:?}, scale-down is postponed until it is offloaded",
+        pod_name, status
+    )))
+}
+
+/// Removes offloaded nodes of ordinals from `replicas` from the cluster view once the StatefulSet is shrunk
+pub async fn remove_nodes(api: &NiFiApi, name: &str, replicas: i32) -> Result<()> {
+    for node in api.cluster().await?.nodes {
+        let removed = node_ordinal(&node, name).is_some_and(|o| o >= replicas);
+        let offloaded = matches!(
+            node.status,
+            NodeStatus::Offloaded | NodeStatus::Disconnected
+        );
+        if removed && offloaded {
+            info!("Removing NiFi node {} from the cluster", &node.address);
+            api.delete_node(&node.node_id).await?;
+        }
+    }
+    Ok(())
+}
+
+fn node_ordinal(node: &Node, name: &str) -> Option<i32> {
+    node.address
+        .split('.')
+        .next()
+        .and_then(|pod| pod.strip_prefix(name))
+        .and_then(|s| s.strip_prefix('-'))
+        .and_then(|o| o.parse().ok())
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::ByteString;
+use kube::api::{Meta, PostParams};
+use kube::Client;
+
+use crate::controller::tls::{operator_secret, random_password};
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{create_resource, get_api};
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::Template;
+
+const KEY_REVISION_ANNOTATION: &str = "io.github.novakov-alexey/sensitive-props-key-revision";
+/// key of the Secret with the replaced key, read by the NiFi nodes to re-encrypt their flows
+const PREVIOUS_KEY: &str = "previousKey";
+
+pub struct SensitivePropsKeyController {
+    pub client: Rc<Client>,
+}
+
+impl SensitivePropsKeyController {
+    /// Generates the sensitive properties key Secret unless one is referenced in the spec, NiFi
+    /// 1.14+ does not start without the key. The Secret is kept when the deployment is deleted,
+    /// since flows encrypted with it can not be read without it.
+    /// A changed `revision` replaces the key and keeps the previous one in the Secret, so the
+    /// nodes re-encrypt their flows on the restart caused by the new revision
+    pub async fn handle_sensitive_props_key(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let cfg = spec
+            .security
+            .as_ref()
+            .and_then(|s| s.sensitive_props_key.clone())
+            .unwrap_or_default();
+        let revision = cfg.revision.map(|r| r.to_string());
+        if cfg.secret.is_some() {
+            return match revision {
+                Some(_) => Err(Error::from(InvalidSpec(
+                    "sensitivePropsKey.revision rotates the generated key only, a referenced secret must be rotated by the nifi.sh set-sensitive-properties-key command".to_string(),
+                ))),
+                None => Ok(false),
+            };
+        }
+        let key_ref = Template::sensitive_props_key(name, spec);
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let current = match secrets.get(&key_ref.name).await {
+            Ok(secret) => secret,
+            Err(_) => {
+                info!("Generating sensitive properties key of {}", name);
+                let mut data = BTreeMap::new();
+                data.insert(key_ref.key, ByteString(random_password()?.into_bytes()));
+                let secret = key_secret(&key_ref.name, name, ns, data, &revision);
+                create_resource(&secrets, secret).await?;
+                return Ok(true);
+            }
+        };
+        let current_revision = current
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(KEY_REVISION_ANNOTATION));
+        if revision.is_none() || current_revision == revision.as_ref() {
+            return Ok(false);
+        }
+        let previous = current
+            .data
+            .as_ref()
+            .and_then(|d| d.get(&key_ref.key))
+            .cloned()
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "Secret {} has no {} key",
+                    &key_ref.name, &key_ref.key
+                ))
+            })?;
+        info!("Rotating sensitive properties key of {}", name);
+        let mut data = BTreeMap::new();
+        data.insert(key_ref.key, ByteString(random_password()?.into_bytes()));
+        data.insert(PREVIOUS_KEY.to_string(), previous);
+        let mut rotated = key_secret(&key_ref.name, name, ns, data, &revision);
+        rotated.metadata.resource_version = Meta::resource_ver(&current);
+        secrets
+            .replace(&key_ref.name, &PostParams::default(), &rotated)
+            .await?;
+        Ok(true)
+    }
+}
+
+fn key_secret(
+    secret_name: &str,
+    name: &str,
+    ns: &str,
+    data: BTreeMap<String, ByteString>,
+    revision: &Option<String>,
+) -> Secret {
+    let mut secret = operator_secret(secret_name, name, ns, data);
+    if let Some(revision) = revision {
+        let mut annotations = BTreeMap::new();
+        annotations.insert(KEY_REVISION_ANNOTATION.to_string(), revision.clone());
+        secret.metadata.annotations = Some(annotations);
+    }
+    secret
+}
@@ -1,21 +1,30 @@
 use std::rc::Rc;
 
-use anyhow::Result;
-use k8s_openapi::api::core::v1::Service;
-use kube::api::DeleteParams;
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::{Service, ServiceSpec};
+use kube::api::{DeleteParams, ListParams, PatchParams, PatchStrategy};
 use kube::Client;
+use serde_json::Value;
 
-use crate::controller::{create_from_yaml, get_api, get_or_create};
-use crate::crd::IngressCfg;
+use crate::controller::{
+    find_names, from_yaml, get_api, get_or_create, recreate_on_change, resource_updated,
+    ControllerError, KUBEFI_LABELS,
+};
+use crate::crd::NiFiDeploymentSpec;
+use crate::discovery::ClusterApis;
+use crate::resources::{HttpRoute, IngressV1, Route};
 use crate::template::Template;
 
 use super::either::Either;
-use super::either::Either::{Left, Right};
-use k8s_openapi::api::networking::v1beta1::Ingress;
+use super::either::Either::Left;
+use k8s_openapi::api::networking::v1beta1::{Ingress, IngressSpec};
+
+const POD_SERVICE_LABEL: &str = "app.kubernetes.io/component=pod-service";
 
 pub struct ServiceController {
     pub client: Rc<Client>,
     pub template: Rc<Template>,
+    pub apis: ClusterApis,
 }
 
 impl ServiceController {
@@ -23,135 +32,417 @@ impl ServiceController {
         &self,
         name: &str,
         ns: &str,
-        ingress_cfg: &Option<IngressCfg>,
+        spec: &NiFiDeploymentSpec,
     ) -> Result<bool> {
-        let svc = get_or_create::<Service, _>(&self.client, &name, &name, &ns, |name| {
-            self.template.nifi_service(name)
+        let svc = get_or_create::<Service, _>(&self.client, name, name, ns, |name| {
+            self.template.nifi_service(name, spec)
         });
 
         let headless_svc_name = format!("{}-headless", &name);
         let headless_svc =
-            get_or_create::<Service, _>(&self.client, &headless_svc_name, &name, &ns, |name| {
-                self.template.nifi_headless_service(name)
+            get_or_create::<Service, _>(&self.client, &headless_svc_name, name, ns, |name| {
+                self.template.nifi_headless_service(name, spec)
             });
 
+        let zk_svc = self.handle_zk_services(name, ns, spec);
+
+        let (svc, headless_svc, zk_svc_updated) =
+            futures::future::join3(svc, headless_svc, zk_svc).await;
+
+        let svc_updated = self
+            .handle_service_update(ns, name, svc, || self.template.nifi_service(name, spec))
+            .await;
+        let headless_svc_updated = self
+            .handle_service_update(ns, &headless_svc_name, headless_svc, || {
+                self.template.nifi_headless_service(name, spec)
+            })
+            .await;
+        let pod_svc_updated = self.handle_pod_services(name, ns, spec).await;
+        let ingress_updated = self.handle_ingress(name, ns, spec).await;
+        let route_updated = self.handle_route(name, ns, spec).await;
+        let http_route_updated = self.handle_http_route(name, ns, spec).await;
+        zk_svc_updated
+            .and_then(|upd| svc_updated.map(|svc_upd| svc_upd || upd))
+            .and_then(|upd| headless_svc_updated.map(|svc_upd| svc_upd || upd))
+            .and_then(|upd| pod_svc_updated.map(|svc_upd| svc_upd || upd))
+            .and_then(|svc_updated| ingress_updated.map(|upd| upd || svc_updated))
+            .and_then(|upd| route_updated.map(|route_upd| route_upd || upd))
+            .and_then(|upd| http_route_updated.map(|route_upd| route_upd || upd))
+    }
+
+    pub async fn handle_zk_services(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
         let zk_svc_name = format!("{}-zookeeper", &name);
-        let zk_svc = get_or_create::<Service, _>(&self.client, &zk_svc_name, &name, &ns, |name| {
-            self.template.zk_service(name)
-        });
+        let zk_svc = async {
+            let svc = get_or_create::<Service, _>(&self.client, &zk_svc_name, name, ns, |name| {
+                self.template.zk_service(name, spec)
+            })
+            .await;
+            // client TLS port is added or removed
+            self.handle_service_update(ns, &zk_svc_name, svc, || {
+                self.template.zk_service(name, spec)
+            })
+            .await
+        };
 
         let zk_headless_svc_name = format!("{}-zookeeper-headless", &name);
         let zk_headless_svc =
-            get_or_create::<Service, _>(&self.client, &zk_headless_svc_name, &name, &ns, |name| {
-                self.template.zk_headless_service(name)
+            get_or_create::<Service, _>(&self.client, &zk_headless_svc_name, name, ns, |name| {
+                self.template.zk_headless_service(name, spec)
             });
 
-        let ingress_name = format!("{}-ingress", &name);
-        let ingress =
-            get_or_create::<Ingress, _>(&self.client, &ingress_name, &name, &ns, |name| {
-                self.template.ingress(name, &ingress_cfg)
-            });
+        let zk_metrics_svc_name = format!("{}-zookeeper-metrics", &name);
+        let zk_metrics_svc = recreate_on_change::<Service, _, _>(
+            &self.client,
+            &zk_metrics_svc_name,
+            name,
+            ns,
+            |name| self.template.zk_metrics_service(name, spec),
+            service_changed,
+        );
 
-        let (svc, headless_svc, zk_svc, zk_headless_svc, ingress) =
-            futures::future::join5(svc, headless_svc, zk_svc, zk_headless_svc, ingress).await;
+        let (zk_svc, zk_headless_svc, zk_metrics_svc) =
+            futures::future::join3(zk_svc, zk_headless_svc, zk_metrics_svc).await;
+        Ok(zk_svc? || resource_updated(zk_headless_svc?) || zk_metrics_svc?)
+    }
 
-        let ingress_updated = self
-            .handle_update(&name, &ns, &ingress_cfg, &ingress_name, ingress)
-            .await;
-        vec![svc, headless_svc, zk_svc, zk_headless_svc]
+    async fn handle_pod_services(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let mut updated = false;
+        let mut expected = vec![];
+        for ordinal in 0..spec.nifi_replicas {
+            let get_yaml = |name: &str| self.template.nifi_pod_service(name, ordinal, spec);
+            if get_yaml(name)?.is_none() {
+                break;
+            }
+            let svc_name = format!("{}-{}", &name, ordinal);
+            let svc =
+                get_or_create::<Service, _>(&self.client, &svc_name, name, ns, get_yaml).await;
+            updated |= self
+                .handle_service_update(ns, &svc_name, svc, || get_yaml(name))
+                .await?;
+            expected.push(svc_name);
+        }
+        // Services of removed Pods after scale down or disabling
+        let lp = ListParams::default().labels(&format!("{},{}", KUBEFI_LABELS, POD_SERVICE_LABEL));
+        let prefix = format!("{}-", &name);
+        let stale = find_names::<Service>(&self.client, ns, &lp)
+            .await?
             .into_iter()
-            .fold(Ok(false), |acc, res| {
-                let resource = res?;
-                acc.map(|a| a || resource_updated(resource))
+            .filter(|n| {
+                n.strip_prefix(&prefix)
+                    .and_then(|ordinal| ordinal.parse::<u8>().ok())
+                    .is_some()
+                    && !expected.contains(n)
             })
-            .and_then(|svc_updated| ingress_updated.map(|upd| upd || svc_updated))
+            .collect::<Vec<_>>();
+        let api = get_api::<Service>(&self.client, ns);
+        for svc_name in &stale {
+            debug!("Deleting Service of removed Pod: {}", svc_name);
+            api.delete(svc_name, &DeleteParams::default()).await?;
+        }
+        Ok(updated || !stale.is_empty())
     }
 
-    async fn handle_update(
+    async fn handle_ingress(
         &self,
         name: &str,
         ns: &str,
-        ingress_cfg: &Option<IngressCfg>,
-        ingress_name: &str,
-        ingress: Result<Either<Option<Ingress>, Option<Ingress>>>,
+        spec: &NiFiDeploymentSpec,
     ) -> Result<bool> {
-        let ingress_changed = ingress_updated(ingress, &ingress_cfg);
-        match ingress_changed {
-            Ok(true) => self
-                .recreate_ingress(&name, &ns, &ingress_name, &ingress_cfg)
-                .await
-                .map(|_| true),
-            Ok(_) => Ok(false),
-            Err(e) => Err(e),
+        let ingress_name = format!("{}-ingress", &name);
+        if self.apis.ingress_v1 {
+            recreate_on_change::<IngressV1, _, _>(
+                &self.client,
+                &ingress_name,
+                name,
+                ns,
+                |name| self.template.ingress_v1(name, spec),
+                ingress_v1_changed,
+            )
+            .await
+        } else {
+            recreate_on_change::<Ingress, _, _>(
+                &self.client,
+                &ingress_name,
+                name,
+                ns,
+                |name| self.template.ingress(name, spec),
+                ingress_changed,
+            )
+            .await
         }
     }
 
-    async fn recreate_ingress(
+    async fn handle_route(&self, name: &str, ns: &str, spec: &NiFiDeploymentSpec) -> Result<bool> {
+        if !self.apis.route {
+            return match self.template.route(name, spec)? {
+                Some(_) => Err(Error::new(ControllerError::InvalidSpec(
+                    "expose.route is enabled, but route.openshift.io API is not available"
+                        .to_string(),
+                ))),
+                None => Ok(false),
+            };
+        }
+        let route_name = format!("{}-route", &name);
+        recreate_on_change::<Route, _, _>(
+            &self.client,
+            &route_name,
+            name,
+            ns,
+            |name| self.template.route(name, spec),
+            route_changed,
+        )
+        .await
+    }
+
+    async fn handle_http_route(
         &self,
-        cr_name: &str,
+        name: &str,
         ns: &str,
-        ingress_name: &str,
-        ingress_cfg: &Option<IngressCfg>,
-    ) -> Result<()> {
-        let params = &DeleteParams::default();
-        let api = get_api::<Ingress>(&self.client, &ns);
-        api.delete(&ingress_name, params).await?;
-
-        debug!("Creating new Ingress: {}", &ingress_name);
-        create_from_yaml::<Ingress, _, _>(
-            &cr_name,
-            &ns,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let gateway_api = spec.expose.as_ref().and_then(|e| e.gateway_api.as_ref());
+        if let Some(cfg) = gateway_api {
+            if cfg.enabled.unwrap_or(false) && cfg.gateway_name.is_none() {
+                return Err(Error::new(ControllerError::InvalidSpec(
+                    "expose.gatewayApi.gatewayName is required".to_string(),
+                )));
+            }
+        }
+        if !self.apis.http_route {
+            return match self.template.http_route(name, spec)? {
+                Some(_) => Err(Error::new(ControllerError::InvalidSpec(
+                    "expose.gatewayApi is enabled, but gateway.networking.k8s.io/v1 API is not available"
+                        .to_string(),
+                ))),
+                None => Ok(false),
+            };
+        }
+        let route_name = format!("{}-httproute", &name);
+        recreate_on_change::<HttpRoute, _, _>(
             &self.client,
-            |name| self.template.ingress(name, ingress_cfg),
-            Ok,
+            &route_name,
+            name,
+            ns,
+            |name| self.template.http_route(name, spec),
+            http_route_changed,
         )
         .await
-        .map(|_| ())
     }
-}
 
-fn ingress_updated(
-    current_ingress: Result<Either<Option<Ingress>, Option<Ingress>>>,
-    ingress_cfg: &Option<IngressCfg>,
-) -> Result<bool> {
-    match ingress_cfg {
-        Some(cfg) => {
-            debug!("Ingress config: {:?}", &cfg);
-            current_ingress.map(|r| match r {
-                Left(Some(ing)) => {
-                    debug!("ing spec: {:?}", &ing.spec);
-                    let host_found = ing
-                        .spec
-                        .and_then(|s| s.rules)
-                        .unwrap_or_default()
-                        .iter()
-                        .any(|r| {
-                            r.host
-                                .as_ref()
-                                .map(|h| h == cfg.host.as_str())
-                                .unwrap_or(false)
-                        });
-                    let class_found =
-                        ing.metadata
-                            .annotations
-                            .unwrap_or_default()
-                            .iter()
-                            .any(|(k, v)| {
-                                k == "kubernetes.io/ingress.class" && v == &cfg.ingress_class
-                            });
-                    !host_found || !class_found
+    async fn handle_service_update<F: FnOnce() -> Result<Option<String>>>(
+        &self,
+        ns: &str,
+        svc_name: &str,
+        svc: Result<Either<Option<Service>, Option<Service>>>,
+        get_yaml: F,
+    ) -> Result<bool> {
+        match svc? {
+            Left(Some(current)) => {
+                let expected = get_yaml()?.map(|y| from_yaml::<Service>(&y)).transpose()?;
+                if let Some(expected) = &expected {
+                    validate_service(svc_name, expected)?;
                 }
-                _ => false,
-            })
+                match expected {
+                    Some(expected) if service_changed(&current, &expected) => self
+                        .patch_service(svc_name, ns, &current, &expected)
+                        .await
+                        .map(|_| true),
+                    _ => Ok(false),
+                }
+            }
+            r => Ok(resource_updated(r)),
         }
-        None => Ok(false),
     }
+
+    async fn patch_service(
+        &self,
+        name: &str,
+        ns: &str,
+        current: &Service,
+        expected: &Service,
+    ) -> Result<Service> {
+        let mut spec = serde_json::to_value(&expected.spec)?;
+        // merge patch keeps absent fields, so drop previously set optional fields explicitly
+        if let Value::Object(s) = &mut spec {
+            s.entry("loadBalancerIP").or_insert(Value::Null);
+            s.entry("sessionAffinityConfig").or_insert(Value::Null);
+        }
+        let mut annotations = serde_json::Map::new();
+        for key in current.metadata.annotations.iter().flat_map(|a| a.keys()) {
+            annotations.insert(key.clone(), Value::Null);
+        }
+        for (key, value) in expected.metadata.annotations.iter().flatten() {
+            annotations.insert(key.clone(), Value::String(value.clone()));
+        }
+        let patch = json!({
+            "metadata": { "annotations": annotations },
+            "spec": spec
+        });
+        debug!("Patching Service {} with: {}", name, &patch);
+        let params = PatchParams {
+            patch_strategy: PatchStrategy::Merge,
+            ..PatchParams::default()
+        };
+        let api = get_api::<Service>(&self.client, ns);
+        Ok(api
+            .patch(name, &params, serde_json::to_vec(&patch)?)
+            .await?)
+    }
+}
+
+pub fn ingress_changed(current: &Ingress, expected: &Ingress) -> bool {
+    let spec = |i: &Ingress| i.spec.clone().unwrap_or_default();
+    let rules = |s: &IngressSpec| {
+        s.rules
+            .iter()
+            .flatten()
+            .flat_map(|r| {
+                r.http.iter().flat_map(|h| &h.paths).map(move |p| {
+                    (
+                        r.host.clone(),
+                        p.path.clone(),
+                        p.backend.service_name.clone(),
+                        p.backend.service_port.clone(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    let tls = |s: &IngressSpec| {
+        s.tls
+            .iter()
+            .flatten()
+            .map(|t| (t.hosts.clone(), t.secret_name.clone()))
+            .collect::<Vec<_>>()
+    };
+    let (current_spec, expected_spec) = (spec(current), spec(expected));
+    debug!("Current Ingress spec: {:?}", &current_spec);
+    let current_annotations = current.metadata.annotations.clone().unwrap_or_default();
+    let annotations_missing = expected
+        .metadata
+        .annotations
+        .iter()
+        .flatten()
+        .any(|(k, v)| current_annotations.get(k) != Some(v));
+
+    rules(&current_spec) != rules(&expected_spec)
+        || tls(&current_spec) != tls(&expected_spec)
+        || current_spec.ingress_class_name != expected_spec.ingress_class_name
+        || annotations_missing
+}
+
+pub fn ingress_v1_changed(current: &IngressV1, expected: &IngressV1) -> bool {
+    debug!("Current Ingress spec: {:?}", &current.spec);
+    let current_annotations = current.metadata.annotations.clone().unwrap_or_default();
+    let annotations_missing = expected
+        .metadata
+        .annotations
+        .iter()
+        .flatten()
+        .any(|(k, v)| current_annotations.get(k) != Some(v));
+
+    current.spec != expected.spec || annotations_missing
+}
+
+fn route_changed(current: &Route, expected: &Route) -> bool {
+    let (current_spec, expected_spec) = (&current.spec, &expected.spec);
+    debug!("Current Route spec: {:?}", current_spec);
+    let current_annotations = current.metadata.annotations.clone().unwrap_or_default();
+    let annotations_missing = expected
+        .metadata
+        .annotations
+        .iter()
+        .flatten()
+        .any(|(k, v)| current_annotations.get(k) != Some(v));
+    // router generates the host when it is not set
+    let host_changed = expected_spec.host.is_some() && current_spec.host != expected_spec.host;
+
+    host_changed
+        || current_spec.path != expected_spec.path
+        || current_spec.port != expected_spec.port
+        || current_spec.to.name != expected_spec.to.name
+        || current_spec.tls != expected_spec.tls
+        || annotations_missing
+}
+
+fn http_route_changed(current: &HttpRoute, expected: &HttpRoute) -> bool {
+    debug!("Current HTTPRoute spec: {:?}", &current.spec);
+    let current_annotations = current.metadata.annotations.clone().unwrap_or_default();
+    let annotations_missing = expected
+        .metadata
+        .annotations
+        .iter()
+        .flatten()
+        .any(|(k, v)| current_annotations.get(k) != Some(v));
+
+    current.spec != expected.spec || annotations_missing
+}
+
+pub fn service_changed(current: &Service, expected: &Service) -> bool {
+    let spec = |s: &Service| s.spec.clone().unwrap_or_default();
+    let node_ports = |s: &ServiceSpec| {
+        s.ports
+            .iter()
+            .flatten()
+            .filter_map(|p| p.node_port)
+            .collect::<Vec<_>>()
+    };
+    let ports = |s: &ServiceSpec| {
+        s.ports
+            .iter()
+            .flatten()
+            .map(|p| (p.name.clone(), p.port, p.target_port.clone()))
+            .collect::<Vec<_>>()
+    };
+    let (current_spec, expected_spec) = (spec(current), spec(expected));
+    let expected_node_ports = node_ports(&expected_spec);
+    // annotations removed from the template or spec are removed from the Service as well
+    let annotations_changed = current.metadata.annotations.clone().unwrap_or_default()
+        != expected.metadata.annotations.clone().unwrap_or_default();
+
+    ports(&current_spec) != ports(&expected_spec)
+        || current_spec.type_ != expected_spec.type_
+        || current_spec.load_balancer_ip != expected_spec.load_balancer_ip
+        || current_spec.session_affinity != expected_spec.session_affinity
+        || current_spec.session_affinity_config != expected_spec.session_affinity_config
+        || (expected_spec.external_traffic_policy.is_some()
+            && current_spec.external_traffic_policy != expected_spec.external_traffic_policy)
+        || (!expected_node_ports.is_empty() && node_ports(&current_spec) != expected_node_ports)
+        || annotations_changed
 }
 
-fn resource_updated<T>(result: Either<Option<T>, Option<T>>) -> bool {
-    match result {
-        Left(Some(_)) => false,
-        Right(Some(_)) => true,
-        _ => false,
+/// nodePort requires a NodePort or LoadBalancer Service and loadBalancerIP a LoadBalancer Service,
+/// the API server would reject the patch otherwise
+fn validate_service(name: &str, svc: &Service) -> Result<()> {
+    let spec = svc.spec.clone().unwrap_or_default();
+    let type_ = spec.type_.as_deref().unwrap_or("ClusterIP");
+    let node_ports = spec
+        .ports
+        .iter()
+        .flatten()
+        .filter_map(|p| p.node_port)
+        .collect::<Vec<_>>();
+    if !node_ports.is_empty() && type_ != "NodePort" && type_ != "LoadBalancer" {
+        Err(Error::new(ControllerError::InvalidSpec(format!(
+            "nodePort {:?} of Service {} requires type NodePort or LoadBalancer, not {}",
+            node_ports, name, type_
+        ))))
+    } else if spec.load_balancer_ip.is_some() && type_ != "LoadBalancer" {
+        Err(Error::new(ControllerError::InvalidSpec(format!(
+            "loadBalancerIP of Service {} requires type LoadBalancer, not {}",
+            name, type_
+        ))))
+    } else {
+        Ok(())
     }
 }
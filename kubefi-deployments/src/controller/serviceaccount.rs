@@ -0,0 +1,77 @@
+use std::rc::Rc;
+
+use anyhow::Result;
+use k8s_openapi::api::core::v1::ServiceAccount;
+use k8s_openapi::api::rbac::v1::{Role, RoleBinding};
+use kube::Client;
+
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{get_or_create, recreate_on_change, resource_updated};
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::Template;
+
+pub struct ServiceAccountController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl ServiceAccountController {
+    pub async fn handle_service_account(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let sa_name = match (
+            &spec.service_account,
+            Template::service_account_name(name, spec),
+        ) {
+            (Some(cfg), Some(sa_name)) if cfg.create => Some(sa_name),
+            (Some(cfg), None) if cfg.rbac => {
+                return Err(InvalidSpec(
+                    "serviceAccount.rbac requires serviceAccount.create or serviceAccountName"
+                        .to_string(),
+                )
+                .into())
+            }
+            _ => None,
+        };
+
+        let sa = async {
+            match &sa_name {
+                Some(sa_name) => {
+                    get_or_create::<ServiceAccount, _>(&self.client, sa_name, name, ns, |name| {
+                        self.template.nifi_service_account(name, spec)
+                    })
+                    .await
+                    .map(resource_updated)
+                }
+                None => Ok(false),
+            }
+        };
+        // Role and RoleBinding are not rendered without serviceAccount.rbac, so they are deleted then
+        let role = recreate_on_change::<Role, _, _>(
+            &self.client,
+            name,
+            name,
+            ns,
+            |name| self.template.nifi_role(name, spec),
+            |current, expected| current.rules != expected.rules,
+        );
+        // roleRef is immutable
+        let binding = recreate_on_change::<RoleBinding, _, _>(
+            &self.client,
+            name,
+            name,
+            ns,
+            |name| self.template.nifi_role_binding(name, spec),
+            |current, expected| {
+                current.role_ref != expected.role_ref || current.subjects != expected.subjects
+            },
+        );
+
+        let (sa, role, binding) = futures::future::join3(sa, role, binding).await;
+        let (role_updated, binding_updated) = (role?, binding?);
+        Ok(sa? || role_updated || binding_updated)
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::Result;
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::ByteString;
+use kube::Client;
+
+use crate::controller::tls::{operator_secret, random_password};
+use crate::controller::{create_resource, get_api};
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::Template;
+
+const DEFAULT_USERNAME: &str = "admin";
+
+pub struct SingleUserController {
+    pub client: Rc<Client>,
+}
+
+impl SingleUserController {
+    /// Generates the credentials Secret of `security.singleUser` when no Secret is given, NiFi
+    /// nodes set the credentials on start. The Secret is kept when the deployment is deleted
+    pub async fn handle_single_user(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let generated = spec
+            .security
+            .as_ref()
+            .and_then(|s| s.single_user.as_ref())
+            .map(|u| u.credentials_secret.is_none())
+            .unwrap_or(false);
+        let secret_name = match Template::single_user_secret_name(name, spec) {
+            Some(secret_name) if generated => secret_name,
+            _ => return Ok(false),
+        };
+        let secrets = get_api::<Secret>(&self.client, ns);
+        if secrets.get(&secret_name).await.is_ok() {
+            return Ok(false);
+        }
+        info!("Generating single user credentials of {}", name);
+        let mut data = BTreeMap::new();
+        data.insert(
+            "username".to_string(),
+            ByteString(DEFAULT_USERNAME.as_bytes().to_vec()),
+        );
+        data.insert(
+            "password".to_string(),
+            ByteString(random_password()?.into_bytes()),
+        );
+        create_resource(&secrets, operator_secret(&secret_name, name, ns, data)).await?;
+        Ok(true)
+    }
+}
@@ -1,16 +1,36 @@
 use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Error, Result};
 use k8s_openapi::api::apps::v1::StatefulSet;
-use k8s_openapi::api::core::v1::Pod;
-use kube::api::{DeleteParams, ListParams, PostParams};
-use kube::Client;
+use k8s_openapi::api::core::v1::{Container, PersistentVolumeClaim, Pod};
+use k8s_openapi::api::storage::v1::StorageClass;
+use kube::api::{
+    DeleteParams, ListParams, Meta, PatchParams, PatchStrategy, PostParams, PropagationPolicy,
+};
+use kube::{Api, Client};
+use openssl::sha::Sha256;
+use tokio::time::{delay_for, Duration};
 
+use crate::controller::backup::restore_claims;
+use crate::controller::events::warning_event;
+use crate::controller::flowbackup::{backup_flow, restore_from_s3};
+use crate::controller::hibernate::stop_flow;
+use crate::controller::nifiupgrade::{
+    advance_nifi_restarts, advance_nifi_rollout, partition, rollout_pending, with_canary,
+    with_partition, with_rollout_state,
+};
+use crate::controller::registry::registry_name;
+use crate::controller::scaledown::{offload_nodes, remove_nodes};
+use crate::controller::version::{validate_fips, validate_upgrade, ALLOW_DOWNGRADE_ANNOTATION};
+use crate::controller::zkupgrade::{advance_zk_roll, with_zk_roll, zk_roll_pending};
+use crate::controller::ControllerError::{InvalidSpec, UpgradeFailed};
 use crate::controller::{
-    delete_resources, from_yaml, get_api, get_or_create, ConfigMapState, KUBEFI_LABELS,
-    NIFI_APP_LABEL, ZK_APP_LABEL,
+    delete_resources, find_names, from_yaml, get_api, get_or_create_convert, hex_digest,
+    ConfigMapState, KUBEFI_LABELS, NIFI_APP_LABEL, ZK_APP_LABEL,
 };
-use crate::crd::NiFiDeployment;
+use crate::crd::{NiFiDeployment, NiFiDeploymentSpec, UpgradeStrategy};
+use crate::nifi_api::NiFiApi;
 use crate::template::Template;
 
 use super::either::Either::{Left, Right};
@@ -27,14 +47,31 @@ struct SetParams {
     pub image: Option<String>,
     pub set_name: String,
     pub app_label: String,
-    pub storage_class: Option<String>,
     pub cm_state: Option<ConfigMapState>,
-    pub svc_updated: bool,
 }
 
 const LOGGING_VOLUME: &str = "logback-xml";
+const DEFAULT_STORAGE_CLASS_ANNOTATION: &str = "storageclass.kubernetes.io/is-default-class";
+// seconds to wait for a deleted StatefulSet to disappear before recreating it
+const SET_DELETION_CHECKS: u8 = 30;
+// unix time a PVC lost its Pod because of scale-down
+const ORPHANED_AT_ANNOTATION: &str = "io.github.novakov-alexey/orphaned-at";
+const DEFAULT_ORPHANED_CLAIMS_RETENTION_SECONDS: u64 = 86400;
+// hash of the rendered Pod template, used to detect any template or spec change
+pub const TEMPLATE_HASH_ANNOTATION: &str = "io.github.novakov-alexey/template-hash";
+// template hash of a spec whose canary failed, it is not rolled out again
+pub const CANARY_FAILED_ANNOTATION: &str = "io.github.novakov-alexey/canary-failed";
 const NIFI_CONTAINER_NAME: &str = "server";
 const ZOOKEEPER_CONTAINER_NAME: &str = "zookeeper";
+// containers and init containers generated by the NiFi StatefulSet template
+const NIFI_RESERVED_CONTAINER_NAMES: [&str; 6] = [
+    "server",
+    "app-log",
+    "bootstrap-log",
+    "user-log",
+    "zookeeper",
+    "copy-conf",
+];
 
 impl StatefulSetController {
     async fn update_existing_set<F: FnOnce(&str, &NiFiDeployment) -> Result<Option<String>>>(
@@ -48,87 +85,401 @@ impl StatefulSetController {
     ) -> Result<bool> {
         let image_changed = image_changed(&set, &params.image.clone(), &params.container);
         let replicas_changed = scale_set(&set, params.replicas);
-        let storage_class_changed = storage_class(&set, &params.storage_class);
         let logging_cm_changed =
             logging_cm(&set, params.clone().cm_state.and_then(|cm| cm.logging_cm));
+        let yaml = get_yaml(cr_name, d)?;
+        let expected_set = yaml
+            .as_deref()
+            .map(|y| from_yaml::<StatefulSet>(y).and_then(with_template_hash))
+            .transpose()?;
+        let template_changed = match &expected_set {
+            Some(expected) => pod_template_changed(&self.client, ns, &set, expected).await?,
+            None => false,
+        };
+        let storage_class_changed = expected_set
+            .as_ref()
+            .map(|expected| storage_class_changed(&set, expected))
+            .unwrap_or(false);
+        // volumeClaimTemplates are immutable, so a different set of claims requires recreation
+        let claims_changed = expected_set
+            .as_ref()
+            .map(|expected| claim_names(&set) != claim_names(expected))
+            .unwrap_or(false);
+
+        let grown_claims = match &expected_set {
+            Some(expected) => grown_claims(&set, expected)?,
+            None => vec![],
+        };
+        let claims_expanded = !grown_claims.is_empty();
+        let nifi_rollout = params.app_label == NIFI_APP_LABEL
+            && (image_changed || template_changed || rollout_pending(&set));
+        let canary_failed = expected_set
+            .as_ref()
+            .and_then(template_hash)
+            .is_some_and(|hash| {
+                set.metadata
+                    .annotations
+                    .as_ref()
+                    .and_then(|a| a.get(CANARY_FAILED_ANNOTATION))
+                    == Some(hash)
+            });
+        if nifi_rollout && template_changed && canary_failed {
+            return Err(Error::from(UpgradeFailed(format!(
+                "canary of this spec failed and {} was rolled back, change the spec to retry",
+                &params.set_name
+            ))));
+        }
 
         if storage_class_changed {
-            let yaml = get_yaml(&cr_name, &d)?;
-            self.recreate_set(&ns, &params, yaml).await?;
+            debug!(
+                "Recreating {} statefulset. Reason: storage_class_changed: {}",
+                &params.set_name, storage_class_changed
+            );
+            self.recreate_set(ns, params, expected_set, DeleteParams::default())
+                .await?;
+        } else if claims_changed {
+            let expected_claims = expected_set.as_ref().map(claim_names).unwrap_or_default();
+            let removed = claim_names(&set)
+                .into_iter()
+                .filter(|c| !expected_claims.contains(c))
+                .collect::<Vec<_>>();
+            let message = format!(
+                "volume claims of {} changed from {:?} to {:?}, data of the removed claims {:?} is not migrated",
+                &params.set_name,
+                claim_names(&set),
+                &expected_claims,
+                &removed
+            );
+            warn!("{}", &message);
+            if let Err(e) = warning_event(&self.client, d, "VolumeClaimsChanged", &message).await {
+                warn!("Failed to emit volume claims Event: {}", e);
+            }
+            // volumeClaimTemplates are immutable, Pods are adopted by the new StatefulSet
+            let dp = DeleteParams {
+                propagation_policy: Some(PropagationPolicy::Orphan),
+                ..DeleteParams::default()
+            };
+            let expected_set = expected_set.map(|s| with_pending_pods(s, params));
+            self.recreate_set(ns, params, expected_set, dp).await?;
+        } else if claims_expanded {
+            self.expand_claims(ns, params, &grown_claims).await?;
+            debug!(
+                "Recreating {} statefulset keeping its Pods. Reason: claims expanded: {:?}",
+                &params.set_name, &grown_claims
+            );
+            // volumeClaimTemplates are immutable, Pods are adopted by the new StatefulSet
+            let dp = DeleteParams {
+                propagation_policy: Some(PropagationPolicy::Orphan),
+                ..DeleteParams::default()
+            };
+            let expected_set = expected_set.map(|s| with_pending_pods(s, params));
+            self.recreate_set(ns, params, expected_set, dp).await?;
         } else {
-            if image_changed || replicas_changed || logging_cm_changed {
+            if image_changed || replicas_changed || logging_cm_changed || template_changed {
                 let reason = format!(
-                    "image_changed: {}, replicas_changed: {}, logging_cm_changed: {}",
-                    image_changed, replicas_changed, logging_cm_changed
+                    "image_changed: {}, replicas_changed: {}, logging_cm_changed: {}, template_changed: {}",
+                    image_changed, replicas_changed, logging_cm_changed, template_changed
                 );
                 debug!(
                     "Updating existing {} statefulset with: {:?}. Reason: {}",
                     &params.set_name, &params, reason
                 );
-                let yaml = get_yaml(&cr_name, &d)?;
-                match yaml {
-                    Some(y) => self.replace_set(&ns, &params, &y).await,
+                match expected_set {
+                    Some(mut new_set) => {
+                        // NiFi Pods are moved to the new revision by advance_nifi_rollout
+                        if nifi_rollout {
+                            let pending = if image_changed || template_changed {
+                                params.replicas
+                            } else {
+                                partition(&set)
+                            };
+                            with_partition(&mut new_set, pending);
+                            if canary(d) && (image_changed || template_changed) {
+                                with_canary(&set, &mut new_set);
+                            }
+                        }
+                        if params.app_label == NIFI_APP_LABEL && !image_changed && !template_changed
+                        {
+                            with_rollout_state(&set, &mut new_set);
+                        }
+                        if params.app_label == ZK_APP_LABEL {
+                            with_zk_roll(&set, &mut new_set, image_changed || template_changed);
+                        }
+                        self.replace_set(ns, params, &new_set).await
+                    }
                     None => Ok(()),
                 }?;
             }
 
-            if image_changed
-                || params
-                    .cm_state
-                    .clone()
-                    .map(|cm| cm.updated)
-                    .unwrap_or(false)
+            if params.app_label == ZK_APP_LABEL {
+                // ZooKeeper StatefulSet uses OnDelete, so its servers are restarted here keeping the quorum
+                if image_changed || template_changed || zk_roll_pending(&set) {
+                    advance_zk_roll(&self.client, ns, &params.set_name).await?;
+                }
+            } else if nifi_rollout {
+                advance_nifi_rollout(&self.client, &self.template, cr_name, ns, &d.spec).await?;
+            } else if params
+                .cm_state
+                .clone()
+                .map(|cm| cm.updated)
+                .unwrap_or(false)
             {
-                self.remove_pods(&ns, params, image_changed).await?;
+                self.remove_pods(ns, params).await?;
+            }
+            if params.app_label == NIFI_APP_LABEL {
+                // queued restarts, e.g. of renewed keystores, wait for a rollout in progress
+                advance_nifi_restarts(&self.client, &self.template, cr_name, ns, &d.spec).await?;
             }
         }
-        let state_changed =
-            storage_class_changed || image_changed || replicas_changed || logging_cm_changed;
+        let state_changed = storage_class_changed
+            || claims_changed
+            || claims_expanded
+            || image_changed
+            || replicas_changed
+            || logging_cm_changed
+            || template_changed;
         Ok(state_changed)
     }
 
-    async fn remove_pods(&self, ns: &str, params: &SetParams, image_changed: bool) -> Result<()> {
+    async fn remove_pods(&self, ns: &str, params: &SetParams) -> Result<()> {
         let dp = &DeleteParams::default();
         let labels = format!("app={},{}", params.app_label, KUBEFI_LABELS);
         let lp = ListParams::default().labels(&labels);
         debug!(
-            "Removing all Pod(s) with: {:?}. Reason: image changed = {}, configMap changed = {}",
-            labels,
-            image_changed,
-            params
-                .cm_state
-                .clone()
-                .map(|cm| cm.updated)
-                .unwrap_or(false)
+            "Removing all Pod(s) with: {:?}. Reason: configMap changed",
+            labels
         );
-        delete_resources::<Pod>(&self.client, &ns, &dp, &lp).await
+        delete_resources::<Pod>(&self.client, ns, dp, &lp).await
     }
 
-    async fn replace_set(&self, ns: &str, set_params: &SetParams, yaml: &str) -> Result<(), Error> {
-        let new_set = from_yaml(&yaml)?;
-        let api = get_api::<StatefulSet>(&self.client, &ns);
+    async fn replace_set(
+        &self,
+        ns: &str,
+        set_params: &SetParams,
+        new_set: &StatefulSet,
+    ) -> Result<(), Error> {
+        let api = get_api::<StatefulSet>(&self.client, ns);
         let pp = PostParams::default();
-        api.replace(&set_params.set_name, &pp, &new_set)
+        api.replace(&set_params.set_name, &pp, new_set)
             .await
             .map(|_| ())
             .map_err(Error::from)
     }
 
-    async fn recreate_set(
+    /// Resizes existing PVCs of all Pods, their StorageClass must allow volume expansion
+    async fn expand_claims(
         &self,
         ns: &str,
         set_params: &SetParams,
+        claims: &[ClaimSize],
+    ) -> Result<()> {
+        for claim in claims {
+            self.validate_expansion(&claim.storage_class).await?;
+        }
+        let api = get_api::<PersistentVolumeClaim>(&self.client, ns);
+        let pvc_names =
+            find_names::<PersistentVolumeClaim>(&self.client, ns, &ListParams::default()).await?;
+        let params = PatchParams {
+            patch_strategy: PatchStrategy::Merge,
+            ..PatchParams::default()
+        };
+        for claim in claims {
+            let pvcs = pvc_names
+                .iter()
+                .filter(|n| is_set_claim(n, &claim.name, &set_params.set_name));
+            for pvc in pvcs {
+                debug!("Expanding PVC {} to {}", pvc, &claim.size);
+                let patch =
+                    json!({ "spec": { "resources": { "requests": { "storage": &claim.size } } } });
+                api.patch(pvc, &params, serde_json::to_vec(&patch)?).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// PVCs of ordinals above `replicas` are marked as orphaned on first sight and
+    /// deleted once the retention period is over, scaling up again unmarks them
+    async fn delete_orphaned_claims(
+        &self,
+        ns: &str,
+        set_name: &str,
+        replicas: u8,
+        retention_seconds: u64,
+    ) -> Result<()> {
+        let set = match get_api::<StatefulSet>(&self.client, ns).get(set_name).await {
+            Ok(set) => set,
+            Err(_) => return Ok(()),
+        };
+        let claims = claim_names(&set);
+        let api = get_api::<PersistentVolumeClaim>(&self.client, ns);
+        let params = PatchParams {
+            patch_strategy: PatchStrategy::Merge,
+            ..PatchParams::default()
+        };
+        let now = unix_now();
+        for pvc in api.list(&ListParams::default()).await? {
+            let pvc_name = Meta::name(&pvc);
+            let ordinal = match claims
+                .iter()
+                .find_map(|claim| claim_ordinal(&pvc_name, claim, set_name))
+            {
+                Some(ordinal) => ordinal,
+                None => continue,
+            };
+            let orphaned_at = pvc
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|a| a.get(ORPHANED_AT_ANNOTATION))
+                .and_then(|t| t.parse::<u64>().ok());
+            let orphaned = ordinal >= replicas;
+            match orphaned_at {
+                Some(since) if orphaned && now.saturating_sub(since) >= retention_seconds => {
+                    info!("Deleting PVC {} of scaled down {}", &pvc_name, set_name);
+                    api.delete(&pvc_name, &DeleteParams::default()).await?;
+                }
+                None if orphaned => {
+                    debug!(
+                        "PVC {} is orphaned, retained for {}s",
+                        &pvc_name, retention_seconds
+                    );
+                    let patch = json!({ "metadata": { "annotations": { ORPHANED_AT_ANNOTATION: now.to_string() } } });
+                    api.patch(&pvc_name, &params, serde_json::to_vec(&patch)?)
+                        .await?;
+                }
+                Some(_) if !orphaned => {
+                    let patch =
+                        json!({ "metadata": { "annotations": { ORPHANED_AT_ANNOTATION: null } } });
+                    api.patch(&pvc_name, &params, serde_json::to_vec(&patch)?)
+                        .await?;
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// PVCs are restored only before the StatefulSet is created
+    async fn restore_set(
+        &self,
+        ns: &str,
+        set_name: &str,
+        source_set: &str,
         yaml: Option<String>,
+        backup: &str,
     ) -> Result<()> {
+        let api = get_api::<StatefulSet>(&self.client, ns);
         match yaml {
-            Some(t) => {
-                let new_set = from_yaml(&t)?;
-                let api = get_api::<StatefulSet>(&self.client, &ns);
-                let dp = DeleteParams::default();
+            Some(yaml) if api.get(set_name).await.is_err() => {
+                let set = from_yaml::<StatefulSet>(&yaml)?;
+                restore_claims(&self.client, ns, &set, source_set, backup).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Flow archives are restored only before the NiFi StatefulSet is created
+    async fn restore_flow(&self, d: &NiFiDeployment, name: &str, ns: &str) -> Result<()> {
+        let api = get_api::<StatefulSet>(&self.client, ns);
+        match self.nifi_template(name, d)? {
+            Some(yaml) if api.get(name).await.is_err() => {
+                let set = from_yaml::<StatefulSet>(&yaml)?;
+                restore_from_s3(&self.client, &self.template, name, ns, &d.spec, &set).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Pods of a StatefulSet with unknown StorageClass would be Pending forever, classes which
+    /// the existing StatefulSets use already are not looked up again
+    async fn validate_storage_classes(&self, ns: &str, expected: &[StatefulSet]) -> Result<()> {
+        let sets = get_api::<StatefulSet>(&self.client, ns);
+        let mut classes = vec![];
+        for set in expected {
+            let current = match sets.get(&Meta::name(set)).await {
+                Ok(current) => claim_sizes(&current),
+                Err(_) => vec![],
+            };
+            for claim in claim_sizes(set) {
+                if let Some(class) = claim.storage_class {
+                    let unchanged = current
+                        .iter()
+                        .any(|c| c.storage_class.as_ref() == Some(&class));
+                    if !unchanged && !classes.contains(&class) {
+                        classes.push(class);
+                    }
+                }
+            }
+        }
+        if classes.is_empty() {
+            return Ok(());
+        }
+        let api: Api<StorageClass> = Api::all(self.client.as_ref().clone());
+        let existing = api
+            .list(&ListParams::default())
+            .await?
+            .into_iter()
+            .map(|sc| Meta::name(&sc))
+            .collect::<Vec<_>>();
+        let missing = classes
+            .into_iter()
+            .filter(|c| !existing.contains(c))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::from(InvalidSpec(format!(
+                "StorageClasses {:?} do not exist, existing are {:?}",
+                missing, existing
+            ))))
+        }
+    }
+
+    async fn validate_expansion(&self, storage_class: &Option<String>) -> Result<()> {
+        let api: Api<StorageClass> = Api::all(self.client.as_ref().clone());
+        let classes = api.list(&ListParams::default()).await?;
+        let class = classes.into_iter().find(|sc| match storage_class {
+            Some(name) => Meta::name(sc) == *name,
+            None => sc
+                .metadata
+                .annotations
+                .iter()
+                .flatten()
+                .any(|(k, v)| k == DEFAULT_STORAGE_CLASS_ANNOTATION && v == "true"),
+        });
+        match class {
+            Some(sc) if sc.allow_volume_expansion.unwrap_or(false) => Ok(()),
+            Some(sc) => Err(Error::from(InvalidSpec(format!(
+                "StorageClass {} does not allow volume expansion",
+                Meta::name(&sc)
+            )))),
+            None => Err(Error::from(InvalidSpec(format!(
+                "StorageClass {:?} to expand volumes is not found",
+                storage_class
+            )))),
+        }
+    }
+
+    async fn recreate_set(
+        &self,
+        ns: &str,
+        set_params: &SetParams,
+        new_set: Option<StatefulSet>,
+        dp: DeleteParams,
+    ) -> Result<()> {
+        match new_set {
+            Some(new_set) => {
+                let api = get_api::<StatefulSet>(&self.client, ns);
                 api.delete(&set_params.set_name, &dp)
                     .await
                     .map(|_| ())
                     .map_err(Error::from)?;
+                for _ in 0..SET_DELETION_CHECKS {
+                    if api.get(&set_params.set_name).await.is_err() {
+                        break;
+                    }
+                    delay_for(Duration::from_secs(1)).await;
+                }
                 let pp = PostParams::default();
                 api.create(&pp, &new_set).await.map(|_| ())
             }
@@ -138,94 +489,423 @@ impl StatefulSetController {
     }
 
     pub fn nifi_template(&self, name: &str, d: &NiFiDeployment) -> Result<Option<String>> {
-        self.template.nifi_statefulset(&name, &d.spec)
+        let yaml = self.template.nifi_statefulset(name, &d.spec)?;
+        if Template::fips_enabled(&d.spec) {
+            let image = yaml
+                .as_ref()
+                .map(|y| from_yaml::<StatefulSet>(y))
+                .transpose()?
+                .and_then(|s| container_image(&s, NIFI_CONTAINER_NAME));
+            if let Some(image) = image {
+                validate_fips(&image)?;
+            }
+        }
+        Ok(yaml)
     }
 
     pub fn zk_template(&self, name: &str, d: &NiFiDeployment) -> Result<Option<String>> {
-        self.template.zk_statefulset(
-            &name,
-            &d.spec.zk.replicas,
-            &d.spec.zk.image,
-            &d.spec.storage_class,
-        )
+        self.template.zk_statefulset(name, &d.spec)
     }
 
-    pub async fn handle_sets(
-        &self,
-        d: &NiFiDeployment,
-        name: &str,
-        ns: &str,
-        nifi_cm_state: ConfigMapState,
-        service_updated: bool,
-    ) -> Result<bool> {
-        let nifi = get_or_create::<StatefulSet, _>(&self.client, &name, &name, &ns, |name| {
-            self.nifi_template(&name, &d)
-        });
-        let zk_set_name = zk_set_name(&name);
-        let get_yaml = |name: &str| self.zk_template(&name, &d);
-        let zk = get_or_create::<StatefulSet, _>(&self.client, &zk_set_name, &name, &ns, get_yaml);
-        let (nifi_res, zk_res) = futures::future::join(nifi, zk).await;
-
-        let nifi_updated = match nifi_res? {
-            Left(Some(existing_set)) => {
-                let params = SetParams {
-                    replicas: d.clone().spec.nifi_replicas as i32,
-                    container: NIFI_CONTAINER_NAME.to_string(),
-                    image: d.clone().spec.image,
-                    set_name: name.to_string(),
-                    app_label: NIFI_APP_LABEL.to_string(),
-                    storage_class: d.clone().spec.storage_class,
-                    cm_state: Some(nifi_cm_state.clone()),
-                    svc_updated: service_updated,
-                };
-                self.update_existing_set(
-                    &d,
-                    &name,
-                    &ns,
-                    existing_set,
-                    &params,
-                    |cr_name, deployment| self.nifi_template(&cr_name, &deployment),
-                )
-                .await
-            }
-            Right(Some(_)) => Ok(true),
-            _ => Ok(false),
-        };
-
-        let zk_updated = match zk_res? {
-            Left(Some(existing_set)) if nifi_updated.is_ok() => {
+    pub async fn handle_zk_set(&self, d: &NiFiDeployment, name: &str, ns: &str) -> Result<bool> {
+        let zk_set_name = zk_set_name(name);
+        let zk = get_or_create_convert::<StatefulSet, _, _>(
+            &self.client,
+            &zk_set_name,
+            name,
+            ns,
+            |name| self.zk_template(name, d),
+            with_template_hash,
+        )
+        .await?;
+        match zk {
+            Left(Some(existing_set)) if d.spec.zk.embedded() => {
+                let current_replicas = existing_set.spec.as_ref().and_then(|s| s.replicas);
+                let replicas = d.spec.running_zk_replicas() as i32;
+                validate_zk_replicas(&d.spec, &zk_set_name, current_replicas)?;
                 let params = SetParams {
-                    replicas: d.clone().spec.zk.replicas as i32,
+                    replicas,
                     container: ZOOKEEPER_CONTAINER_NAME.to_string(),
                     image: d.clone().spec.zk.image,
                     set_name: zk_set_name,
                     app_label: ZK_APP_LABEL.to_string(),
-                    storage_class: d.clone().spec.storage_class,
                     cm_state: None,
-                    svc_updated: false,
                 };
                 self.update_existing_set(
-                    &d,
-                    &name,
-                    &ns,
+                    d,
+                    name,
+                    ns,
                     existing_set,
                     &params,
-                    |cr_name, deployment| self.zk_template(&cr_name, &deployment),
+                    |cr_name, deployment| self.zk_template(cr_name, deployment),
                 )
                 .await
             }
             Right(Some(_)) => Ok(true),
             _ => Ok(false),
+        }
+    }
+
+    /// NiFi image changes are checked against the upgrade paths and the flow is backed up first,
+    /// scale-down offloads flowfiles of the removed NiFi nodes via the NiFi REST API
+    async fn update_nifi_set(
+        &self,
+        d: &NiFiDeployment,
+        name: &str,
+        ns: &str,
+        existing_set: StatefulSet,
+        nifi_cm_state: ConfigMapState,
+    ) -> Result<bool> {
+        let expected_set = self
+            .nifi_template(name, d)?
+            .map(|y| from_yaml::<StatefulSet>(&y))
+            .transpose()?;
+        if let (Some(current), Some(expected)) = (
+            container_image(&existing_set, NIFI_CONTAINER_NAME),
+            expected_set
+                .as_ref()
+                .and_then(|s| container_image(s, NIFI_CONTAINER_NAME)),
+        ) {
+            let allow_downgrade = d
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|a| a.get(ALLOW_DOWNGRADE_ANNOTATION))
+                .is_some_and(|v| v == "true");
+            validate_upgrade(&current, &expected, allow_downgrade)?;
+            let flow_backup = d
+                .spec
+                .upgrade
+                .as_ref()
+                .and_then(|u| u.flow_backup)
+                .unwrap_or(true);
+            if flow_backup && current != expected && !d.spec.hibernated() {
+                backup_flow(&self.client, &self.template, name, ns, &d.spec, &current).await?;
+            }
+        }
+        let replicas = d.spec.running_nifi_replicas() as i32;
+        let current_replicas = existing_set
+            .spec
+            .as_ref()
+            .and_then(|s| s.replicas)
+            .unwrap_or(1);
+        if d.spec.hibernated() && current_replicas > 0 {
+            let pod_name = format!("{}-0", name);
+            let api = NiFiApi::connect(
+                &self.client,
+                &self.template,
+                name,
+                ns,
+                &d.spec,
+                Some(&pod_name),
+            )
+            .await?;
+            stop_flow(&api, name)
+                .await
+                .map_err(|e| Error::msg(format!("Hibernation of {} is postponed: {}", name, e)))?;
+        }
+        let offload = d
+            .spec
+            .api
+            .as_ref()
+            .and_then(|a| a.offload_on_scale_down)
+            .unwrap_or(true);
+        // the first node stays in the cluster, so the REST API is called on it
+        let api = if offload && replicas > 0 && replicas < current_replicas {
+            let pod_name = format!("{}-0", name);
+            let api = NiFiApi::connect(
+                &self.client,
+                &self.template,
+                name,
+                ns,
+                &d.spec,
+                Some(&pod_name),
+            )
+            .await?;
+            offload_nodes(&api, name, current_replicas, replicas)
+                .await
+                .map_err(|e| {
+                    Error::msg(format!("Graceful scale-down of {} failed: {}", name, e))
+                })?;
+            Some(api)
+        } else {
+            None
+        };
+        let params = SetParams {
+            replicas,
+            container: NIFI_CONTAINER_NAME.to_string(),
+            image: d.clone().spec.image,
+            set_name: name.to_string(),
+            app_label: NIFI_APP_LABEL.to_string(),
+            cm_state: Some(nifi_cm_state),
+        };
+        let updated = self
+            .update_existing_set(d, name, ns, existing_set, &params, |cr_name, deployment| {
+                self.nifi_template(cr_name, deployment)
+            })
+            .await?;
+        if let Some(api) = api {
+            remove_nodes(&api, name, replicas).await?;
+        }
+        Ok(updated)
+    }
+
+    pub async fn handle_sets(
+        &self,
+        d: &NiFiDeployment,
+        name: &str,
+        ns: &str,
+        nifi_cm_state: ConfigMapState,
+    ) -> Result<bool> {
+        validate_containers(&d.spec)?;
+        let nifi_yaml = self.nifi_template(name, d)?;
+        let zk_yaml = self.zk_template(name, d)?;
+        let expected = [&nifi_yaml, &zk_yaml]
+            .iter()
+            .filter_map(|yaml| yaml.as_deref())
+            .map(from_yaml::<StatefulSet>)
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(nifi_set) = expected.iter().find(|s| Meta::name(*s) == name) {
+            validate_volumes(&d.spec, nifi_set)?;
+        }
+        self.validate_storage_classes(ns, &expected).await?;
+        if let Some(restore) = d
+            .spec
+            .persistence
+            .as_ref()
+            .and_then(|p| p.restore_from.as_ref())
+        {
+            let source = restore.deployment_name.as_deref().unwrap_or(name);
+            self.restore_set(ns, name, source, nifi_yaml, &restore.backup)
+                .await?;
+            self.restore_set(
+                ns,
+                &zk_set_name(name),
+                &zk_set_name(source),
+                zk_yaml,
+                &restore.backup,
+            )
+            .await?;
+        }
+        if d.spec.restore.is_some() {
+            self.restore_flow(d, name, ns).await?;
+        }
+        let nifi = get_or_create_convert::<StatefulSet, _, _>(
+            &self.client,
+            name,
+            name,
+            ns,
+            |name| self.nifi_template(name, d),
+            with_template_hash,
+        );
+        let nifi_updated = match nifi.await? {
+            Left(Some(existing_set)) => {
+                self.update_nifi_set(d, name, ns, existing_set, nifi_cm_state)
+                    .await
+            }
+            Right(Some(_)) => Ok(true),
+            _ => Ok(false),
+        };
+
+        let zk_updated = match &nifi_updated {
+            Ok(_) => self.handle_zk_set(d, name, ns).await,
+            Err(_) => Ok(false),
         };
 
-        nifi_updated.and(zk_updated)
+        let updated = nifi_updated.and(zk_updated)?;
+        if let Some(cfg) = d
+            .spec
+            .persistence
+            .as_ref()
+            .and_then(|p| p.orphaned_claims.as_ref())
+            .filter(|c| c.delete.unwrap_or(false))
+        {
+            let retention = cfg
+                .retention_seconds
+                .unwrap_or(DEFAULT_ORPHANED_CLAIMS_RETENTION_SECONDS);
+            self.delete_orphaned_claims(ns, name, d.spec.nifi_replicas, retention)
+                .await?;
+            self.delete_orphaned_claims(
+                ns,
+                &self::zk_set_name(name),
+                d.spec.zk.replicas,
+                retention,
+            )
+            .await?;
+        }
+        Ok(updated)
     }
 }
 
+fn validate_containers(spec: &NiFiDeploymentSpec) -> Result<()> {
+    let reserved = |containers: &[Container]| {
+        containers
+            .iter()
+            .map(|c| c.name.clone())
+            .filter(|name| NIFI_RESERVED_CONTAINER_NAMES.contains(&name.as_str()))
+            .collect::<Vec<_>>()
+    };
+    let collisions = [reserved(&spec.init_containers), reserved(&spec.sidecars)].concat();
+    let names = spec
+        .init_containers
+        .iter()
+        .chain(spec.sidecars.iter())
+        .map(|c| c.name.clone())
+        .collect::<Vec<_>>();
+    let duplicates = duplicates(&names, &names);
+    if !collisions.is_empty() {
+        Err(Error::from(InvalidSpec(format!(
+            "container names {:?} are reserved by Kubefi",
+            collisions
+        ))))
+    } else if !duplicates.is_empty() {
+        Err(Error::from(InvalidSpec(format!(
+            "container names {:?} are used more than once",
+            duplicates
+        ))))
+    } else {
+        Ok(())
+    }
+}
+
+/// `spec.volumes` must not clash with each other or with volumes and claims of the NiFi template
+fn validate_volumes(spec: &NiFiDeploymentSpec, set: &StatefulSet) -> Result<()> {
+    let mut names = set
+        .spec
+        .iter()
+        .flat_map(|s| s.template.spec.iter())
+        .flat_map(|pod| pod.volumes.iter().flatten())
+        .map(|v| v.name.clone())
+        .collect::<Vec<_>>();
+    names.extend(claim_names(set));
+    let user_names = spec
+        .volumes
+        .iter()
+        .map(|v| v.name.clone())
+        .collect::<Vec<_>>();
+    let clashes = duplicates(&user_names, &names);
+    if clashes.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::from(InvalidSpec(format!(
+            "volume names {:?} are used more than once or by the NiFi StatefulSet template",
+            clashes
+        ))))
+    }
+}
+
+/// Names of `candidates` which occur more than once in `names`
+fn duplicates(candidates: &[String], names: &[String]) -> Vec<String> {
+    let mut duplicates = vec![];
+    for name in candidates {
+        if names.iter().filter(|n| *n == name).count() > 1 && !duplicates.contains(name) {
+            duplicates.push(name.clone());
+        }
+    }
+    duplicates
+}
+
+/// PVCs created from volumeClaimTemplates of NiFi and ZooKeeper StatefulSets
+pub async fn set_claims(client: &Client, name: &str, ns: &str) -> Result<Vec<String>> {
+    let api = get_api::<StatefulSet>(client, ns);
+    let pvc_names = find_names::<PersistentVolumeClaim>(client, ns, &ListParams::default()).await?;
+    let mut claims = vec![];
+    for set_name in &[name.to_string(), zk_set_name(name), registry_name(name)] {
+        let set = match api.get(set_name).await {
+            Ok(set) => set,
+            Err(_) => continue,
+        };
+        for claim in claim_names(&set) {
+            claims.extend(
+                pvc_names
+                    .iter()
+                    .filter(|n| is_set_claim(n, &claim, set_name))
+                    .cloned(),
+            );
+        }
+    }
+    Ok(claims)
+}
+
+/// Scaling of the ensemble of a running set, the whole ensemble is stopped and started again by
+/// hibernation
+fn validate_zk_replicas(
+    spec: &NiFiDeploymentSpec,
+    set_name: &str,
+    current: Option<i32>,
+) -> Result<()> {
+    let replicas = spec.running_zk_replicas() as i32;
+    match current {
+        Some(current) if current > 0 && !spec.zk_hibernated() => {
+            validate_zk_scale(current, replicas)
+        }
+        Some(_) => Ok(()),
+        None => {
+            warn!(
+                "{} has no replicas, scaling to {} is not validated",
+                set_name, replicas
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Every ZooKeeper server gets the full server list on start. While the ensemble is rolled after scaling,
+/// neither remaining servers nor new ones alone may be able to form a quorum of their own server list.
+/// A standalone server has no quorum, so an ensemble may be started from one.
+fn validate_zk_scale(current: i32, expected: i32) -> Result<()> {
+    let quorum = |replicas: i32| replicas / 2 + 1;
+    let safe = if current == 1 {
+        true
+    } else if expected < current {
+        expected >= quorum(current)
+    } else {
+        expected - current < quorum(expected)
+    };
+    if expected < 1 {
+        Err(Error::from(InvalidSpec(
+            "ZooKeeper requires at least 1 replica, set spec.zk.external or spec.zk.clusterRef to use another ensemble"
+                .to_string(),
+        )))
+    } else if !safe {
+        Err(Error::from(InvalidSpec(format!(
+            "scaling ZooKeeper from {} to {} replicas breaks its quorum, scale in smaller steps",
+            current, expected
+        ))))
+    } else {
+        Ok(())
+    }
+}
+
+/// NiFi set recreated with all its adopted Pods pending, so that they are moved to its revision by
+/// the rollout one node at a time rather than by the StatefulSet controller at once
+fn with_pending_pods(mut set: StatefulSet, params: &SetParams) -> StatefulSet {
+    if params.app_label == NIFI_APP_LABEL {
+        with_partition(&mut set, params.replicas);
+    }
+    set
+}
+
 fn zk_set_name(name: &str) -> String {
     format!("{}-zookeeper", &name)
 }
 
+fn canary(d: &NiFiDeployment) -> bool {
+    d.spec
+        .upgrade
+        .as_ref()
+        .and_then(|u| u.strategy.as_ref())
+        .is_some_and(|s| *s == UpgradeStrategy::Canary)
+}
+
+fn container_image(set: &StatefulSet, container: &str) -> Option<String> {
+    set.spec
+        .as_ref()
+        .and_then(|s| s.template.spec.as_ref())
+        .and_then(|spec| spec.containers.iter().find(|c| c.name == container))
+        .and_then(|c| c.image.clone())
+}
+
 fn image_changed(set: &StatefulSet, image: &Option<String>, container: &str) -> bool {
     match image {
         Some(target_image) => set
@@ -243,30 +923,211 @@ fn image_changed(set: &StatefulSet, image: &Option<String>, container: &str) ->
     }
 }
 
+pub fn with_template_hash(mut set: StatefulSet) -> Result<StatefulSet> {
+    let template = serde_json::to_string(&set.spec.as_ref().map(|s| &s.template))?;
+    let mut hasher = Sha256::new();
+    hasher.update(template.as_bytes());
+    let mut annotations = set.metadata.annotations.unwrap_or_default();
+    annotations.insert(TEMPLATE_HASH_ANNOTATION.to_string(), hex_digest(hasher));
+    set.metadata.annotations = Some(annotations);
+    Ok(set)
+}
+
+/// True when the template hash of `expected` differs from the one of `current`. Sets created
+/// before the hash annotation get the expected hash without being rolled
+pub async fn pod_template_changed(
+    client: &Client,
+    ns: &str,
+    current: &StatefulSet,
+    expected: &StatefulSet,
+) -> Result<bool> {
+    match (template_hash(current), template_hash(expected)) {
+        (None, Some(hash)) => {
+            info!("Recording the template hash of {}", Meta::name(current));
+            let params = PatchParams {
+                patch_strategy: PatchStrategy::Merge,
+                ..PatchParams::default()
+            };
+            let patch =
+                json!({ "metadata": { "annotations": { TEMPLATE_HASH_ANNOTATION: hash } } });
+            get_api::<StatefulSet>(client, ns)
+                .patch(&Meta::name(current), &params, serde_json::to_vec(&patch)?)
+                .await?;
+            Ok(false)
+        }
+        (current_hash, expected_hash) => Ok(current_hash != expected_hash),
+    }
+}
+
+pub fn template_hash(set: &StatefulSet) -> Option<&String> {
+    set.metadata
+        .annotations
+        .as_ref()
+        .and_then(|a| a.get(TEMPLATE_HASH_ANNOTATION))
+}
+
+fn claim_names(set: &StatefulSet) -> Vec<String> {
+    set.spec
+        .as_ref()
+        .and_then(|s| s.volume_claim_templates.as_ref())
+        .map(|claims| {
+            claims
+                .iter()
+                .filter_map(|pvc| pvc.metadata.name.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// StatefulSet PVCs are named as <claim>-<set>-<ordinal>
+fn is_set_claim(pvc: &str, claim: &str, set_name: &str) -> bool {
+    claim_ordinal(pvc, claim, set_name).is_some()
+}
+
+fn claim_ordinal(pvc: &str, claim: &str, set_name: &str) -> Option<u8> {
+    pvc.strip_prefix(&format!("{}-{}-", claim, set_name))
+        .and_then(|ordinal| ordinal.parse::<u8>().ok())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+struct ClaimSize {
+    name: String,
+    size: String,
+    storage_class: Option<String>,
+}
+
+fn claim_sizes(set: &StatefulSet) -> Vec<ClaimSize> {
+    set.spec
+        .iter()
+        .flat_map(|s| s.volume_claim_templates.iter().flatten())
+        .filter_map(|pvc| {
+            let spec = pvc.spec.as_ref()?;
+            let size = spec.resources.as_ref()?.requests.as_ref()?.get("storage")?;
+            Some(ClaimSize {
+                name: pvc.metadata.name.clone()?,
+                size: size.0.clone(),
+                storage_class: spec.storage_class_name.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Claims requesting more storage than the current StatefulSet, shrinking is not supported by Kubernetes
+fn grown_claims(current: &StatefulSet, expected: &StatefulSet) -> Result<Vec<ClaimSize>> {
+    let current = claim_sizes(current);
+    let mut grown = vec![];
+    for claim in claim_sizes(expected) {
+        let size = nano_quantity(&claim.size).ok_or_else(|| {
+            Error::from(InvalidSpec(format!(
+                "storage of {} is not a valid quantity: {}",
+                &claim.name, &claim.size
+            )))
+        })?;
+        let current_size = current
+            .iter()
+            .find(|c| c.name == claim.name)
+            .and_then(|c| nano_quantity(&c.size).map(|q| (c, q)));
+        if let Some((current, current_size)) = current_size {
+            if size > current_size {
+                grown.push(claim);
+            } else if size < current_size {
+                return Err(Error::from(InvalidSpec(format!(
+                    "storage of {} can not be decreased from {} to {}",
+                    &claim.name, &current.size, &claim.size
+                ))));
+            }
+        }
+    }
+    Ok(grown)
+}
+
+/// Kubernetes quantity in bytes or CPU cores, e.g. 512Mi, 5G, 5e9 or 250m
+pub fn quantity(q: &str) -> Option<f64> {
+    quantity_parts(q).map(|(digits, exponent, multiplier)| {
+        digits as f64 * 10f64.powi(exponent) * multiplier as f64
+    })
+}
+
+/// Kubernetes quantity in billionths of bytes or CPU cores, exact to compare sizes without rounding
+fn nano_quantity(q: &str) -> Option<u128> {
+    let (digits, exponent, multiplier) = quantity_parts(q)?;
+    let value = digits.checked_mul(multiplier)?;
+    let exponent = exponent + 9;
+    if exponent >= 0 {
+        value.checked_mul(10u128.checked_pow(exponent as u32)?)
+    } else {
+        Some(value / 10u128.checked_pow(-exponent as u32)?)
+    }
+}
+
+/// Quantity as `digits * 10^exponent * multiplier`, e.g. 1.5Gi is `15 * 10^-1 * 2^30`
+fn quantity_parts(q: &str) -> Option<(u128, i32, u128)> {
+    let q = q.trim();
+    let split = q
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(q.len());
+    let (number, suffix) = q.split_at(split);
+    let (integer, fraction) = match number.find('.') {
+        Some(dot) => (&number[..dot], &number[dot + 1..]),
+        None => (number, ""),
+    };
+    if integer.is_empty() && fraction.is_empty() || fraction.contains('.') {
+        return None;
+    }
+    let digits = format!("{}{}", integer, fraction).parse::<u128>().ok()?;
+    let (exponent, multiplier) = match suffix {
+        "" => (0, 1),
+        "n" => (-9, 1),
+        "u" => (-6, 1),
+        "m" => (-3, 1),
+        "k" => (3, 1),
+        "M" => (6, 1),
+        "G" => (9, 1),
+        "T" => (12, 1),
+        "P" => (15, 1),
+        "E" => (18, 1),
+        "Ki" => (0, 1 << 10),
+        "Mi" => (0, 1 << 20),
+        "Gi" => (0, 1 << 30),
+        "Ti" => (0, 1 << 40),
+        "Pi" => (0, 1 << 50),
+        "Ei" => (0, 1 << 60),
+        // decimal exponent, e.g. 5e9 or 1E-3
+        s if s.starts_with('e') || s.starts_with('E') => (s[1..].parse::<i32>().ok()?, 1),
+        _ => return None,
+    };
+    Some((digits, exponent - fraction.len() as i32, multiplier))
+}
+
 fn scale_set(set: &StatefulSet, expected_replicas: i32) -> bool {
     let replicas = set.clone().spec.as_ref().and_then(|s| s.replicas);
     matches!(replicas, Some(current_replicas) if current_replicas != expected_replicas)
 }
 
-fn storage_class(set: &StatefulSet, storage_class: &Option<String>) -> bool {
-    match storage_class {
-        Some(sc) => set
-            .clone()
-            .spec
-            .and_then(|s| {
-                s.volume_claim_templates.map(|vc| {
-                    vc.iter().any(|pvc| {
-                        pvc.spec.clone().into_iter().any(|spec| {
-                            spec.storage_class_name
-                                .map(|scn| &scn != sc)
-                                .unwrap_or(false)
-                        })
-                    })
-                })
+/// Claims keep their StorageClass if it is not set, otherwise it is compared per claim name
+fn storage_class_changed(current: &StatefulSet, expected: &StatefulSet) -> bool {
+    let classes = |set: &StatefulSet| {
+        set.spec
+            .iter()
+            .flat_map(|s| s.volume_claim_templates.iter().flatten())
+            .map(|pvc| {
+                let class = pvc.spec.as_ref().and_then(|s| s.storage_class_name.clone());
+                (pvc.metadata.name.clone(), class)
             })
-            .unwrap_or(false),
-        None => false,
-    }
+            .collect::<Vec<_>>()
+    };
+    let expected = classes(expected);
+    classes(current).into_iter().any(|(name, class)| {
+        let expected_class = expected.iter().find(|(n, _)| n == &name).map(|(_, c)| c);
+        matches!((class, expected_class), (Some(c), Some(Some(e))) if &c != e)
+    })
 }
 
 fn logging_cm(set: &StatefulSet, logging_cm: Option<String>) -> bool {
@@ -296,3 +1157,299 @@ fn logging_cm(set: &StatefulSet, logging_cm: Option<String>) -> bool {
         None => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::Volume;
+
+    use super::*;
+
+    fn claims_set(size: &str) -> StatefulSet {
+        serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "nifi" },
+            "spec": {
+                "selector": {},
+                "serviceName": "nifi",
+                "template": {},
+                "volumeClaimTemplates": [{
+                    "metadata": { "name": "data" },
+                    "spec": { "resources": { "requests": { "storage": size } } }
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_nano_quantity() {
+        let gi = 1u128 << 30;
+        assert_eq!(nano_quantity("1Ki"), Some(1024 * 1_000_000_000));
+        assert_eq!(nano_quantity("1Mi"), Some((1 << 20) * 1_000_000_000));
+        assert_eq!(nano_quantity("2Gi"), Some(2 * gi * 1_000_000_000));
+        assert_eq!(nano_quantity("1k"), Some(1_000 * 1_000_000_000));
+        assert_eq!(nano_quantity("5M"), Some(5_000_000 * 1_000_000_000));
+        assert_eq!(nano_quantity("5G"), Some(5_000_000_000 * 1_000_000_000));
+        assert_eq!(nano_quantity("1.5Gi"), Some(3 * gi / 2 * 1_000_000_000));
+        assert_eq!(nano_quantity("0.5"), Some(500_000_000));
+        assert_eq!(nano_quantity("250m"), Some(250_000_000));
+        assert_eq!(nano_quantity("5e9"), nano_quantity("5G"));
+        assert_eq!(nano_quantity("5E9"), nano_quantity("5G"));
+        assert_eq!(nano_quantity("1.5e+3"), nano_quantity("1500"));
+        assert_eq!(nano_quantity("1e-3"), nano_quantity("1m"));
+        assert_eq!(
+            nano_quantity("1E"),
+            Some(1_000_000_000_000_000_000 * 1_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_invalid_quantity() {
+        assert_eq!(nano_quantity(""), None);
+        assert_eq!(nano_quantity("Gi"), None);
+        assert_eq!(nano_quantity("1K"), None);
+        assert_eq!(nano_quantity("1GB"), None);
+        assert_eq!(nano_quantity("1.2.3"), None);
+        assert_eq!(nano_quantity("5e"), None);
+        assert_eq!(nano_quantity("-1Gi"), None);
+    }
+
+    #[test]
+    fn test_quantity() {
+        assert_eq!(quantity("250m"), Some(0.25));
+        assert_eq!(quantity("1.5Gi"), Some(1.5 * 1024f64.powi(3)));
+        assert_eq!(quantity("5e9"), Some(5e9));
+        assert_eq!(quantity("2"), Some(2.0));
+    }
+
+    #[test]
+    fn test_grown_claims() {
+        let current = claims_set("1Gi");
+        assert!(grown_claims(&current, &claims_set("1024Mi"))
+            .unwrap()
+            .is_empty());
+        assert!(grown_claims(&current, &claims_set("1073741824"))
+            .unwrap()
+            .is_empty());
+        let grown = grown_claims(&current, &claims_set("1.5Gi")).unwrap();
+        assert_eq!(grown.len(), 1);
+        assert_eq!(grown[0].size, "1.5Gi");
+        assert_eq!(
+            grown_claims(&current, &claims_set("1073741825"))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(grown_claims(&current, &claims_set("512Mi")).is_err());
+        assert!(grown_claims(&current, &claims_set("1GB")).is_err());
+    }
+
+    fn container(name: &str) -> Container {
+        Container {
+            name: name.to_string(),
+            ..Container::default()
+        }
+    }
+
+    fn volume(name: &str) -> Volume {
+        Volume {
+            name: name.to_string(),
+            ..Volume::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_containers() {
+        let spec = NiFiDeploymentSpec {
+            init_containers: vec![container("init")],
+            sidecars: vec![container("proxy"), container("exporter")],
+            ..NiFiDeploymentSpec::default()
+        };
+        assert!(validate_containers(&spec).is_ok());
+
+        let reserved = NiFiDeploymentSpec {
+            sidecars: vec![container("server")],
+            ..NiFiDeploymentSpec::default()
+        };
+        assert!(validate_containers(&reserved).is_err());
+
+        // init container of the restricted security profile
+        let reserved_init = NiFiDeploymentSpec {
+            init_containers: vec![container("copy-conf")],
+            ..NiFiDeploymentSpec::default()
+        };
+        assert!(validate_containers(&reserved_init).is_err());
+
+        let duplicate_sidecars = NiFiDeploymentSpec {
+            sidecars: vec![container("proxy"), container("proxy")],
+            ..NiFiDeploymentSpec::default()
+        };
+        assert!(validate_containers(&duplicate_sidecars).is_err());
+
+        let duplicate_init = NiFiDeploymentSpec {
+            init_containers: vec![container("proxy")],
+            sidecars: vec![container("proxy")],
+            ..NiFiDeploymentSpec::default()
+        };
+        assert!(validate_containers(&duplicate_init).is_err());
+    }
+
+    /// Rendered set with the pod volumes of the template and of `spec.volumes`
+    fn volumes_set(names: &[&str]) -> StatefulSet {
+        let mut set = claims_set("1Gi");
+        let pod = set
+            .spec
+            .as_mut()
+            .unwrap()
+            .template
+            .spec
+            .get_or_insert_with(Default::default);
+        pod.volumes = Some(names.iter().map(|n| volume(n)).collect());
+        set
+    }
+
+    #[test]
+    fn test_validate_volumes() {
+        let spec = NiFiDeploymentSpec {
+            volumes: vec![volume("extra")],
+            ..NiFiDeploymentSpec::default()
+        };
+        assert!(validate_volumes(&spec, &volumes_set(&["bootstrap-conf", "extra"])).is_ok());
+
+        let template_volume = NiFiDeploymentSpec {
+            volumes: vec![volume("bootstrap-conf")],
+            ..NiFiDeploymentSpec::default()
+        };
+        let set = volumes_set(&["bootstrap-conf", "bootstrap-conf"]);
+        assert!(validate_volumes(&template_volume, &set).is_err());
+
+        let claim_volume = NiFiDeploymentSpec {
+            volumes: vec![volume("data")],
+            ..NiFiDeploymentSpec::default()
+        };
+        let set = volumes_set(&["bootstrap-conf", "data"]);
+        assert!(validate_volumes(&claim_volume, &set).is_err());
+
+        let duplicate_volumes = NiFiDeploymentSpec {
+            volumes: vec![volume("extra"), volume("extra")],
+            ..NiFiDeploymentSpec::default()
+        };
+        let set = volumes_set(&["bootstrap-conf", "extra", "extra"]);
+        assert!(validate_volumes(&duplicate_volumes, &set).is_err());
+    }
+
+    #[test]
+    fn test_with_pending_pods() {
+        let mut params = SetParams {
+            replicas: 3,
+            container: NIFI_CONTAINER_NAME.to_string(),
+            image: None,
+            set_name: "nifi".to_string(),
+            app_label: NIFI_APP_LABEL.to_string(),
+            cm_state: None,
+        };
+        let mut set = claims_set("1Gi");
+        set.spec.as_mut().unwrap().update_strategy = Some(Default::default());
+        assert_eq!(partition(&with_pending_pods(set.clone(), &params)), 3);
+        // ZooKeeper servers are restarted by the operator
+        params.app_label = ZK_APP_LABEL.to_string();
+        assert_eq!(partition(&with_pending_pods(set, &params)), 0);
+    }
+
+    #[test]
+    fn test_canary_rollback_hash() {
+        let mut set = claims_set("1Gi");
+        set.metadata.annotations = Some(
+            vec![(TEMPLATE_HASH_ANNOTATION.to_string(), "first".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        let mut canary_set = claims_set("1Gi");
+        with_canary(&set, &mut canary_set);
+        assert!(rollout_pending(&canary_set));
+        // a spec changed during the canary soak still rolls back to the template before both
+        canary_set
+            .metadata
+            .annotations
+            .as_mut()
+            .unwrap()
+            .insert(TEMPLATE_HASH_ANNOTATION.to_string(), "second".to_string());
+        let mut next_set = claims_set("1Gi");
+        with_canary(&canary_set, &mut next_set);
+        // the canary is kept by a replacement which starts no new rollout
+        let mut replaced = claims_set("1Gi");
+        with_rollout_state(&next_set, &mut replaced);
+        let hashes = replaced
+            .metadata
+            .annotations
+            .unwrap()
+            .into_values()
+            .collect::<Vec<_>>();
+        assert_eq!(hashes, vec!["first".to_string()]);
+    }
+
+    #[test]
+    fn test_zk_roll_state() {
+        let set = claims_set("1Gi");
+        let mut replaced = claims_set("1Gi");
+        with_zk_roll(&set, &mut replaced, false);
+        assert!(!zk_roll_pending(&replaced));
+        let mut revised = claims_set("1Gi");
+        with_zk_roll(&set, &mut revised, true);
+        assert!(zk_roll_pending(&revised));
+        // a server restarting for the previous revision has to rejoin first
+        let mut next = claims_set("1Gi");
+        with_zk_roll(&revised, &mut next, false);
+        assert!(zk_roll_pending(&next));
+    }
+
+    #[test]
+    fn test_zk_scale_from_standalone() {
+        assert!(validate_zk_scale(1, 3).is_ok());
+        assert!(validate_zk_scale(1, 5).is_ok());
+    }
+
+    #[test]
+    fn test_zk_scale_to_standalone() {
+        // 1 remaining server is no quorum of 5 or 3, it is a quorum of 2 and 1
+        assert!(validate_zk_scale(5, 1).is_err());
+        assert!(validate_zk_scale(3, 1).is_err());
+        assert!(validate_zk_scale(2, 1).is_err());
+        assert!(validate_zk_scale(1, 1).is_ok());
+    }
+
+    #[test]
+    fn test_zk_scale_up() {
+        assert!(validate_zk_scale(3, 3).is_ok());
+        assert!(validate_zk_scale(3, 4).is_ok());
+        assert!(validate_zk_scale(3, 5).is_ok());
+        assert!(validate_zk_scale(2, 3).is_ok());
+        // 4 new servers alone are a quorum of 7
+        assert!(validate_zk_scale(3, 7).is_err());
+        assert!(validate_zk_scale(2, 5).is_err());
+    }
+
+    #[test]
+    fn test_zk_scale_down() {
+        assert!(validate_zk_scale(5, 3).is_ok());
+        assert!(validate_zk_scale(3, 2).is_ok());
+        // 2 remaining servers are no quorum of 5
+        assert!(validate_zk_scale(5, 2).is_err());
+        assert!(validate_zk_scale(7, 3).is_err());
+    }
+
+    #[test]
+    fn test_zk_scale_to_zero() {
+        assert!(validate_zk_scale(3, 0).is_err());
+        let mut spec = NiFiDeploymentSpec::default();
+        spec.zk.replicas = 0;
+        assert!(validate_zk_replicas(&spec, "nifi-zookeeper", Some(3)).is_err());
+        // hibernation stops the whole ensemble
+        spec.zk.replicas = 3;
+        spec.hibernate = Some(true);
+        spec.hibernate_zk = Some(true);
+        assert!(validate_zk_replicas(&spec, "nifi-zookeeper", Some(3)).is_ok());
+        // the ensemble is started again
+        spec.hibernate = Some(false);
+        assert!(validate_zk_replicas(&spec, "nifi-zookeeper", Some(0)).is_ok());
+    }
+}
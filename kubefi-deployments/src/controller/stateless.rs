@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::ByteString;
+use kube::api::{Meta, PostParams};
+use kube::Client;
+use serde_json::{Map, Value};
+
+use crate::controller::nificomponents::secret_key_value;
+use crate::controller::workload::{
+    apply_workload, config_hash, delete_resource, registry_api, resolve_flow, workload_labels,
+};
+use crate::controller::ControllerError::{InvalidSpec, MissingProperty};
+use crate::controller::{create_resource, get_api, NiFiController, ReplaceResourceStatus};
+use crate::crd::{
+    NiFiStatelessDeployment, NiFiStatelessDeploymentSpec, NiFiStatelessDeploymentStatus,
+    ParameterCfg, StatelessMode,
+};
+use crate::registry_api::RegistryApi;
+use crate::template::Template;
+
+const FLOW_CONFIG_KEY: &str = "flow.json";
+
+pub type ReplaceStatelessStatus = ReplaceResourceStatus<NiFiStatelessDeploymentStatus>;
+
+pub struct StatelessController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiController {
+    /// Writes the flow configuration Secret and runs the flow in a Deployment or a Job,
+    /// the status is replaced only when it is different
+    pub async fn on_apply_stateless(
+        &self,
+        sd: NiFiStatelessDeployment,
+    ) -> Result<Option<ReplaceStatelessStatus>> {
+        let (name, ns) = read_stateless_meta(&sd)?;
+        let status = match self.stateless_controller.apply(&sd.spec, &name, &ns).await {
+            Ok((version, config_hash)) => NiFiStatelessDeploymentStatus {
+                version: Some(version),
+                config_hash: Some(config_hash),
+                error_msg: "".to_string(),
+            },
+            Err(e) => NiFiStatelessDeploymentStatus {
+                error_msg: e.to_string(),
+                ..sd.status.clone().unwrap_or_default()
+            },
+        };
+        if sd.status.as_ref() == Some(&status) {
+            return Ok(None);
+        }
+        Ok(Some(ReplaceStatelessStatus { name, ns, status }))
+    }
+
+    /// Stateless flows keep no data, so the workload and its Secret are deleted with the resource
+    pub async fn on_delete_stateless(&self, sd: NiFiStatelessDeployment) -> Result<()> {
+        let (name, ns) = read_stateless_meta(&sd)?;
+        let client = &self.stateless_controller.client;
+        delete_resource::<Deployment>(client, &name, &ns).await?;
+        delete_resource::<Job>(client, &name, &ns).await?;
+        delete_resource::<Secret>(client, &secret_name(&name), &ns).await
+    }
+}
+
+impl StatelessController {
+    async fn apply(
+        &self,
+        spec: &NiFiStatelessDeploymentSpec,
+        name: &str,
+        ns: &str,
+    ) -> Result<(i32, String)> {
+        let api = registry_api(
+            &self.client,
+            &self.template,
+            &spec.registry_url,
+            &spec.deployment_ref,
+            ns,
+        )
+        .await?;
+        let (config, version) = self.flow_config(spec, ns, &api).await?;
+        let config_hash = config_hash(&config, spec)?;
+        self.apply_secret(name, ns, &config).await?;
+        let yaml = self
+            .template
+            .nifi_stateless(name, spec, &config_hash)?
+            .ok_or_else(|| Error::msg(format!("NiFi Stateless template of {} is empty", name)))?;
+        let client = &self.client;
+        match spec.mode {
+            Some(StatelessMode::Once) => {
+                delete_resource::<Deployment>(client, name, ns).await?;
+                // Pods of a Job can not be changed, so the Job runs again with the new configuration
+                apply_workload::<Job>(client, name, ns, &yaml, &config_hash, true).await?
+            }
+            _ => {
+                delete_resource::<Job>(client, name, ns).await?;
+                apply_workload::<Deployment>(client, name, ns, &yaml, &config_hash, false).await?
+            }
+        }
+        Ok((version, config_hash))
+    }
+
+    /// Configuration of the NiFi Stateless engine with the resolved flow version
+    async fn flow_config(
+        &self,
+        spec: &NiFiStatelessDeploymentSpec,
+        ns: &str,
+        api: &RegistryApi,
+    ) -> Result<(String, i32)> {
+        let flow = resolve_flow(api, &spec.bucket, &spec.flow, spec.version).await?;
+        let mut config = json!({
+            "registryUrl": api.url(),
+            "bucketId": flow.bucket_id,
+            "flowId": flow.flow_id,
+            "flowVersion": flow.version,
+            "parameters": self.parameters(&spec.parameters, ns).await?
+        });
+        if !spec.failure_port_ids.is_empty() {
+            config["failurePortIds"] = json!(spec.failure_port_ids);
+        }
+        Ok((serde_json::to_string_pretty(&config)?, flow.version))
+    }
+
+    /// Plain values for parameters, sensitive ones are marked as such for the engine
+    async fn parameters(&self, parameters: &[ParameterCfg], ns: &str) -> Result<Value> {
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let mut values = Map::new();
+        for p in parameters {
+            let (value, sensitive) = match (&p.value, &p.value_from) {
+                (Some(value), None) => (value.clone(), p.sensitive.unwrap_or(false)),
+                (None, Some(secret_ref)) => {
+                    let (value, _) = secret_key_value(&secrets, secret_ref).await?;
+                    (value, p.sensitive.unwrap_or(true))
+                }
+                _ => {
+                    return Err(Error::from(InvalidSpec(format!(
+                        "parameter {} must have either value or valueFrom",
+                        &p.name
+                    ))))
+                }
+            };
+            let value = if sensitive {
+                json!({ "sensitive": "true", "value": value })
+            } else {
+                Value::String(value)
+            };
+            values.insert(p.name.clone(), value);
+        }
+        Ok(Value::Object(values))
+    }
+
+    async fn apply_secret(&self, name: &str, ns: &str, config: &str) -> Result<()> {
+        let api = get_api::<Secret>(&self.client, ns);
+        let secret_name = secret_name(name);
+        let mut data = BTreeMap::new();
+        data.insert(
+            FLOW_CONFIG_KEY.to_string(),
+            ByteString(config.as_bytes().to_vec()),
+        );
+        let labels = workload_labels("nifi-stateless", name);
+        let mut secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(secret_name.clone()),
+                namespace: Some(ns.to_string()),
+                labels: Some(labels),
+                ..ObjectMeta::default()
+            },
+            data: Some(data),
+            ..Secret::default()
+        };
+        match api.get(&secret_name).await {
+            Ok(current) if current.data == secret.data => Ok(()),
+            Ok(current) => {
+                debug!("Updating flow configuration Secret {}", &secret_name);
+                secret.metadata.resource_version = Meta::resource_ver(&current);
+                api.replace(&secret_name, &PostParams::default(), &secret)
+                    .await
+                    .map(|_| ())
+                    .map_err(Error::from)
+            }
+            Err(_) => create_resource(&api, secret).await.map(|_| ()),
+        }
+    }
+}
+
+fn secret_name(name: &str) -> String {
+    format!("{}-stateless", name)
+}
+
+fn read_stateless_meta(sd: &NiFiStatelessDeployment) -> Result<(String, String)> {
+    let missing =
+        |property: &str| Error::from(MissingProperty(property.to_string(), sd.kind.clone()));
+    let name = sd.metadata.name.clone().ok_or_else(|| missing("name"))?;
+    let ns = sd
+        .metadata
+        .namespace
+        .clone()
+        .ok_or_else(|| missing("namespace"))?;
+    Ok((name, ns))
+}
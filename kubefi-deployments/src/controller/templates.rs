@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use kube::Client;
+
+use crate::config::read_templates_config_map;
+use crate::controller::version::nifi_version;
+use crate::controller::NiFiController;
+use crate::crd::NiFiDeploymentSpec;
+use crate::discovery::ClusterApis;
+use crate::template::{closest_version, Template, TemplateSource, TemplateVersion};
+use crate::Namespace;
+
+impl NiFiController {
+    /// Reloads templates changed in the templates directory or ConfigMap, true when the
+    /// deployments need rendering again
+    pub async fn on_templates(&self) -> Result<bool> {
+        let data = match self.template.source() {
+            TemplateSource::ConfigMap { namespace, name } => Some(
+                read_templates_config_map(self.client.as_ref().clone(), namespace, name).await?,
+            ),
+            TemplateSource::Dir(_) => None,
+        };
+        let reloaded = self.template.reload(data)?;
+        if reloaded {
+            let versions =
+                versioned_controllers(&self.namespace, &self.client, &self.template, &self.apis)?;
+            self.versions.replace(versions);
+        }
+        Ok(reloaded)
+    }
+
+    /// Controller of the template set of the NiFi version of a deployment image, none when no
+    /// set applies to it or the image tag is not a version
+    pub fn for_version(&self, spec: &NiFiDeploymentSpec) -> Option<Rc<NiFiController>> {
+        closest_version(&self.versions.borrow(), nifi_version(&self.template, spec)?)
+    }
+
+    /// Controller rendering one deployment with the templates of its `templateOverrides`
+    /// ConfigMap merged over the operator ones, read again on every event
+    pub async fn with_template_overrides(&self, ns: &str, name: &str) -> Result<NiFiController> {
+        let overrides = read_templates_config_map(self.client.as_ref().clone(), ns, name).await?;
+        let template = self.template.with_overrides(overrides).map_err(|e| {
+            Error::msg(format!(
+                "Invalid template overrides in ConfigMap {}/{}: {}",
+                ns, name, e
+            ))
+        })?;
+        let mut controller = self.with_template(ns, Rc::new(template))?;
+        controller.image_signature_controller.verified =
+            self.image_signature_controller.verified.clone();
+        Ok(controller)
+    }
+
+    /// Controller rendering the deployments of a namespace with other templates
+    pub fn with_template(&self, ns: &str, template: Rc<Template>) -> Result<NiFiController> {
+        NiFiController::new(
+            Namespace::SingleNamespace(ns.to_string()),
+            self.client.clone(),
+            template,
+            self.apis.clone(),
+        )
+    }
+}
+
+/// Controllers of the template sets of the `versions` directory, their templates have no
+/// versions of their own
+pub(super) fn versioned_controllers(
+    ns: &Namespace,
+    client: &Rc<Client>,
+    template: &Template,
+    apis: &ClusterApis,
+) -> Result<BTreeMap<TemplateVersion, Rc<NiFiController>>> {
+    template
+        .versions()
+        .into_iter()
+        .map(|(version, template)| {
+            let controller =
+                NiFiController::new(ns.clone(), client.clone(), template, apis.clone())?;
+            Ok((version, Rc::new(controller)))
+        })
+        .collect()
+}
@@ -0,0 +1,901 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::chrono::{self, Utc};
+use k8s_openapi::ByteString;
+use kube::api::{Meta, PostParams};
+use kube::{Api, Client};
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::stack::Stack;
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName, SubjectKeyIdentifier,
+};
+use openssl::x509::{X509Name, X509NameBuilder, X509};
+use tokio::time::{delay_for, Duration};
+
+use crate::controller::nifiupgrade::queue_nifi_restarts;
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{
+    create_resource, get_api, read_name, read_namespace, recreate_on_change, NiFiController,
+    ReplaceStatus, NIFI_APP_LABEL,
+};
+use crate::crd::{CertManagerCfg, NiFiDeployment, NiFiDeploymentSpec, NiFiDeploymentStatus};
+use crate::discovery::ClusterApis;
+use crate::resources::Certificate;
+use crate::template::Template;
+
+const CA_CERT_KEY: &str = "ca.crt";
+const CA_KEY_KEY: &str = "ca.key";
+const CERT_KEY: &str = "tls.crt";
+const KEY_KEY: &str = "tls.key";
+const KEYSTORE_EXTENSION: &str = ".p12";
+// `passwordRevision` the current keystore passwords were generated for
+const PASSWORD_REVISION_ANNOTATION: &str = "io.github.novakov-alexey/password-revision";
+const PASSWORD_KEYS: [&str; 3] = ["keystorePasswd", "keyPasswd", "truststorePasswd"];
+const CA_VALIDITY_DAYS: u32 = 3650;
+const NODE_VALIDITY_DAYS: u32 = 365;
+const KEY_BITS: u32 = 2048;
+const PASSWORD_LENGTH: usize = 32;
+const ISSUANCE_CHECKS: u8 = 30;
+const RENEWAL_FAILED: &str = "Certificate renewal failed";
+const PASSWORD_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+pub struct TlsController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+    pub apis: ClusterApis,
+}
+
+struct Node {
+    pod: String,
+    subject: Vec<(String, String)>,
+    dns_names: Vec<String>,
+}
+
+struct Ca {
+    cert: X509,
+    key: PKey<Private>,
+}
+
+impl NiFiController {
+    /// Renews node keystores which are due and records when the first node certificate expires,
+    /// the status is replaced only when it is different
+    pub async fn on_certificates(&self, d: &NiFiDeployment) -> Result<Option<ReplaceStatus>> {
+        if d.spec.tls_enabled() != Some(true) {
+            return Ok(None);
+        }
+        let name = read_name(d)?;
+        let ns = read_namespace(d)?;
+        let mut status = d.status.clone().unwrap_or_else(|| NiFiDeploymentStatus {
+            nifi_replicas: d.spec.nifi_replicas,
+            ..NiFiDeploymentStatus::default()
+        });
+        let tls = &self.tls_controller;
+        let expiry = match tls.handle_tls(&name, &ns, &d.spec).await {
+            Ok(_) => tls.certificate_expiry(&name, &ns, &d.spec).await,
+            Err(e) => Err(e),
+        };
+        match expiry {
+            Ok(expiry) => {
+                status.certificate_expiry = expiry;
+                if status.error_msg.starts_with(RENEWAL_FAILED) {
+                    status.error_msg = "".to_string();
+                }
+            }
+            Err(e) => status.error_msg = format!("{}: {}", RENEWAL_FAILED, e),
+        }
+        if d.status.as_ref() == Some(&status) {
+            return Ok(None);
+        }
+        Ok(Some(ReplaceStatus { name, ns, status }))
+    }
+}
+
+impl TlsController {
+    /// Issues the CA, keystore passwords and a keystore per NiFi node when `security.tls` is enabled.
+    /// Keystores of new nodes are added on scale-up, renewed keystores of existing nodes are loaded
+    /// by restarting the nodes one at a time. The Secrets are not deleted with the deployment,
+    /// so a recreated cluster keeps trusting the same CA
+    pub async fn handle_tls(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        if spec.tls_enabled() != Some(true) {
+            return Ok(false);
+        }
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let (password, pwd_created) = self.passwords(&secrets, name, ns, spec).await?;
+        let secret_name = Template::tls_secret_name(name);
+        let current = secrets.get(&secret_name).await.ok();
+        let mut data = current
+            .as_ref()
+            .and_then(|s| s.data.clone())
+            .unwrap_or_default();
+        let existing = data.keys().cloned().collect::<Vec<_>>();
+        let cert_manager = spec
+            .security
+            .as_ref()
+            .and_then(|s| s.tls.as_ref())
+            .and_then(|tls| tls.cert_manager.as_ref());
+        let (issued, updated) = match cert_manager {
+            Some(cfg) => {
+                self.converted_keystores(name, ns, spec, cfg, &password, &mut data)
+                    .await?
+            }
+            None => {
+                self.issued_keystores(&secrets, name, ns, spec, &password, &mut data)
+                    .await?
+            }
+        };
+        if issued.is_empty() && current.is_some() {
+            return Ok(updated || pwd_created);
+        }
+        info!("Writing node keystores of {}: {:?}", name, issued);
+        let mut secret = operator_secret(&secret_name, name, ns, data);
+        match current {
+            Some(current) => {
+                secret.metadata.resource_version = Meta::resource_ver(&current);
+                secrets
+                    .replace(&secret_name, &PostParams::default(), &secret)
+                    .await?;
+            }
+            None => {
+                create_resource(&secrets, secret).await?;
+            }
+        }
+        let renewed = issued
+            .into_iter()
+            .filter(|pod| existing.contains(&keystore_key(pod)))
+            .collect::<Vec<_>>();
+        if !renewed.is_empty() {
+            info!(
+                "Queueing restarts of NiFi nodes with renewed keystores: {:?}",
+                &renewed
+            );
+            queue_nifi_restarts(&self.client, name, ns, &renewed).await?;
+        }
+        Ok(true)
+    }
+
+    /// RFC 3339 time the first node certificate expires, none until the keystores are issued
+    pub async fn certificate_expiry(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<Option<String>> {
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let password = match secrets.get(&Template::tls_pwd_secret_name(name)).await {
+            Ok(secret) => secret_password(&secret)?,
+            Err(_) => return Ok(None),
+        };
+        let data = match secrets.get(&Template::tls_secret_name(name)).await {
+            Ok(secret) => secret.data.unwrap_or_default(),
+            Err(_) => return Ok(None),
+        };
+        let mut expiry = None;
+        for ordinal in 0..spec.nifi_replicas {
+            let pod = format!("{}-{}", name, ordinal);
+            if let Some(keystore) = data.get(&keystore_key(&pod)) {
+                let remaining = remaining_validity(&keystore.0, &password)?;
+                expiry = Some(expiry.map_or(remaining, |e: chrono::Duration| e.min(remaining)));
+            }
+        }
+        Ok(expiry.map(|remaining| (Utc::now() + remaining).to_rfc3339()))
+    }
+
+    /// Keystores of nodes which have none yet or whose certificate is due for renewal, signed by
+    /// the operator CA
+    async fn issued_keystores(
+        &self,
+        secrets: &Api<Secret>,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+        password: &str,
+        data: &mut BTreeMap<String, ByteString>,
+    ) -> Result<(Vec<String>, bool)> {
+        let (ca, ca_created) = self.ca(secrets, name, ns).await?;
+        let ca_pem = ca.cert.to_pem()?;
+        // a new CA invalidates all node keystores
+        if data.get(CA_CERT_KEY).map(|c| &c.0) != Some(&ca_pem) {
+            data.clear();
+            data.insert(CA_CERT_KEY.to_string(), ByteString(ca_pem));
+        }
+        let renew_before = chrono::Duration::days(renew_before_days(spec) as i64);
+        let mut issued = vec![];
+        for node in self.nodes(name, ns, spec)? {
+            let key = keystore_key(&node.pod);
+            // keystores which can not be read with the current password are issued again
+            let valid = data
+                .get(&key)
+                .map(|keystore| remaining_validity(&keystore.0, password))
+                .and_then(|remaining| remaining.ok())
+                .is_some_and(|remaining| remaining > renew_before);
+            if valid {
+                continue;
+            }
+            let keystore = node_keystore(
+                &ca,
+                &node.subject,
+                &node.dns_names,
+                password,
+                validity_days(spec),
+            )?;
+            data.insert(key, ByteString(keystore));
+            issued.push(node.pod);
+        }
+        Ok((issued, ca_created))
+    }
+
+    /// Keystores converted from the Secrets of node Certificates, a keystore is converted again
+    /// when cert-manager renews its certificate
+    async fn converted_keystores(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+        cfg: &CertManagerCfg,
+        password: &str,
+        data: &mut BTreeMap<String, ByteString>,
+    ) -> Result<(Vec<String>, bool)> {
+        if !self.apis.cert_manager {
+            return Err(Error::from(InvalidSpec(
+                "security.tls.certManager is set, but cert-manager.io API is not available"
+                    .to_string(),
+            )));
+        }
+        let mut updated = false;
+        if cfg.ingress == Some(true) {
+            updated |= self.ingress_certificate(name, ns, spec).await?;
+        }
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let mut issued = vec![];
+        for node in self.nodes(name, ns, spec)? {
+            let cert_name = certificate_name(&node.pod);
+            updated |= self
+                .apply_certificate(name, ns, &cert_name, &node.subject, &node.dns_names, spec)
+                .await?;
+            let secret = issued_secret(&secrets, &cert_name).await?;
+            let cert = data_value(&secret, CERT_KEY)?;
+            let cert_key = certificate_key(&node.pod);
+            if data.get(&cert_key) == Some(&cert) {
+                continue;
+            }
+            let ca = secret
+                .data
+                .as_ref()
+                .and_then(|d| d.get(CA_CERT_KEY))
+                .map(|ca| ca.0.as_slice());
+            let (keystore, ca) =
+                converted_keystore(&cert.0, &data_value(&secret, KEY_KEY)?.0, ca, password)?;
+            data.insert(CA_CERT_KEY.to_string(), ByteString(ca));
+            data.insert(keystore_key(&node.pod), ByteString(keystore));
+            data.insert(cert_key, cert);
+            issued.push(node.pod);
+        }
+        Ok((issued, updated))
+    }
+
+    /// Certificate of `ingress.host` written into `ingress.tlsSecret`, the Ingress terminates
+    /// TLS with it
+    async fn ingress_certificate(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let ingress = spec.ingress.as_ref();
+        match (
+            ingress.and_then(|i| i.host.clone()),
+            ingress.and_then(|i| i.tls_secret.clone()),
+        ) {
+            (Some(host), Some(tls_secret)) => {
+                let subject = default_subject(&host);
+                self.apply_certificate(name, ns, &tls_secret, &subject, &[host], spec)
+                    .await
+            }
+            _ => Err(Error::from(InvalidSpec(
+                "security.tls.certManager.ingress requires ingress.host and ingress.tlsSecret"
+                    .to_string(),
+            ))),
+        }
+    }
+
+    async fn apply_certificate(
+        &self,
+        name: &str,
+        ns: &str,
+        cert_name: &str,
+        subject: &[(String, String)],
+        dns_names: &[String],
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        let validity_days = validity_days(spec);
+        recreate_on_change::<Certificate, _, _>(
+            &self.client,
+            cert_name,
+            name,
+            ns,
+            |name| {
+                self.template
+                    .certificate(name, cert_name, subject, dns_names, validity_days, spec)
+            },
+            |current, expected| current.spec != expected.spec,
+        )
+        .await
+    }
+
+    /// Nodes of the NiFi StatefulSet with their subjects and names, the first name is the address
+    /// of the node
+    fn nodes(&self, name: &str, ns: &str, spec: &NiFiDeploymentSpec) -> Result<Vec<Node>> {
+        let cluster_domain = self.template.cluster_domain();
+        (0..spec.nifi_replicas)
+            .map(|ordinal| {
+                let pod = format!("{}-{}", name, ordinal);
+                let mut dns_names = vec![self.template.node_address(name, ns, &pod)];
+                dns_names.extend(service_names(&pod, name, ns, &cluster_domain));
+                let subject = self.template.node_subject(name, ns, ordinal, spec)?;
+                Ok(Node {
+                    pod,
+                    subject,
+                    dns_names,
+                })
+            })
+            .collect()
+    }
+
+    /// Self-signed CA, generated once and read from its Secret afterwards
+    async fn ca(&self, secrets: &Api<Secret>, name: &str, ns: &str) -> Result<(Ca, bool)> {
+        let secret_name = Template::ca_secret_name(name);
+        if let Ok(secret) = secrets.get(&secret_name).await {
+            let value = |key: &str| {
+                secret
+                    .data
+                    .as_ref()
+                    .and_then(|d| d.get(key))
+                    .map(|v| v.0.clone())
+                    .ok_or_else(|| {
+                        Error::msg(format!("key {} is missing in Secret {}", key, &secret_name))
+                    })
+            };
+            let ca = Ca {
+                cert: X509::from_pem(&value(CA_CERT_KEY)?)?,
+                key: PKey::private_key_from_pem(&value(CA_KEY_KEY)?)?,
+            };
+            return Ok((ca, false));
+        }
+        info!("Creating CA of {}", name);
+        let ca = new_ca(&format!("{} CA", name))?;
+        let mut data = BTreeMap::new();
+        data.insert(CA_CERT_KEY.to_string(), ByteString(ca.cert.to_pem()?));
+        data.insert(
+            CA_KEY_KEY.to_string(),
+            ByteString(ca.key.private_key_to_pem_pkcs8()?),
+        );
+        create_resource(secrets, operator_secret(&secret_name, name, ns, data)).await?;
+        Ok((ca, true))
+    }
+
+    /// Random password shared by the keystores, the keys and the truststore of the nodes.
+    /// A different `passwordRevision` generates a new password, the node keystores are wrapped
+    /// with it before the password Secret is replaced and the nodes are restarted one at a time
+    async fn passwords(
+        &self,
+        secrets: &Api<Secret>,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<(String, bool)> {
+        let secret_name = Template::tls_pwd_secret_name(name);
+        let revision = password_revision(spec);
+        if let Ok(secret) = secrets.get(&secret_name).await {
+            let password = secret_password(&secret)?;
+            let current_revision = secret
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|a| a.get(PASSWORD_REVISION_ANNOTATION));
+            if revision.is_none() || current_revision == revision.as_ref() {
+                return Ok((password, false));
+            }
+            info!("Rotating keystore passwords of {}", name);
+            let new_password = random_password()?;
+            let rewrapped = self
+                .rewrap_keystores(secrets, name, &password, &new_password)
+                .await?;
+            let mut rotated = password_secret(&secret_name, name, ns, &new_password, &revision);
+            rotated.metadata.resource_version = Meta::resource_ver(&secret);
+            secrets
+                .replace(&secret_name, &PostParams::default(), &rotated)
+                .await?;
+            if !rewrapped.is_empty() {
+                info!(
+                    "Queueing restarts of NiFi nodes with rewrapped keystores: {:?}",
+                    &rewrapped
+                );
+                queue_nifi_restarts(&self.client, name, ns, &rewrapped).await?;
+            }
+            return Ok((new_password, true));
+        }
+        let password = random_password()?;
+        let secret = password_secret(&secret_name, name, ns, &password, &revision);
+        create_resource(secrets, secret).await?;
+        Ok((password, true))
+    }
+
+    /// Wraps node keystores with the `new` password. Keystores which can not be read are left for
+    /// renewal, returns the Pods of the rewrapped ones
+    async fn rewrap_keystores(
+        &self,
+        secrets: &Api<Secret>,
+        name: &str,
+        old: &str,
+        new: &str,
+    ) -> Result<Vec<String>> {
+        let secret_name = Template::tls_secret_name(name);
+        let mut secret = match secrets.get(&secret_name).await {
+            Ok(secret) => secret,
+            Err(_) => return Ok(vec![]),
+        };
+        let mut data = secret.data.take().unwrap_or_default();
+        let mut rewrapped = vec![];
+        let pods = data
+            .keys()
+            .filter_map(|k| k.strip_suffix(KEYSTORE_EXTENSION))
+            .map(|pod| pod.to_string())
+            .collect::<Vec<_>>();
+        for pod in pods {
+            let key = keystore_key(&pod);
+            match rewrapped_keystore(&data[&key].0, old, new) {
+                Ok(keystore) => {
+                    data.insert(key, ByteString(keystore));
+                    rewrapped.push(pod);
+                }
+                Err(e) => {
+                    warn!("Keystore of {} is left for renewal: {}", &pod, e);
+                    // cert-manager keystores are converted again
+                    data.remove(&certificate_key(&pod));
+                }
+            }
+        }
+        secret.data = Some(data);
+        secrets
+            .replace(&secret_name, &PostParams::default(), &secret)
+            .await?;
+        rewrapped.sort_by_key(|pod| pod_ordinal(pod));
+        Ok(rewrapped)
+    }
+}
+
+fn new_ca(common_name: &str) -> Result<Ca> {
+    let key = PKey::from_rsa(Rsa::generate(KEY_BITS)?)?;
+    let subject = x509_name(&default_subject(common_name))?;
+    let serial = serial_number()?;
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&subject)?;
+    builder.set_issuer_name(&subject)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+    builder.set_not_after(&*Asn1Time::days_from_now(CA_VALIDITY_DAYS)?)?;
+    builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .key_cert_sign()
+            .crl_sign()
+            .build()?,
+    )?;
+    let key_id = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(key_id)?;
+    builder.sign(&key, MessageDigest::sha256())?;
+    Ok(Ca {
+        cert: builder.build(),
+        key,
+    })
+}
+
+/// PKCS12 keystore of a node with a `subject` certificate, `CN=<node address>, OU=NIFI` by
+/// default, the DN maps to the node identity of authorizers.xml
+fn node_keystore(
+    ca: &Ca,
+    subject: &[(String, String)],
+    dns_names: &[String],
+    password: &str,
+    validity_days: u32,
+) -> Result<Vec<u8>> {
+    let key = PKey::from_rsa(Rsa::generate(KEY_BITS)?)?;
+    let subject = x509_name(subject)?;
+    let serial = serial_number()?;
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&subject)?;
+    builder.set_issuer_name(ca.cert.subject_name())?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+    builder.set_not_after(&*Asn1Time::days_from_now(validity_days)?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .digital_signature()
+            .key_encipherment()
+            .build()?,
+    )?;
+    builder.append_extension(
+        ExtendedKeyUsage::new()
+            .server_auth()
+            .client_auth()
+            .build()?,
+    )?;
+    let mut san = SubjectAlternativeName::new();
+    for dns_name in dns_names {
+        san.dns(dns_name);
+    }
+    let san = san.build(&builder.x509v3_context(Some(&ca.cert), None))?;
+    builder.append_extension(san)?;
+    builder.sign(&ca.key, MessageDigest::sha256())?;
+    pkcs12(&key, &builder.build(), vec![ca.cert.clone()], password)
+}
+
+/// PKCS12 keystore of a cert-manager certificate and its CA, the CA is the last certificate
+/// of the chain when the issuer does not set `ca.crt`
+fn converted_keystore(
+    cert: &[u8],
+    key: &[u8],
+    ca: Option<&[u8]>,
+    password: &str,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut chain = X509::stack_from_pem(cert)?;
+    if chain.is_empty() {
+        return Err(Error::msg("certificate of cert-manager Secret is empty"));
+    }
+    let cert = chain.remove(0);
+    let ca = match ca.filter(|ca| !ca.is_empty()) {
+        Some(ca) => X509::from_pem(ca)?,
+        None => chain
+            .last()
+            .cloned()
+            .ok_or_else(|| Error::msg("cert-manager Secret has no CA certificate"))?,
+    };
+    if !chain.iter().any(|c| c.to_der().ok() == ca.to_der().ok()) {
+        chain.push(ca.clone());
+    }
+    let keystore = pkcs12(&PKey::private_key_from_pem(key)?, &cert, chain, password)?;
+    Ok((keystore, ca.to_pem()?))
+}
+
+/// The same key and certificates wrapped with the `new` password
+fn rewrapped_keystore(keystore: &[u8], old: &str, new: &str) -> Result<Vec<u8>> {
+    let parsed = Pkcs12::from_der(keystore)?.parse2(old)?;
+    match (parsed.pkey, parsed.cert) {
+        (Some(key), Some(cert)) => {
+            let chain = parsed
+                .ca
+                .map(|ca| ca.into_iter().collect())
+                .unwrap_or_default();
+            pkcs12(&key, &cert, chain, new)
+        }
+        _ => Err(Error::msg("node keystore has no key or certificate")),
+    }
+}
+
+fn pkcs12(key: &PKey<Private>, cert: &X509, chain: Vec<X509>, password: &str) -> Result<Vec<u8>> {
+    let mut ca = Stack::new()?;
+    for c in chain {
+        ca.push(c)?;
+    }
+    // Java 8 reads the legacy PKCS12 encryption only
+    let keystore = Pkcs12::builder()
+        .name("nifi-key")
+        .pkey(key)
+        .cert(cert)
+        .ca(ca)
+        .key_algorithm(Nid::PBE_WITHSHA1AND3_KEY_TRIPLEDES_CBC)
+        .cert_algorithm(Nid::PBE_WITHSHA1AND3_KEY_TRIPLEDES_CBC)
+        .mac_md(MessageDigest::sha1())
+        .build2(password)?;
+    Ok(keystore.to_der()?)
+}
+
+/// Secret of a Certificate once cert-manager has written the certificate and its key
+async fn issued_secret(secrets: &Api<Secret>, secret_name: &str) -> Result<Secret> {
+    for _ in 0..ISSUANCE_CHECKS {
+        if let Ok(secret) = secrets.get(secret_name).await {
+            if data_value(&secret, CERT_KEY).is_ok() && data_value(&secret, KEY_KEY).is_ok() {
+                return Ok(secret);
+            }
+        }
+        delay_for(Duration::from_secs(1)).await;
+    }
+    Err(Error::msg(format!(
+        "cert-manager has not issued Secret {} yet",
+        secret_name
+    )))
+}
+
+fn data_value(secret: &Secret, key: &str) -> Result<ByteString> {
+    secret
+        .data
+        .as_ref()
+        .and_then(|d| d.get(key))
+        .filter(|v| !v.0.is_empty())
+        .cloned()
+        .ok_or_else(|| {
+            Error::msg(format!(
+                "key {} is missing in Secret {}",
+                key,
+                Meta::name(secret)
+            ))
+        })
+}
+
+fn validity_days(spec: &NiFiDeploymentSpec) -> u32 {
+    spec.security
+        .as_ref()
+        .and_then(|s| s.tls.as_ref())
+        .and_then(|tls| tls.validity_days)
+        .unwrap_or(NODE_VALIDITY_DAYS)
+}
+
+fn renew_before_days(spec: &NiFiDeploymentSpec) -> u32 {
+    spec.security
+        .as_ref()
+        .and_then(|s| s.tls.as_ref())
+        .and_then(|tls| tls.renew_before_days)
+        .unwrap_or_else(|| validity_days(spec) / 3)
+}
+
+/// Time left until the certificate of a node keystore expires
+fn remaining_validity(keystore: &[u8], password: &str) -> Result<chrono::Duration> {
+    let cert = Pkcs12::from_der(keystore)?
+        .parse2(password)?
+        .cert
+        .ok_or_else(|| Error::msg("node keystore has no certificate"))?;
+    let diff = Asn1Time::days_from_now(0)?.diff(cert.not_after())?;
+    Ok(chrono::Duration::days(diff.days as i64) + chrono::Duration::seconds(diff.secs as i64))
+}
+
+/// Names the operator and the Ingress connect to a node with, besides its address
+fn service_names(pod: &str, name: &str, ns: &str, cluster_domain: &str) -> Vec<String> {
+    vec![
+        format!("{}.{}-headless.{}.svc", pod, name, ns),
+        name.to_string(),
+        format!("{}.{}", name, ns),
+        format!("{}.{}.svc", name, ns),
+        format!("{}.{}.svc.{}", name, ns, cluster_domain),
+    ]
+}
+
+fn keystore_key(pod: &str) -> String {
+    format!("{}{}", pod, KEYSTORE_EXTENSION)
+}
+
+fn pod_ordinal(pod: &str) -> u32 {
+    pod.rsplit('-')
+        .next()
+        .and_then(|o| o.parse().ok())
+        .unwrap_or_default()
+}
+
+/// certificate of cert-manager a node keystore is converted from
+fn certificate_key(pod: &str) -> String {
+    format!("{}.crt", pod)
+}
+
+fn certificate_name(pod: &str) -> String {
+    format!("{}-cert", pod)
+}
+
+fn x509_name(subject: &[(String, String)]) -> Result<X509Name> {
+    let mut name = X509NameBuilder::new()?;
+    for (attribute, value) in subject {
+        name.append_entry_by_text(attribute, value)?;
+    }
+    Ok(name.build())
+}
+
+/// Java prints names in the reverse order of their entries, OU goes first to read as `CN=.., OU=NIFI`
+fn default_subject(common_name: &str) -> Vec<(String, String)> {
+    vec![
+        ("OU".to_string(), "NIFI".to_string()),
+        ("CN".to_string(), common_name.to_string()),
+    ]
+}
+
+fn serial_number() -> Result<Asn1Integer> {
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    Ok(serial.to_asn1_integer()?)
+}
+
+pub fn random_password() -> Result<String> {
+    let mut bytes = [0u8; PASSWORD_LENGTH];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(bytes
+        .iter()
+        .map(|b| PASSWORD_CHARS[*b as usize % PASSWORD_CHARS.len()] as char)
+        .collect())
+}
+
+fn password_revision(spec: &NiFiDeploymentSpec) -> Option<String> {
+    spec.security
+        .as_ref()
+        .and_then(|s| s.tls.as_ref())
+        .and_then(|tls| tls.password_revision)
+        .map(|r| r.to_string())
+}
+
+fn secret_password(secret: &Secret) -> Result<String> {
+    let password = secret
+        .data
+        .as_ref()
+        .and_then(|d| d.get(PASSWORD_KEYS[0]))
+        .ok_or_else(|| {
+            Error::msg(format!(
+                "key {} is missing in Secret {}",
+                PASSWORD_KEYS[0],
+                Meta::name(secret)
+            ))
+        })?;
+    Ok(String::from_utf8(password.0.clone())?)
+}
+
+fn password_secret(
+    secret_name: &str,
+    name: &str,
+    ns: &str,
+    password: &str,
+    revision: &Option<String>,
+) -> Secret {
+    let data = PASSWORD_KEYS
+        .iter()
+        .map(|k| (k.to_string(), ByteString(password.as_bytes().to_vec())))
+        .collect();
+    let mut secret = operator_secret(secret_name, name, ns, data);
+    if let Some(revision) = revision {
+        let mut annotations = BTreeMap::new();
+        annotations.insert(PASSWORD_REVISION_ANNOTATION.to_string(), revision.clone());
+        secret.metadata.annotations = Some(annotations);
+    }
+    secret
+}
+
+/// Secret labeled as managed by Kubefi for the deployment `name`
+pub fn operator_secret(
+    secret_name: &str,
+    name: &str,
+    ns: &str,
+    data: BTreeMap<String, ByteString>,
+) -> Secret {
+    let mut labels = BTreeMap::new();
+    labels.insert("app".to_string(), NIFI_APP_LABEL.to_string());
+    labels.insert("app.kubernetes.io/instance".to_string(), name.to_string());
+    labels.insert(
+        "app.kubernetes.io/managed-by".to_string(),
+        "Kubefi".to_string(),
+    );
+    Secret {
+        metadata: ObjectMeta {
+            name: Some(secret_name.to_string()),
+            namespace: Some(ns.to_string()),
+            labels: Some(labels),
+            ..ObjectMeta::default()
+        },
+        data: Some(data),
+        ..Secret::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_keystore() {
+        let ca = new_ca("nifi CA").unwrap();
+        let address = "nifi-0.nifi-headless.test.svc.cluster.local";
+        let mut dns_names = vec![address.to_string()];
+        dns_names.extend(service_names("nifi-0", "nifi", "test", "cluster.local"));
+        let keystore =
+            node_keystore(&ca, &default_subject(address), &dns_names, "secret", 30).unwrap();
+        let parsed = Pkcs12::from_der(&keystore)
+            .unwrap()
+            .parse2("secret")
+            .unwrap();
+        let cert = parsed.cert.unwrap();
+        assert!(cert.verify(&ca.key).unwrap());
+        let cn = cert
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .unwrap()
+            .data()
+            .as_utf8()
+            .unwrap()
+            .to_string();
+        assert_eq!(cn, address);
+        let dns_names = cert
+            .subject_alt_names()
+            .unwrap()
+            .iter()
+            .filter_map(|n| n.dnsname().map(|d| d.to_string()))
+            .collect::<Vec<_>>();
+        assert!(dns_names.contains(&"nifi.test.svc".to_string()));
+        assert!(Pkcs12::from_der(&keystore)
+            .unwrap()
+            .parse2("wrong")
+            .is_err());
+    }
+
+    #[test]
+    fn test_rewrapped_keystore() {
+        let ca = new_ca("nifi CA").unwrap();
+        let dns_names = vec!["nifi-0.nifi-headless.test.svc.cluster.local".to_string()];
+        let subject = default_subject(&dns_names[0]);
+        let keystore = node_keystore(&ca, &subject, &dns_names, "old", 30).unwrap();
+        let rewrapped = rewrapped_keystore(&keystore, "old", "new").unwrap();
+        let parsed = Pkcs12::from_der(&rewrapped).unwrap().parse2("new").unwrap();
+        assert!(parsed.cert.unwrap().verify(&ca.key).unwrap());
+        assert!(parsed.pkey.is_some());
+        assert!(Pkcs12::from_der(&rewrapped).unwrap().parse2("old").is_err());
+        assert!(rewrapped_keystore(&keystore, "wrong", "new").is_err());
+    }
+
+    #[test]
+    fn test_remaining_validity() {
+        let ca = new_ca("nifi CA").unwrap();
+        let dns_names = vec!["nifi-0.nifi-headless.test.svc.cluster.local".to_string()];
+        let subject = default_subject(&dns_names[0]);
+        let keystore = node_keystore(&ca, &subject, &dns_names, "secret", 30).unwrap();
+        let remaining = remaining_validity(&keystore, "secret").unwrap();
+        assert!(remaining > chrono::Duration::days(29));
+        assert!(remaining <= chrono::Duration::days(30));
+        assert!(remaining_validity(&keystore, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_converted_keystore() {
+        let ca = new_ca("issuer CA").unwrap();
+        let dns_names = vec!["nifi-0.nifi-headless.test.svc.cluster.local".to_string()];
+        let subject = default_subject(&dns_names[0]);
+        let issued =
+            Pkcs12::from_der(&node_keystore(&ca, &subject, &dns_names, "issuer", 30).unwrap())
+                .unwrap()
+                .parse2("issuer")
+                .unwrap();
+        let mut cert = issued.cert.unwrap().to_pem().unwrap();
+        cert.extend(ca.cert.to_pem().unwrap());
+        let key = issued.pkey.unwrap().private_key_to_pem_pkcs8().unwrap();
+        let (keystore, ca_pem) = converted_keystore(&cert, &key, None, "secret").unwrap();
+        assert_eq!(ca_pem, ca.cert.to_pem().unwrap());
+        let parsed = Pkcs12::from_der(&keystore)
+            .unwrap()
+            .parse2("secret")
+            .unwrap();
+        assert!(parsed.cert.unwrap().verify(&ca.key).unwrap());
+        assert_eq!(parsed.ca.unwrap().len(), 1);
+        assert!(converted_keystore(&cert[..cert.len() / 2], &key, None, "secret").is_err());
+    }
+
+    #[test]
+    fn test_random_password() {
+        let password = random_password().unwrap();
+        assert_eq!(password.len(), PASSWORD_LENGTH);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(password, random_password().unwrap());
+    }
+}
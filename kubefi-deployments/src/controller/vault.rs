@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::ByteString;
+use kube::api::{Api, DeleteParams, ListParams, Meta, PostParams};
+use kube::Client;
+
+use crate::controller::nifiupgrade::queue_nifi_restarts;
+use crate::controller::tls::operator_secret;
+use crate::controller::{create_resource, get_api, read_name, read_namespace, NiFiController};
+use crate::crd::{NiFiDeployment, NiFiDeploymentSpec, VaultSecretCfg};
+use crate::template::Template;
+use crate::vault_api::VaultApi;
+
+const VAULT_PATH_ANNOTATION: &str = "io.github.novakov-alexey/vault-path";
+const VAULT_COMPONENT: &str = "vault-secret";
+
+pub struct VaultController {
+    pub client: Rc<Client>,
+    pub template: Rc<Template>,
+}
+
+impl NiFiController {
+    /// Keeps the Secrets of `spec.vault` in sync with Vault, NiFi nodes are restarted one at a time
+    /// when a Secret changed, so they read the rotated values
+    pub async fn on_vault_secrets(&self, d: &NiFiDeployment) -> Result<bool> {
+        let name = read_name(d)?;
+        let ns = read_namespace(d)?;
+        self.vault_controller
+            .sync_secrets(&name, &ns, &d.spec, true)
+            .await
+    }
+}
+
+impl VaultController {
+    /// Secrets of `spec.vault` missing in the namespace, they must exist before the NiFi Pods
+    /// referencing them. Changed values are left to the periodic sync
+    pub async fn handle_vault_secrets(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+    ) -> Result<bool> {
+        self.sync_secrets(name, ns, spec, false).await
+    }
+
+    /// Writes the Secrets of `spec.vault` and deletes the ones removed from it, `update` replaces
+    /// Secrets whose values differ from Vault and queues restarts of the NiFi nodes
+    async fn sync_secrets(
+        &self,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+        update: bool,
+    ) -> Result<bool> {
+        let cfgs = spec
+            .vault
+            .as_ref()
+            .map(|v| v.secrets.clone())
+            .unwrap_or_default();
+        let secrets = get_api::<Secret>(&self.client, ns);
+        let deleted = self.delete_removed(&secrets, name, &cfgs).await?;
+        if cfgs.is_empty() {
+            return Ok(deleted);
+        }
+        let mut vault = None;
+        let mut created = false;
+        let mut rotated = false;
+        for cfg in &cfgs {
+            let current = secrets.get(&cfg.name).await.ok();
+            if current.is_some() && !update {
+                continue;
+            }
+            if vault.is_none() {
+                vault = Some(VaultApi::login(&self.template.vault_config()).await?);
+            }
+            let data = match &vault {
+                Some(vault) => vault_data(vault, cfg).await?,
+                None => continue,
+            };
+            match current {
+                Some(current) if current.data.as_ref() == Some(&data) => (),
+                Some(current) => {
+                    info!(
+                        "Updating Secret {} from Vault path {}",
+                        &cfg.name, &cfg.path
+                    );
+                    let mut secret = vault_secret(cfg, name, ns, data);
+                    secret.metadata.resource_version = Meta::resource_ver(&current);
+                    secrets
+                        .replace(&cfg.name, &PostParams::default(), &secret)
+                        .await?;
+                    rotated = true;
+                }
+                None => {
+                    info!(
+                        "Creating Secret {} from Vault path {}",
+                        &cfg.name, &cfg.path
+                    );
+                    create_resource(&secrets, vault_secret(cfg, name, ns, data)).await?;
+                    created = true;
+                }
+            }
+        }
+        if rotated && !spec.hibernated() {
+            let pods = (0..spec.nifi_replicas)
+                .map(|i| format!("{}-{}", name, i))
+                .collect::<Vec<_>>();
+            info!(
+                "Queueing restarts of NiFi nodes of {} with rotated Vault secrets",
+                name
+            );
+            queue_nifi_restarts(&self.client, name, ns, &pods).await?;
+        }
+        Ok(deleted || created || rotated)
+    }
+
+    async fn delete_removed(
+        &self,
+        secrets: &Api<Secret>,
+        name: &str,
+        cfgs: &[VaultSecretCfg],
+    ) -> Result<bool> {
+        let selector = format!(
+            "app.kubernetes.io/instance={},app.kubernetes.io/component={}",
+            name, VAULT_COMPONENT
+        );
+        let current = secrets
+            .list(&ListParams::default().labels(&selector))
+            .await?;
+        let mut deleted = false;
+        for secret in current.items {
+            let secret_name = Meta::name(&secret);
+            if !cfgs.iter().any(|c| c.name == secret_name) {
+                info!("Deleting Secret {} removed from spec.vault", &secret_name);
+                secrets
+                    .delete(&secret_name, &DeleteParams::default())
+                    .await?;
+                deleted = true;
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+async fn vault_data(
+    vault: &VaultApi,
+    cfg: &VaultSecretCfg,
+) -> Result<BTreeMap<String, ByteString>> {
+    vault
+        .kv_data(&cfg.path)
+        .await?
+        .into_iter()
+        .map(|(k, v)| {
+            let value = if cfg.base64_keys.contains(&k) {
+                base64::decode(v.trim()).map_err(|e| {
+                    Error::msg(format!(
+                        "{} of Vault path {} is not base64: {}",
+                        k, cfg.path, e
+                    ))
+                })?
+            } else {
+                v.into_bytes()
+            };
+            Ok((k, ByteString(value)))
+        })
+        .collect()
+}
+
+fn vault_secret(
+    cfg: &VaultSecretCfg,
+    name: &str,
+    ns: &str,
+    data: BTreeMap<String, ByteString>,
+) -> Secret {
+    let mut secret = operator_secret(&cfg.name, name, ns, data);
+    if let Some(labels) = secret.metadata.labels.as_mut() {
+        labels.insert(
+            "app.kubernetes.io/component".to_string(),
+            VAULT_COMPONENT.to_string(),
+        );
+    }
+    let mut annotations = BTreeMap::new();
+    annotations.insert(VAULT_PATH_ANNOTATION.to_string(), cfg.path.clone());
+    secret.metadata.annotations = Some(annotations);
+    secret
+}
@@ -0,0 +1,177 @@
+use std::rc::Rc;
+
+use anyhow::{Error, Result};
+
+use crate::controller::ControllerError::InvalidSpec;
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::{Template, TemplateVersion};
+
+/// Set to "true" to allow a NiFi downgrade to an older minor version of the same major version
+pub const ALLOW_DOWNGRADE_ANNOTATION: &str = "io.github.novakov-alexey/allow-downgrade";
+
+type Version = (u32, u32, u32);
+
+// direct upgrades to the first version and newer must start from the second one at least
+const UPGRADE_PATHS: [(Version, Version); 1] = [
+    // NiFi 2 loads flow.json.gz only and drops deprecated components, the latest 1.x migrates both
+    ((2, 0, 0), (1, 27, 0)),
+];
+
+// BCFKS keystores and the NIFI_PBKDF2_AES_GCM_256 sensitive properties algorithm
+const FIPS_VERSION: Version = (1, 14, 0);
+
+/// FIPS mode is rejected for images older than the first version supporting it,
+/// images with tags which are not versions are not checked
+pub fn validate_fips(image: &str) -> Result<()> {
+    match image_version(image) {
+        Some(version) if version < FIPS_VERSION => Err(Error::from(InvalidSpec(format!(
+            "security.fips requires NiFi {} or later, image is {}",
+            format_version(&FIPS_VERSION),
+            image
+        )))),
+        _ => Ok(()),
+    }
+}
+
+/// Template set of the NiFi version of a deployment image, none when no set applies to it or the
+/// image tag is not a version
+pub fn versioned_template(template: &Template, spec: &NiFiDeploymentSpec) -> Option<Rc<Template>> {
+    template.for_version(nifi_version(template, spec)?)
+}
+
+/// NiFi `(major, minor)` version of a deployment image, none when the image tag is not a version
+pub fn nifi_version(template: &Template, spec: &NiFiDeploymentSpec) -> Option<TemplateVersion> {
+    let (major, minor, _) = image_version(&template.nifi_image(spec)?)?;
+    Some((major, minor))
+}
+
+/// Checks a NiFi image change against the supported upgrade paths before any Pod is restarted,
+/// images with tags which are not versions are not checked
+pub fn validate_upgrade(current_image: &str, new_image: &str, allow_downgrade: bool) -> Result<()> {
+    let (current, new) = match (image_version(current_image), image_version(new_image)) {
+        (Some(current), Some(new)) => (current, new),
+        _ => {
+            debug!(
+                "Upgrade path from {} to {} is not checked, image tags are not versions",
+                current_image, new_image
+            );
+            return Ok(());
+        }
+    };
+    let reject = |reason: String| Err(Error::from(InvalidSpec(reason)));
+    if new < current {
+        if new.0 != current.0 {
+            return reject(format!(
+                "NiFi cannot be downgraded from {} to another major version {}",
+                current_image, new_image
+            ));
+        }
+        if new.1 != current.1 && !allow_downgrade {
+            return reject(format!(
+                "downgrade from {} to {} may not read the current flow, set {} annotation to \"true\" to proceed",
+                current_image, new_image, ALLOW_DOWNGRADE_ANNOTATION
+            ));
+        }
+        return Ok(());
+    }
+    if new.0 > current.0 + 1 {
+        return reject(format!(
+            "upgrade from {} to {} skips a major version, upgrade to the latest {}.x first",
+            current_image,
+            new_image,
+            current.0 + 1
+        ));
+    }
+    match UPGRADE_PATHS
+        .iter()
+        .find(|(target, source)| new >= *target && current < *source)
+    {
+        Some((_, source)) => reject(format!(
+            "upgrade from {} to {} is not supported, upgrade to {} first",
+            current_image,
+            new_image,
+            format_version(source)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Version of an image tag like `1.11.4` or `v1.11.4-jdk11`, missing patch version is 0
+fn image_version(image: &str) -> Option<Version> {
+    let image = image.split('@').next()?;
+    let (path, tag) = image.rsplit_once(':')?;
+    // e.g. registry:5000/nifi without a tag
+    if tag.contains('/') || path.is_empty() {
+        return None;
+    }
+    let mut parts = tag.trim_start_matches('v').splitn(3, '.').map(|p| {
+        let digits = p
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>();
+        digits.parse::<u32>().ok()
+    });
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+fn format_version((major, minor, patch): &Version) -> String {
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_version() {
+        assert_eq!(image_version("apache/nifi:1.11.4"), Some((1, 11, 4)));
+        assert_eq!(image_version("apache/nifi:v1.11.4-jdk11"), Some((1, 11, 4)));
+        assert_eq!(image_version("apache/nifi:2.0"), Some((2, 0, 0)));
+        assert_eq!(
+            image_version("registry:5000/apache/nifi:1.16.3@sha256:abc"),
+            Some((1, 16, 3))
+        );
+        assert_eq!(image_version("apache/nifi:latest"), None);
+        assert_eq!(image_version("apache/nifi"), None);
+        assert_eq!(image_version("registry:5000/apache/nifi"), None);
+        assert_eq!(image_version("apache/nifi:1"), None);
+    }
+
+    #[test]
+    fn test_upgrade() {
+        assert!(validate_upgrade("apache/nifi:1.11.4", "apache/nifi:1.27.0", false).is_ok());
+        assert!(validate_upgrade("apache/nifi:1.27.0", "apache/nifi:2.0.0", false).is_ok());
+        assert!(validate_upgrade("apache/nifi:1.28.1", "apache/nifi:2.1.0", false).is_ok());
+        assert!(validate_upgrade("apache/nifi:1.11.4", "apache/nifi:1.11.4", false).is_ok());
+        // images with tags which are not versions are not checked
+        assert!(validate_upgrade("apache/nifi:latest", "apache/nifi:1.11.4", false).is_ok());
+    }
+
+    #[test]
+    fn test_upgrade_paths() {
+        let e = validate_upgrade("apache/nifi:1.26.0", "apache/nifi:2.0.0", false).unwrap_err();
+        assert!(e.to_string().contains("upgrade to 1.27.0 first"));
+        let e = validate_upgrade("apache/nifi:1.27.0", "apache/nifi:3.0.0", false).unwrap_err();
+        assert!(e.to_string().contains("upgrade to the latest 2.x first"));
+    }
+
+    #[test]
+    fn test_downgrade() {
+        // patch versions of the same minor version read the same flow
+        assert!(validate_upgrade("apache/nifi:1.11.4", "apache/nifi:1.11.2", false).is_ok());
+        assert!(validate_upgrade("apache/nifi:1.12.0", "apache/nifi:1.11.4", true).is_ok());
+        let e = validate_upgrade("apache/nifi:1.12.0", "apache/nifi:1.11.4", false).unwrap_err();
+        assert!(e.to_string().contains(ALLOW_DOWNGRADE_ANNOTATION));
+        assert!(validate_upgrade("apache/nifi:2.0.0", "apache/nifi:1.27.0", true).is_err());
+    }
+
+    #[test]
+    fn test_fips() {
+        assert!(validate_fips("apache/nifi:1.14.0").is_ok());
+        assert!(validate_fips("apache/nifi:latest").is_ok());
+        assert!(validate_fips("apache/nifi:1.13.2").is_err());
+    }
+}
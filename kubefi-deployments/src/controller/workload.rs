@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Error, Result};
+use k8s_openapi::Resource;
+use kube::api::{DeleteParams, Meta, PatchParams, PatchStrategy, PropagationPolicy};
+use kube::Client;
+use openssl::sha::Sha256;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::time::{delay_for, Duration};
+
+use crate::controller::ControllerError::InvalidSpec;
+use crate::controller::{create_resource, from_yaml, get_api, hex_digest};
+use crate::crd::NiFiDeployment;
+use crate::registry_api::RegistryApi;
+use crate::template::Template;
+
+const CONFIG_HASH_ANNOTATION: &str = "io.github.novakov-alexey/config-hash";
+const DELETION_CHECKS: u8 = 30;
+
+/// Versioned flow of a NiFi Registry resolved to identifiers
+pub struct RegistryFlow {
+    pub bucket_id: String,
+    pub flow_id: String,
+    pub version: i32,
+}
+
+/// NiFi Registry at `registry_url`, or the Registry of the `deployment_ref` NiFiDeployment
+pub async fn registry_api(
+    client: &Client,
+    template: &Template,
+    registry_url: &Option<String>,
+    deployment_ref: &Option<String>,
+    ns: &str,
+) -> Result<RegistryApi> {
+    if let Some(url) = registry_url {
+        return Ok(RegistryApi::new(url));
+    }
+    let deployment = deployment_ref.as_ref().ok_or_else(|| {
+        Error::from(InvalidSpec(
+            "either registryUrl or deploymentRef must be set".to_string(),
+        ))
+    })?;
+    let d = get_api::<NiFiDeployment>(client, ns)
+        .get(deployment)
+        .await?;
+    template
+        .registry_client(deployment, ns, &d.spec)
+        .map(|(_, url)| RegistryApi::new(&url))
+        .ok_or_else(|| {
+            Error::from(InvalidSpec(format!(
+                "registryUrl is not set and {} has no NiFi Registry",
+                deployment
+            )))
+        })
+}
+
+/// Identifiers of the bucket and the flow, with the latest version when `version` is not set
+pub async fn resolve_flow(
+    api: &RegistryApi,
+    bucket: &str,
+    flow: &str,
+    version: Option<i32>,
+) -> Result<RegistryFlow> {
+    let invalid = |reason: String| Error::from(InvalidSpec(reason));
+    let bucket_id = api
+        .bucket_id(bucket)
+        .await?
+        .ok_or_else(|| invalid(format!("bucket {} is not found", bucket)))?;
+    let flow_id = api
+        .flow_id(&bucket_id, flow)
+        .await?
+        .ok_or_else(|| invalid(format!("flow {} is not found in bucket {}", flow, bucket)))?;
+    let version = match version {
+        Some(version) => version,
+        None => api
+            .latest_flow_version(&bucket_id, &flow_id)
+            .await?
+            .ok_or_else(|| invalid(format!("flow {} has no versions", flow)))?,
+    };
+    Ok(RegistryFlow {
+        bucket_id,
+        flow_id,
+        version,
+    })
+}
+
+/// Hash of the generated configuration and the spec, a workload is updated when it changes
+pub fn config_hash<S: Serialize>(config: &str, spec: &S) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(config.as_bytes());
+    hasher.update(serde_json::to_string(spec)?.as_bytes());
+    Ok(hex_digest(hasher))
+}
+
+/// Labels of workloads which are not part of a NiFi cluster
+pub fn workload_labels(app: &str, name: &str) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    labels.insert("app".to_string(), app.to_string());
+    labels.insert("app.kubernetes.io/instance".to_string(), name.to_string());
+    labels.insert(
+        "app.kubernetes.io/managed-by".to_string(),
+        "Kubefi".to_string(),
+    );
+    labels
+}
+
+/// Creates the workload or updates it when its config hash is different,
+/// `recreate` replaces it for resources whose Pod template can not be changed
+pub async fn apply_workload<T: Resource + Serialize + Clone + DeserializeOwned + Meta>(
+    client: &Client,
+    name: &str,
+    ns: &str,
+    yaml: &str,
+    config_hash: &str,
+    recreate: bool,
+) -> Result<()> {
+    let api = get_api::<T>(client, ns);
+    let expected = from_yaml::<T>(yaml)?;
+    match api.get(name).await {
+        Ok(current) if config_hash_of(&current) == Some(config_hash) => Ok(()),
+        Ok(_) if recreate => {
+            info!("Recreating {} {} with a new configuration", T::KIND, name);
+            delete_resource::<T>(client, name, ns).await?;
+            for _ in 0..DELETION_CHECKS {
+                if api.get(name).await.is_err() {
+                    break;
+                }
+                delay_for(Duration::from_secs(1)).await;
+            }
+            create_resource(&api, expected).await.map(|_| ())
+        }
+        Ok(_) => {
+            info!("Updating {} {} with a new configuration", T::KIND, name);
+            let params = PatchParams {
+                patch_strategy: PatchStrategy::Merge,
+                ..PatchParams::default()
+            };
+            api.patch(name, &params, serde_json::to_vec(&expected)?)
+                .await
+                .map(|_| ())
+                .map_err(Error::from)
+        }
+        Err(_) => {
+            info!("Creating {} {}", T::KIND, name);
+            create_resource(&api, expected).await.map(|_| ())
+        }
+    }
+}
+
+pub async fn delete_resource<T: Resource + Clone + DeserializeOwned + Meta>(
+    client: &Client,
+    name: &str,
+    ns: &str,
+) -> Result<()> {
+    let api = get_api::<T>(client, ns);
+    if api.get(name).await.is_err() {
+        return Ok(());
+    }
+    let params = DeleteParams {
+        propagation_policy: Some(PropagationPolicy::Background),
+        ..DeleteParams::default()
+    };
+    api.delete(name, &params)
+        .await
+        .map(|_| ())
+        .map_err(Error::from)
+}
+
+pub fn config_hash_of<T: Meta>(resource: &T) -> Option<&str> {
+    resource
+        .meta()
+        .annotations
+        .as_ref()
+        .and_then(|a| a.get(CONFIG_HASH_ANNOTATION))
+        .map(|h| h.as_str())
+}
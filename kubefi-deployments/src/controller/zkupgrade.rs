@@ -0,0 +1,213 @@
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::chrono::Utc;
+use kube::api::{DeleteParams, ListParams, Meta, PatchParams, PatchStrategy};
+use kube::Client;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+use crate::controller::health::elapsed;
+use crate::controller::{get_api, KUBEFI_LABELS, ZK_APP_LABEL};
+
+const REVISION_LABEL: &str = "controller-revision-hash";
+const ZK_CLIENT_PORT: u16 = 2181;
+// `<Pod>/<uid>` of the ZooKeeper server deleted by the last step of a rolling upgrade
+const ZK_RESTARTING_ANNOTATION: &str = "io.github.novakov-alexey/zk-restarting";
+// RFC 3339 time of the last step of a rolling upgrade, set while it is in progress
+const ZK_ROLL_STEP_ANNOTATION: &str = "io.github.novakov-alexey/zk-roll-step-since";
+// seconds a restarted ZooKeeper server has to rejoin the quorum
+const QUORUM_SECONDS: u64 = 300;
+
+/// Restarts the next ZooKeeper server of an `OnDelete` StatefulSet which does not run its update
+/// revision, followers first and the leader last. The next server is restarted only once the
+/// previous one is Ready and the ensemble has a leader again, one server per apply while
+/// `zk_roll_pending` holds for the set, so a stopped upgrade resumes.
+pub async fn advance_zk_roll(client: &Client, ns: &str, set_name: &str) -> Result<()> {
+    let set = get_api::<StatefulSet>(client, ns).get(set_name).await?;
+    let status = set.status.clone().unwrap_or_default();
+    // the update revision is of an older generation until the set is observed
+    if status.observed_generation.is_none() || status.observed_generation < set.metadata.generation
+    {
+        return Ok(());
+    }
+    let api = get_api::<Pod>(client, ns);
+    if let Some(restarting) = annotation(&set, ZK_RESTARTING_ANNOTATION) {
+        let (pod_name, uid) = restarting.split_once('/').unwrap_or((&restarting, ""));
+        let pod = api.get(pod_name).await.ok();
+        // the StatefulSet creates the Pod again with the same name
+        if pod.as_ref().and_then(|p| p.metadata.uid.as_deref()) == Some(uid) {
+            api.delete(pod_name, &DeleteParams::default()).await?;
+            return Ok(());
+        }
+        let restarted = pod.as_ref().is_some_and(is_ready);
+        if !restarted || !has_leader(client, ns, set_name).await? {
+            return match annotation(&set, ZK_ROLL_STEP_ANNOTATION) {
+                Some(since) if elapsed(&since, QUORUM_SECONDS) => Err(Error::msg(format!(
+                    "ZooKeeper server {} did not rejoin the quorum, rolling upgrade of {} is stopped",
+                    pod_name, set_name
+                ))),
+                _ => Ok(()),
+            };
+        }
+        debug!("ZooKeeper server {} rejoined the quorum", pod_name);
+    }
+    let revision = status.update_revision;
+    let pods = set_pods(client, ns, set_name).await?;
+    let mut outdated = Vec::new();
+    for pod in &pods {
+        let pod_revision = pod
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|l| l.get(REVISION_LABEL));
+        if revision.is_some() && pod_revision == revision.as_ref() {
+            continue;
+        }
+        let mode = match pod_ip(pod) {
+            Some(ip) => server_mode(&ip).await,
+            None => None,
+        };
+        outdated.push((pod, mode.as_deref() == Some("leader")));
+    }
+    // followers by descending ordinal as StatefulSet does, the leader at the end
+    outdated.sort_by_key(|(pod, leader)| {
+        (
+            *leader,
+            std::cmp::Reverse(ordinal(&Meta::name(*pod), set_name)),
+        )
+    });
+    debug!(
+        "ZooKeeper servers to restart in order: {:?}",
+        outdated
+            .iter()
+            .map(|(pod, _)| Meta::name(*pod))
+            .collect::<Vec<_>>()
+    );
+    let next = outdated.first().map(|(pod, _)| *pod);
+    let annotations = match next {
+        Some(pod) => json!({
+            ZK_RESTARTING_ANNOTATION: format!(
+                "{}/{}",
+                Meta::name(pod),
+                pod.metadata.uid.clone().unwrap_or_default()
+            ),
+            ZK_ROLL_STEP_ANNOTATION: Utc::now().to_rfc3339(),
+        }),
+        None if zk_roll_pending(&set) => json!({
+            ZK_RESTARTING_ANNOTATION: Value::Null,
+            ZK_ROLL_STEP_ANNOTATION: Value::Null,
+        }),
+        None => return Ok(()),
+    };
+    let params = PatchParams {
+        patch_strategy: PatchStrategy::Merge,
+        ..PatchParams::default()
+    };
+    let patch = json!({ "metadata": { "annotations": annotations } });
+    get_api::<StatefulSet>(client, ns)
+        .patch(set_name, &params, serde_json::to_vec(&patch)?)
+        .await?;
+    match next {
+        Some(pod) => {
+            let pod_name = Meta::name(pod);
+            info!("Restarting ZooKeeper server {}", &pod_name);
+            api.delete(&pod_name, &DeleteParams::default())
+                .await
+                .map(|_| ())
+                .map_err(Error::from)
+        }
+        None => {
+            info!("ZooKeeper servers of {} are restarted", set_name);
+            Ok(())
+        }
+    }
+}
+
+/// Whether a ZooKeeper StatefulSet has servers left to restart or a restarted one to rejoin
+pub fn zk_roll_pending(set: &StatefulSet) -> bool {
+    annotation(set, ZK_ROLL_STEP_ANNOTATION).is_some()
+}
+
+/// Keeps the rolling upgrade state of the existing set in its replacement, so a server restarted
+/// for a previous revision rejoins before the next one. A `revised` replacement starts a rolling
+/// upgrade
+pub fn with_zk_roll(set: &StatefulSet, new_set: &mut StatefulSet, revised: bool) {
+    let annotations = new_set
+        .metadata
+        .annotations
+        .get_or_insert_with(Default::default);
+    for key in [ZK_RESTARTING_ANNOTATION, ZK_ROLL_STEP_ANNOTATION].iter() {
+        if let Some(value) = annotation(set, key) {
+            annotations.insert(key.to_string(), value);
+        }
+    }
+    if revised && !annotations.contains_key(ZK_ROLL_STEP_ANNOTATION) {
+        annotations.insert(ZK_ROLL_STEP_ANNOTATION.to_string(), Utc::now().to_rfc3339());
+    }
+}
+
+pub fn annotation(set: &StatefulSet, key: &str) -> Option<String> {
+    set.metadata
+        .annotations
+        .as_ref()
+        .and_then(|a| a.get(key))
+        .cloned()
+}
+
+async fn set_pods(client: &Client, ns: &str, set_name: &str) -> Result<Vec<Pod>> {
+    let labels = format!("app={},{}", ZK_APP_LABEL, KUBEFI_LABELS);
+    let lp = ListParams::default().labels(&labels);
+    let pods = get_api::<Pod>(client, ns).list(&lp).await?;
+    Ok(pods
+        .into_iter()
+        .filter(|p| ordinal(&Meta::name(p), set_name).is_some())
+        .collect())
+}
+
+async fn has_leader(client: &Client, ns: &str, set_name: &str) -> Result<bool> {
+    for pod in set_pods(client, ns, set_name).await? {
+        if let Some(ip) = pod_ip(&pod) {
+            match server_mode(&ip).await.as_deref() {
+                Some("leader") | Some("standalone") => return Ok(true),
+                _ => continue,
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// `Mode` of the `srvr` four letter word command, None if the server does not serve requests
+async fn server_mode(ip: &str) -> Option<String> {
+    let query = async {
+        let mut stream = TcpStream::connect((ip, ZK_CLIENT_PORT)).await.ok()?;
+        stream.write_all(b"srvr").await.ok()?;
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.ok()?;
+        out.lines()
+            .find_map(|l| l.strip_prefix("Mode: "))
+            .map(|m| m.trim().to_string())
+    };
+    timeout(Duration::from_secs(5), query).await.ok().flatten()
+}
+
+fn pod_ip(pod: &Pod) -> Option<String> {
+    pod.status.as_ref().and_then(|s| s.pod_ip.clone())
+}
+
+pub fn is_ready(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .map(|c| c.iter().any(|c| c.type_ == "Ready" && c.status == "True"))
+        .unwrap_or(false)
+}
+
+pub fn ordinal(pod_name: &str, set_name: &str) -> Option<u32> {
+    pod_name
+        .strip_prefix(set_name)
+        .and_then(|s| s.strip_prefix('-'))
+        .and_then(|o| o.parse().ok())
+}
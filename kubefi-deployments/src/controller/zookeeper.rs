@@ -0,0 +1,123 @@
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::{ConfigMap, Service};
+use k8s_openapi::api::policy::v1beta1::PodDisruptionBudget;
+use k8s_openapi::Resource;
+use kube::api::{DeleteParams, Meta};
+use serde::de::DeserializeOwned;
+
+use crate::controller::zkupgrade::zk_roll_pending;
+use crate::controller::ControllerError::MissingProperty;
+use crate::controller::{get_api, NiFiController, ReplaceResourceStatus};
+use crate::crd::{NiFiDeployment, ZookeeperCluster, ZookeeperClusterStatus};
+
+/// Label of ZooKeeper resources owned by a ZookeeperCluster, NiFiDeployments never delete them
+pub const ZK_CLUSTER_LABEL: &str = "io.github.novakov-alexey/zookeeper-cluster";
+
+pub type ReplaceZkStatus = ReplaceResourceStatus<ZookeeperClusterStatus>;
+
+impl NiFiController {
+    pub async fn on_apply_zk(&self, zk: ZookeeperCluster) -> Result<Option<ReplaceZkStatus>> {
+        let (name, ns) = read_zk_meta(&zk)?;
+        let status = match self.handle_zk_event(&zk, &name, &ns).await {
+            Ok(true) => ZookeeperClusterStatus {
+                replicas: zk.spec.zk.replicas,
+                error_msg: "".to_string(),
+            },
+            Ok(_) => return Ok(None),
+            Err(e) => ZookeeperClusterStatus {
+                replicas: zk.spec.zk.replicas,
+                error_msg: e.to_string(),
+            },
+        };
+        Ok(Some(ReplaceZkStatus { name, ns, status }))
+    }
+
+    /// Whether the StatefulSet of a ZookeeperCluster has a rolling upgrade in progress, every
+    /// apply restarts its next server
+    pub async fn on_zk_rollout(&self, zk: &ZookeeperCluster) -> Result<bool> {
+        let (name, ns) = read_zk_meta(zk)?;
+        let zk_name = format!("{}-zookeeper", &name);
+        Ok(
+            match get_api::<StatefulSet>(&self.client, &ns)
+                .get(&zk_name)
+                .await
+            {
+                Ok(set) => zk_roll_pending(&set),
+                Err(_) => false,
+            },
+        )
+    }
+
+    pub async fn on_delete_zk(&self, zk: ZookeeperCluster) -> Result<()> {
+        let (name, ns) = read_zk_meta(&zk)?;
+        let zk_name = format!("{}-zookeeper", &name);
+        let headless_name = format!("{}-zookeeper-headless", &name);
+        let metrics_name = format!("{}-zookeeper-metrics", &name);
+        let params = &DeleteParams::default();
+        let sts = delete_by_name::<StatefulSet>(self, &ns, params, &zk_name);
+        let svc = delete_by_name::<Service>(self, &ns, params, &zk_name);
+        let headless_svc = delete_by_name::<Service>(self, &ns, params, &headless_name);
+        let metrics_svc = delete_by_name::<Service>(self, &ns, params, &metrics_name);
+        let cm = delete_by_name::<ConfigMap>(self, &ns, params, &zk_name);
+        let pdb = delete_by_name::<PodDisruptionBudget>(self, &ns, params, &zk_name);
+        let ((r1, r2, r3), (r4, r5, r6)) = futures::future::join(
+            futures::future::join3(sts, svc, headless_svc),
+            futures::future::join3(metrics_svc, cm, pdb),
+        )
+        .await;
+        r1.and(r2).and(r3).and(r4).and(r5).and(r6)
+    }
+
+    async fn handle_zk_event(&self, zk: &ZookeeperCluster, name: &str, ns: &str) -> Result<bool> {
+        let mut d = NiFiDeployment::new(name, zk.deployment_spec());
+        d.metadata.namespace = Some(ns.to_string());
+        d.metadata.uid = zk.metadata.uid.clone();
+        d.metadata.generation = zk.metadata.generation;
+        d.spec = self.image_signature_controller.verify_images(&d).await?;
+        let cm_updated = self
+            .cm_controller
+            .handle_zk_configmap(name, ns, &d.spec)
+            .await?;
+        let services_updated = self
+            .svc_controller
+            .handle_zk_services(name, ns, &d.spec)
+            .await?;
+        let set_updated = self.sets_controller.handle_zk_set(&d, name, ns).await?;
+        let pdb_updated = self.pdb_controller.handle_zk_pdb(name, ns, &d.spec).await?;
+        debug!(
+            "ZookeeperCluster resource updates: configmap = {}, services = {}, statefulset = {}, pdb = {}",
+            cm_updated, services_updated, set_updated, pdb_updated
+        );
+        Ok(cm_updated || services_updated || set_updated || pdb_updated)
+    }
+}
+
+async fn delete_by_name<T: Resource + Clone + DeserializeOwned + Meta>(
+    controller: &NiFiController,
+    ns: &str,
+    params: &DeleteParams,
+    name: &str,
+) -> Result<()> {
+    let api = get_api::<T>(&controller.client, ns);
+    match api.get(name).await {
+        Ok(_) => api
+            .delete(name, params)
+            .await
+            .map(|_| ())
+            .map_err(Error::from),
+        Err(_) => Ok(()),
+    }
+}
+
+fn read_zk_meta(zk: &ZookeeperCluster) -> Result<(String, String)> {
+    let missing =
+        |property: &str| Error::from(MissingProperty(property.to_string(), zk.kind.clone()));
+    let name = zk.metadata.name.clone().ok_or_else(|| missing("name"))?;
+    let ns = zk
+        .metadata
+        .namespace
+        .clone()
+        .ok_or_else(|| missing("namespace"))?;
+    Ok((name, ns))
+}
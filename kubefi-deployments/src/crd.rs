@@ -1,23 +1,36 @@
 extern crate schemars;
 extern crate serde_json;
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fs;
 use std::path::PathBuf;
 
 use anyhow::Result;
+use k8s_openapi::api::core::v1::{
+    Container, HostAlias, LocalObjectReference, PodDNSConfig, PodSecurityContext, Probe,
+    SecurityContext, Volume, VolumeMount,
+};
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1beta1::{
     CustomResourceDefinition, CustomResourceDefinitionSpec, CustomResourceValidation,
     JSONSchemaProps,
 };
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use kube::api::{DeleteParams, Meta, PostParams};
 use kube::Api;
 use kube_derive::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio::time::{delay_for, Duration};
 
 pub const CRD_NAME: &str = "nifideployments.io.github.novakov-alexey";
+pub const ZK_CRD_NAME: &str = "zookeeperclusters.io.github.novakov-alexey";
+pub const FLOW_CRD_NAME: &str = "flowdeployments.io.github.novakov-alexey";
+pub const PARAMETER_CONTEXT_CRD_NAME: &str = "nifiparametercontexts.io.github.novakov-alexey";
+pub const AUTHORIZATION_CRD_NAME: &str = "nifiauthorizations.io.github.novakov-alexey";
+pub const STATELESS_CRD_NAME: &str = "nifistatelessdeployments.io.github.novakov-alexey";
+pub const MINIFI_CRD_NAME: &str = "minifideployments.io.github.novakov-alexey";
 
 #[derive(CustomResource, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
 #[kube(
@@ -27,6 +40,8 @@ pub const CRD_NAME: &str = "nifideployments.io.github.novakov-alexey";
     shortname = "nidp",
     status = "NiFiDeploymentStatus",
     printcolumn = r#"{"name":"Replicas", "jsonPath": ".spec.nifiReplicas", "type": "integer"}"#,
+    printcolumn = r#"{"name":"Connected", "jsonPath": ".status.cluster.connectedNodes", "type": "integer"}"#,
+    printcolumn = r#"{"name":"Coordinator", "jsonPath": ".status.cluster.coordinator", "type": "string"}"#,
     apiextensions = "v1beta1"
 )]
 #[kube(
@@ -36,30 +51,1224 @@ pub const CRD_NAME: &str = "nifideployments.io.github.novakov-alexey";
 pub struct NiFiDeploymentSpec {
     pub nifi_replicas: u8,
     pub zk: ZooKeeper,
+    /// stops the flow and scales NiFi to zero keeping its volumes, Services and ConfigMaps,
+    /// the health probe starts the flow again once all nodes rejoin after unset
+    pub hibernate: Option<bool>,
+    /// embedded ZooKeeper is scaled to zero too while hibernating, false by default
+    pub hibernate_zk: Option<bool>,
     pub image: Option<String>,
+    pub image_pull_policy: Option<ImagePullPolicy>,
+    pub priority_class_name: Option<String>,
+    /// NiFi graceful shutdown timeout is derived from it, leaving time for the preStop hook
+    pub termination_grace_period_seconds: Option<u32>,
     pub storage_class: Option<String>,
     pub ldap: Option<AuthLdap>,
     pub logging_config_map: Option<String>,
     pub nifi_resources: Option<Resources>,
+    pub pod_disruption_budget: Option<DisruptionBudget>,
+    pub ports: Option<NiFiPorts>,
+    /// TLS of the NiFi web and cluster ports
+    pub security: Option<SecurityCfg>,
+    /// Kubernetes Secrets kept in sync with HashiCorp Vault, e.g. for `ldap.managerPasswordSecret`,
+    /// `security.tls.jksSecret` or parameter contexts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultCfg>,
+    /// load-balanced connections between NiFi nodes
+    pub cluster_load_balance: Option<ClusterLoadBalanceCfg>,
+    pub probes: Option<NiFiProbes>,
+    pub storage: Option<NiFiStorage>,
+    pub persistence: Option<PersistenceCfg>,
+    pub service: Option<ServiceCfg>,
+    /// one Service per NiFi Pod for direct site-to-site access from outside the cluster
+    pub pod_services: Option<PodServicesCfg>,
+    /// `nifi.remote.input.*` settings for remote NiFi and MiNiFi instances pushing data
+    pub site_to_site: Option<SiteToSiteCfg>,
+    /// sticky sessions for the NiFi UI on Service, Ingress, Route and DestinationRule
+    pub session_affinity: Option<SessionAffinityCfg>,
     pub ingress: Option<IngressCfg>,
+    /// alternatives to Ingress for the NiFi UI, an enabled one replaces the Ingress
+    pub expose: Option<ExposeCfg>,
+    /// SSO for the NiFi UI by an oauth2-proxy Deployment the Ingress routes to instead of NiFi
+    pub auth_proxy: Option<AuthProxyCfg>,
+    pub network_policy: Option<NetworkPolicyCfg>,
+    /// Istio service mesh integration
+    pub mesh: Option<MeshCfg>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub sidecars: Vec<Container>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub init_containers: Vec<Container>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub volumes: Vec<Volume>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub volume_mounts: Vec<VolumeMount>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub image_pull_secrets: Vec<LocalObjectReference>,
+    /// corporate proxy for outbound connections of flows
+    pub proxy: Option<ProxyCfg>,
+    pub dns_policy: Option<DnsPolicy>,
+    #[schemars(with = "Option<Value>")]
+    pub dns_config: Option<PodDNSConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub host_aliases: Vec<HostAlias>,
+    #[schemars(with = "Option<Value>")]
+    pub pod_security_context: Option<PodSecurityContext>,
+    /// applied to every container generated by the operator
+    #[schemars(with = "Option<Value>")]
+    pub security_context: Option<SecurityContext>,
+    /// hardens every generated Pod to pass the given Pod Security Standard, merged over
+    /// `podSecurityContext` and `securityContext`
+    pub security_profile: Option<SecurityProfile>,
+    /// ConfigMap in the deployment namespace with templates and `configs.<file>` keys replacing
+    /// the ones of the operator for this deployment only
+    pub template_overrides: Option<String>,
+    /// existing ServiceAccount for NiFi Pods, or the name of the one to create
+    pub service_account_name: Option<String>,
+    pub service_account: Option<ServiceAccountCfg>,
+    /// operator access to the NiFi REST API, e.g. to offload nodes on scale-down
+    pub api: Option<NiFiApiCfg>,
+    /// restarts Pods of nodes the health probe sees disconnected for too long
+    pub auto_heal: Option<AutoHealCfg>,
+    /// scales `nifiReplicas` by queued flowfiles or CPU utilization
+    pub autoscaling: Option<AutoscalingCfg>,
+    /// Warning Events and a `BackPressure` condition when connection queues fill up
+    pub back_pressure_alerts: Option<BackPressureAlertsCfg>,
+    /// NiFi image and version rollouts
+    pub upgrade: Option<UpgradeCfg>,
+    /// periodic flow backups to object storage
+    pub backup: Option<BackupCfg>,
+    /// flow, users and state of a new deployment seeded from an object storage backup
+    pub restore: Option<FlowRestoreCfg>,
+    /// NiFi Registry of versioned flows
+    pub registry: Option<RegistryCfg>,
+    /// controller services provisioned and enabled via the NiFi REST API
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub controller_services: Vec<ControllerServiceCfg>,
+    /// reporting tasks created and started via the NiFi REST API, e.g. PrometheusReportingTask
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reporting_tasks: Vec<ReportingTaskCfg>,
+    /// set by Kubefi to the `<image>@<digest>` of each image by its reference, once the images are
+    /// verified by `imageVerification` of the operator config
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub verified_images: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct ZooKeeper {
+    /// scaling rolls the ensemble with the new server list, the remaining servers must be a quorum
+    /// of the current ones and the new servers must not be a quorum on their own, e.g. 3 to 5 or
+    /// 5 to 3 but not 3 to 7 in one step. Scaling from a single server is allowed, e.g. 1 to 3
     pub replicas: u8,
     pub image: Option<String>,
+    pub image_pull_policy: Option<ImagePullPolicy>,
+    /// defaults to the NiFi `priorityClassName`
+    pub priority_class_name: Option<String>,
+    #[schemars(with = "Option<Value>")]
+    pub pod_security_context: Option<PodSecurityContext>,
+    #[schemars(with = "Option<Value>")]
+    pub security_context: Option<SecurityContext>,
+    pub pod_disruption_budget: Option<DisruptionBudget>,
+    /// data volume with snapshots, `storageClass` defaults to `spec.storageClass`
+    pub storage: Option<VolumeStorage>,
+    /// separate volume for transaction logs, they are kept in the data volume otherwise
+    pub data_log_storage: Option<VolumeStorage>,
+    /// JVM heap size and container resources of ZooKeeper
+    pub resources: Option<Resources>,
+    /// cleanup of old snapshots and transaction logs, so they do not fill up the volumes
+    pub autopurge: Option<ZkAutopurge>,
+    /// Prometheus metrics of ZooKeeper servers, exposed by the `<name>-zookeeper-metrics` Service
+    pub metrics: Option<ZkMetrics>,
+    /// existing ZooKeeper ensemble, no ZooKeeper resources are created then
+    pub external: Option<ExternalZooKeeper>,
+    /// name of a ZookeeperCluster in the same namespace, no ZooKeeper resources are created then
+    pub cluster_ref: Option<String>,
+    /// `Pravega` creates a ZookeeperCluster of the pravega zookeeper-operator instead of ZooKeeper StatefulSet
+    pub operator: Option<ZooKeeperOperator>,
+    /// TLS between ZooKeeper servers and for NiFi connections to ZooKeeper
+    pub tls: Option<ZooKeeperTls>,
+    /// set by Kubefi when this is the spec of a ZookeeperCluster
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub cluster_name: Option<String>,
+}
+
+impl NiFiDeploymentSpec {
+    pub fn hibernated(&self) -> bool {
+        self.hibernate == Some(true)
+    }
+
+    /// ZooKeeper is stopped together with NiFi while hibernating
+    pub fn zk_hibernated(&self) -> bool {
+        self.hibernated() && self.hibernate_zk == Some(true)
+    }
+
+    /// NiFi nodes which should be running, none while hibernating
+    pub fn running_nifi_replicas(&self) -> u8 {
+        if self.hibernated() {
+            0
+        } else {
+            self.nifi_replicas
+        }
+    }
+
+    /// ZooKeeper servers which should be running, none while hibernating with `hibernateZk`
+    pub fn running_zk_replicas(&self) -> u8 {
+        if self.zk_hibernated() {
+            0
+        } else {
+            self.zk.replicas
+        }
+    }
+
+    /// `Some(true)` when keystores are issued by the operator, `Some(false)` for plain HTTP
+    pub fn tls_enabled(&self) -> Option<bool> {
+        self.security
+            .as_ref()
+            .and_then(|s| s.tls.as_ref())
+            .and_then(|tls| tls.enabled)
+    }
+}
+
+impl ZooKeeper {
+    /// ZooKeeper resources are managed as part of this spec
+    pub fn embedded(&self) -> bool {
+        self.external.is_none() && self.cluster_ref.is_none() && !self.pravega()
+    }
+
+    /// ZooKeeper is deployed by the pravega zookeeper-operator
+    pub fn pravega(&self) -> bool {
+        self.external.is_none()
+            && self.cluster_ref.is_none()
+            && matches!(self.operator, Some(ZooKeeperOperator::Pravega))
+    }
+}
+
+/// ZooKeeper ensemble deployed, sized and upgraded independently of NiFi
+#[derive(CustomResource, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "io.github.novakov-alexey",
+    version = "v1",
+    namespaced,
+    shortname = "zkc",
+    status = "ZookeeperClusterStatus",
+    printcolumn = r#"{"name":"Replicas", "jsonPath": ".spec.replicas", "type": "integer"}"#,
+    apiextensions = "v1beta1"
+)]
+#[kube(scale = r#"{"specReplicasPath":".spec.replicas", "statusReplicasPath":".status.replicas"}"#)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperClusterSpec {
+    #[serde(flatten)]
+    pub zk: ZooKeeper,
+    pub storage_class: Option<String>,
+    pub persistence: Option<PersistenceCfg>,
+}
+
+impl ZookeeperCluster {
+    /// ZooKeeper part of a NiFiDeployment, so the cluster is rendered with ZooKeeper templates
+    pub fn deployment_spec(&self) -> NiFiDeploymentSpec {
+        NiFiDeploymentSpec {
+            zk: ZooKeeper {
+                external: None,
+                cluster_ref: None,
+                operator: None,
+                cluster_name: self.metadata.name.clone(),
+                ..self.spec.zk.clone()
+            },
+            storage_class: self.spec.storage_class.clone(),
+            persistence: self.spec.persistence.clone(),
+            ..NiFiDeploymentSpec::default()
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ZookeeperClusterStatus {
+    pub replicas: u8,
+    pub error_msg: String,
+}
+
+/// Versioned flow of a NiFi Registry deployed into a NiFiDeployment via the NiFi REST API
+#[derive(CustomResource, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "io.github.novakov-alexey",
+    version = "v1",
+    namespaced,
+    shortname = "flowd",
+    status = "FlowDeploymentStatus",
+    printcolumn = r#"{"name":"Deployment", "jsonPath": ".spec.deploymentRef", "type": "string"}"#,
+    printcolumn = r#"{"name":"Flow", "jsonPath": ".spec.flow", "type": "string"}"#,
+    printcolumn = r#"{"name":"Version", "jsonPath": ".status.version", "type": "integer"}"#,
+    apiextensions = "v1beta1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowDeploymentSpec {
+    /// name of a NiFiDeployment in the same namespace
+    pub deployment_ref: String,
+    /// name of the Registry client in NiFi, `spec.registry.clientName` of the deployment by default
+    pub registry_client: Option<String>,
+    pub bucket: String,
+    pub flow: String,
+    /// the latest version is imported and followed when not set
+    pub version: Option<i32>,
+    /// process group the flow is imported into, the root group by default
+    pub parent_group_id: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowDeploymentStatus {
+    /// process group under version control of the flow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process_group_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    pub error_msg: String,
+}
+
+/// Parameter context of a NiFiDeployment kept in NiFi via the REST API
+#[derive(CustomResource, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "io.github.novakov-alexey",
+    version = "v1",
+    namespaced,
+    shortname = "nipc",
+    status = "NiFiParameterContextStatus",
+    printcolumn = r#"{"name":"Deployment", "jsonPath": ".spec.deploymentRef", "type": "string"}"#,
+    apiextensions = "v1beta1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiParameterContextSpec {
+    /// name of a NiFiDeployment in the same namespace
+    pub deployment_ref: String,
+    /// name of the context in NiFi, name of the resource by default
+    pub context_name: Option<String>,
+    pub description: Option<String>,
+    /// parameters missing here are removed from the context
+    #[serde(default)]
+    pub parameters: Vec<ParameterCfg>,
+    /// FlowDeployments in the same namespace whose process groups are bound to the context
+    #[serde(default)]
+    pub flow_deployments: Vec<String>,
+    /// other process groups bound to the context
+    #[serde(default)]
+    pub process_group_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterCfg {
+    pub name: String,
+    pub value: Option<String>,
+    /// sensitive value from a Secret in the same namespace, instead of `value`
+    pub value_from: Option<SecretKeyRef>,
+    /// true by default when the value comes from a Secret
+    pub sensitive: Option<bool>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct SecretKeyRef {
+    pub name: String,
+    pub key: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiParameterContextStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_id: Option<String>,
+    /// hash of the applied parameters and resource versions of their Secrets,
+    /// sensitive values are masked by NiFi so they are not compared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters_hash: Option<String>,
+    pub error_msg: String,
+}
+
+/// Users, groups and access policies of a secured NiFiDeployment kept in NiFi via the REST API,
+/// it requires the managed authorizer of NiFi
+#[derive(CustomResource, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "io.github.novakov-alexey",
+    version = "v1",
+    namespaced,
+    shortname = "niauth",
+    status = "NiFiAuthorizationStatus",
+    printcolumn = r#"{"name":"Deployment", "jsonPath": ".spec.deploymentRef", "type": "string"}"#,
+    apiextensions = "v1beta1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiAuthorizationSpec {
+    /// name of a NiFiDeployment in the same namespace
+    pub deployment_ref: String,
+    /// user identities to create, members of groups and policies are created as well
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// members of these groups are replaced with the declared ones
+    #[serde(default)]
+    pub groups: Vec<UserGroupCfg>,
+    /// users and groups of these policies are replaced with the declared ones,
+    /// other users, groups and policies are left as they are
+    #[serde(default)]
+    pub policies: Vec<AccessPolicyCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserGroupCfg {
+    pub name: String,
+    /// user identities
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessPolicyCfg {
+    /// e.g. /flow, /controller, /provenance or /process-groups/<id>
+    pub resource: String,
+    pub action: PolicyAction,
+    /// user identities
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// names of user groups
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityProfile {
+    /// non-root, read-only root filesystem with emptyDirs for work directories, no capabilities
+    /// and the RuntimeDefault seccomp profile
+    Restricted,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    Read,
+    Write,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiAuthorizationStatus {
+    /// policies kept in NiFi at the last reconcile
+    pub policies: u32,
+    pub error_msg: String,
+}
+
+/// Versioned flow of a NiFi Registry run by the NiFi Stateless engine, without repositories or ZooKeeper
+#[derive(CustomResource, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "io.github.novakov-alexey",
+    version = "v1",
+    namespaced,
+    shortname = "nisd",
+    status = "NiFiStatelessDeploymentStatus",
+    printcolumn = r#"{"name":"Mode", "jsonPath": ".spec.mode", "type": "string"}"#,
+    printcolumn = r#"{"name":"Flow", "jsonPath": ".spec.flow", "type": "string"}"#,
+    printcolumn = r#"{"name":"Version", "jsonPath": ".status.version", "type": "integer"}"#,
+    apiextensions = "v1beta1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiStatelessDeploymentSpec {
+    /// `Continuous` runs the flow in a Deployment, `Once` in a Job until the flow has no more data,
+    /// `Continuous` by default
+    pub mode: Option<StatelessMode>,
+    /// Pods of the Deployment, 1 by default
+    pub replicas: Option<u8>,
+    pub image: Option<String>,
+    pub image_pull_policy: Option<ImagePullPolicy>,
+    /// name of a NiFiDeployment in the same namespace whose NiFi Registry holds the flow
+    pub deployment_ref: Option<String>,
+    /// NiFi Registry URL, instead of the Registry of `deploymentRef`
+    pub registry_url: Option<String>,
+    pub bucket: String,
+    pub flow: String,
+    /// the latest version is run and followed when not set
+    pub version: Option<i32>,
+    #[serde(default)]
+    pub parameters: Vec<ParameterCfg>,
+    /// output ports which fail the run when they receive a flowfile
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failure_port_ids: Vec<String>,
+    /// JVM heap size and container resources of the NiFi Stateless engine
+    pub resources: Option<Resources>,
+    #[schemars(with = "Option<Value>")]
+    pub pod_security_context: Option<PodSecurityContext>,
+    #[schemars(with = "Option<Value>")]
+    pub security_context: Option<SecurityContext>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum StatelessMode {
+    Continuous,
+    Once,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiStatelessDeploymentStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    /// hash of the flow configuration the workload runs with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    pub error_msg: String,
+}
+
+/// MiNiFi agents sending data of a flow to a NiFi cluster via Site-to-Site
+#[derive(CustomResource, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "io.github.novakov-alexey",
+    version = "v1",
+    namespaced,
+    shortname = "minifi",
+    status = "MiNiFiDeploymentStatus",
+    printcolumn = r#"{"name":"Workload", "jsonPath": ".spec.workload", "type": "string"}"#,
+    printcolumn = r#"{"name":"Version", "jsonPath": ".status.version", "type": "integer"}"#,
+    apiextensions = "v1beta1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct MiNiFiDeploymentSpec {
+    /// `DaemonSet` runs an agent on every node, e.g. to tail host logs, `Deployment` by default
+    pub workload: Option<MiNiFiWorkload>,
+    /// Pods of the Deployment, 1 by default
+    pub replicas: Option<u8>,
+    pub image: Option<String>,
+    pub image_pull_policy: Option<ImagePullPolicy>,
+    /// name of a NiFiDeployment in the same namespace receiving the data, Remote Process Groups
+    /// of a generated config.yml point to it
+    pub deployment_ref: Option<String>,
+    /// MiNiFi config.yml used as is, instead of generating it from `flow`
+    pub config: Option<String>,
+    pub flow: Option<MiNiFiFlowCfg>,
+    /// JVM heap size and container resources of the agent
+    pub resources: Option<Resources>,
+    #[schemars(with = "Option<Value>")]
+    pub pod_security_context: Option<PodSecurityContext>,
+    #[schemars(with = "Option<Value>")]
+    pub security_context: Option<SecurityContext>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub volumes: Vec<Volume>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Value>")]
+    pub volume_mounts: Vec<VolumeMount>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum MiNiFiWorkload {
+    Deployment,
+    DaemonSet,
+}
+
+/// Versioned flow of a NiFi Registry converted to a MiNiFi config.yml
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MiNiFiFlowCfg {
+    /// NiFi Registry URL, instead of the Registry of `deploymentRef`
+    pub registry_url: Option<String>,
+    pub bucket: String,
+    pub flow: String,
+    /// the latest version is used and followed when not set
+    pub version: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MiNiFiDeploymentStatus {
+    /// version of the Registry flow the config.yml is generated from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    /// hash of the config.yml the agents run with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    pub error_msg: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub enum ZooKeeperOperator {
+    Kubefi,
+    Pravega,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ZkMetrics {
+    pub enabled: bool,
+    /// `Native` requires ZooKeeper 3.6+, `Exporter` runs a sidecar polling the `mntr` command
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<ZkMetricsProvider>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exporter_image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub enum ZkMetricsProvider {
+    Native,
+    Exporter,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ZkAutopurge {
+    /// number of the most recent snapshots and their transaction logs to keep, at least 3
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snap_retain_count: Option<u32>,
+    /// hours between purges, `0` disables autopurge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_interval: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ZooKeeperTls {
+    /// Secret with `keystore.jks` and `truststore.jks`
+    pub jks_secret: String,
+    /// Secret with `keystorePasswd` and `truststorePasswd`
+    pub pwd_secret: String,
+    /// TLS between ZooKeeper servers, enabled by default
+    pub quorum: Option<bool>,
+    /// TLS for NiFi connections on port 2281, enabled by default
+    pub client: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalZooKeeper {
+    /// e.g. zk-0.zk:2181,zk-1.zk:2181,zk-2.zk:2181
+    pub connect_string: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DisruptionBudget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// number or percentage of Pods, for example `1` or `"25%"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Value>")]
+    pub max_unavailable: Option<IntOrString>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub enum DnsPolicy {
+    ClusterFirst,
+    ClusterFirstWithHostNet,
+    Default,
+    None,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub enum ImagePullPolicy {
+    Always,
+    IfNotPresent,
+    Never,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceCfg {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub service_type: Option<ServiceType>,
+    /// used for NodePort and LoadBalancer types only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_port: Option<i32>,
+    #[serde(rename = "loadBalancerIP", skip_serializing_if = "Option::is_none")]
+    pub load_balancer_ip: Option<String>,
+    /// used for NodePort and LoadBalancer types only, Local preserves client source IP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_traffic_policy: Option<ExternalTrafficPolicy>,
+    /// rendered as `external-dns.alpha.kubernetes.io/hostname` annotation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_dns_hostname: Option<String>,
+    /// also exposes NiFi site-to-site socket port for remote process groups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_to_site: Option<bool>,
+    /// e.g. cloud LB settings like internal LB or idle timeout
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ServiceType {
+    ClusterIP,
+    NodePort,
+    LoadBalancer,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PodServicesCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// NodePort or LoadBalancer, defaults to LoadBalancer
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub service_type: Option<ServiceType>,
+    /// Pods advertise `<pod name>.<hostDomain>` as site-to-site host, also registered via external-dns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_traffic_policy: Option<ExternalTrafficPolicy>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteToSiteCfg {
+    /// false disables both socket and HTTP site-to-site input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// host advertised by every node, e.g. of a load balancer, the Pod DNS name otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// socket port, same as `ports.siteToSite`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secure: Option<bool>,
+    /// site-to-site over the web port, also routed via the Ingress
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAffinityCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cookie_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cookie_max_age_seconds: Option<u32>,
+    /// Service ClientIP affinity timeout, at most 86400
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip_timeout_seconds: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ExternalTrafficPolicy {
+    Cluster,
+    Local,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MeshCfg {
+    /// injects Istio sidecars, NiFi cluster, load balance, site-to-site and ZooKeeper quorum ports bypass them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// further ports to exclude from sidecar interception
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_ports: Vec<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub virtual_service: Option<VirtualServiceCfg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination_rule: Option<DestinationRuleCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct VirtualServiceCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// defaults to Ingress host
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// Istio gateways to bind to, e.g. istio-system/ingressgateway
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gateways: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationRuleCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// DISABLE, SIMPLE, MUTUAL or ISTIO_MUTUAL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_mode: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkPolicyCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// namespace of the ingress controller allowed to reach NiFi UI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingress_controller_namespace: Option<String>,
+    /// extra CIDRs allowed to reach NiFi UI and site-to-site ports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_cidrs: Vec<String>,
+    /// extra namespaces allowed to reach NiFi UI and site-to-site ports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_namespaces: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceAccountCfg {
+    /// create a ServiceAccount named after `serviceAccountName` or the deployment itself
+    #[serde(default)]
+    pub create: bool,
+    /// also create a Role and RoleBinding from the `nifi-role` and `nifi-rolebinding` templates
+    #[serde(default)]
+    pub rbac: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct IngressCfg {
-    pub host: String,
-    pub ingress_class: String,
+    /// set to false to skip Ingress creation for this deployment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// networking.k8s.io/v1 only: Prefix, Exact or ImplementationSpecific
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_type: Option<String>,
+    /// rendered as `kubernetes.io/ingress.class` annotation, also selects controller specific annotations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingress_class: Option<String>,
+    /// replaces the class annotation with `spec.ingressClassName`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingress_class_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthProxyCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// oauth2-proxy provider, e.g. oidc, keycloak-oidc, github or google
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// issuer of the oidc providers, e.g. https://keycloak.example.com/realms/nifi
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oidc_issuer_url: Option<String>,
+    pub client_id: String,
+    /// Secret with `client-secret` and `cookie-secret`
+    pub credentials_secret: String,
+    /// e-mail domains of allowed users, any by default
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub email_domains: Vec<String>,
+    /// further oauth2-proxy flags, e.g. --allowed-group=nifi-admins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<i32>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExposeCfg {
+    /// OpenShift Route, requires route.openshift.io API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<RouteCfg>,
+    /// Gateway API HTTPRoute attached to an existing Gateway, requires gateway.networking.k8s.io/v1 API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway_api: Option<GatewayApiCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayApiCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway_name: Option<String>,
+    /// defaults to NiFiDeployment namespace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway_namespace: Option<String>,
+    /// Gateway listener to attach to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section_name: Option<String>,
+    /// first hostname is used as NiFi web proxy host
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hostnames: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct RouteCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// generated by the OpenShift router when not set, NiFi web proxy host is then left empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// not allowed with passthrough termination
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// defaults to passthrough termination when NiFi is secured, otherwise plain HTTP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<RouteTls>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteTls {
+    pub termination: RouteTermination,
+    /// Allow, Redirect or None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insecure_edge_termination_policy: Option<String>,
+    /// CA to validate NiFi certificate with reencrypt termination
+    #[serde(
+        rename = "destinationCACertificate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub destination_ca_certificate: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteTermination {
+    Edge,
+    Passthrough,
+    Reencrypt,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiApiCfg {
+    /// Secret with `username` and `password` of a NiFi user the operator logs in with when NiFi is secured,
+    /// optional `ca.crt` is trusted in addition to system roots
+    pub credentials_secret: Option<String>,
+    /// accept self-signed NiFi certificates when `ca.crt` is not provided
+    pub insecure_skip_tls_verify: Option<bool>,
+    /// disconnect and offload removed nodes before scale-down, true by default
+    pub offload_on_scale_down: Option<bool>,
+    /// records connected nodes and the cluster coordinator in the status every minute, true by default
+    pub health_probe: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoHealCfg {
+    /// false by default, the Pod is deleted and recreated by its StatefulSet to rejoin the cluster
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// how long a node stays DISCONNECTED or OFFLOADED before its Pod is deleted, 300 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disconnected_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoscalingCfg {
+    /// false by default, the health probe patches `nifiReplicas` and removed nodes are offloaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// 1 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_replicas: Option<u8>,
+    pub max_replicas: u8,
+    /// average queued flowfiles per node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_queued_flow_files_per_node: Option<u64>,
+    /// average CPU usage of NiFi Pods in percent of their requests, read from metrics-server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_cpu_utilization_percent: Option<u32>,
+    /// time between two scaling steps, scale-down removes one node per step, 300 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_seconds: Option<u64>,
+    /// scaling windows, the schedule fired last sets the replicas, metrics may only add nodes to it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<ScalingScheduleCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScalingScheduleCfg {
+    /// five fields cron expression in UTC, e.g. `0 8 * * MON-FRI`
+    pub cron: String,
+    /// at least 1 and at most `maxReplicas`, `minReplicas` does not apply
+    pub replicas: u8,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackPressureAlertsCfg {
+    /// false by default, connection queues are checked by the health probe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// percentage of the object count or size back-pressure threshold of a connection, 80 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold_percent: Option<u32>,
+    /// how long a queue stays above the threshold before it is reported, 300 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sustained_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupCfg {
+    /// S3 compatible bucket, e.g. AWS S3, GCS with HMAC keys or MinIO
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3: Option<S3BackupCfg>,
+    /// seconds between backups taken by Kubefi, 86400 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_seconds: Option<u64>,
+    /// cron schedule of a CronJob taking the backups instead of Kubefi, e.g. "0 2 * * *"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<BackupRetention>,
+    /// upload image with the AWS CLI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct S3BackupCfg {
+    pub bucket: String,
+    /// key prefix of backup archives
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// e.g. https://storage.googleapis.com or http://minio.minio:9000, AWS S3 otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Secret with `accessKeyId` and `secretAccessKey`
+    pub credentials_secret: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryCfg {
+    /// deploys a single NiFi Registry instance next to the NiFi cluster
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<RegistryStorage>,
+    /// Ingress of the Registry UI and API, disabled by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingress: Option<IngressCfg>,
+    /// external Registry registered in NiFi instead of the deployed one, e.g. http://registry.tools:18080
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// name of the Registry client created in NiFi
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    /// versioned flows are stored in a Git repository instead of the flow storage volume only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<RegistryGitCfg>,
+    /// external metadata database instead of the embedded H2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<RegistryDatabaseCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryDatabaseCfg {
+    #[serde(rename = "type")]
+    pub type_: RegistryDatabaseType,
+    /// e.g. jdbc:postgresql://postgres.db:5432/nifi_registry
+    pub url: String,
+    /// Secret with `username` and `password`
+    pub credentials_secret: String,
+    /// JDBC driver class, the default one of the database type otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver_class: Option<String>,
+    /// JDBC driver JAR downloaded into the Registry Pod, the configured one of the database type otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u16>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum RegistryDatabaseType {
+    PostgreSQL,
+    MySQL,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryGitCfg {
+    /// e.g. https://github.com/org/flows.git or git@github.com:org/flows.git for SSH
+    pub repo_url: String,
+    /// branch checked out into the flow storage, main by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// remote the Registry pushes new flow versions to, origin by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Secret with `username` and `password` (or access token) for HTTPS repositories,
+    /// `ssh-privatekey` and `known_hosts` for SSH ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credentials_secret: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryStorage {
+    /// metadata database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<VolumeStorage>,
+    /// flow snapshots of the file system persistence provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_storage: Option<VolumeStorage>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerServiceCfg {
+    #[serde(flatten)]
+    pub component: NiFiComponentCfg,
+    /// root process group by default
+    pub scope: Option<ControllerServiceScope>,
+    /// true by default
+    pub enabled: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportingTaskCfg {
+    #[serde(flatten)]
+    pub component: NiFiComponentCfg,
+    /// e.g. "1 min", default of the task type otherwise
+    pub scheduling_period: Option<String>,
+    /// true by default
+    pub running: Option<bool>,
+}
+
+/// Controller service or reporting task, the ones created by Kubefi and removed from the list
+/// are deleted from NiFi
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiComponentCfg {
+    /// unique per scope
+    pub name: String,
+    /// e.g. org.apache.nifi.dbcp.DBCPConnectionPool
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// required when several versions of the type are installed
+    pub bundle: Option<BundleCfg>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
+    /// sensitive properties, e.g. Password, from Secrets in the same namespace
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secret_properties: BTreeMap<String, SecretKeyRef>,
+    /// properties referencing controller services listed before by their names,
+    /// reporting tasks reference services of the Controller scope
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub service_properties: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ControllerServiceScope {
+    ProcessGroup,
+    /// services of the controller are available to reporting tasks only
+    Controller,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct BundleCfg {
+    /// e.g. org.apache.nifi
+    pub group: String,
+    /// e.g. nifi-dbcp-service-nar
+    pub artifact: String,
+    pub version: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowRestoreCfg {
+    /// backup archive name in `spec.backup.s3`, e.g. nifi-20201001T020000Z, or its s3://bucket/key path.
+    /// It is restored only when the NiFi StatefulSet is created
+    pub from: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRetention {
+    /// newest backups kept in the bucket, 7 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpgradeCfg {
+    /// copy the flow, users and authorizations of the first node into a ConfigMap before
+    /// a NiFi image change, true by default
+    pub flow_backup: Option<bool>,
+    /// RollingUpdate by default, Canary moves the highest ordinal first and soaks it before the other nodes
+    pub strategy: Option<UpgradeStrategy>,
+    /// seconds the canary node and the whole cluster have to stay connected, 300 by default
+    pub canary_soak_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum UpgradeStrategy {
+    RollingUpdate,
+    Canary,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct AuthLdap {
     pub host: String,
+    /// bind DN the users are searched with, `auth.ldap.managerDn` of the operator config by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manager_dn: Option<String>,
+    /// Secret key with the password of `managerDn`, it is not written into the NiFi ConfigMap.
+    /// `auth.ldap.managerPassword` of the operator config is used when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manager_password_secret: Option<SecretKeyRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_base: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_filter: Option<String>,
+    /// SIMPLE by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authentication_strategy: Option<LdapAuthenticationStrategy>,
+    /// Secret key with the PEM CA of the LDAP server for LDAPS and START_TLS,
+    /// the NiFi truststore is used when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_secret: Option<SecretKeyRef>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum LdapAuthenticationStrategy {
+    #[serde(rename = "SIMPLE")]
+    Simple,
+    #[serde(rename = "LDAPS")]
+    Ldaps,
+    #[serde(rename = "START_TLS")]
+    StartTls,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
@@ -78,25 +1287,508 @@ pub struct PodResources {
 
 #[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
 #[serde(rename_all = "camelCase")]
+pub struct VaultCfg {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<VaultSecretCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultSecretCfg {
+    /// Secret written with the keys of the Vault secret
+    pub name: String,
+    /// path of the secret in the KV engine of the operator config, e.g. nifi/ldap
+    pub path: String,
+    /// keys with base64 encoded binary values in Vault, e.g. keystore.jks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub base64_keys: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SecurityCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsCfg>,
+    /// identity of the user granted the admin policies of a new cluster,
+    /// `auth.ldap.managerUsername` of the operator config by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_admin: Option<String>,
+    /// FIPS 140-2 mode with BCFKS keystores and approved TLS cipher suites and sensitive properties
+    /// algorithm, requires a NiFi 1.14+ image with the Bouncy Castle FIPS jars in `lib`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fips: Option<bool>,
+    /// key of `nifi.sensitive.props.key` encrypting sensitive values of the flow,
+    /// generated into a `<name>-sensitive-props-key` Secret by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensitive_props_key: Option<SensitivePropsKeyCfg>,
+    /// NiFi single-user login for clusters without `ldap`, `oidc` or Kerberos, NiFi 1.14 or later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_user: Option<SingleUserCfg>,
+    /// providers of login-identity-providers.xml besides the ones of `ldap`, `singleUser` and
+    /// `kerberos.login`, e.g. a composite or a custom provider
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub identity_providers: Vec<IdentityProviderCfg>,
+    /// identifier of the provider users log in with, the provider of `ldap`, `singleUser` or
+    /// `kerberos.login`, else the first of `identityProviders` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_identity_provider: Option<String>,
+    /// OpenID Connect login of NiFi users instead of `ldap`, requires HTTPS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oidc: Option<OidcCfg>,
+    /// Kerberos principals and keytabs of NiFi, used by the HDFS and Hive processors among others
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kerberos: Option<KerberosCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct KerberosCfg {
+    /// ConfigMap with `krb5.conf`, `kerberos.krb5ConfigMap` of the operator config by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub krb5_config_map: Option<String>,
+    /// `nifi.kerberos.service.principal` with `{address}` replaced by the node address,
+    /// e.g. `nifi/{address}@EXAMPLE.COM`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_principal: Option<String>,
+    /// Secret key with the keytab of the service principal,
+    /// `kerberos.serviceKeytab` of the operator config by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_keytab: Option<SecretKeyRef>,
+    /// `nifi.kerberos.spnego.principal` with `{address}` replaced by the node address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spnego_principal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spnego_keytab: Option<SecretKeyRef>,
+    /// login of NiFi users with their Kerberos username and password instead of `ldap`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login: Option<KerberosLoginCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct KerberosLoginCfg {
+    /// realm of user principals entered without one
+    pub default_realm: String,
+    /// 12 hours by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authentication_expiration: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SensitivePropsKeyCfg {
+    /// existing key of at least 12 characters instead of the generated one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<SecretKeyRef>,
+    /// changing it replaces the generated key, the nodes re-encrypt their flows with the new key
+    /// on the rolling restart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityProviderCfg {
+    pub identifier: String,
+    /// LoginIdentityProvider implementation, its NAR must be in the NiFi image
+    pub class: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleUserCfg {
+    /// Secret with `username` and `password` of at least 12 characters, Kubefi generates
+    /// `<name>-single-user` with a random password for the `admin` user when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credentials_secret: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcCfg {
+    /// `.well-known/openid-configuration` URL of the provider, e.g. of a Keycloak realm
+    pub discovery_url: String,
+    pub client_id: String,
+    /// Secret key with the client secret, it is not written into the NiFi ConfigMap
+    pub client_secret: SecretKeyRef,
+    /// claim NiFi identifies users by, `email` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_claim: Option<String>,
+    /// claims tried in order when the identity claim is missing
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_claims: Vec<String>,
+    /// scopes requested besides `openid` and `email`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_scopes: Vec<String>,
+    /// claim with the groups of a user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups_claim: Option<String>,
+    /// RS256 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_jws_algorithm: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsCfg {
+    /// HTTPS with node keystores issued by a CA of the operator, `protocol.security` Secrets of
+    /// the operator config are used when not set, false switches NiFi to HTTP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// validity of node certificates, 365 by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validity_days: Option<u32>,
+    /// operator issued node certificates are renewed this many days before they expire, a third of
+    /// `validityDays` by default. cert-manager renews its certificates by their own schedule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renew_before_days: Option<u32>,
+    /// subject of node certificates with `{pod}`, `{address}`, `{name}` and `{ns}` replaced,
+    /// `CN={address}, OU=NIFI` by default. When set, node identities of authorizers.xml are the DN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_dn: Option<String>,
+    /// changing it generates new keystore passwords, the node keystores are wrapped with them and
+    /// the nodes are restarted one at a time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_revision: Option<u32>,
+    /// node certificates are issued by cert-manager instead of the operator CA
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_manager: Option<CertManagerCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CertManagerCfg {
+    pub issuer_ref: IssuerRef,
+    /// issues `ingress.tlsSecret` for `ingress.host` with the same issuer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingress: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuerRef {
+    pub name: String,
+    /// Issuer by default, or ClusterIssuer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiPorts {
+    /// HTTPS port when NiFi is secured, HTTP port otherwise
+    pub web: Option<u16>,
+    /// cluster node protocol port
+    pub cluster: Option<u16>,
+    pub site_to_site: Option<u16>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PersistenceCfg {
+    /// emptyDir volumes instead of PVCs when false, e.g. for CI or local clusters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// whether PVCs of NiFi and ZooKeeper are deleted together with the deployment, Retain by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reclaim_policy: Option<ReclaimPolicy>,
+    /// VolumeSnapshotClass of backups, cluster default class otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_snapshot_class_name: Option<String>,
+    /// creates PVCs of a new deployment from VolumeSnapshots of a backup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restore_from: Option<RestoreCfg>,
+    /// PVCs left by removed ordinals after scale-down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orphaned_claims: Option<OrphanedClaimsCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedClaimsCfg {
+    /// opt-in, orphaned PVCs are kept forever by default
+    pub delete: Option<bool>,
+    /// grace period before an orphaned PVC is deleted, 24 hours by default
+    pub retention_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreCfg {
+    /// backup id, i.e. value of the backup annotation
+    pub backup: String,
+    /// deployment the backup was taken from, this deployment by default
+    pub deployment_name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ReclaimPolicy {
+    Retain,
+    Delete,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyCfg {
+    /// e.g. http://proxy.corp:3128
+    pub http_proxy: Option<String>,
+    pub https_proxy: Option<String>,
+    /// hosts and domains to reach directly, cluster internal names are always added
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterLoadBalanceCfg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connections_per_node: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_thread_count: Option<u32>,
+    /// NiFi time period, e.g. "30 sec"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comms_timeout: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct NiFiProbes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liveness: Option<ProbeCfg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readiness: Option<ProbeCfg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup: Option<ProbeCfg>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeCfg {
+    /// set to false to remove the probe from the NiFi container
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_delay_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success_threshold: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_threshold: Option<u32>,
+    /// replaces the generated probe entirely, timings above are ignored then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Value>")]
+    pub custom: Option<Probe>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NiFiStorage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<VolumeStorage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flowfile_repository: Option<VolumeStorage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_repository: Option<VolumeStorage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance_repository: Option<VolumeStorage>,
+    /// when set, the database repository gets its own volume instead of living in `data`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database_repository: Option<VolumeStorage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logs: Option<VolumeStorage>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeStorage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// overrides `spec.storageClass` for this volume, e.g. faster class for the content repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct NiFiDeploymentStatus {
     pub nifi_replicas: u8,
     pub error_msg: String,
+    /// e.g. `Upgraded: False` when a rolling upgrade is stopped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<StatusCondition>,
+    /// ConfigMap with the flow taken before the last NiFi image change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_backup: Option<String>,
+    /// RFC 3339 time of the last backup uploaded to object storage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_backup_time: Option<String>,
+    /// RFC 3339 time `nifiReplicas` was last changed by the autoscaling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_scale_time: Option<String>,
+    /// seen by the last health probe, absent when the NiFi REST API is not reachable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<ClusterHealth>,
+    /// RFC 3339 time the first node certificate expires when keystores are issued by Kubefi
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate_expiry: Option<String>,
+    /// Secret with the credentials of the NiFi single user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_user_secret: Option<String>,
+    /// Secret with the sensitive properties key of the flow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensitive_props_key_secret: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterHealth {
+    pub connected_nodes: u32,
+    pub total_nodes: u32,
+    #[serde(default)]
+    pub queued_flow_files: u64,
+    #[serde(default)]
+    pub queued_bytes: u64,
+    #[serde(default)]
+    pub active_threads: u32,
+    /// address of the elected cluster coordinator node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordinator: Option<String>,
+    /// Pods of DISCONNECTED or OFFLOADED nodes with RFC 3339 time they were first seen so
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub disconnected_nodes: BTreeMap<String, String>,
+    /// connections above the back-pressure alert threshold by their ids
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub saturated_connections: BTreeMap<String, SaturatedConnection>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SaturatedConnection {
+    pub name: String,
+    /// RFC 3339 time the queue was first seen above the threshold
+    pub since: String,
+    /// whether the Warning Event is emitted
+    #[serde(default)]
+    pub alerted: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusCondition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub status: String,
+    pub reason: String,
+    pub message: String,
+    pub last_transition_time: String,
+}
+
+impl StatusCondition {
+    pub fn new(type_: &str, status: bool, reason: &str, message: &str) -> StatusCondition {
+        StatusCondition {
+            type_: type_.to_string(),
+            status: if status { "True" } else { "False" }.to_string(),
+            reason: reason.to_string(),
+            message: message.to_string(),
+            last_transition_time: k8s_openapi::chrono::Utc::now().to_rfc3339(),
+        }
+    }
 }
 
 pub async fn replace_crd(crds: Api<CustomResourceDefinition>, schema: PathBuf) -> Result<()> {
-    delete_old_version(crds.clone()).await?;
+    replace(crds, CRD_NAME, NiFiDeployment::crd(), schema).await
+}
+
+pub async fn replace_zk_crd(crds: Api<CustomResourceDefinition>, schema: PathBuf) -> Result<()> {
+    replace(crds, ZK_CRD_NAME, ZookeeperCluster::crd(), schema).await
+}
+
+pub async fn replace_flow_crd(crds: Api<CustomResourceDefinition>, schema: PathBuf) -> Result<()> {
+    replace(crds, FLOW_CRD_NAME, FlowDeployment::crd(), schema).await
+}
+
+pub async fn replace_authorization_crd(
+    crds: Api<CustomResourceDefinition>,
+    schema: PathBuf,
+) -> Result<()> {
+    replace(
+        crds,
+        AUTHORIZATION_CRD_NAME,
+        NiFiAuthorization::crd(),
+        schema,
+    )
+    .await
+}
+
+pub async fn replace_stateless_crd(
+    crds: Api<CustomResourceDefinition>,
+    schema: PathBuf,
+) -> Result<()> {
+    replace(
+        crds,
+        STATELESS_CRD_NAME,
+        NiFiStatelessDeployment::crd(),
+        schema,
+    )
+    .await
+}
+
+pub async fn replace_minifi_crd(
+    crds: Api<CustomResourceDefinition>,
+    schema: PathBuf,
+) -> Result<()> {
+    replace(crds, MINIFI_CRD_NAME, MiNiFiDeployment::crd(), schema).await
+}
+
+pub async fn replace_parameter_context_crd(
+    crds: Api<CustomResourceDefinition>,
+    schema: PathBuf,
+) -> Result<()> {
+    replace(
+        crds,
+        PARAMETER_CONTEXT_CRD_NAME,
+        NiFiParameterContext::crd(),
+        schema,
+    )
+    .await
+}
+
+/// Whether the CRD is served, resources of a missing CRD can not be watched
+pub async fn crd_installed(crds: &Api<CustomResourceDefinition>, crd_name: &str) -> Result<bool> {
+    match crds.get(crd_name).await {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn replace(
+    crds: Api<CustomResourceDefinition>,
+    crd_name: &str,
+    crd: CustomResourceDefinition,
+    schema: PathBuf,
+) -> Result<()> {
+    delete_old_version(crds.clone(), crd_name).await?;
     delay_for(Duration::from_secs(2)).await;
 
     let schema = fs::read_to_string(schema)?;
-    create_new_version(crds, schema).await?;
+    create_new_version(crds, crd, schema).await?;
     delay_for(Duration::from_secs(1)).await;
     Ok(())
 }
 
-async fn delete_old_version(crds: Api<CustomResourceDefinition>) -> Result<()> {
+async fn delete_old_version(crds: Api<CustomResourceDefinition>, crd_name: &str) -> Result<()> {
     let dp = DeleteParams::default();
     // but ignore delete err if not exists
-    let deleted = crds.delete(CRD_NAME, &dp).await;
+    let deleted = crds.delete(crd_name, &dp).await;
     deleted
         .map(|res| {
             res.map_left(|o| {
@@ -108,7 +1800,7 @@ async fn delete_old_version(crds: Api<CustomResourceDefinition>) -> Result<()> {
             })
             .map_right(|s| {
                 // it's gone.
-                info!("Deleted {:?}: ({:?})", CRD_NAME, s);
+                info!("Deleted {:?}: ({:?})", crd_name, s);
             });
         })
         .or(Ok(()))
@@ -116,10 +1808,11 @@ async fn delete_old_version(crds: Api<CustomResourceDefinition>) -> Result<()> {
 
 async fn create_new_version(
     crds: Api<CustomResourceDefinition>,
+    crd: CustomResourceDefinition,
     json_schema: String,
 ) -> Result<()> {
     let schema: JSONSchemaProps = serde_json::from_str(&json_schema)?;
-    let crd = with_schema(schema, NiFiDeployment::crd());
+    let crd = with_schema(schema, crd);
     debug!("Creating CRD: {}", serde_json::to_string_pretty(&crd)?);
     let pp = PostParams::default();
     match crds.create(&pp, &crd).await {
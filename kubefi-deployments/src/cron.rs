@@ -0,0 +1,141 @@
+//! Cron expressions of five fields: minute, hour, day of month, month and day of week, in UTC.
+//! Fields take `*`, numbers, ranges, lists and steps, e.g. `0 8-18/2 * * MON-FRI`.
+
+use anyhow::{Error, Result};
+use k8s_openapi::chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const DAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Cron> {
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(Error::msg(format!(
+                "cron expression '{}' must have 5 fields",
+                expression
+            )));
+        }
+        let mut weekdays = field(fields[4], 0, 7, &DAYS, 0)?;
+        // both 0 and 7 are Sunday
+        weekdays[0] |= weekdays[7];
+        Ok(Cron {
+            minutes: field(fields[0], 0, 59, &[], 0)?,
+            hours: field(fields[1], 0, 23, &[], 0)?,
+            days: field(fields[2], 1, 31, &[], 0)?,
+            months: field(fields[3], 1, 12, &MONTHS, 1)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    /// Day of month and day of week match either when both are restricted, as in crontab
+    pub fn matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days[time.day() as usize];
+        let weekday = self.weekdays[time.weekday().num_days_from_sunday() as usize];
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes[time.minute() as usize]
+            && self.hours[time.hour() as usize]
+            && self.months[time.month() as usize]
+            && day_matches
+    }
+
+    /// Latest minute not after `time` which matches, looking back at most `lookback`
+    pub fn last_before(&self, time: DateTime<Utc>, lookback: Duration) -> Option<DateTime<Utc>> {
+        let start = time.with_second(0)?.with_nanosecond(0)?;
+        let minutes = lookback.num_minutes();
+        (0..=minutes)
+            .map(|m| start - Duration::minutes(m))
+            .find(|t| self.matches(t))
+    }
+}
+
+/// Allowed values of a field indexed by value, `names` are aliases of values from `first_name`
+fn field(
+    expression: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<Vec<bool>> {
+    let invalid = || Error::msg(format!("invalid cron field '{}'", expression));
+    let value = |s: &str| -> Result<u32> {
+        let upper = s.to_ascii_uppercase();
+        match names.iter().position(|n| *n == upper) {
+            Some(i) => Ok(i as u32 + first_name),
+            None => s.parse::<u32>().map_err(|_| invalid()),
+        }
+    };
+    let mut allowed = vec![false; max as usize + 1];
+    for part in expression.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((from, to)) => (value(from)?, value(to)?),
+                // a single value with a step runs to the maximum, e.g. 5/15
+                None if part.contains('/') => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if step == 0 || from < min || to > max || from > to {
+            return Err(invalid());
+        }
+        for v in (from..=to).step_by(step as usize) {
+            allowed[v as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::chrono::TimeZone;
+
+    #[test]
+    fn test_business_hours() {
+        let cron = Cron::parse("0 8-18/2 * * MON-FRI").unwrap();
+        // 2020-11-02 is a Monday
+        assert!(cron.matches(&Utc.ymd(2020, 11, 2).and_hms(10, 0, 0)));
+        assert!(!cron.matches(&Utc.ymd(2020, 11, 2).and_hms(9, 0, 0)));
+        assert!(!cron.matches(&Utc.ymd(2020, 11, 1).and_hms(10, 0, 0)));
+    }
+
+    #[test]
+    fn test_last_before() {
+        let cron = Cron::parse("30 20 * * 0,7").unwrap();
+        let now = Utc.ymd(2020, 11, 4).and_hms(12, 15, 42);
+        let fired = cron.last_before(now, Duration::days(7));
+        assert_eq!(fired, Some(Utc.ymd(2020, 11, 1).and_hms(20, 30, 0)));
+        assert_eq!(cron.last_before(now, Duration::days(1)), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Cron::parse("0 8 * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(Cron::parse("0 18-8 * * *").is_err());
+    }
+}
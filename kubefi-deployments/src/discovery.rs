@@ -0,0 +1,85 @@
+use kube::Client;
+
+/// Optional Kubernetes APIs Kubefi can work with, detected once at startup
+#[derive(Clone, Debug, Default)]
+pub struct ClusterApis {
+    /// networking.k8s.io/v1 Ingress is served, Kubernetes 1.19+
+    pub ingress_v1: bool,
+    /// route.openshift.io/v1 Route is served, OpenShift
+    pub route: bool,
+    /// gateway.networking.k8s.io/v1 HTTPRoute is served, Gateway API CRDs are installed
+    pub http_route: bool,
+    /// networking.istio.io/v1beta1 is served, Istio is installed
+    pub istio: bool,
+    /// snapshot.storage.k8s.io/v1 VolumeSnapshot is served, CSI snapshot controller is installed
+    pub volume_snapshot: bool,
+    /// zookeeper.pravega.io/v1beta1 ZookeeperCluster is served, pravega zookeeper-operator is installed
+    pub pravega_zk: bool,
+    /// cert-manager.io/v1 Certificate is served, cert-manager is installed
+    pub cert_manager: bool,
+}
+
+const INGRESS_V1_API: &str = "networking.k8s.io/v1";
+const ROUTE_API: &str = "route.openshift.io/v1";
+const HTTP_ROUTE_API: &str = "gateway.networking.k8s.io/v1";
+const ISTIO_API: &str = "networking.istio.io/v1beta1";
+const VOLUME_SNAPSHOT_API: &str = "snapshot.storage.k8s.io/v1";
+const PRAVEGA_ZK_API: &str = "zookeeper.pravega.io/v1beta1";
+const CERT_MANAGER_API: &str = "cert-manager.io/v1";
+
+pub const OPTIONAL_APIS: [&str; 7] = [
+    INGRESS_V1_API,
+    ROUTE_API,
+    HTTP_ROUTE_API,
+    ISTIO_API,
+    VOLUME_SNAPSHOT_API,
+    PRAVEGA_ZK_API,
+    CERT_MANAGER_API,
+];
+
+impl ClusterApis {
+    pub async fn discover(client: &Client) -> ClusterApis {
+        let ingress_v1 = serves(client, INGRESS_V1_API, "ingresses").await;
+        let route = serves(client, ROUTE_API, "routes").await;
+        let http_route = serves(client, HTTP_ROUTE_API, "httproutes").await;
+        let istio = serves(client, ISTIO_API, "virtualservices").await;
+        let volume_snapshot = serves(client, VOLUME_SNAPSHOT_API, "volumesnapshots").await;
+        let pravega_zk = serves(client, PRAVEGA_ZK_API, "zookeeperclusters").await;
+        let cert_manager = serves(client, CERT_MANAGER_API, "certificates").await;
+        let apis = ClusterApis {
+            ingress_v1,
+            route,
+            http_route,
+            istio,
+            volume_snapshot,
+            pravega_zk,
+            cert_manager,
+        };
+        info!("Discovered cluster APIs: {:?}", &apis);
+        apis
+    }
+
+    /// APIs of a cluster serving the given `group/version`s, e.g. `networking.k8s.io/v1`
+    pub fn from_api_versions(api_versions: &[&str]) -> ClusterApis {
+        let serves = |api: &str| api_versions.contains(&api);
+        ClusterApis {
+            ingress_v1: serves(INGRESS_V1_API),
+            route: serves(ROUTE_API),
+            http_route: serves(HTTP_ROUTE_API),
+            istio: serves(ISTIO_API),
+            volume_snapshot: serves(VOLUME_SNAPSHOT_API),
+            pravega_zk: serves(PRAVEGA_ZK_API),
+            cert_manager: serves(CERT_MANAGER_API),
+        }
+    }
+}
+
+async fn serves(client: &Client, api_version: &str, resource: &str) -> bool {
+    match client.list_api_group_resources(api_version).await {
+        Ok(list) => list.resources.iter().any(|r| r.name == resource),
+        Err(e) => {
+            debug!("API {} is not available: {}", api_version, e);
+            false
+        }
+    }
+}
@@ -1,27 +1,78 @@
+use std::collections::BTreeMap;
 use std::fs::{DirEntry, File};
 use std::io::{BufRead, BufReader, Error};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
-use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, JsonRender, Output, RenderContext,
+    RenderError,
+};
+use serde_json::Value;
 
-pub fn get_files_helper(
-    h: &Helper,
-    hs: &Handlebars,
-    ctx: &Context,
-    _: &mut RenderContext,
-    out: &mut dyn Output,
-) -> HelperResult {
-    let path_param = read_path(h)?;
-    let indent_param = read_indent(h)?;
-    let excluded_files = read_exclude_filter(h, ctx)?;
+/// Files rendered by `get_files`, read from a directory on every render or kept from the data
+/// of a ConfigMap where `<dir>.<file>` keys are the files of `<dir>`
+pub enum TemplateFiles {
+    Dir(PathBuf),
+    ConfigMap(BTreeMap<String, String>),
+}
+
+pub struct GetFilesHelper {
+    pub files: TemplateFiles,
+}
+
+impl HelperDef for GetFilesHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        hs: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let path_param = read_path(h)?;
+        let indent_param = read_indent(h)?;
+        let excluded_files = read_exclude_filter(h, ctx)?;
+
+        let result = match &self.files {
+            TemplateFiles::Dir(dir) => {
+                read_dir_files(dir, &path_param, indent_param, &excluded_files)?
+            }
+            TemplateFiles::ConfigMap(data) => {
+                let prefix = format!("{}.", &path_param);
+                data.iter()
+                    .filter_map(|(key, content)| key.strip_prefix(&prefix).map(|f| (f, content)))
+                    .filter(|(file_name, _)| !excluded_files.iter().any(|f| f == file_name))
+                    .map(|(file_name, content)| {
+                        let content = content
+                            .lines()
+                            .map(|l| format!("{: >1$}{2}\n", "", indent_param, l))
+                            .collect::<String>();
+                        format!("  {}: |-\n{}\n", file_name, content)
+                    })
+                    .collect::<String>()
+            }
+        };
+
+        let rendered = hs
+            .render_template(result.as_str(), ctx.data())
+            .map_err(|e| RenderError::from_error("Failed to render get_files content", e))?;
+        out.write(rendered.as_str())?;
+        Ok(())
+    }
+}
 
-    let template_dir_path = format!("./templates/{}", path_param);
-    let path = Path::new(&template_dir_path);
+fn read_dir_files(
+    dir: &Path,
+    path_param: &str,
+    indent_param: usize,
+    excluded_files: &[String],
+) -> Result<String, RenderError> {
+    let path = dir.join(path_param);
 
     if path.exists() {
-        let result = fs::read_dir(path)?
-            .filter(|entry| !excluded(entry, &excluded_files))
+        let result = fs::read_dir(&path)?
+            .filter(|entry| !excluded(entry, excluded_files))
             .map(|entry| {
                 entry.map(|e| {
                     let p = e.path();
@@ -39,12 +90,7 @@ pub fn get_files_helper(
                 })
             })
             .collect::<Result<String, io::Error>>()?;
-
-        let rendered = hs
-            .render_template(result.as_str(), ctx.data())
-            .map_err(|e| RenderError::from_error("Failed to render get_files content", e))?;
-        out.write(rendered.as_str())?;
-        Ok(())
+        Ok(result)
     } else {
         Err(RenderError::new(format!(
             "templates path {:?} does not exist",
@@ -53,6 +99,87 @@ pub fn get_files_helper(
     }
 }
 
+pub fn to_json_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = h
+        .param(0)
+        .ok_or_else(|| RenderError::new("to_json 'value' parameter at index 0 is missing"))?
+        .value();
+    let json = serde_json::to_string(value)
+        .map_err(|e| RenderError::from_error("Failed to render to_json value", e))?;
+    out.write(json.as_str())?;
+    Ok(())
+}
+
+/// `{{default value "fallback"}}` renders the fallback when the value is missing, null, false or
+/// empty
+pub fn default_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let fallback = h
+        .param(1)
+        .ok_or_else(|| RenderError::new("default 'fallback' parameter at index 1 is missing"))?;
+    let value = h
+        .param(0)
+        .map(|p| p.value())
+        .filter(|v| !is_empty(v))
+        .unwrap_or_else(|| fallback.value());
+    out.write(value.render().as_str())?;
+    Ok(())
+}
+
+/// `{{include "_partial" 6}}` renders another template with the current data or the value of the
+/// optional third parameter, a non-zero indent puts every line on a new line indented by it
+pub fn include_helper(
+    h: &Helper,
+    hs: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let name = h
+        .param(0)
+        .and_then(|v| v.value().as_str())
+        .ok_or_else(|| RenderError::new("include 'template' parameter at index 0 is missing"))?;
+    let indent = h.param(1).and_then(|v| v.value().as_u64()).unwrap_or(0) as usize;
+    let data = h.param(2).map(|v| v.value()).unwrap_or_else(|| ctx.data());
+    let rendered = hs
+        .render(name, data)
+        .map_err(|e| RenderError::from_error(&format!("Failed to include {}", name), e))?;
+    let result = match indent {
+        0 => rendered,
+        _ => rendered
+            .lines()
+            .map(|l| match l.trim().is_empty() {
+                true => "\n".to_string(),
+                false => format!("\n{: >1$}{2}", "", indent, l),
+            })
+            .collect::<String>(),
+    };
+    out.write(result.as_str())?;
+    Ok(())
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        Value::Number(_) => false,
+    }
+}
+
 fn excluded(entry: &Result<DirEntry, Error>, files: &[String]) -> bool {
     entry
         .as_ref()
@@ -108,8 +235,12 @@ mod tests {
     use crate::crd::PodResources;
     use crate::crd::Resources;
     use crate::crd::{NiFiDeploymentSpec, ZooKeeper};
+    use handlebars::Handlebars;
+    use serde_json::{json, Value};
     use std::path::Path;
 
+    use super::{default_helper, include_helper};
+
     use crate::template::Template;
 
     #[test]
@@ -148,12 +279,52 @@ mod tests {
         println!("content:\n{}", content.unwrap())
     }
 
+    #[test]
+    fn test_default_and_include() {
+        let mut hs = Handlebars::new();
+        hs.set_strict_mode(true);
+        hs.register_helper("default", Box::new(default_helper));
+        hs.register_helper("include", Box::new(include_helper));
+        hs.register_template_string("_labels", "app: {{default app \"nifi\"}}\nrelease: nifi")
+            .unwrap();
+        let render = |t: &str, data: Value| hs.render_template(t, &data).unwrap();
+        assert_eq!(render("{{default missing \"x\"}}", json!({})), "x");
+        assert_eq!(render("{{default v \"x\"}}", json!({ "v": "" })), "x");
+        assert_eq!(render("{{default v \"x\"}}", json!({ "v": 0 })), "0");
+        assert_eq!(
+            render("labels:{{include \"_labels\" 2}}", json!({})),
+            "labels:\n  app: nifi\n  release: nifi"
+        );
+        assert_eq!(
+            render(
+                "{{include \"_labels\" 0 zk}}",
+                json!({ "zk": { "app": "zk" } })
+            ),
+            "app: zk\nrelease: nifi"
+        );
+    }
+
     fn test_spec(res: Option<Resources>) -> NiFiDeploymentSpec {
         NiFiDeploymentSpec {
             nifi_replicas: 2,
             zk: ZooKeeper {
                 replicas: 2,
                 image: None,
+                image_pull_policy: None,
+                priority_class_name: None,
+                pod_security_context: None,
+                security_context: None,
+                pod_disruption_budget: None,
+                storage: None,
+                data_log_storage: None,
+                resources: None,
+                external: None,
+                cluster_ref: None,
+                operator: None,
+                tls: None,
+                autopurge: None,
+                metrics: None,
+                cluster_name: None,
             },
             image: None,
             storage_class: None,
@@ -161,6 +332,9 @@ mod tests {
             logging_config_map: None,
             nifi_resources: res,
             ingress: None,
+            expose: None,
+            auth_proxy: None,
+            ..Default::default()
         }
     }
 }
@@ -0,0 +1,754 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::{
+    Container, HostAlias, LocalObjectReference, PodSecurityContext, Secret, Volume, VolumeMount,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::crd::{
+    AuthLdap, ExposeCfg, ExternalZooKeeper, IngressCfg, LdapAuthenticationStrategy, NiFiDeployment,
+    NiFiDeploymentSpec, NiFiPorts, NiFiStorage, OidcCfg, PersistenceCfg, PodResources, RegistryCfg,
+    Resources, RouteCfg, SecretKeyRef, SecurityCfg, SensitivePropsKeyCfg, ServiceAccountCfg,
+    ServiceCfg, ServiceType, SingleUserCfg, TlsCfg, VolumeStorage, ZooKeeper,
+};
+
+/// values of the chart the operator renders itself or has no use for
+const IGNORED_VALUES: [&str; 7] = [
+    "headless",
+    "sidecar",
+    "logresources",
+    "auth.SSL",
+    "persistence.accessMode",
+    "persistence.configStorage",
+    "persistence.authconfStorage",
+];
+const DEFAULT_ZK_REPLICAS: u8 = 3;
+const DEFAULT_ZK_PORT: u16 = 2181;
+const DEFAULT_REGISTRY_PORT: u16 = 80;
+
+/// NiFiDeployment converted from values of the cetic/nifi Helm chart
+pub struct HelmImport {
+    pub deployment: NiFiDeployment,
+    /// credentials the chart takes as plain values, referenced by the NiFiDeployment
+    pub secrets: Vec<Secret>,
+    /// values without a NiFiDeployment counterpart which need to be migrated by hand
+    pub warnings: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmValues {
+    replica_count: Option<u8>,
+    image: HelmImage,
+    security_context: Option<PodSecurityContext>,
+    sts: HelmSts,
+    properties: HelmProperties,
+    auth: HelmAuth,
+    service: HelmService,
+    ingress: HelmIngress,
+    jvm_memory: Option<String>,
+    resources: HelmResources,
+    persistence: HelmPersistence,
+    /// container specs by name, or a list of containers
+    init_containers: Value,
+    extra_volume_mounts: Vec<VolumeMount>,
+    extra_volumes: Vec<Volume>,
+    extra_containers: Vec<Container>,
+    termination_grace_period_seconds: Option<u32>,
+    openshift: HelmOpenshift,
+    ca: HelmEnabled,
+    cert_manager: HelmEnabled,
+    zookeeper: HelmZooKeeper,
+    registry: HelmRegistry,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmImage {
+    repository: Option<String>,
+    tag: Option<String>,
+    pull_policy: Option<String>,
+    pull_secret: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmSts {
+    service_account: HelmServiceAccount,
+    host_aliases: Vec<HostAlias>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmServiceAccount {
+    create: Option<bool>,
+    name: Option<String>,
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmProperties {
+    http_port: Option<u16>,
+    https_port: Option<u16>,
+    cluster_port: Option<u16>,
+    site_to_site: HelmSiteToSite,
+    sensitive_key: Option<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmSiteToSite {
+    port: Option<u16>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmAuth {
+    admin: Option<String>,
+    single_user: HelmSingleUser,
+    ldap: HelmLdap,
+    oidc: HelmOidc,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmSingleUser {
+    username: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmLdap {
+    enabled: bool,
+    host: Option<String>,
+    search_base: Option<String>,
+    admin: Option<String>,
+    pass: Option<String>,
+    search_filter: Option<String>,
+    auth_strategy: Option<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmOidc {
+    enabled: bool,
+    discovery_url: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    claim_identifying_user: Option<String>,
+    admin: Option<String>,
+    preferred_jws_algorithm: Option<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmService {
+    #[serde(rename = "type")]
+    service_type: Option<ServiceType>,
+    #[serde(rename = "loadBalancerIP")]
+    load_balancer_ip: Option<String>,
+    annotations: BTreeMap<String, String>,
+    http_port: Option<u16>,
+    https_port: Option<u16>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmIngress {
+    enabled: bool,
+    class_name: Option<String>,
+    annotations: BTreeMap<String, String>,
+    tls: Vec<HelmIngressTls>,
+    hosts: Vec<String>,
+    path: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmIngressTls {
+    secret_name: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmResources {
+    requests: Option<PodResources>,
+    limits: Option<PodResources>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmPersistence {
+    enabled: Option<bool>,
+    storage_class: Option<String>,
+    data_storage: HelmStorage,
+    flowfile_repo_storage: HelmStorage,
+    content_repo_storage: HelmStorage,
+    provenance_repo_storage: HelmStorage,
+    log_storage: HelmStorage,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmStorage {
+    size: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmOpenshift {
+    route: HelmRoute,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmRoute {
+    enabled: bool,
+    host: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmEnabled {
+    enabled: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HelmZooKeeper {
+    enabled: Option<bool>,
+    url: Option<String>,
+    port: Option<u16>,
+    replica_count: Option<u8>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HelmRegistry {
+    enabled: bool,
+    url: Option<String>,
+    port: Option<u16>,
+}
+
+/// Maps values of the cetic/nifi Helm chart to a NiFiDeployment named `name`. Values are read as
+/// given, defaults of the chart are not applied, so they should be the ones the release was
+/// installed with, e.g. `helm get values <release> -o yaml`
+pub fn import_helm_values(name: &str, values: &str) -> Result<HelmImport> {
+    let values = match values.trim() {
+        "" => HelmValues::default(),
+        values => serde_yaml::from_str::<Option<HelmValues>>(values)
+            .map_err(|e| Error::msg(format!("Invalid Helm values: {}", e)))?
+            .unwrap_or_default(),
+    };
+    let mut import = HelmImport {
+        deployment: NiFiDeployment::new(name, NiFiDeploymentSpec::default()),
+        secrets: vec![],
+        warnings: vec![],
+    };
+    import.map_workload(&values)?;
+    import.map_security(name, &values);
+    import.map_network(&values);
+    import.map_storage(&values);
+
+    let sections = vec![
+        ("", &values.other),
+        ("sts.", &values.sts.other),
+        ("properties.", &values.properties.other),
+        ("auth.", &values.auth.other),
+        ("auth.ldap.", &values.auth.ldap.other),
+        ("auth.oidc.", &values.auth.oidc.other),
+        ("service.", &values.service.other),
+        ("persistence.", &values.persistence.other),
+        ("openshift.", &values.openshift.other),
+    ];
+    for (prefix, other) in sections {
+        for (key, value) in other {
+            let path = format!("{}{}", prefix, key);
+            if !IGNORED_VALUES.contains(&path.as_str()) && !is_unset(value) {
+                import.warn(format!("{} is not mapped to a NiFiDeployment field", path));
+            }
+        }
+    }
+    Ok(import)
+}
+
+impl HelmImport {
+    fn spec(&mut self) -> &mut NiFiDeploymentSpec {
+        &mut self.deployment.spec
+    }
+
+    fn warn(&mut self, warning: String) {
+        self.warnings.push(warning)
+    }
+
+    fn map_workload(&mut self, values: &HelmValues) -> Result<()> {
+        let spec = self.spec();
+        spec.nifi_replicas = values.replica_count.unwrap_or(1);
+        spec.image = values
+            .image
+            .repository
+            .as_ref()
+            .map(|repository| match &values.image.tag {
+                Some(tag) => format!("{}:{}", repository, tag),
+                None => repository.clone(),
+            });
+        spec.image_pull_policy = values
+            .image
+            .pull_policy
+            .as_ref()
+            .map(|p| serde_yaml::from_value(Value::String(p.clone())))
+            .transpose()
+            .map_err(|e| Error::msg(format!("Invalid image.pullPolicy: {}", e)))?;
+        spec.image_pull_secrets = values
+            .image
+            .pull_secret
+            .iter()
+            .map(|s| LocalObjectReference {
+                name: Some(s.clone()),
+            })
+            .collect();
+        spec.pod_security_context = values.security_context.clone();
+        spec.termination_grace_period_seconds = values.termination_grace_period_seconds;
+        spec.host_aliases = values.sts.host_aliases.clone();
+        let account = &values.sts.service_account;
+        if account.create == Some(true) || !account.annotations.is_empty() {
+            spec.service_account = Some(ServiceAccountCfg {
+                create: account.create == Some(true),
+                rbac: false,
+                annotations: account.annotations.clone(),
+            });
+        }
+        spec.service_account_name = account.name.clone();
+
+        let requests = values.resources.requests.clone();
+        let limits = values.resources.limits.clone();
+        if values.jvm_memory.is_some() || requests.is_some() || limits.is_some() {
+            spec.nifi_resources = Some(Resources {
+                jvm_heap_size: values.jvm_memory.clone(),
+                requests,
+                limits,
+            });
+        }
+
+        spec.sidecars = values.extra_containers.clone();
+        spec.volumes = values.extra_volumes.clone();
+        spec.volume_mounts = values.extra_volume_mounts.clone();
+        spec.init_containers = match &values.init_containers {
+            Value::Null => vec![],
+            Value::Sequence(_) => serde_yaml::from_value(values.init_containers.clone())?,
+            Value::Mapping(containers) => containers
+                .iter()
+                .map(|(name, container)| {
+                    let mut container = container.clone();
+                    if let Value::Mapping(c) = &mut container {
+                        c.insert(Value::String("name".into()), name.clone());
+                    }
+                    serde_yaml::from_value(container)
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(Error::msg("initContainers has to be a map or a list")),
+        };
+
+        spec.zk = match (&values.zookeeper.enabled, &values.zookeeper.url) {
+            (Some(false), Some(url)) if !url.is_empty() => ZooKeeper {
+                external: Some(ExternalZooKeeper {
+                    connect_string: format!(
+                        "{}:{}",
+                        url,
+                        values.zookeeper.port.unwrap_or(DEFAULT_ZK_PORT)
+                    ),
+                }),
+                ..ZooKeeper::default()
+            },
+            _ => ZooKeeper {
+                replicas: values
+                    .zookeeper
+                    .replica_count
+                    .unwrap_or(DEFAULT_ZK_REPLICAS),
+                ..ZooKeeper::default()
+            },
+        };
+
+        match &values.registry.url {
+            Some(url) if !url.is_empty() => {
+                let port = values.registry.port.unwrap_or(DEFAULT_REGISTRY_PORT);
+                self.spec().registry = Some(RegistryCfg {
+                    url: Some(format!("http://{}:{}", url, port)),
+                    ..RegistryCfg::default()
+                });
+            }
+            _ if values.registry.enabled => {
+                self.spec().registry = Some(RegistryCfg {
+                    enabled: Some(true),
+                    ..RegistryCfg::default()
+                });
+                self.warn(
+                    "registry.enabled deploys a new NiFi Registry, its flows are not migrated"
+                        .to_string(),
+                );
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn map_security(&mut self, name: &str, values: &HelmValues) {
+        let auth = &values.auth;
+        let mut security = SecurityCfg {
+            initial_admin: auth.admin.clone(),
+            ..SecurityCfg::default()
+        };
+        let https = values.properties.https_port.is_some()
+            || values.ca.enabled
+            || values.cert_manager.enabled;
+        if https {
+            security.tls = Some(TlsCfg {
+                enabled: Some(true),
+                ..TlsCfg::default()
+            });
+        }
+        if values.cert_manager.enabled {
+            self.warn(
+                "certManager issues node certificates with its own CA, set \
+                security.tls.certManager.issuerRef to keep using cert-manager"
+                    .to_string(),
+            );
+        }
+        if let Some(key) = &values.properties.sensitive_key {
+            let secret = self.add_secret(format!("{}-helm-sensitive-props-key", name), "key", key);
+            security.sensitive_props_key = Some(SensitivePropsKeyCfg {
+                secret: Some(secret),
+                revision: None,
+            });
+        }
+
+        if auth.ldap.enabled {
+            let password = auth
+                .ldap
+                .pass
+                .as_ref()
+                .map(|p| self.add_secret(format!("{}-ldap", name), "managerPassword", p));
+            let authentication_strategy = auth.ldap.auth_strategy.as_ref().and_then(|s| {
+                serde_yaml::from_value::<LdapAuthenticationStrategy>(Value::String(s.clone())).ok()
+            });
+            if auth.ldap.auth_strategy.is_some() && authentication_strategy.is_none() {
+                self.warn("auth.ldap.authStrategy is not supported".to_string());
+            }
+            self.spec().ldap = Some(AuthLdap {
+                host: auth.ldap.host.clone().unwrap_or_default(),
+                manager_dn: auth.ldap.admin.clone(),
+                manager_password_secret: password,
+                search_base: auth.ldap.search_base.clone(),
+                search_filter: auth.ldap.search_filter.clone(),
+                authentication_strategy,
+                ca_secret: None,
+            });
+        }
+        if auth.oidc.enabled {
+            let secret = auth.oidc.client_secret.clone().unwrap_or_default();
+            let client_secret = self.add_secret(format!("{}-oidc", name), "clientSecret", &secret);
+            security.oidc = Some(OidcCfg {
+                discovery_url: auth.oidc.discovery_url.clone().unwrap_or_default(),
+                client_id: auth.oidc.client_id.clone().unwrap_or_default(),
+                client_secret,
+                identity_claim: auth.oidc.claim_identifying_user.clone(),
+                preferred_jws_algorithm: auth.oidc.preferred_jws_algorithm.clone(),
+                ..OidcCfg::default()
+            });
+            if security.initial_admin.is_none() {
+                security.initial_admin = auth.oidc.admin.clone();
+            }
+        }
+        let single_user = &auth.single_user;
+        if let (false, false, Some(username)) =
+            (auth.ldap.enabled, auth.oidc.enabled, &single_user.username)
+        {
+            let secret_name = format!("{}-single-user", name);
+            let mut data = BTreeMap::new();
+            data.insert("username".to_string(), username.clone());
+            data.insert(
+                "password".to_string(),
+                single_user.password.clone().unwrap_or_default(),
+            );
+            self.secrets.push(secret(&secret_name, data));
+            security.single_user = Some(SingleUserCfg {
+                credentials_secret: Some(secret_name),
+            });
+        }
+        if serde_json::to_value(&security).ok() != Some(json!({})) {
+            self.spec().security = Some(security);
+        }
+    }
+
+    fn map_network(&mut self, values: &HelmValues) {
+        let properties = &values.properties;
+        let web = properties.https_port.or(properties.http_port);
+        if web.is_some()
+            || properties.cluster_port.is_some()
+            || properties.site_to_site.port.is_some()
+        {
+            self.spec().ports = Some(NiFiPorts {
+                web,
+                cluster: properties.cluster_port,
+                site_to_site: properties.site_to_site.port,
+            });
+        }
+        let service = &values.service;
+        let service_port = service.https_port.or(service.http_port);
+        if service_port.is_some() && service_port != web {
+            self.warn(format!(
+                "service port {} differs from the NiFi web port, the Service exposes the web port",
+                service_port.unwrap_or_default()
+            ));
+        }
+        if service.service_type.is_some()
+            || service.load_balancer_ip.is_some()
+            || !service.annotations.is_empty()
+        {
+            self.spec().service = Some(ServiceCfg {
+                service_type: service.service_type.clone(),
+                load_balancer_ip: service.load_balancer_ip.clone(),
+                annotations: service.annotations.clone(),
+                ..ServiceCfg::default()
+            });
+        }
+
+        let ingress = &values.ingress;
+        if ingress.hosts.len() > 1 {
+            self.warn(format!(
+                "ingress.hosts has {} hosts, only {} is mapped",
+                ingress.hosts.len(),
+                ingress.hosts[0]
+            ));
+        }
+        self.spec().ingress = Some(IngressCfg {
+            enabled: Some(ingress.enabled),
+            host: ingress.hosts.first().cloned(),
+            path: ingress.path.clone(),
+            ingress_class_name: ingress.class_name.clone(),
+            tls_secret: ingress.tls.first().and_then(|t| t.secret_name.clone()),
+            annotations: ingress.annotations.clone(),
+            ..IngressCfg::default()
+        });
+
+        let route = &values.openshift.route;
+        if route.enabled {
+            self.spec().expose = Some(ExposeCfg {
+                route: Some(RouteCfg {
+                    enabled: Some(true),
+                    host: route.host.clone(),
+                    path: route.path.clone(),
+                    ..RouteCfg::default()
+                }),
+                ..ExposeCfg::default()
+            });
+        }
+    }
+
+    fn map_storage(&mut self, values: &HelmValues) {
+        let persistence = &values.persistence;
+        let spec = self.spec();
+        spec.storage_class = persistence.storage_class.clone();
+        if persistence.enabled.is_some() {
+            spec.persistence = Some(PersistenceCfg {
+                enabled: persistence.enabled,
+                ..PersistenceCfg::default()
+            });
+        }
+        let storage = NiFiStorage {
+            data: volume(&persistence.data_storage),
+            flowfile_repository: volume(&persistence.flowfile_repo_storage),
+            content_repository: volume(&persistence.content_repo_storage),
+            provenance_repository: volume(&persistence.provenance_repo_storage),
+            database_repository: None,
+            logs: volume(&persistence.log_storage),
+        };
+        let sized = [
+            &storage.data,
+            &storage.flowfile_repository,
+            &storage.content_repository,
+            &storage.provenance_repository,
+            &storage.logs,
+        ];
+        if sized.iter().any(|v| v.is_some()) {
+            spec.storage = Some(storage);
+        }
+    }
+
+    fn add_secret(&mut self, name: String, key: &str, value: &str) -> SecretKeyRef {
+        let mut data = BTreeMap::new();
+        data.insert(key.to_string(), value.to_string());
+        self.secrets.push(secret(&name, data));
+        SecretKeyRef {
+            name,
+            key: key.to_string(),
+        }
+    }
+}
+
+fn secret(name: &str, data: BTreeMap<String, String>) -> Secret {
+    Secret {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            ..ObjectMeta::default()
+        },
+        string_data: Some(data),
+        type_: Some("Opaque".to_string()),
+        ..Secret::default()
+    }
+}
+
+fn volume(storage: &HelmStorage) -> Option<VolumeStorage> {
+    storage.size.as_ref().map(|size| VolumeStorage {
+        size: Some(size.clone()),
+        storage_class: None,
+    })
+}
+
+/// values which keep the chart defaults: empty ones and sections with `enabled: false`
+fn is_unset(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Sequence(s) => s.is_empty(),
+        Value::Mapping(m) => {
+            m.get(&Value::String("enabled".into())) == Some(&Value::Bool(false))
+                || m.iter().all(|(_, v)| is_unset(v))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: &str = r#"
+replicaCount: 3
+image:
+  repository: apache/nifi
+  tag: "1.16.3"
+  pullPolicy: IfNotPresent
+properties:
+  sensitiveKey: changeMechangeMe
+  httpsPort: 8443
+  clusterPort: 6007
+  siteToSite:
+    port: 10000
+  safetyValve:
+    nifi.web.http.network.interface.default: eth0
+auth:
+  admin: CN=admin, OU=NIFI
+  singleUser:
+    username: admin
+    password: changemechangeme
+  ldap:
+    enabled: true
+    host: ldap://ldap:389
+    searchBase: dc=example,dc=org
+    admin: cn=admin,dc=example,dc=org
+    pass: secret
+ingress:
+  enabled: true
+  className: nginx
+  hosts:
+  - nifi.example.com
+  tls:
+  - secretName: nifi-tls
+jvmMemory: 2g
+persistence:
+  enabled: true
+  storageClass: fast
+  configStorage:
+    size: 100Mi
+  dataStorage:
+    size: 1Gi
+initContainers:
+  wait:
+    image: busybox
+nodeSelector:
+  disk: ssd
+metrics:
+  prometheus:
+    enabled: false
+zookeeper:
+  enabled: false
+  url: zk.tools
+"#;
+
+    #[test]
+    fn test_import_helm_values() {
+        let import = import_helm_values("nifi", VALUES).unwrap();
+        let spec = &import.deployment.spec;
+        assert_eq!(spec.nifi_replicas, 3);
+        assert_eq!(spec.image.as_deref(), Some("apache/nifi:1.16.3"));
+        assert_eq!(spec.ports.as_ref().and_then(|p| p.web), Some(8443));
+        assert_eq!(
+            spec.zk.external.as_ref().map(|e| e.connect_string.as_str()),
+            Some("zk.tools:2181")
+        );
+        assert_eq!(spec.init_containers[0].name, "wait");
+        assert_eq!(
+            spec.ingress.as_ref().and_then(|i| i.tls_secret.as_deref()),
+            Some("nifi-tls")
+        );
+        let ldap = spec.ldap.as_ref().unwrap();
+        assert_eq!(
+            ldap.manager_password_secret
+                .as_ref()
+                .map(|s| s.name.as_str()),
+            Some("nifi-ldap")
+        );
+        let security = spec.security.as_ref().unwrap();
+        assert!(security.single_user.is_none());
+        assert_eq!(security.initial_admin.as_deref(), Some("CN=admin, OU=NIFI"));
+        assert_eq!(
+            import
+                .secrets
+                .iter()
+                .filter_map(|s| s.metadata.name.as_deref())
+                .collect::<Vec<_>>(),
+            vec!["nifi-helm-sensitive-props-key", "nifi-ldap"]
+        );
+        assert_eq!(
+            import.warnings,
+            vec![
+                "nodeSelector is not mapped to a NiFiDeployment field",
+                "properties.safetyValve is not mapped to a NiFiDeployment field",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_empty_values() {
+        let import = import_helm_values("nifi", "").unwrap();
+        assert_eq!(import.deployment.spec.nifi_replicas, 1);
+        assert_eq!(import.deployment.spec.zk.replicas, DEFAULT_ZK_REPLICAS);
+        assert!(import.secrets.is_empty());
+        assert!(import.warnings.is_empty());
+    }
+}
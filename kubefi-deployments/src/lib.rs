@@ -17,10 +17,21 @@ use crate::Namespace::*;
 pub mod config;
 pub mod controller;
 pub mod crd;
+mod cron;
+pub mod discovery;
 mod handelbars_ext;
+pub mod helm;
+pub mod metrics;
+pub mod nifi_api;
+pub mod oci_api;
+pub mod registry_api;
+pub mod resources;
 pub mod template;
+mod template_validation;
+pub mod vault_api;
 pub mod watcher;
 
+#[derive(Clone)]
 pub enum Namespace {
     All,
     SingleNamespace(String),
@@ -37,7 +48,7 @@ pub fn read_namespace() -> Namespace {
 pub fn get_api<T: Resource>(ns: &Namespace, client: Client) -> Api<T> {
     match ns {
         All => Api::all(client),
-        SingleNamespace(name) => Api::namespaced(client, &name),
+        SingleNamespace(name) => Api::namespaced(client, name),
     }
 }
 
@@ -7,27 +7,55 @@ extern crate kubefi_deployments;
 #[macro_use]
 extern crate log;
 
+use std::io::Read;
 use std::path::Path;
 use std::rc::Rc;
 
-use anyhow::Result;
+use anyhow::{Error, Result};
 use dotenv::dotenv;
 use futures::StreamExt;
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1beta1::CustomResourceDefinition;
 use kube::api::{Api, ListParams};
 use kube::Client;
+use serde_json::Value;
 
-use kubefi_deployments::config::{read_kubefi_config, read_nifi_config};
-use kubefi_deployments::controller::NiFiController;
-use kubefi_deployments::crd::{replace_crd, NiFiDeployment};
+use kubefi_deployments::config::{
+    read_kubefi_config, read_nifi_config, read_nifi_config_file, read_templates_config_map,
+};
+use kubefi_deployments::controller::{render_manifests, NiFiController};
+use kubefi_deployments::crd::{
+    crd_installed, replace_authorization_crd, replace_crd, replace_flow_crd, replace_minifi_crd,
+    replace_parameter_context_crd, replace_stateless_crd, replace_zk_crd, FlowDeployment,
+    MiNiFiDeployment, NiFiAuthorization, NiFiDeployment, NiFiParameterContext,
+    NiFiStatelessDeployment, ZookeeperCluster, AUTHORIZATION_CRD_NAME, FLOW_CRD_NAME,
+    MINIFI_CRD_NAME, PARAMETER_CONTEXT_CRD_NAME, STATELESS_CRD_NAME, ZK_CRD_NAME,
+};
+use kubefi_deployments::discovery::{ClusterApis, OPTIONAL_APIS};
+use kubefi_deployments::helm::import_helm_values;
+use kubefi_deployments::metrics::{serve_metrics, Metrics};
 use kubefi_deployments::template::Template;
-use kubefi_deployments::watcher::watch;
+use kubefi_deployments::watcher::{
+    run_health_probes, run_periodic_tasks, run_rollouts, run_template_reloads, watch,
+    watch_if_installed, watch_resources,
+};
 use kubefi_deployments::{get_api, read_namespace, read_type};
 
+const RENDER_USAGE: &str =
+    "usage: kubefi render -f <NiFiDeployment YAML or -> [--config <nifi.conf>] \
+    [--templates <dir>] [--api-versions <group/version>,...]";
+const IMPORT_HELM_USAGE: &str =
+    "usage: kubefi import-helm -f <cetic/nifi values YAML or -> [--name <NiFiDeployment name>]";
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
     env_logger::init();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(|a| a.as_str()) {
+        Some("render") => return render(&args[1..]),
+        Some("import-helm") => return import_helm(&args[1..]),
+        _ => (),
+    }
     let version = env!("CARGO_PKG_VERSION");
     let banner = r#"
      _  __     _           __ _
@@ -45,26 +73,253 @@ async fn main() -> Result<()> {
 
     let crds: Api<CustomResourceDefinition> = Api::all(client.clone());
     if kubefi_cfg.replace_existing_crd {
-        replace_crd(crds, kubefi_cfg.crd_schema_path).await?;
+        replace_crd(crds.clone(), kubefi_cfg.crd_schema_path).await?;
+        replace_zk_crd(crds.clone(), kubefi_cfg.zk_crd_schema_path).await?;
+        replace_flow_crd(crds.clone(), kubefi_cfg.flow_crd_schema_path).await?;
+        replace_parameter_context_crd(crds.clone(), kubefi_cfg.parameter_context_crd_schema_path)
+            .await?;
+        replace_authorization_crd(crds.clone(), kubefi_cfg.authorization_crd_schema_path).await?;
+        replace_stateless_crd(crds.clone(), kubefi_cfg.stateless_crd_schema_path).await?;
+        replace_minifi_crd(crds.clone(), kubefi_cfg.minifi_crd_schema_path).await?;
     }
+    let zk_installed = crd_installed(&crds, ZK_CRD_NAME).await?;
+    let minifi_installed = crd_installed(&crds, MINIFI_CRD_NAME).await?;
+    let stateless_installed = crd_installed(&crds, STATELESS_CRD_NAME).await?;
+    let authorization_installed = crd_installed(&crds, AUTHORIZATION_CRD_NAME).await?;
+    let parameter_context_installed = crd_installed(&crds, PARAMETER_CONTEXT_CRD_NAME).await?;
+    let flow_installed = crd_installed(&crds, FLOW_CRD_NAME).await?;
 
     let namespace = read_namespace();
     let api = get_api::<NiFiDeployment>(&namespace, client.clone());
 
     let mut watcher = kube_runtime::watcher(api.clone(), ListParams::default()).boxed();
+    let zk_api = get_api::<ZookeeperCluster>(&namespace, client.clone());
+    let mut zk_watcher = kube_runtime::watcher(zk_api.clone(), ListParams::default()).boxed();
+    let flow_api = get_api::<FlowDeployment>(&namespace, client.clone());
+    let mut flow_watcher = kube_runtime::watcher(flow_api.clone(), ListParams::default()).boxed();
+    let parameter_context_api = get_api::<NiFiParameterContext>(&namespace, client.clone());
+    let mut parameter_context_watcher =
+        kube_runtime::watcher(parameter_context_api.clone(), ListParams::default()).boxed();
+    let authorization_api = get_api::<NiFiAuthorization>(&namespace, client.clone());
+    let mut authorization_watcher =
+        kube_runtime::watcher(authorization_api.clone(), ListParams::default()).boxed();
+    let stateless_api = get_api::<NiFiStatelessDeployment>(&namespace, client.clone());
+    let mut stateless_watcher =
+        kube_runtime::watcher(stateless_api.clone(), ListParams::default()).boxed();
+    let minifi_api = get_api::<MiNiFiDeployment>(&namespace, client.clone());
+    let mut minifi_watcher =
+        kube_runtime::watcher(minifi_api.clone(), ListParams::default()).boxed();
     let nifi_cfg = read_nifi_config()?;
     debug!(">>>> Loaded NiFi config {}", &nifi_cfg);
 
-    let controller = NiFiController::new(
-        namespace,
-        Rc::new(client.clone()),
-        Rc::new(Template::new(Path::new("./templates"), nifi_cfg)?),
-    )?;
+    let template = if kubefi_cfg.templates_config_map.is_empty() {
+        Template::new(Path::new("./templates"), nifi_cfg)?
+    } else {
+        let ns = &kubefi_cfg.templates_config_map_namespace;
+        let name = &kubefi_cfg.templates_config_map;
+        let data = read_templates_config_map(client.clone(), ns, name).await?;
+        Template::from_config_map(ns, name, data, nifi_cfg)?
+    };
+    template.validate()?;
+    info!("Validated templates with a dry render");
+
+    let apis = ClusterApis::discover(&client).await;
+    let controller =
+        NiFiController::new(namespace, Rc::new(client.clone()), Rc::new(template), apis)?;
+    let metrics = Metrics::default();
 
     info!(
         "Starting Kubefi event loop for {:?}",
         read_type::<NiFiDeployment>("NiFi")
     );
 
-    watch(client, &mut watcher, &controller).await
+    futures::future::try_join4(
+        run_template_reloads(client.clone(), api.clone(), &controller),
+        run_rollouts(
+            client.clone(),
+            api.clone(),
+            Some(zk_api).filter(|_| zk_installed),
+            &controller,
+        ),
+        futures::future::try_join5(
+            watch(client.clone(), &mut watcher, &controller),
+            watch_if_installed(
+                zk_installed,
+                ZK_CRD_NAME,
+                watch_resources(client.clone(), &mut zk_watcher, &controller),
+            ),
+            watch_if_installed(
+                flow_installed,
+                FLOW_CRD_NAME,
+                watch_resources(client.clone(), &mut flow_watcher, &controller),
+            ),
+            run_health_probes(client.clone(), api.clone(), &controller, &metrics),
+            serve_metrics(metrics.clone(), kubefi_cfg.metrics_port),
+        ),
+        futures::future::try_join5(
+            watch_if_installed(
+                parameter_context_installed,
+                PARAMETER_CONTEXT_CRD_NAME,
+                watch_resources(client.clone(), &mut parameter_context_watcher, &controller),
+            ),
+            watch_if_installed(
+                authorization_installed,
+                AUTHORIZATION_CRD_NAME,
+                watch_resources(client.clone(), &mut authorization_watcher, &controller),
+            ),
+            watch_if_installed(
+                stateless_installed,
+                STATELESS_CRD_NAME,
+                watch_resources(client.clone(), &mut stateless_watcher, &controller),
+            ),
+            watch_if_installed(
+                minifi_installed,
+                MINIFI_CRD_NAME,
+                watch_resources(client.clone(), &mut minifi_watcher, &controller),
+            ),
+            run_periodic_tasks(
+                client,
+                api,
+                flow_api,
+                parameter_context_api,
+                authorization_api,
+                stateless_api,
+                minifi_api,
+                &controller,
+            ),
+        ),
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Prints the manifests the controller would create for the NiFiDeployments of a file, other
+/// resources of the file are skipped. It does not connect to a cluster, all optional APIs are
+/// assumed to be served unless `--api-versions` lists them
+fn render(args: &[String]) -> Result<()> {
+    let mut file = None;
+    let mut config = None;
+    let mut templates = "./templates".to_string();
+    let mut api_versions = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .cloned()
+            .ok_or_else(|| Error::msg(format!("{} requires a value, {}", arg, RENDER_USAGE)));
+        match arg.as_str() {
+            "-f" | "--file" => file = Some(value?),
+            "--config" => config = Some(value?),
+            "--templates" => templates = value?,
+            "--api-versions" => api_versions = Some(value?),
+            _ => {
+                return Err(Error::msg(format!(
+                    "unknown argument {}, {}",
+                    arg, RENDER_USAGE
+                )))
+            }
+        }
+    }
+    let input = read_input(&file.ok_or_else(|| Error::msg(RENDER_USAGE))?)?;
+    let nifi_cfg = match &config {
+        Some(path) => read_nifi_config_file(Path::new(path))?,
+        None => read_nifi_config()?,
+    };
+    let template = Template::new(Path::new(&templates), nifi_cfg)?;
+    let apis = match &api_versions {
+        Some(versions) => ClusterApis::from_api_versions(&versions.split(',').collect::<Vec<_>>()),
+        None => ClusterApis::from_api_versions(&OPTIONAL_APIS),
+    };
+    let documents = input.split("\n---").map(|d| d.trim()).filter(|d| {
+        d.lines()
+            .map(|l| l.trim())
+            .any(|l| !l.is_empty() && l != "---" && !l.starts_with('#'))
+    });
+    for document in documents {
+        let kind = serde_yaml::from_str::<serde_yaml::Value>(document)?["kind"].clone();
+        if kind.as_str() != Some("NiFiDeployment") {
+            continue;
+        }
+        let d: NiFiDeployment = serde_yaml::from_str(document)?;
+        let name = d
+            .metadata
+            .name
+            .clone()
+            .ok_or_else(|| Error::msg("NiFiDeployment metadata.name is missing"))?;
+        let ns = d.metadata.namespace.as_deref().unwrap_or("default");
+        if d.spec.template_overrides.is_some() {
+            warn!(
+                "{}: templateOverrides ConfigMap is not read, rendering with {}",
+                &name, &templates
+            );
+        }
+        for (template_name, yaml) in render_manifests(&template, &apis, &name, ns, &d.spec)? {
+            println!("---\n# Source: {}.yaml\n{}", template_name, yaml);
+        }
+    }
+    Ok(())
+}
+
+/// Prints a NiFiDeployment and the Secrets of its credentials converted from values of the
+/// cetic/nifi Helm chart, values which are not migrated are listed in a comment
+fn import_helm(args: &[String]) -> Result<()> {
+    let mut file = None;
+    let mut name = "nifi".to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .cloned()
+            .ok_or_else(|| Error::msg(format!("{} requires a value, {}", arg, IMPORT_HELM_USAGE)));
+        match arg.as_str() {
+            "-f" | "--file" => file = Some(value?),
+            "--name" => name = value?,
+            _ => {
+                return Err(Error::msg(format!(
+                    "unknown argument {}, {}",
+                    arg, IMPORT_HELM_USAGE
+                )))
+            }
+        }
+    }
+    let input = read_input(&file.ok_or_else(|| Error::msg(IMPORT_HELM_USAGE))?)?;
+    let import = import_helm_values(&name, &input)?;
+    if !import.warnings.is_empty() {
+        println!("# Helm values to migrate by hand:");
+        for warning in &import.warnings {
+            println!("# - {}", warning);
+        }
+    }
+    for secret in &import.secrets {
+        println!("{}", serde_yaml::to_string(secret)?);
+    }
+    let deployment = without_nulls(serde_json::to_value(&import.deployment)?);
+    println!("{}", serde_yaml::to_string(&deployment)?);
+    Ok(())
+}
+
+/// Content of a file, or of stdin for `-`
+fn read_input(file: &str) -> Result<String> {
+    match file {
+        "-" => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            Ok(input)
+        }
+        path => Ok(std::fs::read_to_string(path)?),
+    }
+}
+
+/// Unset optional fields of the CRD types are serialized as null
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, without_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
 }
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Error, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+use crate::crd::ClusterHealth;
+
+const METRICS_PATH: &str = "/metrics";
+
+/// Name, help text and value of a gauge
+type Gauge = (&'static str, &'static str, fn(&ClusterHealth) -> u64);
+
+/// Gauges of NiFi clusters by namespace and deployment name, updated by the health probes
+#[derive(Clone, Default)]
+pub struct Metrics {
+    clusters: Arc<Mutex<BTreeMap<(String, String), ClusterHealth>>>,
+}
+
+impl Metrics {
+    /// Gauges of the deployment are removed when its cluster health is unknown
+    pub fn set(&self, ns: &str, name: &str, health: Option<&ClusterHealth>) {
+        let mut clusters = self.clusters.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (ns.to_string(), name.to_string());
+        match health {
+            Some(health) => clusters.insert(key, health.clone()),
+            None => clusters.remove(&key),
+        };
+    }
+
+    /// Keeps gauges of the listed deployments only
+    pub fn retain(&self, deployments: &[(String, String)]) {
+        let mut clusters = self.clusters.lock().unwrap_or_else(|e| e.into_inner());
+        clusters.retain(|key, _| deployments.contains(key));
+    }
+
+    /// Prometheus text exposition format
+    fn render(&self) -> String {
+        let clusters = self.clusters.lock().unwrap_or_else(|e| e.into_inner());
+        let gauges: [Gauge; 5] = [
+            (
+                "kubefi_nifi_queued_flowfiles",
+                "Flowfiles queued in the NiFi cluster",
+                |h| h.queued_flow_files,
+            ),
+            (
+                "kubefi_nifi_queued_bytes",
+                "Bytes queued in the NiFi cluster",
+                |h| h.queued_bytes,
+            ),
+            (
+                "kubefi_nifi_active_threads",
+                "Active threads of the NiFi cluster",
+                |h| h.active_threads as u64,
+            ),
+            (
+                "kubefi_nifi_connected_nodes",
+                "Nodes connected to the NiFi cluster",
+                |h| h.connected_nodes as u64,
+            ),
+            (
+                "kubefi_nifi_total_nodes",
+                "Nodes of the NiFi cluster",
+                |h| h.total_nodes as u64,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, help, value) in gauges.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for ((ns, deployment), health) in clusters.iter() {
+                let _ = writeln!(
+                    out,
+                    "{}{{namespace=\"{}\",deployment=\"{}\"}} {}",
+                    name,
+                    ns,
+                    deployment,
+                    value(health)
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Serves the gauges at `/metrics` until the server fails
+pub async fn serve_metrics(metrics: Metrics, port: u16) -> Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let response = respond(&metrics, &request);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    info!("Serving metrics at {}{}", &addr, METRICS_PATH);
+    Server::bind(&addr).serve(service).await?;
+    Err(Error::msg("Metrics server was stopped, exiting..."))
+}
+
+fn respond(metrics: &Metrics, request: &Request<Body>) -> Response<Body> {
+    if request.uri().path() != METRICS_PATH {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+    let mut response = Response::new(Body::from(metrics.render()));
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    response
+}
@@ -0,0 +1,1147 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Error, Result};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use reqwest::{Certificate, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::time::{delay_for, Duration};
+
+use crate::crd::NiFiDeploymentSpec;
+use crate::template::Template;
+
+const USERNAME_KEY: &str = "username";
+const PASSWORD_KEY: &str = "password";
+const CA_KEY: &str = "ca.crt";
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+const COORDINATOR_ROLE: &str = "Cluster Coordinator";
+// 2 second polls while NiFi stops, changes and restarts the affected components
+const UPDATE_CHECKS: u16 = 150;
+
+/// Client of the NiFi REST API of one NiFiDeployment
+pub struct NiFiApi {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterSummary {
+    pub connected_node_count: u32,
+    pub total_node_count: u32,
+    pub connected_to_cluster: bool,
+    pub clustered: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Cluster {
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    pub node_id: String,
+    pub address: String,
+    pub api_port: u16,
+    pub status: NodeStatus,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// e.g. "1,234 / 5.5 MB"
+    pub queued: Option<String>,
+    pub active_thread_count: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum NodeStatus {
+    Connecting,
+    Connected,
+    Disconnecting,
+    Disconnected,
+    Offloading,
+    Offloaded,
+    Removed,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerStatus {
+    pub active_thread_count: u32,
+    pub flow_files_queued: u64,
+    pub bytes_queued: u64,
+    pub running_count: u32,
+    pub stopped_count: u32,
+    pub invalid_count: u32,
+    pub disabled_count: u32,
+}
+
+/// Registry client of the NiFi controller with the revision required to update it
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryClient {
+    pub id: String,
+    pub revision: Revision,
+    pub component: RegistryClientComponent,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryClientComponent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    pub version: i64,
+}
+
+/// Process group with the revision required to change it
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessGroup {
+    pub id: String,
+    pub revision: Revision,
+    pub component: ProcessGroupComponent,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessGroupComponent {
+    pub name: String,
+    pub version_control_information: Option<VersionControlInformation>,
+    pub parameter_context: Option<ParameterContextReference>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParameterContextReference {
+    pub id: String,
+}
+
+/// Parameter context with the revision required to update it
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterContext {
+    pub id: String,
+    pub revision: Revision,
+    pub component: ParameterContextComponent,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterContextComponent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parameters: Vec<ParameterEntity>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ParameterEntity {
+    pub parameter: Parameter,
+}
+
+/// Value of a sensitive parameter is masked in responses, parameters sent without a value are removed
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub sensitive: bool,
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Registry flow a process group is tracking
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionControlInformation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    pub registry_id: String,
+    pub bucket_id: String,
+    pub flow_id: String,
+    pub version: i32,
+}
+
+/// Controller service or reporting task with the revision required to change it
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfiguredComponent {
+    pub id: String,
+    pub revision: Revision,
+    pub component: ComponentConfig,
+}
+
+/// Configuration of a controller service or a reporting task, unset properties of a NiFi response are null
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<Bundle>,
+    #[serde(default)]
+    pub properties: BTreeMap<String, Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<String>,
+    /// e.g. ENABLED or RUNNING, changed via the run status only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// of reporting tasks, e.g. "1 min"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduling_period: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub group: String,
+    pub artifact: String,
+    pub version: String,
+}
+
+/// User or user group with the revision required to change it
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Tenant {
+    pub id: String,
+    pub revision: Revision,
+    pub component: TenantComponent,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantComponent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub identity: String,
+    /// members of a user group
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<TenantReference>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TenantReference {
+    pub id: String,
+}
+
+/// Access policy with the revision required to change it
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessPolicy {
+    pub id: String,
+    pub revision: Revision,
+    pub component: AccessPolicyComponent,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessPolicyComponent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// e.g. /flow or /process-groups/<id>
+    pub resource: String,
+    /// read or write
+    pub action: String,
+    #[serde(default)]
+    pub users: Vec<TenantReference>,
+    #[serde(default)]
+    pub user_groups: Vec<TenantReference>,
+}
+
+/// Queue of a connection, percentages are of its back-pressure object count and size thresholds
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionStatus {
+    pub id: String,
+    pub group_id: String,
+    pub name: Option<String>,
+    pub source_name: Option<String>,
+    pub destination_name: Option<String>,
+    pub flow_files_queued: u64,
+    pub bytes_queued: u64,
+    pub percent_use_count: Option<u32>,
+    pub percent_use_bytes: Option<u32>,
+}
+
+/// Asynchronous change of a flow version or of a parameter context
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateRequest {
+    pub request_id: String,
+    pub complete: bool,
+    pub failure_reason: Option<String>,
+    pub state: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BucketsEntity {
+    #[serde(default)]
+    buckets: Vec<BucketEntity>,
+}
+
+#[derive(Deserialize)]
+struct BucketEntity {
+    id: String,
+    bucket: Bucket,
+}
+
+#[derive(Deserialize)]
+struct Bucket {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionedFlowsEntity {
+    #[serde(default)]
+    versioned_flows: Vec<VersionedFlowEntity>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionedFlowEntity {
+    versioned_flow: VersionedFlow,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionedFlow {
+    flow_id: String,
+    flow_name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FlowVersionsEntity {
+    #[serde(default)]
+    versioned_flow_snapshot_metadata_set: Vec<FlowVersionEntity>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FlowVersionEntity {
+    versioned_flow_snapshot_metadata: FlowVersion,
+}
+
+#[derive(Deserialize)]
+struct FlowVersion {
+    version: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessGroupFlowEntity {
+    process_group_flow: ProcessGroupFlow,
+}
+
+#[derive(Deserialize)]
+struct ProcessGroupFlow {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessGroupsEntity {
+    #[serde(default)]
+    process_groups: Vec<ProcessGroup>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ParameterContextsEntity {
+    #[serde(default)]
+    parameter_contexts: Vec<ParameterContext>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ControllerServicesEntity {
+    #[serde(default)]
+    controller_services: Vec<ConfiguredComponent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportingTasksEntity {
+    #[serde(default)]
+    reporting_tasks: Vec<ConfiguredComponent>,
+}
+
+#[derive(Deserialize)]
+struct UsersEntity {
+    #[serde(default)]
+    users: Vec<Tenant>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserGroupsEntity {
+    #[serde(default)]
+    user_groups: Vec<Tenant>,
+}
+
+#[derive(Deserialize)]
+struct UpdateRequestEntity {
+    request: UpdateRequest,
+}
+
+#[derive(Deserialize)]
+struct RegistryClientsEntity {
+    #[serde(default)]
+    registries: Vec<RegistryClient>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClusterSummaryEntity {
+    cluster_summary: ClusterSummary,
+}
+
+#[derive(Deserialize)]
+struct ClusterEntity {
+    cluster: Cluster,
+}
+
+#[derive(Deserialize)]
+struct NodeEntity {
+    node: Node,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ControllerStatusEntity {
+    controller_status: ControllerStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessGroupStatusEntity {
+    process_group_status: ProcessGroupStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessGroupStatus {
+    aggregate_snapshot: ProcessGroupStatusSnapshot,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessGroupStatusSnapshot {
+    #[serde(default)]
+    connection_status_snapshots: Vec<ConnectionStatusSnapshotEntity>,
+    #[serde(default)]
+    process_group_status_snapshots: Vec<ProcessGroupStatusSnapshotEntity>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnectionStatusSnapshotEntity {
+    connection_status_snapshot: ConnectionStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessGroupStatusSnapshotEntity {
+    process_group_status_snapshot: ProcessGroupStatusSnapshot,
+}
+
+impl ProcessGroupStatusSnapshot {
+    fn connections(self, all: &mut Vec<ConnectionStatus>) {
+        all.extend(
+            self.connection_status_snapshots
+                .into_iter()
+                .map(|c| c.connection_status_snapshot),
+        );
+        for group in self.process_group_status_snapshots {
+            group.process_group_status_snapshot.connections(all);
+        }
+    }
+}
+
+impl Cluster {
+    /// Node running in the given Pod, NiFi nodes are addressed by their headless Service host names
+    pub fn node(&self, pod_name: &str) -> Option<&Node> {
+        let prefix = format!("{}.", pod_name);
+        self.nodes
+            .iter()
+            .find(|n| n.address == pod_name || n.address.starts_with(&prefix))
+    }
+
+    pub fn coordinator(&self) -> Option<&Node> {
+        self.nodes
+            .iter()
+            .find(|n| n.roles.iter().any(|r| r == COORDINATOR_ROLE))
+    }
+}
+
+impl NiFiApi {
+    /// Client of the NiFi Service of a deployment or of one of its Pods,
+    /// logged in when `spec.api.credentialsSecret` is set
+    pub async fn connect(
+        client: &Client,
+        template: &Template,
+        name: &str,
+        ns: &str,
+        spec: &NiFiDeploymentSpec,
+        pod: Option<&str>,
+    ) -> Result<NiFiApi> {
+        let cfg = spec.api.clone().unwrap_or_default();
+        let credentials_secret = cfg
+            .credentials_secret
+            .clone()
+            .or_else(|| Template::single_user_secret_name(name, spec));
+        let secret = match &credentials_secret {
+            Some(secret_name) => {
+                let api: Api<Secret> = Api::namespaced(client.clone(), ns);
+                Some(api.get(secret_name).await?)
+            }
+            None => None,
+        };
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .danger_accept_invalid_certs(cfg.insecure_skip_tls_verify.unwrap_or(false));
+        if let Some(ca) = secret.as_ref().and_then(|s| secret_value(s, CA_KEY)) {
+            builder = builder.add_root_certificate(Certificate::from_pem(&ca)?);
+        }
+        // nodes present certificates of the operator CA or of the cert-manager issuer when keystores
+        // are issued for them, the keystore Secret has the CA in both cases
+        if spec.tls_enabled() == Some(true) {
+            let api: Api<Secret> = Api::namespaced(client.clone(), ns);
+            let ca_secret = api.get(&Template::tls_secret_name(name)).await?;
+            if let Some(ca) = secret_value(&ca_secret, CA_KEY) {
+                builder = builder.add_root_certificate(Certificate::from_pem(&ca)?);
+            }
+        }
+        let mut nifi = NiFiApi {
+            http: builder.build()?,
+            base_url: template.nifi_api_url(name, ns, spec, pod),
+            token: None,
+        };
+        if let Some(secret) = &secret {
+            let username = secret_string(secret, USERNAME_KEY)?;
+            let password = secret_string(secret, PASSWORD_KEY)?;
+            nifi.token = Some(nifi.login(&username, &password).await?);
+        }
+        Ok(nifi)
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<String> {
+        let request = self
+            .http
+            .post(&self.url("/access/token"))
+            .form(&[(USERNAME_KEY, username), (PASSWORD_KEY, password)]);
+        let response = send(request, "/access/token").await?;
+        Ok(response.text().await?)
+    }
+
+    pub async fn cluster_summary(&self) -> Result<ClusterSummary> {
+        self.get::<ClusterSummaryEntity>("/flow/cluster/summary")
+            .await
+            .map(|e| e.cluster_summary)
+    }
+
+    /// Nodes of the cluster with their connection status
+    pub async fn cluster(&self) -> Result<Cluster> {
+        self.get::<ClusterEntity>("/controller/cluster")
+            .await
+            .map(|e| e.cluster)
+    }
+
+    pub async fn node(&self, node_id: &str) -> Result<Node> {
+        self.get::<NodeEntity>(&format!("/controller/cluster/nodes/{}", node_id))
+            .await
+            .map(|e| e.node)
+    }
+
+    /// Requests a node status change, i.e. DISCONNECTING, OFFLOADING or CONNECTING
+    pub async fn update_node_status(&self, node_id: &str, status: NodeStatus) -> Result<Node> {
+        let path = format!("/controller/cluster/nodes/{}", node_id);
+        let body = json!({ "node": { "nodeId": node_id, "status": status } });
+        let request = self.authorized(self.http.put(&self.url(&path)).json(&body));
+        let response = send(request, &path).await?;
+        Ok(response.json::<NodeEntity>().await?.node)
+    }
+
+    pub async fn disconnect_node(&self, node_id: &str) -> Result<Node> {
+        self.update_node_status(node_id, NodeStatus::Disconnecting)
+            .await
+    }
+
+    /// Moves flowfiles of a disconnected node to the remaining nodes
+    pub async fn offload_node(&self, node_id: &str) -> Result<Node> {
+        self.update_node_status(node_id, NodeStatus::Offloading)
+            .await
+    }
+
+    pub async fn connect_node(&self, node_id: &str) -> Result<Node> {
+        self.update_node_status(node_id, NodeStatus::Connecting)
+            .await
+    }
+
+    /// Removes a disconnected or offloaded node from the cluster view
+    pub async fn delete_node(&self, node_id: &str) -> Result<()> {
+        let path = format!("/controller/cluster/nodes/{}", node_id);
+        let request = self.authorized(self.http.delete(&self.url(&path)));
+        send(request, &path).await.map(|_| ())
+    }
+
+    /// Queued flowfiles and component counts of the whole flow
+    pub async fn flow_status(&self) -> Result<ControllerStatus> {
+        self.get::<ControllerStatusEntity>("/flow/status")
+            .await
+            .map(|e| e.controller_status)
+    }
+
+    /// Queues of all connections of the flow, including nested process groups
+    pub async fn connection_statuses(&self) -> Result<Vec<ConnectionStatus>> {
+        let path = "/flow/process-groups/root/status?recursive=true";
+        let entity = self.get::<ProcessGroupStatusEntity>(path).await?;
+        let mut connections = Vec::new();
+        entity
+            .process_group_status
+            .aggregate_snapshot
+            .connections(&mut connections);
+        Ok(connections)
+    }
+
+    pub async fn registry_clients(&self) -> Result<Vec<RegistryClient>> {
+        self.get::<RegistryClientsEntity>("/controller/registry-clients")
+            .await
+            .map(|e| e.registries)
+    }
+
+    pub async fn create_registry_client(&self, name: &str, uri: &str) -> Result<RegistryClient> {
+        let component = RegistryClientComponent {
+            id: None,
+            name: name.to_string(),
+            uri: uri.to_string(),
+            description: Some("Managed by Kubefi".to_string()),
+        };
+        let body = json!({ "revision": Revision::default(), "component": component });
+        self.send_json(
+            self.http.post(&self.url("/controller/registry-clients")),
+            "/controller/registry-clients",
+            &body,
+        )
+        .await
+    }
+
+    /// Points an existing Registry client to another Registry URL
+    pub async fn update_registry_client(
+        &self,
+        client: &RegistryClient,
+        uri: &str,
+    ) -> Result<RegistryClient> {
+        let path = format!("/controller/registry-clients/{}", &client.id);
+        let component = RegistryClientComponent {
+            id: Some(client.id.clone()),
+            uri: uri.to_string(),
+            ..client.component.clone()
+        };
+        let body = json!({ "revision": client.revision, "component": component });
+        self.send_json(self.http.put(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    /// Id of a Registry bucket as seen by the given Registry client
+    pub async fn bucket_id(&self, registry_id: &str, bucket: &str) -> Result<Option<String>> {
+        self.get::<BucketsEntity>(&format!("/flow/registries/{}/buckets", registry_id))
+            .await
+            .map(|e| {
+                e.buckets
+                    .into_iter()
+                    .find(|b| b.bucket.name == bucket)
+                    .map(|b| b.id)
+            })
+    }
+
+    pub async fn flow_id(
+        &self,
+        registry_id: &str,
+        bucket_id: &str,
+        flow: &str,
+    ) -> Result<Option<String>> {
+        let path = format!(
+            "/flow/registries/{}/buckets/{}/flows",
+            registry_id, bucket_id
+        );
+        self.get::<VersionedFlowsEntity>(&path).await.map(|e| {
+            e.versioned_flows
+                .into_iter()
+                .find(|f| f.versioned_flow.flow_name == flow)
+                .map(|f| f.versioned_flow.flow_id)
+        })
+    }
+
+    pub async fn latest_flow_version(
+        &self,
+        registry_id: &str,
+        bucket_id: &str,
+        flow_id: &str,
+    ) -> Result<Option<i32>> {
+        let path = format!(
+            "/flow/registries/{}/buckets/{}/flows/{}/versions",
+            registry_id, bucket_id, flow_id
+        );
+        self.get::<FlowVersionsEntity>(&path).await.map(|e| {
+            e.versioned_flow_snapshot_metadata_set
+                .iter()
+                .map(|v| v.versioned_flow_snapshot_metadata.version)
+                .max()
+        })
+    }
+
+    pub async fn root_group_id(&self) -> Result<String> {
+        self.get::<ProcessGroupFlowEntity>("/flow/process-groups/root")
+            .await
+            .map(|e| e.process_group_flow.id)
+    }
+
+    /// Schedules all components of a process group and its descendants, `state` is RUNNING or STOPPED
+    pub async fn schedule_process_group(&self, id: &str, state: &str) -> Result<()> {
+        let path = format!("/flow/process-groups/{}", id);
+        let body = json!({ "id": id, "state": state });
+        self.send_json::<serde_json::Value>(self.http.put(&self.url(&path)), &path, &body)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn process_groups(&self, parent_id: &str) -> Result<Vec<ProcessGroup>> {
+        self.get::<ProcessGroupsEntity>(&format!("/process-groups/{}/process-groups", parent_id))
+            .await
+            .map(|e| e.process_groups)
+    }
+
+    /// Imports a Registry flow as a new process group under version control
+    pub async fn import_flow(
+        &self,
+        parent_id: &str,
+        flow: &VersionControlInformation,
+    ) -> Result<ProcessGroup> {
+        let path = format!("/process-groups/{}/process-groups", parent_id);
+        let body = json!({
+            "revision": Revision::default(),
+            "component": {
+                "position": { "x": 0.0, "y": 0.0 },
+                "versionControlInformation": flow
+            }
+        });
+        self.send_json(self.http.post(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    /// Starts an asynchronous change of the flow version of a process group
+    pub async fn update_flow_version(
+        &self,
+        group: &ProcessGroup,
+        flow: &VersionControlInformation,
+    ) -> Result<UpdateRequest> {
+        let path = format!("/versions/update-requests/process-groups/{}", &group.id);
+        let flow = VersionControlInformation {
+            group_id: Some(group.id.clone()),
+            ..flow.clone()
+        };
+        let body =
+            json!({ "processGroupRevision": group.revision, "versionControlInformation": flow });
+        self.send_json::<UpdateRequestEntity>(self.http.post(&self.url(&path)), &path, &body)
+            .await
+            .map(|e| e.request)
+    }
+
+    pub async fn process_group(&self, id: &str) -> Result<ProcessGroup> {
+        self.get::<ProcessGroup>(&format!("/process-groups/{}", id))
+            .await
+    }
+
+    /// Binds a process group to a parameter context, its components then may reference the parameters
+    pub async fn set_parameter_context(
+        &self,
+        group: &ProcessGroup,
+        context_id: &str,
+    ) -> Result<ProcessGroup> {
+        let path = format!("/process-groups/{}", &group.id);
+        let body = json!({
+            "revision": group.revision,
+            "component": { "id": &group.id, "parameterContext": { "id": context_id } }
+        });
+        self.send_json(self.http.put(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    pub async fn parameter_contexts(&self) -> Result<Vec<ParameterContext>> {
+        self.get::<ParameterContextsEntity>("/flow/parameter-contexts")
+            .await
+            .map(|e| e.parameter_contexts)
+    }
+
+    pub async fn create_parameter_context(
+        &self,
+        component: &ParameterContextComponent,
+    ) -> Result<ParameterContext> {
+        let body = json!({ "revision": Revision::default(), "component": component });
+        self.send_json(
+            self.http.post(&self.url("/parameter-contexts")),
+            "/parameter-contexts",
+            &body,
+        )
+        .await
+    }
+
+    /// Starts an asynchronous update of a parameter context, components referencing changed
+    /// parameters are stopped and started again by NiFi
+    pub async fn update_parameter_context(
+        &self,
+        context: &ParameterContext,
+        component: &ParameterContextComponent,
+    ) -> Result<UpdateRequest> {
+        let path = format!("/parameter-contexts/{}/update-requests", &context.id);
+        let component = ParameterContextComponent {
+            id: Some(context.id.clone()),
+            ..component.clone()
+        };
+        let body =
+            json!({ "id": &context.id, "revision": context.revision, "component": component });
+        self.send_json::<UpdateRequestEntity>(self.http.post(&self.url(&path)), &path, &body)
+            .await
+            .map(|e| e.request)
+    }
+
+    /// Controller services of a process group and its ancestors, or of the controller when `group_id` is None
+    pub async fn controller_services(
+        &self,
+        group_id: Option<&str>,
+    ) -> Result<Vec<ConfiguredComponent>> {
+        let path = match group_id {
+            Some(id) => format!("/flow/process-groups/{}/controller-services", id),
+            None => "/flow/controller/controller-services".to_string(),
+        };
+        self.get::<ControllerServicesEntity>(&path)
+            .await
+            .map(|e| e.controller_services)
+    }
+
+    pub async fn controller_service(&self, id: &str) -> Result<ConfiguredComponent> {
+        self.get::<ConfiguredComponent>(&format!("/controller-services/{}", id))
+            .await
+    }
+
+    pub async fn create_controller_service(
+        &self,
+        group_id: Option<&str>,
+        component: &ComponentConfig,
+    ) -> Result<ConfiguredComponent> {
+        let path = match group_id {
+            Some(id) => format!("/process-groups/{}/controller-services", id),
+            None => "/controller/controller-services".to_string(),
+        };
+        let body = json!({ "revision": Revision::default(), "component": component });
+        self.send_json(self.http.post(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    /// Properties of a controller service can be changed only while it is disabled
+    pub async fn update_controller_service(
+        &self,
+        service: &ConfiguredComponent,
+        component: &ComponentConfig,
+    ) -> Result<ConfiguredComponent> {
+        self.update_component("/controller-services", service, component)
+            .await
+    }
+
+    /// Requests ENABLED or DISABLED state, NiFi refuses to disable services of running components
+    pub async fn set_controller_service_state(
+        &self,
+        service: &ConfiguredComponent,
+        state: &str,
+    ) -> Result<ConfiguredComponent> {
+        self.set_component_state("/controller-services", service, state)
+            .await
+    }
+
+    pub async fn delete_controller_service(&self, service: &ConfiguredComponent) -> Result<()> {
+        self.delete_component("/controller-services", service).await
+    }
+
+    pub async fn reporting_tasks(&self) -> Result<Vec<ConfiguredComponent>> {
+        self.get::<ReportingTasksEntity>("/flow/reporting-tasks")
+            .await
+            .map(|e| e.reporting_tasks)
+    }
+
+    pub async fn create_reporting_task(
+        &self,
+        component: &ComponentConfig,
+    ) -> Result<ConfiguredComponent> {
+        let body = json!({ "revision": Revision::default(), "component": component });
+        self.send_json(
+            self.http.post(&self.url("/controller/reporting-tasks")),
+            "/controller/reporting-tasks",
+            &body,
+        )
+        .await
+    }
+
+    /// Reporting tasks can be changed only while they are stopped
+    pub async fn update_reporting_task(
+        &self,
+        task: &ConfiguredComponent,
+        component: &ComponentConfig,
+    ) -> Result<ConfiguredComponent> {
+        self.update_component("/reporting-tasks", task, component)
+            .await
+    }
+
+    /// Requests RUNNING or STOPPED state
+    pub async fn set_reporting_task_state(
+        &self,
+        task: &ConfiguredComponent,
+        state: &str,
+    ) -> Result<ConfiguredComponent> {
+        self.set_component_state("/reporting-tasks", task, state)
+            .await
+    }
+
+    pub async fn delete_reporting_task(&self, task: &ConfiguredComponent) -> Result<()> {
+        self.delete_component("/reporting-tasks", task).await
+    }
+
+    async fn update_component(
+        &self,
+        resource: &str,
+        current: &ConfiguredComponent,
+        component: &ComponentConfig,
+    ) -> Result<ConfiguredComponent> {
+        let path = format!("{}/{}", resource, &current.id);
+        let component = ComponentConfig {
+            id: Some(current.id.clone()),
+            state: None,
+            ..component.clone()
+        };
+        let body = json!({ "revision": current.revision, "component": component });
+        self.send_json(self.http.put(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    async fn set_component_state(
+        &self,
+        resource: &str,
+        current: &ConfiguredComponent,
+        state: &str,
+    ) -> Result<ConfiguredComponent> {
+        let path = format!("{}/{}/run-status", resource, &current.id);
+        let body = json!({ "revision": current.revision, "state": state });
+        self.send_json(self.http.put(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    async fn delete_component(&self, resource: &str, current: &ConfiguredComponent) -> Result<()> {
+        let path = format!(
+            "{}/{}?version={}",
+            resource, &current.id, current.revision.version
+        );
+        let request = self.authorized(self.http.delete(&self.url(&path)));
+        send(request, &path).await.map(|_| ())
+    }
+
+    pub async fn users(&self) -> Result<Vec<Tenant>> {
+        self.get::<UsersEntity>("/tenants/users")
+            .await
+            .map(|e| e.users)
+    }
+
+    pub async fn create_user(&self, identity: &str) -> Result<Tenant> {
+        let component = TenantComponent {
+            identity: identity.to_string(),
+            ..TenantComponent::default()
+        };
+        let body = json!({ "revision": Revision::default(), "component": component });
+        self.send_json(
+            self.http.post(&self.url("/tenants/users")),
+            "/tenants/users",
+            &body,
+        )
+        .await
+    }
+
+    pub async fn user_groups(&self) -> Result<Vec<Tenant>> {
+        self.get::<UserGroupsEntity>("/tenants/user-groups")
+            .await
+            .map(|e| e.user_groups)
+    }
+
+    pub async fn create_user_group(&self, component: &TenantComponent) -> Result<Tenant> {
+        let body = json!({ "revision": Revision::default(), "component": component });
+        self.send_json(
+            self.http.post(&self.url("/tenants/user-groups")),
+            "/tenants/user-groups",
+            &body,
+        )
+        .await
+    }
+
+    pub async fn update_user_group(
+        &self,
+        group: &Tenant,
+        component: &TenantComponent,
+    ) -> Result<Tenant> {
+        let path = format!("/tenants/user-groups/{}", &group.id);
+        let component = TenantComponent {
+            id: Some(group.id.clone()),
+            ..component.clone()
+        };
+        let body = json!({ "revision": group.revision, "component": component });
+        self.send_json(self.http.put(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    /// Policy of exactly this resource, None when the resource only inherits a policy
+    pub async fn access_policy(
+        &self,
+        action: &str,
+        resource: &str,
+    ) -> Result<Option<AccessPolicy>> {
+        let path = format!("/policies/{}{}", action, resource);
+        let request = self.authorized(self.http.get(&self.url(&path)));
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let policy = error_for_status(response, &path)
+            .await?
+            .json::<AccessPolicy>()
+            .await?;
+        Ok(Some(policy).filter(|p| p.component.resource == resource))
+    }
+
+    pub async fn create_access_policy(
+        &self,
+        component: &AccessPolicyComponent,
+    ) -> Result<AccessPolicy> {
+        let body = json!({ "revision": Revision::default(), "component": component });
+        self.send_json(self.http.post(&self.url("/policies")), "/policies", &body)
+            .await
+    }
+
+    pub async fn update_access_policy(
+        &self,
+        policy: &AccessPolicy,
+        component: &AccessPolicyComponent,
+    ) -> Result<AccessPolicy> {
+        let path = format!("/policies/{}", &policy.id);
+        let component = AccessPolicyComponent {
+            id: Some(policy.id.clone()),
+            ..component.clone()
+        };
+        let body = json!({ "revision": policy.revision, "component": component });
+        self.send_json(self.http.put(&self.url(&path)), &path, &body)
+            .await
+    }
+
+    /// Waits until an asynchronous update request at `path` completes and deletes it,
+    /// NiFi keeps completed requests otherwise. `change` describes the update in errors
+    pub async fn wait_for_update(
+        &self,
+        path: &str,
+        mut request: UpdateRequest,
+        change: &str,
+    ) -> Result<()> {
+        let mut checks = 0;
+        while !request.complete && checks < UPDATE_CHECKS {
+            delay_for(Duration::from_secs(2)).await;
+            request = self.get::<UpdateRequestEntity>(path).await?.request;
+            checks += 1;
+        }
+        let delete = self.authorized(self.http.delete(&self.url(path)));
+        send(delete, path).await?;
+        match (request.complete, request.failure_reason) {
+            (true, None) => Ok(()),
+            (true, Some(reason)) => Err(Error::msg(format!("{} failed: {}", change, reason))),
+            (false, _) => Err(Error::msg(format!(
+                "{} is not complete: {}",
+                change,
+                request.state.unwrap_or_default()
+            ))),
+        }
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<T> {
+        let response = send(self.authorized(request.json(body)), path).await?;
+        Ok(response.json::<T>().await?)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let request = self.authorized(self.http.get(&self.url(path)));
+        let response = send(request, path).await?;
+        Ok(response.json::<T>().await?)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", &self.base_url, path)
+    }
+}
+
+async fn send(request: RequestBuilder, path: &str) -> Result<reqwest::Response> {
+    let response = request.send().await?;
+    error_for_status(response, path).await
+}
+
+async fn error_for_status(response: reqwest::Response, path: &str) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        let body = response.text().await.unwrap_or_default();
+        Err(Error::msg(format!(
+            "NiFi API request {} failed with {}: {}",
+            path, status, body
+        )))
+    }
+}
+
+fn secret_value(secret: &Secret, key: &str) -> Option<Vec<u8>> {
+    secret
+        .data
+        .as_ref()
+        .and_then(|d| d.get(key))
+        .map(|v| v.0.clone())
+}
+
+fn secret_string(secret: &Secret, key: &str) -> Result<String> {
+    let value = secret_value(secret, key).ok_or_else(|| {
+        Error::msg(format!(
+            "key {} is missing in the NiFi API credentials Secret",
+            key
+        ))
+    })?;
+    Ok(String::from_utf8(value)?)
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Error, Result};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Public};
+use openssl::sign::Verifier;
+use reqwest::header::{ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+
+const DOCKER_HUB: &str = "docker.io";
+const DOCKER_HUB_REGISTRY: &str = "registry-1.docker.io";
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+
+/// OCI distribution API of the registry of one image, pulled anonymously to resolve its digest
+/// and cosign signatures
+pub struct OciApi {
+    registry: String,
+    repository: String,
+    reference: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct SignatureManifest {
+    #[serde(default)]
+    layers: Vec<SignatureLayer>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SignatureLayer {
+    digest: String,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+impl OciApi {
+    /// `image` is a reference like `apache/nifi:1.14.0`, `quay.io/org/nifi@sha256:...` or
+    /// `registry:5000/nifi:1.14.0@sha256:...`, the digest wins over the tag
+    pub fn new(image: &str) -> OciApi {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (image, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((path, tag)) if !tag.contains('/') => (path, Some(tag)),
+            _ => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, path))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), path.to_string())
+            }
+            _ => (DOCKER_HUB.to_string(), name.to_string()),
+        };
+        let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+        let registry = if registry == DOCKER_HUB {
+            DOCKER_HUB_REGISTRY.to_string()
+        } else {
+            registry
+        };
+        OciApi {
+            registry,
+            repository,
+            reference: digest.or(tag).unwrap_or("latest").to_string(),
+            token: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Digest of the image manifest, an image list digest for multi-arch images
+    pub async fn digest(&mut self) -> Result<String> {
+        let path = format!("manifests/{}", self.reference);
+        let manifest = self.get_bytes(&path, MANIFEST_TYPES).await?;
+        let digest = sha256_digest(&manifest);
+        if self.reference.starts_with("sha256:") && self.reference != digest {
+            return Err(Error::msg(format!(
+                "manifest digest {} does not match {}",
+                digest, self.reference
+            )));
+        }
+        Ok(digest)
+    }
+
+    /// Signatures pushed by `cosign sign` as the `sha256-<hex>.sig` tag, one valid signature
+    /// of `digest` by `public_key` is enough
+    pub async fn verify_cosign_signature(
+        &mut self,
+        digest: &str,
+        public_key: &PKey<Public>,
+    ) -> Result<()> {
+        let path = format!("manifests/{}.sig", digest.replace(':', "-"));
+        let manifest = self
+            .get_bytes(&path, "application/vnd.oci.image.manifest.v1+json")
+            .await
+            .map_err(|e| Error::msg(format!("no cosign signature found: {}", e)))?;
+        let manifest = serde_json::from_slice::<SignatureManifest>(&manifest)?;
+        for layer in &manifest.layers {
+            let signature = match layer.annotations.get(SIGNATURE_ANNOTATION) {
+                Some(s) => base64::decode(s)?,
+                None => continue,
+            };
+            let payload = self
+                .get_bytes(&format!("blobs/{}", layer.digest), "*/*")
+                .await?;
+            if sha256_digest(&payload) != layer.digest {
+                continue;
+            }
+            let signed_digest = serde_json::from_slice::<Value>(&payload)?["critical"]["image"]
+                ["docker-manifest-digest"]
+                .as_str()
+                .map(|d| d == digest);
+            let mut verifier = Verifier::new(MessageDigest::sha256(), public_key)?;
+            verifier.update(&payload)?;
+            if signed_digest == Some(true) && verifier.verify(&signature).unwrap_or(false) {
+                return Ok(());
+            }
+        }
+        Err(Error::msg(format!(
+            "no signature of {} is valid for the configured public key",
+            digest
+        )))
+    }
+
+    async fn get_bytes(&mut self, path: &str, accept: &str) -> Result<Vec<u8>> {
+        let mut response = self.get(path, accept).await?;
+        if response.status() == StatusCode::UNAUTHORIZED && self.token.is_none() {
+            let challenge = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            self.token = Some(self.login(&challenge).await?);
+            response = self.get(path, accept).await?;
+        }
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::msg(format!(
+                "{}/{} request {} failed with {}",
+                &self.registry, &self.repository, path, status
+            )));
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn get(&self, path: &str, accept: &str) -> Result<Response> {
+        let url = format!(
+            "https://{}/v2/{}/{}",
+            &self.registry, &self.repository, path
+        );
+        let mut request = self.http.get(&url).header(ACCEPT, accept);
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        Ok(request.send().await?)
+    }
+
+    /// Anonymous pull token of a `Bearer realm="...",service="...",scope="..."` challenge
+    async fn login(&self, challenge: &str) -> Result<String> {
+        let params = challenge
+            .trim_start_matches("Bearer ")
+            .split(',')
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.trim(), v.trim_matches('"')))
+            .collect::<BTreeMap<_, _>>();
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| Error::msg(format!("unsupported registry challenge: {}", challenge)))?;
+        let query = params
+            .iter()
+            .filter(|(k, _)| **k != "realm")
+            .collect::<Vec<_>>();
+        let response = self.http.get(*realm).query(&query).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::msg(format!(
+                "token request to {} failed with {}",
+                realm,
+                response.status()
+            )));
+        }
+        let token = response.json::<TokenResponse>().await?;
+        token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| Error::msg(format!("no token in the response of {}", realm)))
+    }
+}
+
+fn sha256_digest(bytes: &[u8]) -> String {
+    let hex = openssl::sha::sha256(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!("sha256:{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(image: &str) -> (String, String, String) {
+        let api = OciApi::new(image);
+        (api.registry, api.repository, api.reference)
+    }
+
+    #[test]
+    fn test_image_references() {
+        assert_eq!(
+            parts("apache/nifi:1.14.0"),
+            (
+                "registry-1.docker.io".into(),
+                "apache/nifi".into(),
+                "1.14.0".into()
+            )
+        );
+        assert_eq!(
+            parts("busybox"),
+            (
+                "registry-1.docker.io".into(),
+                "library/busybox".into(),
+                "latest".into()
+            )
+        );
+        assert_eq!(
+            parts("registry:5000/nifi:1.14.0@sha256:abc"),
+            ("registry:5000".into(), "nifi".into(), "sha256:abc".into())
+        );
+        assert_eq!(
+            parts("quay.io/org/nifi@sha256:abc"),
+            ("quay.io".into(), "org/nifi".into(), "sha256:abc".into())
+        );
+    }
+}
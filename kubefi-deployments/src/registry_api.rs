@@ -0,0 +1,88 @@
+use anyhow::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// NiFi Registry REST API, for flows which run without a NiFi cluster to import them
+pub struct RegistryApi {
+    url: String,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct RegistryItem {
+    identifier: String,
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct FlowVersion {
+    version: i32,
+}
+
+impl RegistryApi {
+    pub fn new(url: &str) -> RegistryApi {
+        RegistryApi {
+            url: url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub async fn bucket_id(&self, bucket: &str) -> Result<Option<String>> {
+        self.get::<Vec<RegistryItem>>("/buckets")
+            .await
+            .map(|buckets| find_id(buckets, bucket))
+    }
+
+    pub async fn flow_id(&self, bucket_id: &str, flow: &str) -> Result<Option<String>> {
+        self.get::<Vec<RegistryItem>>(&format!("/buckets/{}/flows", bucket_id))
+            .await
+            .map(|flows| find_id(flows, flow))
+    }
+
+    pub async fn latest_flow_version(&self, bucket_id: &str, flow_id: &str) -> Result<Option<i32>> {
+        let path = format!("/buckets/{}/flows/{}/versions", bucket_id, flow_id);
+        self.get::<Vec<FlowVersion>>(&path)
+            .await
+            .map(|versions| versions.iter().map(|v| v.version).max())
+    }
+
+    /// VersionedFlowSnapshot with the components of the flow in `flowContents`
+    pub async fn flow_snapshot(
+        &self,
+        bucket_id: &str,
+        flow_id: &str,
+        version: i32,
+    ) -> Result<Value> {
+        let path = format!(
+            "/buckets/{}/flows/{}/versions/{}",
+            bucket_id, flow_id, version
+        );
+        self.get::<Value>(&path).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = format!("{}/nifi-registry-api{}", &self.url, path);
+        let response = self.http.get(&url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::msg(format!(
+                "NiFi Registry request {} failed with {}: {}",
+                path, status, body
+            )));
+        }
+        Ok(response.json::<T>().await?)
+    }
+}
+
+fn find_id(items: Vec<RegistryItem>, name: &str) -> Option<String> {
+    items
+        .into_iter()
+        .find(|i| i.name == name)
+        .map(|i| i.identifier)
+}
@@ -0,0 +1,282 @@
+use anyhow::{Error, Result};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::batch::v1beta1::CronJob;
+use k8s_openapi::api::core::v1::{ConfigMap, Pod, Service, ServiceAccount};
+use k8s_openapi::api::networking::v1::NetworkPolicy;
+use k8s_openapi::api::networking::v1beta1::Ingress;
+use k8s_openapi::api::policy::v1beta1::PodDisruptionBudget;
+use k8s_openapi::api::rbac::v1::{Role, RoleBinding};
+use k8s_openapi::Resource;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::controller::render_manifests;
+use crate::crd::{MiNiFiDeploymentSpec, NiFiDeploymentSpec, NiFiStatelessDeploymentSpec};
+use crate::discovery::{ClusterApis, OPTIONAL_APIS};
+use crate::template::Template;
+
+const SAMPLE_NAME: &str = "nifi";
+const SAMPLE_NAMESPACE: &str = "default";
+
+/// NiFiDeployments rendering the optional resources of the templates between them
+const NIFI_SAMPLES: [&str; 5] = [
+    r#"
+nifiReplicas: 2
+zk:
+  replicas: 3
+"#,
+    r#"
+nifiReplicas: 3
+zk:
+  replicas: 3
+  metrics:
+    enabled: true
+  tls:
+    jksSecret: zk-tls
+    pwdSecret: zk-tls-pwd
+securityProfile: restricted
+ingress:
+  enabled: true
+  host: nifi.example.com
+  tlsSecret: nifi-ingress-tls
+authProxy:
+  enabled: true
+  oidcIssuerUrl: https://idp.example.com
+  clientId: nifi
+  credentialsSecret: nifi-oauth
+mesh:
+  enabled: true
+  virtualService:
+    enabled: true
+    hosts:
+    - nifi.example.com
+  destinationRule:
+    enabled: true
+networkPolicy:
+  enabled: true
+podServices:
+  enabled: true
+serviceAccount:
+  create: true
+  rbac: true
+registry:
+  enabled: true
+  ingress:
+    enabled: true
+  git:
+    repoUrl: https://git.example.com/flows.git
+  database:
+    type: PostgreSQL
+    url: jdbc:postgresql://postgres:5432/registry
+    credentialsSecret: registry-db
+backup:
+  schedule: "0 2 * * *"
+  s3:
+    bucket: nifi-backups
+    credentialsSecret: nifi-s3
+sidecars:
+- name: sidecar
+  image: busybox
+initContainers:
+- name: init
+  image: busybox
+volumes:
+- name: extra
+  emptyDir: {}
+volumeMounts:
+- name: extra
+  mountPath: /extra
+dnsConfig:
+  nameservers:
+  - 10.0.0.10
+hostAliases:
+- ip: 10.0.0.1
+  hostnames:
+  - nifi.internal
+"#,
+    r#"
+nifiReplicas: 1
+zk:
+  replicas: 1
+expose:
+  route:
+    enabled: true
+    host: nifi.apps.example.com
+    tls:
+      termination: reencrypt
+"#,
+    r#"
+nifiReplicas: 1
+zk:
+  replicas: 1
+expose:
+  gatewayApi:
+    enabled: true
+    gatewayName: gateway
+    hostnames:
+    - nifi.example.com
+"#,
+    r#"
+nifiReplicas: 1
+zk:
+  replicas: 3
+  operator: Pravega
+"#,
+];
+
+const STATELESS_SAMPLES: [&str; 2] = [
+    r#"
+bucket: flows
+flow: flow
+"#,
+    r#"
+mode: Once
+bucket: flows
+flow: flow
+"#,
+];
+
+const MINIFI_SAMPLES: [&str; 2] = [
+    r#"
+workload: Deployment
+"#,
+    r#"
+workload: DaemonSet
+"#,
+];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    api_version: String,
+    kind: String,
+}
+
+impl Template {
+    /// Dry render of the templates and the template sets of NiFi versions with sample deployments,
+    /// every rendered resource has to parse into its Kubernetes type, so broken templates fail the
+    /// operator start
+    pub fn validate(&self) -> Result<()> {
+        self.validate_set()?;
+        for ((major, minor), template) in self.versions() {
+            template
+                .validate_set()
+                .map_err(|e| Error::msg(format!("Templates of NiFi {}.{}: {}", major, minor, e)))?;
+        }
+        Ok(())
+    }
+
+    fn validate_set(&self) -> Result<()> {
+        for (i, sample) in NIFI_SAMPLES.iter().enumerate() {
+            let spec = serde_yaml::from_str::<NiFiDeploymentSpec>(sample)?;
+            self.validate_nifi(&spec)
+                .map_err(|e| Error::msg(format!("NiFiDeployment sample {}: {}", i + 1, e)))?;
+        }
+        for (i, sample) in STATELESS_SAMPLES.iter().enumerate() {
+            let spec = serde_yaml::from_str::<NiFiStatelessDeploymentSpec>(sample)?;
+            check(
+                "nifi-stateless",
+                self.nifi_stateless(SAMPLE_NAME, &spec, "hash"),
+            )
+            .map_err(|e| Error::msg(format!("NiFiStatelessDeployment sample {}: {}", i + 1, e)))?;
+        }
+        for (i, sample) in MINIFI_SAMPLES.iter().enumerate() {
+            let spec = serde_yaml::from_str::<MiNiFiDeploymentSpec>(sample)?;
+            check("minifi", self.minifi(SAMPLE_NAME, &spec, "hash"))
+                .map_err(|e| Error::msg(format!("MiNiFiDeployment sample {}: {}", i + 1, e)))?;
+        }
+        Ok(())
+    }
+
+    fn validate_nifi(&self, spec: &NiFiDeploymentSpec) -> Result<()> {
+        let name = SAMPLE_NAME;
+        let pod = format!("{}-0", name);
+        let subject = vec![("CN".to_string(), name.to_string())];
+        let dns_names = vec![format!("{}.{}.svc", name, SAMPLE_NAMESPACE)];
+        // networking.k8s.io/v1 Ingress or the v1beta1 one of older clusters
+        for ingress_v1 in [true, false].iter() {
+            let apis = ClusterApis {
+                ingress_v1: *ingress_v1,
+                ..ClusterApis::from_api_versions(&OPTIONAL_APIS)
+            };
+            for (template, yaml) in render_manifests(self, &apis, name, SAMPLE_NAMESPACE, spec)? {
+                check(template, Ok(Some(yaml)))?;
+            }
+        }
+        check(
+            "certificate",
+            self.certificate(name, &pod, &subject, &dns_names, 365, spec),
+        )?;
+        check(
+            "volumesnapshot",
+            self.volume_snapshot(name, &format!("data-{}", pod), "backup", spec),
+        )?;
+        check(
+            "flow-backup-pod",
+            self.flow_backup_pod(name, "backup", "node", "busybox", &["flow.xml.gz"], spec),
+        )?;
+        check("s3-backup-pod", self.s3_backup_pod(name, "backup", spec))?;
+        check(
+            "s3-restore-pod",
+            self.s3_restore_pod(name, "restore", "s3://backups/flow.xml.gz", true, spec),
+        )
+    }
+}
+
+/// Parses a rendered template into the type of its `apiVersion` and `kind`, custom resources
+/// and API versions missing in the Kubernetes models only need `apiVersion` and `kind`
+fn check(template: &str, rendered: Result<Option<String>>) -> Result<()> {
+    let yaml = match rendered {
+        Ok(Some(yaml)) => yaml,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(Error::msg(format!("{}.yaml: {}", template, e))),
+    };
+    parse_resource(&yaml).map_err(|e| {
+        let line = e
+            .location()
+            .and_then(|l| {
+                yaml.lines()
+                    .nth(l.line().saturating_sub(1))
+                    .map(|s| (l.line(), s))
+            })
+            .map(|(n, s)| format!("\n{:>5} | {}", n, s))
+            .unwrap_or_default();
+        Error::msg(format!(
+            "{}.yaml rendered invalid YAML: {}{}",
+            template, e, line
+        ))
+    })
+}
+
+fn parse_resource(yaml: &str) -> Result<(), serde_yaml::Error> {
+    let manifest = serde_yaml::from_str::<Manifest>(yaml)?;
+    let (api_version, kind) = (manifest.api_version.as_str(), manifest.kind.as_str());
+    match kind {
+        "StatefulSet" => parse::<StatefulSet>(yaml, api_version),
+        "Deployment" => parse::<Deployment>(yaml, api_version),
+        "DaemonSet" => parse::<DaemonSet>(yaml, api_version),
+        "Job" => parse::<Job>(yaml, api_version),
+        "CronJob" => parse::<CronJob>(yaml, api_version),
+        "Pod" => parse::<Pod>(yaml, api_version),
+        "Service" => parse::<Service>(yaml, api_version),
+        "ConfigMap" => parse::<ConfigMap>(yaml, api_version),
+        "ServiceAccount" => parse::<ServiceAccount>(yaml, api_version),
+        "Role" => parse::<Role>(yaml, api_version),
+        "RoleBinding" => parse::<RoleBinding>(yaml, api_version),
+        "PodDisruptionBudget" => parse::<PodDisruptionBudget>(yaml, api_version),
+        "NetworkPolicy" => parse::<NetworkPolicy>(yaml, api_version),
+        "Ingress" => parse::<Ingress>(yaml, api_version),
+        _ => Ok(()),
+    }
+}
+
+fn parse<T: Resource + DeserializeOwned>(
+    yaml: &str,
+    api_version: &str,
+) -> Result<(), serde_yaml::Error> {
+    if api_version == T::API_VERSION {
+        serde_yaml::from_str::<T>(yaml)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// HashiCorp Vault HTTP API, logged in with the Kubernetes auth method of the operator config
+pub struct VaultApi {
+    url: String,
+    kv_mount: String,
+    token: String,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct LoginResponse {
+    auth: LoginAuth,
+}
+
+#[derive(Deserialize, Debug)]
+struct LoginAuth {
+    client_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct KvResponse {
+    data: KvData,
+}
+
+#[derive(Deserialize, Debug)]
+struct KvData {
+    data: BTreeMap<String, String>,
+}
+
+impl VaultApi {
+    /// Logs in with the ServiceAccount token of the operator Pod, `cfg` is the `vault` block
+    /// of the operator config
+    pub async fn login(cfg: &Value) -> Result<VaultApi> {
+        let setting = |path: &[&str]| {
+            path.iter()
+                .fold(cfg, |v, p| &v[p])
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| Error::msg(format!("vault.{} is not configured", path.join("."))))
+        };
+        let url = setting(&["address"])?.trim_end_matches('/').to_string();
+        let jwt = tokio::fs::read_to_string(setting(&["auth", "tokenFile"])?).await?;
+        let http = reqwest::Client::new();
+        let login_url = format!("{}/v1/auth/{}/login", &url, setting(&["auth", "path"])?);
+        let body = json!({ "role": setting(&["auth", "role"])?, "jwt": jwt.trim() });
+        let response = http.post(&login_url).json(&body).send().await?;
+        let login = read_response::<LoginResponse>(response, "login").await?;
+        Ok(VaultApi {
+            url,
+            kv_mount: setting(&["kvMount"])?,
+            token: login.auth.client_token,
+            http,
+        })
+    }
+
+    /// Latest version of a secret of the KV version 2 engine
+    pub async fn kv_data(&self, path: &str) -> Result<BTreeMap<String, String>> {
+        let url = format!(
+            "{}/v1/{}/data/{}",
+            &self.url,
+            &self.kv_mount,
+            path.trim_start_matches('/')
+        );
+        let response = self
+            .http
+            .get(&url)
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?;
+        read_response::<KvResponse>(response, path)
+            .await
+            .map(|kv| kv.data.data)
+    }
+}
+
+async fn read_response<T: DeserializeOwned>(response: reqwest::Response, path: &str) -> Result<T> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(Error::msg(format!(
+            "Vault request {} failed with {}: {}",
+            path, status, body
+        )));
+    }
+    Ok(response.json::<T>().await?)
+}
@@ -1,12 +1,25 @@
+use std::fmt::Debug;
+
 use anyhow::{Error, Result};
-use futures::TryStreamExt;
+use futures::future::LocalBoxFuture;
+use futures::{Future, FutureExt, TryStreamExt};
 use futures_core::stream::BoxStream;
-use kube::api::{Meta, PostParams};
+use k8s_openapi::Resource;
+use kube::api::{ListParams, Meta, PostParams};
 use kube::{Api, Client};
 use kube_runtime::watcher::Event;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::time::{delay_for, Duration};
 
-use crate::controller::{NiFiController, ReplaceStatus};
-use crate::crd::NiFiDeployment;
+use crate::controller::{NiFiController, ReplaceResourceStatus, ReplaceStatus};
+use crate::crd::{
+    FlowDeployment, FlowDeploymentStatus, MiNiFiDeployment, MiNiFiDeploymentStatus,
+    NiFiAuthorization, NiFiAuthorizationStatus, NiFiDeployment, NiFiParameterContext,
+    NiFiParameterContextStatus, NiFiStatelessDeployment, NiFiStatelessDeploymentStatus,
+    ZookeeperCluster, ZookeeperClusterStatus,
+};
+use crate::metrics::Metrics;
 use crate::{get_api, read_type, Namespace};
 
 pub async fn watch<'a>(
@@ -15,7 +28,7 @@ pub async fn watch<'a>(
     controller: &NiFiController,
 ) -> Result<()> {
     while let Some(event) = watcher.try_next().await? {
-        let status = handle_event(&controller, event.clone()).await?;
+        let status = handle_event(controller, event.clone()).await?;
         for s in status {
             let api = get_api::<NiFiDeployment>(
                 &Namespace::SingleNamespace(s.ns.as_str().to_string()),
@@ -31,6 +44,530 @@ pub async fn watch<'a>(
     )))
 }
 
+// how often deployments are checked for due backups and NiFi REST API tasks,
+// flows, parameter contexts, authorizations, stateless flows and MiNiFi agents are applied again
+// meanwhile to follow the latest flow versions, Secret changes and changes made in the NiFi UI
+const PERIODIC_TASKS_SECONDS: u64 = 300;
+
+/// Tasks which need running NiFi or a schedule, rather than a change of the deployment
+#[allow(clippy::too_many_arguments)]
+pub async fn run_periodic_tasks(
+    client: Client,
+    api: Api<NiFiDeployment>,
+    flow_api: Api<FlowDeployment>,
+    parameter_context_api: Api<NiFiParameterContext>,
+    authorization_api: Api<NiFiAuthorization>,
+    stateless_api: Api<NiFiStatelessDeployment>,
+    minifi_api: Api<MiNiFiDeployment>,
+    controller: &NiFiController,
+) -> Result<()> {
+    loop {
+        delay_for(Duration::from_secs(PERIODIC_TASKS_SECONDS)).await;
+        apply_all(&client, &flow_api, controller).await;
+        apply_all(&client, &parameter_context_api, controller).await;
+        apply_all(&client, &authorization_api, controller).await;
+        apply_all(&client, &stateless_api, controller).await;
+        apply_all(&client, &minifi_api, controller).await;
+        let deployments = match api.list(&ListParams::default()).await {
+            Ok(list) => list.items,
+            Err(e) => {
+                error!("Failed to list deployments for periodic tasks: {}", e);
+                continue;
+            }
+        };
+        for mut d in deployments {
+            match controller.on_certificates(&d).await {
+                Ok(Some(s)) => {
+                    let api = get_api::<NiFiDeployment>(
+                        &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                        client.clone(),
+                    );
+                    // the backup below starts from the replaced status
+                    d.status = Some(s.status.clone());
+                    if let Err(e) = replace_status(&api, s).await {
+                        error!(
+                            "Failed to replace status after the certificate renewal: {}",
+                            e
+                        )
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => error!("Certificate renewal failed: {}", e),
+            }
+            if let Err(e) = controller.on_vault_secrets(&d).await {
+                warn!(
+                    "Vault secrets of {} are not synchronized: {}",
+                    Meta::name(&d),
+                    e
+                );
+            }
+            if let Err(e) = controller.on_registry_client(&d).await {
+                warn!(
+                    "NiFi Registry client of {} is not registered: {}",
+                    Meta::name(&d),
+                    e
+                );
+            }
+            if let Err(e) = controller.on_node_identities(&d).await {
+                warn!(
+                    "Node identities of {} are not authorized: {}",
+                    Meta::name(&d),
+                    e
+                );
+            }
+            if let Err(e) = controller.on_nifi_components(&d).await {
+                warn!(
+                    "Controller services or reporting tasks of {} are not provisioned: {}",
+                    Meta::name(&d),
+                    e
+                );
+            }
+            match controller.on_backup(d).await {
+                Ok(Some(s)) => {
+                    let api = get_api::<NiFiDeployment>(
+                        &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                        client.clone(),
+                    );
+                    if let Err(e) = replace_status(&api, s).await {
+                        error!("Failed to replace status after the backup: {}", e)
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => error!("Backup failed: {}", e),
+            }
+        }
+    }
+}
+
+// how often the templates directory or ConfigMap is checked for changes
+const TEMPLATE_RELOAD_SECONDS: u64 = 30;
+
+/// Applies every deployment again once changed templates are reloaded, templates failing to load
+/// are reported and the previous ones stay in use
+pub async fn run_template_reloads(
+    client: Client,
+    api: Api<NiFiDeployment>,
+    controller: &NiFiController,
+) -> Result<()> {
+    loop {
+        delay_for(Duration::from_secs(TEMPLATE_RELOAD_SECONDS)).await;
+        match controller.on_templates().await {
+            Ok(true) => info!("Templates are reloaded, rendering deployments again"),
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Failed to reload templates: {}", e);
+                continue;
+            }
+        }
+        let deployments = match api.list(&ListParams::default()).await {
+            Ok(list) => list.items,
+            Err(e) => {
+                error!("Failed to list deployments for the template reload: {}", e);
+                continue;
+            }
+        };
+        for d in deployments {
+            match controller.on_apply(d).await {
+                Ok(Some(s)) => {
+                    let api = get_api::<NiFiDeployment>(
+                        &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                        client.clone(),
+                    );
+                    if let Err(e) = replace_status(&api, s).await {
+                        error!("Failed to replace status after the template reload: {}", e)
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => error!("Deployment failed after the template reload: {}", e),
+            }
+        }
+    }
+}
+
+// how often rollouts in progress are checked for their next step
+const ROLLOUT_STEP_SECONDS: u64 = 10;
+
+/// Applies deployments and ZookeeperClusters with a rollout in progress again, so every node of a
+/// rollout is updated without blocking the other events once the previous one is back.
+/// ZookeeperClusters are left out without `zk_api`, as their CRD is not installed
+pub async fn run_rollouts(
+    client: Client,
+    api: Api<NiFiDeployment>,
+    zk_api: Option<Api<ZookeeperCluster>>,
+    controller: &NiFiController,
+) -> Result<()> {
+    loop {
+        delay_for(Duration::from_secs(ROLLOUT_STEP_SECONDS)).await;
+        if let Some(zk_api) = &zk_api {
+            run_zk_rollouts(&client, zk_api, controller).await;
+        }
+        let deployments = match api.list(&ListParams::default()).await {
+            Ok(list) => list.items,
+            Err(e) => {
+                error!("Failed to list deployments for rollouts: {}", e);
+                continue;
+            }
+        };
+        for d in deployments {
+            match controller.on_rollout(&d).await {
+                Ok(true) => (),
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("Failed to check rollout of {}: {}", Meta::name(&d), e);
+                    continue;
+                }
+            }
+            match controller.on_apply(d).await {
+                Ok(Some(s)) => {
+                    let api = get_api::<NiFiDeployment>(
+                        &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                        client.clone(),
+                    );
+                    if let Err(e) = replace_status(&api, s).await {
+                        error!("Failed to replace status after the rollout step: {}", e)
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => error!("Rollout step failed: {}", e),
+            }
+        }
+    }
+}
+
+async fn run_zk_rollouts(
+    client: &Client,
+    api: &Api<ZookeeperCluster>,
+    controller: &NiFiController,
+) {
+    let clusters = match api.list(&ListParams::default()).await {
+        Ok(list) => list.items,
+        Err(e) => {
+            error!("Failed to list ZookeeperClusters for rollouts: {}", e);
+            return;
+        }
+    };
+    for zk in clusters {
+        match controller.on_zk_rollout(&zk).await {
+            Ok(true) => (),
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Failed to check rollout of {}: {}", Meta::name(&zk), e);
+                continue;
+            }
+        }
+        match controller.on_apply_zk(zk).await {
+            Ok(Some(s)) => {
+                let api = get_api::<ZookeeperCluster>(
+                    &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                    client.clone(),
+                );
+                if let Err(e) = replace_resource_status(&api, s).await {
+                    error!("Failed to replace status after the rollout step: {}", e)
+                }
+            }
+            Ok(None) => (),
+            Err(e) => error!("ZookeeperCluster rollout step failed: {}", e),
+        }
+    }
+}
+
+// how often connected NiFi nodes are recorded in the status of deployments
+const HEALTH_PROBE_SECONDS: u64 = 60;
+
+/// Cluster health of every deployment, probed independently of the longer periodic tasks
+pub async fn run_health_probes(
+    client: Client,
+    api: Api<NiFiDeployment>,
+    controller: &NiFiController,
+    metrics: &Metrics,
+) -> Result<()> {
+    loop {
+        delay_for(Duration::from_secs(HEALTH_PROBE_SECONDS)).await;
+        let deployments = match api.list(&ListParams::default()).await {
+            Ok(list) => list.items,
+            Err(e) => {
+                error!("Failed to list deployments for health probes: {}", e);
+                continue;
+            }
+        };
+        let keys = deployments
+            .iter()
+            .map(|d| (Meta::namespace(d).unwrap_or_default(), Meta::name(d)))
+            .collect::<Vec<_>>();
+        metrics.retain(&keys);
+        for (d, (ns, name)) in deployments.into_iter().zip(keys) {
+            let health = d.status.as_ref().and_then(|s| s.cluster.clone());
+            match controller.on_health_probe(d).await {
+                Ok(Some(s)) => {
+                    metrics.set(&ns, &name, s.status.cluster.as_ref());
+                    let api = get_api::<NiFiDeployment>(
+                        &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                        client.clone(),
+                    );
+                    if let Err(e) = replace_status(&api, s).await {
+                        error!("Failed to replace status after the health probe: {}", e)
+                    }
+                }
+                Ok(None) => metrics.set(&ns, &name, health.as_ref()),
+                Err(e) => error!("Health probe failed: {}", e),
+            }
+        }
+    }
+}
+
+/// Runs the watcher of a custom resource only when its CRD is installed, so that a CRD which is
+/// not installed does not stop the watchers of the other resources
+pub async fn watch_if_installed<F: Future<Output = Result<()>>>(
+    installed: bool,
+    crd_name: &str,
+    watcher: F,
+) -> Result<()> {
+    if installed {
+        watcher.await
+    } else {
+        warn!(
+            "{} is not installed, its resources are not watched",
+            crd_name
+        );
+        futures::future::pending().await
+    }
+}
+
+/// Custom resource other than NiFiDeployment, which is applied by the controller on its events
+/// and in the periodic tasks
+pub trait Watched: Resource + Clone + DeserializeOwned + Serialize + Meta {
+    type Status: Clone + Debug;
+
+    fn set_status(&mut self, status: Self::Status);
+
+    fn apply(
+        controller: &NiFiController,
+        resource: Self,
+    ) -> LocalBoxFuture<'_, Result<Option<ReplaceResourceStatus<Self::Status>>>>;
+
+    fn delete(controller: &NiFiController, resource: Self) -> LocalBoxFuture<'_, Result<()>>;
+}
+
+pub async fn watch_resources<'a, K: Watched>(
+    client: Client,
+    watcher: &mut BoxStream<'a, Result<Event<K>, kube_runtime::watcher::Error>>,
+    controller: &NiFiController,
+) -> Result<()> {
+    while let Some(event) = watcher.try_next().await? {
+        let status = handle_resource_event(controller, event).await?;
+        for s in status {
+            let api = get_api::<K>(
+                &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                client.clone(),
+            );
+            replace_resource_status(&api, s).await?
+        }
+    }
+
+    Err(Error::msg(format!(
+        "Event stream for {:?} was closed, exiting...",
+        K::KIND
+    )))
+}
+
+/// Applies every resource of `api` again, failures are logged
+async fn apply_all<K: Watched>(client: &Client, api: &Api<K>, controller: &NiFiController) {
+    match api.list(&ListParams::default()).await {
+        Ok(list) => {
+            for resource in list.items {
+                match K::apply(controller, resource).await {
+                    Ok(Some(s)) => {
+                        let api = get_api::<K>(
+                            &Namespace::SingleNamespace(s.ns.as_str().to_string()),
+                            client.clone(),
+                        );
+                        if let Err(e) = replace_resource_status(&api, s).await {
+                            error!("Failed to replace status of {}: {}", K::KIND, e)
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(e) => error!("{} update failed: {}", K::KIND, e),
+                }
+            }
+        }
+        Err(e) => error!("Failed to list {} for periodic tasks: {}", K::KIND, e),
+    }
+}
+
+async fn replace_resource_status<K: Watched>(
+    api: &Api<K>,
+    s: ReplaceResourceStatus<K::Status>,
+) -> Result<()> {
+    debug!("replacing status: {:?}", &s);
+    let mut resource = api.get_status(&s.name).await?;
+    resource.set_status(s.status.clone());
+    let pp = PostParams::default();
+    let data = serde_json::to_vec(&resource)?;
+    api.replace_status(&s.name, &pp, data)
+        .await
+        .map(|_| {
+            info!("Status updated: {:?}", s.status);
+            Ok(())
+        })
+        .unwrap_or_else(|e| {
+            error!("Update status failed {}", e);
+            Ok(())
+        })
+}
+
+async fn handle_resource_event<K: Watched>(
+    controller: &NiFiController,
+    event: Event<K>,
+) -> Result<Vec<ReplaceResourceStatus<K::Status>>> {
+    match event {
+        Event::Applied(event) => {
+            info!("applied {}: {}", K::KIND, Meta::name(&event));
+            K::apply(controller, event)
+                .await
+                .map(|status| status.into_iter().collect())
+        }
+        Event::Restarted(events) => {
+            info!(
+                "Got {} Restarted event with length: {}",
+                K::KIND,
+                events.len()
+            );
+            let applies = events.into_iter().map(|e| K::apply(controller, e));
+            futures::future::join_all(applies)
+                .await
+                .into_iter()
+                .try_fold(Vec::new(), |mut all_res, res| {
+                    res.map(|r| {
+                        all_res.extend(r);
+                        all_res
+                    })
+                })
+        }
+        Event::Deleted(event) => {
+            info!("deleting {}: {}", K::KIND, Meta::name(&event));
+            K::delete(controller, event).await.map(|_| Vec::new())
+        }
+    }
+}
+
+impl Watched for ZookeeperCluster {
+    type Status = ZookeeperClusterStatus;
+
+    fn set_status(&mut self, status: Self::Status) {
+        self.status = Some(status);
+    }
+
+    fn apply(
+        controller: &NiFiController,
+        resource: Self,
+    ) -> LocalBoxFuture<'_, Result<Option<ReplaceResourceStatus<Self::Status>>>> {
+        controller.on_apply_zk(resource).boxed_local()
+    }
+
+    fn delete(controller: &NiFiController, resource: Self) -> LocalBoxFuture<'_, Result<()>> {
+        controller.on_delete_zk(resource).boxed_local()
+    }
+}
+
+impl Watched for FlowDeployment {
+    type Status = FlowDeploymentStatus;
+
+    fn set_status(&mut self, status: Self::Status) {
+        self.status = Some(status);
+    }
+
+    fn apply(
+        controller: &NiFiController,
+        resource: Self,
+    ) -> LocalBoxFuture<'_, Result<Option<ReplaceResourceStatus<Self::Status>>>> {
+        controller.on_apply_flow(resource).boxed_local()
+    }
+
+    fn delete(controller: &NiFiController, resource: Self) -> LocalBoxFuture<'_, Result<()>> {
+        controller.on_delete_flow(resource).boxed_local()
+    }
+}
+
+impl Watched for NiFiParameterContext {
+    type Status = NiFiParameterContextStatus;
+
+    fn set_status(&mut self, status: Self::Status) {
+        self.status = Some(status);
+    }
+
+    fn apply(
+        controller: &NiFiController,
+        resource: Self,
+    ) -> LocalBoxFuture<'_, Result<Option<ReplaceResourceStatus<Self::Status>>>> {
+        controller
+            .on_apply_parameter_context(resource)
+            .boxed_local()
+    }
+
+    fn delete(controller: &NiFiController, resource: Self) -> LocalBoxFuture<'_, Result<()>> {
+        controller
+            .on_delete_parameter_context(resource)
+            .boxed_local()
+    }
+}
+
+impl Watched for NiFiAuthorization {
+    type Status = NiFiAuthorizationStatus;
+
+    fn set_status(&mut self, status: Self::Status) {
+        self.status = Some(status);
+    }
+
+    fn apply(
+        controller: &NiFiController,
+        resource: Self,
+    ) -> LocalBoxFuture<'_, Result<Option<ReplaceResourceStatus<Self::Status>>>> {
+        controller.on_apply_authorization(resource).boxed_local()
+    }
+
+    fn delete(controller: &NiFiController, resource: Self) -> LocalBoxFuture<'_, Result<()>> {
+        controller.on_delete_authorization(resource).boxed_local()
+    }
+}
+
+impl Watched for NiFiStatelessDeployment {
+    type Status = NiFiStatelessDeploymentStatus;
+
+    fn set_status(&mut self, status: Self::Status) {
+        self.status = Some(status);
+    }
+
+    fn apply(
+        controller: &NiFiController,
+        resource: Self,
+    ) -> LocalBoxFuture<'_, Result<Option<ReplaceResourceStatus<Self::Status>>>> {
+        controller.on_apply_stateless(resource).boxed_local()
+    }
+
+    fn delete(controller: &NiFiController, resource: Self) -> LocalBoxFuture<'_, Result<()>> {
+        controller.on_delete_stateless(resource).boxed_local()
+    }
+}
+
+impl Watched for MiNiFiDeployment {
+    type Status = MiNiFiDeploymentStatus;
+
+    fn set_status(&mut self, status: Self::Status) {
+        self.status = Some(status);
+    }
+
+    fn apply(
+        controller: &NiFiController,
+        resource: Self,
+    ) -> LocalBoxFuture<'_, Result<Option<ReplaceResourceStatus<Self::Status>>>> {
+        controller.on_apply_minifi(resource).boxed_local()
+    }
+
+    fn delete(controller: &NiFiController, resource: Self) -> LocalBoxFuture<'_, Result<()>> {
+        controller.on_delete_minifi(resource).boxed_local()
+    }
+}
+
 async fn replace_status(api: &Api<NiFiDeployment>, s: ReplaceStatus) -> Result<()> {
     debug!("replacing status: {:?}", &s);
     let mut resource = api.get_status(&s.name).await?;
@@ -73,13 +610,11 @@ async fn handle_event(
             futures::future::join_all(applies)
                 .await
                 .into_iter()
-                .fold(Ok(Vec::new()), |acc, res| {
-                    acc.and_then(|mut all_res: Vec<ReplaceStatus>| {
-                        res.map(|r| {
-                            let mut l = r.into_iter().collect::<Vec<_>>();
-                            all_res.append(&mut l);
-                            all_res
-                        })
+                .try_fold(Vec::new(), |mut all_res: Vec<ReplaceStatus>, res| {
+                    res.map(|r| {
+                        let mut l = r.into_iter().collect::<Vec<_>>();
+                        all_res.append(&mut l);
+                        all_res
                     })
                 })
         }
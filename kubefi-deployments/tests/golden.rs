@@ -0,0 +1,195 @@
+//! Golden files of the templates: every spec of `tests/golden/specs` is rendered and compared to
+//! the manifests of `tests/golden/expected` with the same file name. Spec files are prefixed by
+//! the resource they describe: `nifi-`, `stateless-` or `minifi-`.
+//!
+//! Run `UPDATE_GOLDEN=1 cargo test --test golden` to write the expected files after an intended
+//! template change.
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use kubefi_deployments::config::read_nifi_config;
+use kubefi_deployments::controller::render_manifests;
+use kubefi_deployments::crd::{
+    MiNiFiDeploymentSpec, NiFiDeploymentSpec, NiFiStatelessDeploymentSpec,
+};
+use kubefi_deployments::discovery::{ClusterApis, OPTIONAL_APIS};
+use kubefi_deployments::template::Template;
+
+const SPECS_DIR: &str = "tests/golden/specs";
+const EXPECTED_DIR: &str = "tests/golden/expected";
+const NAME: &str = "nifi";
+const NAMESPACE: &str = "default";
+const CONFIG_HASH: &str = "hash";
+const DIFF_CONTEXT: usize = 3;
+
+#[test]
+fn golden_templates() {
+    let template = Template::new(Path::new("./templates"), read_nifi_config().unwrap()).unwrap();
+    let update = std::env::var("UPDATE_GOLDEN").is_ok();
+    let mut failures = vec![];
+    let mut cases = BTreeSet::new();
+
+    let mut specs = fs::read_dir(SPECS_DIR)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect::<Vec<_>>();
+    specs.sort();
+    for path in specs {
+        let case = path.file_name().unwrap().to_string_lossy().to_string();
+        let expected_path = Path::new(EXPECTED_DIR).join(&case);
+        cases.insert(case.clone());
+        let actual = match render_case(&template, &case, &fs::read_to_string(&path).unwrap()) {
+            Ok(manifests) => manifests,
+            Err(e) => {
+                failures.push(format!("{}: {}", case, e));
+                continue;
+            }
+        };
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{} differs from {}:\n{}",
+                case,
+                expected_path.display(),
+                diff(&expected, &actual)
+            )),
+            Err(_) => failures.push(format!("{} is missing", expected_path.display())),
+        }
+    }
+
+    for entry in fs::read_dir(EXPECTED_DIR).unwrap() {
+        let path = entry.unwrap().path();
+        let case = path.file_name().unwrap().to_string_lossy().to_string();
+        if !cases.contains(&case) {
+            if update {
+                fs::remove_file(&path).unwrap();
+            } else {
+                failures.push(format!("{} has no spec in {}", path.display(), SPECS_DIR));
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nRun `UPDATE_GOLDEN=1 cargo test --test golden` if the changes are intended",
+        failures.join("\n\n")
+    );
+}
+
+/// Manifests of a spec in the format of `kubefi render`
+fn render_case(template: &Template, case: &str, spec: &str) -> Result<String> {
+    let manifests = if case.starts_with("nifi-") {
+        render_nifi(template, &serde_yaml::from_str(spec)?)?
+    } else if case.starts_with("stateless-") {
+        let spec = serde_yaml::from_str::<NiFiStatelessDeploymentSpec>(spec)?;
+        vec![(
+            "nifi-stateless",
+            template.nifi_stateless(NAME, &spec, CONFIG_HASH)?,
+        )]
+    } else if case.starts_with("minifi-") {
+        let spec = serde_yaml::from_str::<MiNiFiDeploymentSpec>(spec)?;
+        vec![("minifi", template.minifi(NAME, &spec, CONFIG_HASH)?)]
+    } else {
+        return Err(Error::msg(
+            "spec file name has to start with nifi-, stateless- or minifi-",
+        ));
+    };
+    Ok(manifests
+        .into_iter()
+        .filter_map(|(t, yaml)| yaml.map(|y| (t, y.trim().to_string())))
+        .filter(|(_, yaml)| !yaml.is_empty())
+        .map(|(t, yaml)| format!("---\n# Source: {}.yaml\n{}\n", t, yaml))
+        .collect())
+}
+
+/// All manifests of the controller and the ones it renders from the cluster state
+fn render_nifi(
+    template: &Template,
+    spec: &NiFiDeploymentSpec,
+) -> Result<Vec<(&'static str, Option<String>)>> {
+    let apis = ClusterApis::from_api_versions(&OPTIONAL_APIS);
+    let pod = format!("{}-0", NAME);
+    let subject = vec![("CN".to_string(), NAME.to_string())];
+    let dns_names = vec![format!("{}.{}.svc", NAME, NAMESPACE)];
+    let mut manifests = render_manifests(template, &apis, NAME, NAMESPACE, spec)?
+        .into_iter()
+        .map(|(t, yaml)| (t, Some(yaml)))
+        .collect::<Vec<_>>();
+    manifests.extend(vec![
+        (
+            "certificate",
+            template.certificate(NAME, &pod, &subject, &dns_names, 365, spec)?,
+        ),
+        (
+            "volumesnapshot",
+            template.volume_snapshot(NAME, &format!("data-{}", pod), "backup", spec)?,
+        ),
+        (
+            "flow-backup-pod",
+            template.flow_backup_pod(NAME, "backup", "node", "busybox", &["flow.xml.gz"], spec)?,
+        ),
+        (
+            "s3-backup-pod",
+            template.s3_backup_pod(NAME, "backup", spec)?,
+        ),
+        (
+            "s3-restore-pod",
+            template.s3_restore_pod(NAME, "restore", "s3://backups/flow.xml.gz", true, spec)?,
+        ),
+    ]);
+    Ok(manifests)
+}
+
+/// Line diff of the expected and actual manifests with a few lines of context around changes
+fn diff(expected: &str, actual: &str) -> String {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    // lengths of the longest common subsequences of the line suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', i, old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', i, old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', i, new[j]));
+            j += 1;
+        }
+    }
+    let shown = |n: usize| {
+        let from = n.saturating_sub(DIFF_CONTEXT);
+        let to = (n + DIFF_CONTEXT).min(lines.len() - 1);
+        lines[from..=to].iter().any(|(op, _, _)| *op != ' ')
+    };
+    let mut out = vec![];
+    for (n, (op, old_line, line)) in lines.iter().enumerate() {
+        if !shown(n) {
+            continue;
+        }
+        if n == 0 || !shown(n - 1) {
+            out.push(format!("@@ expected line {} @@", old_line + 1));
+        }
+        out.push(format!("{}{}", op, line));
+    }
+    out.join("\n")
+}
@@ -0,0 +1,94 @@
+//! Images of the rendered Pods replaced by the `verifiedImages` of the spec, which Kubefi sets
+//! once `imageVerification` of the operator config accepted them.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use k8s_openapi::api::apps::v1::StatefulSet;
+use kubefi_deployments::config::read_nifi_config;
+use kubefi_deployments::crd::NiFiDeploymentSpec;
+use kubefi_deployments::template::Template;
+
+const SPEC: &str = "tests/golden/specs/nifi-full.yaml";
+const NAME: &str = "nifi";
+const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+#[test]
+fn rendered_images() {
+    let (template, spec) = template_and_spec();
+    let images = template.images(NAME, &spec).unwrap();
+    for image in &[
+        "apache/nifi:1.11.4",
+        "ez123/alpine-tini",
+        "busybox",
+        "zookeeper:3.5.5",
+        "dabealu/zookeeper-exporter:v0.1.13",
+        "apache/nifi-registry:1.11.4",
+        "curlimages/curl:8.4.0",
+        "alpine/git:2.40.1",
+        "quay.io/oauth2-proxy/oauth2-proxy:v7.5.1",
+        "amazon/aws-cli:2.13.0",
+    ] {
+        assert!(
+            images.contains(&image.to_string()),
+            "{} in {:?}",
+            image,
+            images
+        );
+    }
+}
+
+#[test]
+fn pinned_images() {
+    let (template, mut spec) = template_and_spec();
+    let verified = template
+        .images(NAME, &spec)
+        .unwrap()
+        .into_iter()
+        .map(|image| (image.clone(), format!("{}@{}", image, DIGEST)))
+        .collect::<BTreeMap<_, _>>();
+    spec.verified_images = Some(verified);
+    let yaml = template.nifi_statefulset(NAME, &spec).unwrap().unwrap();
+    let set: StatefulSet = serde_yaml::from_str(&yaml).unwrap();
+    let pod = set.spec.and_then(|s| s.template.spec).unwrap();
+    let containers = pod
+        .containers
+        .iter()
+        .chain(pod.init_containers.iter().flatten())
+        .collect::<Vec<_>>();
+    assert!(containers.len() > 2);
+    for c in containers {
+        let image = c.image.clone().unwrap();
+        assert!(image.ends_with(&format!("@{}", DIGEST)), "{}", image);
+    }
+    let yaml = template
+        .auth_proxy_deployment(NAME, &spec, "h")
+        .unwrap()
+        .unwrap();
+    assert!(yaml.contains(&format!("oauth2-proxy:v7.5.1@{}", DIGEST)));
+}
+
+#[test]
+fn unverified_images() {
+    let (template, mut spec) = template_and_spec();
+    let verified = template
+        .images(NAME, &spec)
+        .unwrap()
+        .into_iter()
+        .filter(|image| image != "busybox")
+        .map(|image| (image.clone(), image))
+        .collect::<BTreeMap<_, _>>();
+    spec.verified_images = Some(verified);
+    let error = template.nifi_statefulset(NAME, &spec).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Image verification failed: busybox is not verified"
+    );
+    assert!(template.zk_statefulset(NAME, &spec).is_ok());
+}
+
+fn template_and_spec() -> (Template, NiFiDeploymentSpec) {
+    let template = Template::new(Path::new("./templates"), read_nifi_config().unwrap()).unwrap();
+    let spec = serde_yaml::from_str(&fs::read_to_string(SPEC).unwrap()).unwrap();
+    (template, spec)
+}
@@ -0,0 +1,221 @@
+//! Functions of the NiFi container startup script run with bash on the conf files of a temporary
+//! `NIFI_HOME`: secrets with sed, regex and XML metacharacters have to be written as they are.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use k8s_openapi::api::apps::v1::StatefulSet;
+use kubefi_deployments::config::read_nifi_config;
+use kubefi_deployments::crd::NiFiDeploymentSpec;
+use kubefi_deployments::template::Template;
+
+const SPEC: &str = "tests/golden/specs/nifi-default.yaml";
+const FIPS_SPEC: &str = r#"
+nifiReplicas: 2
+zk:
+  replicas: 3
+security:
+  fips: true
+  tls:
+    enabled: true
+"#;
+const NIFI_CONTAINER_NAME: &str = "server";
+// breaks `sed "s|...|${SECRET}|"` and is escaped in XML and properties files
+const SECRET: &str = r#"a|b&c\d"<e>$f/"#;
+
+#[test]
+fn ldap_manager_password() {
+    let providers = r#"<loginIdentityProviders>
+    <provider>
+        <property name="Manager Password">old</property>
+        <property name="TLS - Truststore Password"></property>
+    </provider>
+</loginIdentityProviders>
+"#;
+    let conf = run(
+        "ldap",
+        &[("login-identity-providers.xml", providers)],
+        r#"xml_property_replace "Manager Password" "${SECRET}" login-identity-providers.xml"#,
+    );
+    assert_eq!(
+        conf["login-identity-providers.xml"],
+        providers.replace(">old<", r#">a|b&amp;c\d&quot;&lt;e&gt;$f/<"#)
+    );
+}
+
+#[test]
+fn single_user_initial_admin() {
+    let authorizers = r#"<authorizers>
+    <userGroupProvider>
+        <property name="Initial User Identity admin">admin</property>
+    </userGroupProvider>
+    <accessPolicyProvider>
+        <property name="Initial Admin Identity">admin</property>
+    </accessPolicyProvider>
+</authorizers>
+"#;
+    let conf = run(
+        "single-user",
+        &[("authorizers.xml", authorizers)],
+        r#"xml_property_replace "Initial User Identity admin" "${SECRET}" authorizers.xml
+xml_property_replace "Initial Admin Identity" "${SECRET}" authorizers.xml"#,
+    );
+    assert_eq!(
+        conf["authorizers.xml"],
+        authorizers.replace(">admin<", r#">a|b&amp;c\d&quot;&lt;e&gt;$f/<"#)
+    );
+}
+
+#[test]
+fn oidc_client_secret() {
+    let properties =
+        "nifi.security.user.oidc.client.id=nifi\nnifi.security.user.oidc.client.secret=\n";
+    let conf = run(
+        "oidc",
+        &[("nifi.properties", properties)],
+        r#"prop_replace nifi.security.user.oidc.client.secret "${SECRET}""#,
+    );
+    // backslashes are escapes of the properties format
+    assert_eq!(
+        conf["nifi.properties"],
+        properties.replace("secret=\n", "secret=a|b&c\\\\d\"<e>$f/\n")
+    );
+}
+
+#[test]
+fn new_property() {
+    let conf = run(
+        "new-property",
+        &[("nifi.properties", "nifi.cluster.is.node=true\n")],
+        r#"prop_replace nifi.ui.banner.text "${SECRET}""#,
+    );
+    assert_eq!(
+        conf["nifi.properties"],
+        "nifi.cluster.is.node=true\nnifi.ui.banner.text=a|b&c\\\\d\"<e>$f/\n"
+    );
+}
+
+#[test]
+fn sensitive_props_key_rotation() {
+    let properties =
+        "nifi.sensitive.props.key=\nnifi.sensitive.props.algorithm=NIFI_PBKDF2_AES_GCM_256\n";
+    let conf = run(
+        "sensitive-props-key",
+        &[("nifi.properties", properties)],
+        r#"prop_replace nifi.sensitive.props.key "previous&key"
+grep -qx 'nifi.sensitive.props.key=previous&key' "${NIFI_HOME}/conf/nifi.properties"
+prop_replace nifi.sensitive.props.key "${SECRET}""#,
+    );
+    assert_eq!(
+        conf["nifi.properties"],
+        properties.replace("key=\n", "key=a|b&c\\\\d\"<e>$f/\n")
+    );
+}
+
+#[test]
+fn fips_keystore_password() {
+    let properties =
+        "nifi.security.keystore=./conf/keystore.p12\nnifi.security.keystoreType=PKCS12\n";
+    // keytool records its arguments, one per line
+    let conf = run_spec(
+        FIPS_SPEC,
+        "fips",
+        &[("nifi.properties", properties), ("keytool.args", "")],
+        r#"keytool () { printf '%s\n' "$@" > conf/keytool.args; }
+cd "${NIFI_HOME}"
+BC_FIPS_JAR="${NIFI_HOME}/lib/bc-fips-1.0.2.jar"
+prop_replace nifi.security.keystorePasswd "${SECRET}=="
+to_bcfks keystore"#,
+    );
+    let args: Vec<_> = conf["keytool.args"].lines().collect();
+    let arg = |name: &str| args[args.iter().position(|a| *a == name).unwrap() + 1];
+    assert_eq!(arg("-srckeystore"), "./conf/keystore.p12");
+    assert_eq!(arg("-srcstorepass"), format!("{}==", SECRET));
+    assert_eq!(arg("-deststorepass"), format!("{}==", SECRET));
+    assert!(arg("-providerpath").ends_with("/lib/bc-fips-1.0.2.jar"));
+    assert!(conf["nifi.properties"].contains("nifi.security.keystore=./conf/keystore.bcfks\n"));
+    assert!(conf["nifi.properties"].contains("nifi.security.keystoreType=BCFKS\n"));
+}
+
+/// Startup script of the NiFi container rendered for the spec
+fn startup_script(spec: &str) -> String {
+    let template = Template::new(Path::new("./templates"), read_nifi_config().unwrap()).unwrap();
+    let spec: NiFiDeploymentSpec = serde_yaml::from_str(spec).unwrap();
+    let yaml = template.nifi_statefulset("nifi", &spec).unwrap().unwrap();
+    let set: StatefulSet = serde_yaml::from_str(&yaml).unwrap();
+    set.spec
+        .and_then(|s| s.template.spec)
+        .and_then(|pod| {
+            pod.containers
+                .into_iter()
+                .find(|c| c.name == NIFI_CONTAINER_NAME)
+        })
+        .and_then(|c| c.command)
+        .and_then(|mut command| command.pop())
+        .unwrap()
+}
+
+/// Function definitions of the script, each ends with the first `}` at the start of a line
+fn functions(script: &str) -> String {
+    let mut lines = vec![];
+    let mut inside = false;
+    for line in script.lines() {
+        inside = inside || line.ends_with("() {");
+        if inside {
+            lines.push(line);
+        }
+        inside = inside && line != "}";
+    }
+    lines.join("\n")
+}
+
+/// Runs `commands` after the script functions of the default spec, see `run_spec`
+fn run(case: &str, conf: &[(&str, &str)], commands: &str) -> BTreeMap<String, String> {
+    run_spec(&fs::read_to_string(SPEC).unwrap(), case, conf, commands)
+}
+
+/// Runs `commands` after the script functions with `SECRET` in the environment, returns the
+/// `conf` files afterwards by name
+fn run_spec(
+    spec: &str,
+    case: &str,
+    conf: &[(&str, &str)],
+    commands: &str,
+) -> BTreeMap<String, String> {
+    let home = std::env::temp_dir().join(format!(
+        "kubefi-startup-script-{}-{}",
+        std::process::id(),
+        case
+    ));
+    let conf_dir = home.join("conf");
+    fs::create_dir_all(&conf_dir).unwrap();
+    for (name, content) in conf {
+        fs::write(conf_dir.join(name), content).unwrap();
+    }
+    let output = Command::new("bash")
+        .arg("-ec")
+        .arg(format!(
+            "{}\n{}",
+            functions(&startup_script(spec)),
+            commands
+        ))
+        .env("NIFI_HOME", &home)
+        .env("SECRET", SECRET)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let files = conf
+        .iter()
+        .map(|(name, _)| {
+            let content = fs::read_to_string(conf_dir.join(name)).unwrap();
+            (name.to_string(), content)
+        })
+        .collect();
+    fs::remove_dir_all(&home).unwrap();
+    files
+}